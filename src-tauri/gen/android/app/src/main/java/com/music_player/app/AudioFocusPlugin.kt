@@ -0,0 +1,68 @@
+package com.music_player.app
+
+import android.app.Activity
+import android.content.Context
+import android.media.AudioAttributes
+import android.media.AudioFocusRequest
+import android.media.AudioManager
+import app.tauri.annotation.Command
+import app.tauri.annotation.InvokeArg
+import app.tauri.annotation.TauriPlugin
+import app.tauri.plugin.Channel
+import app.tauri.plugin.Invoke
+import app.tauri.plugin.JSObject
+import app.tauri.plugin.Plugin
+
+@InvokeArg
+class ListenerArgs {
+    lateinit var channel: Channel
+}
+
+// Holds the player's audio focus request and reports focus changes to the Rust audio engine
+// (see audio_focus.rs), which decides whether to pause, duck or resume.
+@TauriPlugin
+class AudioFocusPlugin(private val activity: Activity) : Plugin(activity) {
+    private val audioManager = activity.getSystemService(Context.AUDIO_SERVICE) as AudioManager
+    private var channel: Channel? = null
+
+    private val listener = AudioManager.OnAudioFocusChangeListener { focusChange ->
+        val change = when (focusChange) {
+            AudioManager.AUDIOFOCUS_GAIN -> "gain"
+            AudioManager.AUDIOFOCUS_LOSS -> "loss"
+            AudioManager.AUDIOFOCUS_LOSS_TRANSIENT -> "loss_transient"
+            AudioManager.AUDIOFOCUS_LOSS_TRANSIENT_CAN_DUCK -> "loss_transient_can_duck"
+            else -> return@OnAudioFocusChangeListener
+        }
+        channel?.send(JSObject().put("change", change))
+    }
+
+    // Ducking is done by the engine, so the system is told not to lower the volume itself.
+    private val request = AudioFocusRequest.Builder(AudioManager.AUDIOFOCUS_GAIN)
+        .setAudioAttributes(
+            AudioAttributes.Builder()
+                .setUsage(AudioAttributes.USAGE_MEDIA)
+                .setContentType(AudioAttributes.CONTENT_TYPE_MUSIC)
+                .build()
+        )
+        .setWillPauseWhenDucked(true)
+        .setOnAudioFocusChangeListener(listener)
+        .build()
+
+    @Command
+    fun setListener(invoke: Invoke) {
+        channel = invoke.parseArgs(ListenerArgs::class.java).channel
+        invoke.resolve()
+    }
+
+    @Command
+    fun requestFocus(invoke: Invoke) {
+        val result = audioManager.requestAudioFocus(request)
+        invoke.resolve(JSObject().put("granted", result == AudioManager.AUDIOFOCUS_REQUEST_GRANTED))
+    }
+
+    @Command
+    fun abandonFocus(invoke: Invoke) {
+        audioManager.abandonAudioFocusRequest(request)
+        invoke.resolve()
+    }
+}
@@ -0,0 +1,114 @@
+import AVFoundation
+import MediaPlayer
+import SwiftRs
+import Tauri
+import UIKit
+import WebKit
+
+class ListenerArgs: Decodable {
+  let channel: Channel
+}
+
+class MetadataArgs: Decodable {
+  let title: String?
+  let artist: String?
+  let album: String?
+  let duration: Double?
+  let coverPath: String?
+}
+
+class PlaybackArgs: Decodable {
+  let playing: Bool
+  let stopped: Bool
+  let position: Double?
+}
+
+// Keeps audio playing in the background and connects the lock screen, Control Center and
+// CarPlay controls to the Rust audio engine (see now_playing.rs).
+class NowPlayingPlugin: Plugin {
+  private var channel: Channel?
+  private var info: [String: Any] = [:]
+
+  override func load(webview: WKWebView) {
+    do {
+      try AVAudioSession.sharedInstance().setCategory(.playback, mode: .default)
+      try AVAudioSession.sharedInstance().setActive(true)
+    } catch {
+      NSLog("Failed to set up the audio session: \(error)")
+    }
+
+    let center = MPRemoteCommandCenter.shared()
+    center.playCommand.addTarget { [weak self] _ in self?.send("play") ?? .commandFailed }
+    center.pauseCommand.addTarget { [weak self] _ in self?.send("pause") ?? .commandFailed }
+    center.togglePlayPauseCommand.addTarget { [weak self] _ in
+      self?.send("toggle") ?? .commandFailed
+    }
+    center.stopCommand.addTarget { [weak self] _ in self?.send("stop") ?? .commandFailed }
+    center.nextTrackCommand.addTarget { [weak self] _ in self?.send("next") ?? .commandFailed }
+    center.previousTrackCommand.addTarget { [weak self] _ in
+      self?.send("previous") ?? .commandFailed
+    }
+    center.changePlaybackPositionCommand.addTarget { [weak self] event in
+      guard let event = event as? MPChangePlaybackPositionCommandEvent else {
+        return .commandFailed
+      }
+      return self?.send("seek", position: event.positionTime) ?? .commandFailed
+    }
+  }
+
+  private func send(_ command: String, position: Double? = nil) -> MPRemoteCommandHandlerStatus {
+    guard let channel = channel else {
+      return .noActionableNowPlayingItem
+    }
+
+    var payload: JsonObject = ["command": command]
+    if let position = position {
+      payload["position"] = position
+    }
+    channel.send(payload)
+    return .success
+  }
+
+  @objc public func setListener(_ invoke: Invoke) throws {
+    channel = try invoke.parseArgs(ListenerArgs.self).channel
+    invoke.resolve()
+  }
+
+  @objc public func setMetadata(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(MetadataArgs.self)
+
+    info = [:]
+    info[MPMediaItemPropertyTitle] = args.title
+    info[MPMediaItemPropertyArtist] = args.artist
+    info[MPMediaItemPropertyAlbumTitle] = args.album
+    info[MPMediaItemPropertyPlaybackDuration] = args.duration
+    if let path = args.coverPath, let image = UIImage(contentsOfFile: path) {
+      info[MPMediaItemPropertyArtwork] = MPMediaItemArtwork(boundsSize: image.size) { _ in image }
+    }
+
+    MPNowPlayingInfoCenter.default().nowPlayingInfo = info
+    invoke.resolve()
+  }
+
+  @objc public func setPlayback(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(PlaybackArgs.self)
+
+    if args.stopped {
+      info = [:]
+      MPNowPlayingInfoCenter.default().nowPlayingInfo = nil
+    } else {
+      // The elapsed time is extrapolated from the rate, so it only needs updating on changes.
+      info[MPNowPlayingInfoPropertyPlaybackRate] = args.playing ? 1.0 : 0.0
+      if let position = args.position {
+        info[MPNowPlayingInfoPropertyElapsedPlaybackTime] = position
+      }
+      MPNowPlayingInfoCenter.default().nowPlayingInfo = info
+    }
+    invoke.resolve()
+  }
+}
+
+@_cdecl("init_plugin_now_playing")
+func initPlugin() -> Plugin {
+  return NowPlayingPlugin()
+}
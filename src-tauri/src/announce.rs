@@ -0,0 +1,311 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Frames between checks for a newly requested chime.
+const CHECK_FRAMES: u64 = 1024;
+
+/// Peak level of the chime, as a share of full scale.
+const CHIME_LEVEL: f32 = 0.3;
+
+/// Level the music is ducked to while the chime plays.
+const DUCK_LEVEL: f32 = 0.4;
+
+/// Fade in and out of each tone and of the ducking, so neither clicks.
+const RAMP: Duration = Duration::from_millis(10);
+
+const OPENING: [f32; 2] = [659.25, 523.25];
+const HOUR_TONE: f32 = 880.0;
+const QUARTER_TONE: f32 = 1318.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnounceMode {
+    #[default]
+    Off,
+    /// On the hour, whenever something is playing then.
+    Hourly,
+    /// As each track ends and the next one starts.
+    BetweenTracks,
+}
+
+/// A chime waiting to be mixed into whatever the player outputs next.
+pub type Pending = Arc<Mutex<Option<Chime>>>;
+
+/// Tones telling the time like a clock: a two-note opening, one strike per hour on a
+/// 12-hour clock, then a high pip per quarter hour past it. A zero frequency is a rest.
+pub struct Chime {
+    tones: Vec<(f32, Duration)>,
+}
+
+impl Chime {
+    pub fn for_time(hour: u32, minute: u32) -> Chime {
+        let strikes = match hour % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        let quarters = minute / 15;
+
+        let mut tones = Vec::new();
+        for frequency in OPENING {
+            tones.push((frequency, Duration::from_millis(300)));
+        }
+        tones.push((0.0, Duration::from_millis(400)));
+        for _ in 0..strikes {
+            tones.push((HOUR_TONE, Duration::from_millis(180)));
+            tones.push((0.0, Duration::from_millis(220)));
+        }
+        if quarters > 0 {
+            tones.push((0.0, Duration::from_millis(200)));
+        }
+        for _ in 0..quarters {
+            tones.push((QUARTER_TONE, Duration::from_millis(90)));
+            tones.push((0.0, Duration::from_millis(110)));
+        }
+
+        Chime { tones }
+    }
+
+    pub fn now() -> Chime {
+        let now = Local::now();
+        Chime::for_time(now.hour(), now.minute())
+    }
+}
+
+/// Whether the local hour has just struck and differs from `last`, which then becomes it.
+pub fn struck_hour(last: &mut Option<(NaiveDate, u32)>) -> bool {
+    struck_hour_at(Local::now().naive_local(), last)
+}
+
+fn struck_hour_at(now: NaiveDateTime, last: &mut Option<(NaiveDate, u32)>) -> bool {
+    let hour = (now.date(), now.hour());
+    if now.minute() != 0 || *last == Some(hour) {
+        return false;
+    }
+    *last = Some(hour);
+    true
+}
+
+/// Passes a track through, mixing in any chime put in `pending` over the top of it with
+/// the track ducked underneath. A chime still playing when the track ends or is replaced
+/// goes back into `pending`, so the next track finishes it.
+pub struct Announcer<S> {
+    inner: S,
+    pending: Pending,
+    chime: Option<Chime>,
+    tone: usize,
+    tone_frame: u64,
+    duck: f32,
+    frame: u64,
+    channel: u16,
+    sample_rate: f32,
+}
+
+impl<S: Source<Item = i16>> Announcer<S> {
+    pub fn new(inner: S, pending: Pending) -> Announcer<S> {
+        Announcer {
+            inner,
+            pending,
+            chime: None,
+            tone: 0,
+            tone_frame: 0,
+            duck: 1.0,
+            frame: 0,
+            channel: 0,
+            sample_rate: 0.0,
+        }
+    }
+
+    /// The chime's sample for the current frame, with its tone's fade applied.
+    fn chime_sample(&self, sample_rate: f32) -> f32 {
+        let Some((frequency, length)) = self
+            .chime
+            .as_ref()
+            .and_then(|chime| chime.tones.get(self.tone))
+        else {
+            return 0.0;
+        };
+        if *frequency == 0.0 {
+            return 0.0;
+        }
+
+        let length = length.as_secs_f32() * sample_rate;
+        let ramp = RAMP.as_secs_f32() * sample_rate;
+        let at = self.tone_frame as f32;
+        let envelope = (at / ramp).min((length - at) / ramp).clamp(0.0, 1.0);
+
+        (TAU * frequency * at / sample_rate).sin() * envelope * CHIME_LEVEL * i16::MAX as f32
+    }
+
+    /// Moves the chime on by a frame, dropping it once its last tone is done.
+    fn advance_chime(&mut self, sample_rate: f32) {
+        let Some(chime) = &self.chime else {
+            return;
+        };
+        let Some((_, length)) = chime.tones.get(self.tone) else {
+            self.chime = None;
+            return;
+        };
+
+        self.tone_frame += 1;
+        if self.tone_frame as f32 >= length.as_secs_f32() * sample_rate {
+            self.tone += 1;
+            self.tone_frame = 0;
+            if self.tone >= chime.tones.len() {
+                self.chime = None;
+            }
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Announcer<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let Some(sample) = self.inner.next() else {
+            self.hand_back();
+            return None;
+        };
+        let sample_rate = self.inner.sample_rate() as f32;
+        self.sample_rate = sample_rate;
+
+        if self.channel == 0 && self.chime.is_none() && self.frame % CHECK_FRAMES == 0 {
+            if let Some(chime) = self
+                .pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+            {
+                self.chime = Some(chime);
+                self.tone = 0;
+                self.tone_frame = 0;
+            }
+        }
+
+        let mixed = sample as f32 * self.duck + self.chime_sample(sample_rate);
+
+        self.channel += 1;
+        if self.channel >= self.inner.channels().max(1) {
+            self.channel = 0;
+            self.frame += 1;
+
+            let target = if self.chime.is_some() {
+                DUCK_LEVEL
+            } else {
+                1.0
+            };
+            let step = (1.0 - DUCK_LEVEL) / (RAMP.as_secs_f32() * sample_rate).max(1.0);
+            self.duck = if self.duck > target {
+                (self.duck - step).max(target)
+            } else {
+                (self.duck + step).min(target)
+            };
+            self.advance_chime(sample_rate);
+        }
+
+        Some(mixed.clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl<S> Announcer<S> {
+    /// Puts what's left of the chime back into `pending` for the next track, unless a newer
+    /// one is already waiting.
+    fn hand_back(&mut self) {
+        let Some(chime) = self.chime.take() else {
+            return;
+        };
+        let mut tones = chime.tones.into_iter().skip(self.tone);
+        let Some((frequency, length)) = tones.next() else {
+            return;
+        };
+        let played = Duration::from_secs_f32(self.tone_frame as f32 / self.sample_rate.max(1.0));
+        let rest = std::iter::once((frequency, length.saturating_sub(played)));
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.is_none() {
+            *pending = Some(Chime {
+                tones: rest.chain(tones).collect(),
+            });
+        }
+    }
+}
+
+impl<S> Drop for Announcer<S> {
+    fn drop(&mut self) {
+        self.hand_back();
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Announcer<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(chime: &Chime, tone: f32) -> usize {
+        chime
+            .tones
+            .iter()
+            .filter(|(frequency, _)| *frequency == tone)
+            .count()
+    }
+
+    #[test]
+    fn chimes_strike_the_hour_and_pip_the_quarters() {
+        let chime = Chime::for_time(15, 47);
+        assert_eq!(count(&chime, HOUR_TONE), 3);
+        assert_eq!(count(&chime, QUARTER_TONE), 3);
+
+        let chime = Chime::for_time(9, 14);
+        assert_eq!(count(&chime, HOUR_TONE), 9);
+        assert_eq!(count(&chime, QUARTER_TONE), 0);
+    }
+
+    #[test]
+    fn midnight_and_noon_strike_twelve() {
+        assert_eq!(count(&Chime::for_time(0, 0), HOUR_TONE), 12);
+        assert_eq!(count(&Chime::for_time(12, 30), HOUR_TONE), 12);
+        assert_eq!(count(&Chime::for_time(12, 30), QUARTER_TONE), 2);
+    }
+
+    #[test]
+    fn each_hour_is_struck_once() {
+        let at = |hour, minute| {
+            NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        let mut last = None;
+
+        assert!(!struck_hour_at(at(13, 59), &mut last));
+        assert!(struck_hour_at(at(14, 0), &mut last));
+        assert!(!struck_hour_at(at(14, 0), &mut last));
+        assert!(!struck_hour_at(at(14, 1), &mut last));
+        assert!(struck_hour_at(at(15, 0), &mut last));
+    }
+}
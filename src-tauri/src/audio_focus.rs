@@ -0,0 +1,227 @@
+use serde::Deserialize;
+use std::sync::mpsc;
+use tauri::AppHandle;
+
+use crate::audio_player::AudioCommand;
+
+/// Share of the volume kept while another app talks over us, e.g. navigation prompts.
+const DUCK_VOLUME: f32 = 0.2;
+
+/// Focus changes reported by Android's `AudioManager`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusChange {
+    Gain,
+    /// Another app took over for good, e.g. a different music player.
+    Loss,
+    /// A call or similar; playback resumes once it's over.
+    LossTransient,
+    /// A short interruption that allows playing on quietly.
+    LossTransientCanDuck,
+}
+
+/// What the engine should do with playback after a focus change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FocusAction {
+    None,
+    Pause,
+    Resume,
+    /// The volume scale changed, so the sink's volume has to be applied again.
+    UpdateVolume,
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum FocusState {
+    #[default]
+    Released,
+    Held,
+    Ducked,
+    /// Paused by a transient loss, to be resumed when focus comes back.
+    Suspended,
+}
+
+/// Audio focus on Android, so other apps can interrupt playback and hand it back. Elsewhere
+/// focus is always granted and never changes.
+pub struct AudioFocus {
+    #[cfg(target_os = "android")]
+    plugin: Option<android::FocusPlugin>,
+    state: FocusState,
+}
+
+impl AudioFocus {
+    pub fn new(app: &AppHandle, sender: mpsc::Sender<AudioCommand>) -> AudioFocus {
+        #[cfg(target_os = "android")]
+        let plugin = android::FocusPlugin::connect(app, sender);
+
+        #[cfg(not(target_os = "android"))]
+        let _ = (app, sender);
+
+        AudioFocus {
+            #[cfg(target_os = "android")]
+            plugin,
+            state: FocusState::Released,
+        }
+    }
+
+    /// Focus without the Android plugin, which is always granted.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn granted() -> AudioFocus {
+        AudioFocus {
+            #[cfg(target_os = "android")]
+            plugin: None,
+            state: FocusState::Released,
+        }
+    }
+
+    /// Asks for focus before playing. Playback shouldn't start when this returns false.
+    pub fn request(&mut self) -> bool {
+        if matches!(self.state, FocusState::Held | FocusState::Ducked) {
+            return true;
+        }
+
+        #[cfg(target_os = "android")]
+        if let Some(plugin) = &self.plugin {
+            match plugin.request() {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => log::error!("Failed to request audio focus: {}", e),
+            }
+        }
+
+        self.state = FocusState::Held;
+        true
+    }
+
+    /// Cancels a pending resume when the user pauses during an interruption.
+    pub fn user_paused(&mut self) {
+        if self.state == FocusState::Suspended {
+            self.state = FocusState::Held;
+        }
+    }
+
+    pub fn volume_scale(&self) -> f32 {
+        match self.state {
+            FocusState::Ducked => DUCK_VOLUME,
+            _ => 1.0,
+        }
+    }
+
+    pub fn handle_change(&mut self, change: FocusChange, playing: bool) -> FocusAction {
+        match change {
+            FocusChange::Loss => {
+                let ducked = self.state == FocusState::Ducked;
+                self.state = FocusState::Released;
+
+                #[cfg(target_os = "android")]
+                if let Some(plugin) = &self.plugin {
+                    if let Err(e) = plugin.abandon() {
+                        log::error!("Failed to abandon audio focus: {}", e);
+                    }
+                }
+
+                match (playing, ducked) {
+                    (true, _) => FocusAction::Pause,
+                    (false, true) => FocusAction::UpdateVolume,
+                    (false, false) => FocusAction::None,
+                }
+            }
+            FocusChange::LossTransient if playing => {
+                self.state = FocusState::Suspended;
+                FocusAction::Pause
+            }
+            FocusChange::LossTransientCanDuck if playing => {
+                self.state = FocusState::Ducked;
+                FocusAction::UpdateVolume
+            }
+            FocusChange::Gain => {
+                let previous = self.state;
+                self.state = FocusState::Held;
+                match previous {
+                    FocusState::Ducked => FocusAction::UpdateVolume,
+                    FocusState::Suspended => FocusAction::Resume,
+                    _ => FocusAction::None,
+                }
+            }
+            _ => FocusAction::None,
+        }
+    }
+}
+
+/// Bridge to `AudioFocusPlugin.kt`, which owns the `AudioFocusRequest` and its listener.
+#[cfg(target_os = "android")]
+mod android {
+    use serde::{Deserialize, Serialize};
+    use std::sync::mpsc;
+    use tauri::ipc::{Channel, InvokeResponseBody};
+    use tauri::plugin::{Builder, PluginHandle, TauriPlugin};
+    use tauri::{AppHandle, Manager, Wry};
+
+    use super::FocusChange;
+    use crate::audio_player::AudioCommand;
+
+    const PLUGIN_IDENTIFIER: &str = "com.music_player.app";
+
+    #[derive(Deserialize)]
+    struct FocusEvent {
+        change: FocusChange,
+    }
+
+    #[derive(Clone)]
+    pub struct FocusPlugin(PluginHandle<Wry>);
+
+    #[derive(Serialize)]
+    struct ListenerArgs {
+        channel: Channel<serde_json::Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct RequestResponse {
+        granted: bool,
+    }
+
+    pub fn init() -> TauriPlugin<Wry> {
+        Builder::new("audio-focus")
+            .setup(|app, api| {
+                let handle = api.register_android_plugin(PLUGIN_IDENTIFIER, "AudioFocusPlugin")?;
+                app.manage(FocusPlugin(handle));
+                Ok(())
+            })
+            .build()
+    }
+
+    impl FocusPlugin {
+        /// Routes focus changes to the audio thread.
+        pub fn connect(app: &AppHandle, sender: mpsc::Sender<AudioCommand>) -> Option<FocusPlugin> {
+            let plugin = app.try_state::<FocusPlugin>()?.inner().clone();
+
+            let channel = Channel::new(move |body| {
+                if let InvokeResponseBody::Json(json) = body {
+                    let event: FocusEvent = serde_json::from_str(&json)?;
+                    let _ = sender.send(AudioCommand::AudioFocusChanged(event.change));
+                }
+                Ok(())
+            });
+            if let Err(e) = plugin
+                .0
+                .run_mobile_plugin::<()>("setListener", ListenerArgs { channel })
+            {
+                log::error!("Failed to listen for audio focus changes: {}", e);
+            }
+
+            Some(plugin)
+        }
+
+        pub fn request(&self) -> Result<bool, tauri::plugin::mobile::PluginInvokeError> {
+            self.0
+                .run_mobile_plugin::<RequestResponse>("requestFocus", ())
+                .map(|response| response.granted)
+        }
+
+        pub fn abandon(&self) -> Result<(), tauri::plugin::mobile::PluginInvokeError> {
+            self.0.run_mobile_plugin("abandonFocus", ())
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+pub use android::init;
@@ -1,14 +1,92 @@
-use rodio::{OutputStream, Sink};
-use souvlaki::{MediaControlEvent, MediaControls, MediaPlayback, PlatformConfig};
+use rodio::Sink;
+use souvlaki::{MediaMetadata, MediaPlayback};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
-use std::sync::mpsc;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
-use tauri::{AppHandle, Emitter, Manager};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
 use thiserror::Error;
 
-use crate::util;
-use util::{get_track_info_from_path, play_track};
+use crate::announce::{self, AnnounceMode, Chime, Pending};
+use crate::audio_focus::{AudioFocus, FocusAction, FocusChange};
+use crate::bluetooth::BluetoothVolume;
+use crate::cache::MetadataCache;
+use crate::chromecast::{CastCommand, CastOutput};
+use crate::crossfade::{self, Remaining, Slot};
+use crate::exclusive::ExclusiveOutput;
+use crate::library::{LibraryError, PlaylistSettings, RadioSeed, SortOrder, StartOffset};
+use crate::lyrics::Lyrics;
+use crate::media_controls::MediaControls;
+use crate::output::{self, AudioOutput, Output, OutputBackend, OutputFormat};
+use crate::output_device;
+use crate::paths;
+use crate::player_host::{self, PlayerHost};
+use crate::playlist;
+use crate::power;
+use crate::seek;
+use crate::settings::{Settings, SettingsStore};
+use crate::shuffle::{self, ShuffleMode, ShuffleWeighting};
+use crate::silence::{SharedTrimConfig, TrimConfig};
+use crate::sleep_inhibitor::SleepInhibitor;
+use crate::util::{self, ImportOrder, SourceKind};
+use crate::volume::VolumeCurve;
+use util::play_track;
+
+/// Upper bound of the volume slider (125%).
+pub const MAX_VOLUME: f32 = 1.25;
+
+/// Bounds of the volume up/down step.
+const MIN_VOLUME_STEP: f32 = 0.01;
+const MAX_VOLUME_STEP: f32 = 0.25;
+
+/// How often the position in an audiobook file is saved while it plays.
+const LISTENING_SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Leeway for the output buffer when telling played audio from a seek.
+const PLAYED_SLACK: Duration = Duration::from_millis(250);
+
+/// Seeks forward made this early in a track, to no further than `MAX_START_OFFSET`, teach
+/// the library where the user likes it to start.
+const START_SEEK_WINDOW: Duration = Duration::from_secs(15);
+const MAX_START_OFFSET: Duration = Duration::from_secs(120);
+
+/// Bounds of the playback speed a playlist can ask for.
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 3.0;
+
+/// Bounds of the interval between position events.
+const MIN_EMIT_INTERVAL_MS: u64 = 50;
+const MAX_EMIT_INTERVAL_MS: u64 = 5000;
+
+/// Tracks kept in the session history for stepping back with `prev`.
+const MAX_SESSION_HISTORY: usize = 1000;
+
+/// Queue edits that can be undone.
+const MAX_UNDO: usize = 50;
+
+/// Tracks appended at a time when endless play extends the queue.
+const ENDLESS_BATCH: usize = 10;
+
+/// Radio tracks added at a time, and how few may be left before more are added.
+const RADIO_BATCH: usize = 15;
+const RADIO_LOOKAHEAD: usize = 3;
+
+/// Wake-up interval while synced lyrics are shown, so line changes aren't late.
+const LYRICS_TICK: Duration = Duration::from_millis(100);
+
+/// How long query methods wait for the audio thread to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pause before the audio loop restarts after a panic, so a panic on every command doesn't
+/// spin.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Wait between attempts to set up media controls that failed, e.g. with no D-Bus session yet.
+const CONTROLS_RETRY: Duration = Duration::from_secs(30);
 
 #[derive(Error, Debug)]
 pub enum AudioError {
@@ -36,9 +114,45 @@ pub enum AudioError {
     #[error("Index out of bounds")]
     OutOfBoundsError,
 
+    #[error("Failed to serialize data")]
+    SerializeError(#[from] serde_json::Error),
+
     #[error("Failed to emit event")]
     EmitError(#[from] tauri::Error),
 
+    #[error("No track matches the search")]
+    NoMatchError,
+
+    #[error("Found no tracks to play")]
+    NothingToPlayError,
+
+    #[error("Nothing to undo")]
+    NothingToUndoError,
+
+    #[error("Nothing to redo")]
+    NothingToRedoError,
+
+    #[error("Queue version {0} is out of date")]
+    StaleQueueError(u64),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+
+    #[error("Audio thread did not respond")]
+    NoResponseError,
+
+    #[error("Unsupported file type")]
+    UnsupportedFileError,
+
+    #[error("Can't queue {0}: {1}")]
+    RejectedFileError(String, String),
+
+    #[error("Another app is playing audio")]
+    AudioFocusDenied,
+
+    #[error("Output changes are only handled by the audio thread")]
+    OutputCommandError,
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
@@ -46,36 +160,856 @@ pub enum AudioError {
 pub struct AudioState {
     pub queue: Vec<TrackInfo>,
     pub current_index: usize,
-    pub duration: Option<u64>,
+    /// Bumped whenever the queue changes, so edits made against an older queue can be told
+    /// apart.
+    pub queue_version: u64,
     pub looped: bool,
-    pub handle: AppHandle,
-    pub controls: MediaControls,
+    /// The app around the player, or a stand-in for it.
+    pub host: Arc<dyn PlayerHost>,
+    /// `None` when the platform's media controls are turned off or couldn't be set up.
+    pub controls: Option<MediaControls>,
+    pub controls_enabled: bool,
+    /// When to next try setting up media controls that failed.
+    pub controls_retry_at: Instant,
     pub sender: mpsc::Sender<AudioCommand>,
+    pub cache: MetadataCache,
+    pub cast: Option<CastOutput>,
+    pub skip_silence: Arc<AtomicBool>,
+    /// Adds TPDF dither when track gain is rounded back to 16 bits.
+    pub dither: Arc<AtomicBool>,
+    /// Plays tracks unprocessed, without gain, dither, silence trimming or skipping,
+    /// crossfades or a playlist's speed, for comparing against the processed sound. Not
+    /// saved, so every session starts processed.
+    pub dsp_bypass: Arc<AtomicBool>,
+    /// Nanoseconds of silence skipped in the current track since it started or was seeked.
+    pub skipped: Arc<AtomicU64>,
+    /// History entry of the current play and how much of it has been heard, written back to
+    /// the library when the play ends.
+    pub play_record: Option<(i64, Duration)>,
+    /// When the played time was last counted, and the sink position then. Cleared while
+    /// paused.
+    played_mark: Option<(Instant, Duration)>,
+    /// Time left in the current track once its end has been decoded, for starting crossfades.
+    pub remaining: Remaining,
+    /// Shared with the playing track's `TrimSilence`.
+    pub trim_silence: SharedTrimConfig,
+    /// Set while a track is in the sink and should advance the queue once it finishes.
+    pub track_loaded: bool,
+    /// Incremented for every track started, so a late end notification for a replaced
+    /// track is ignored.
+    pub track_id: u64,
+    /// Incremented for every track played. Unlike `track_id` it stays put while the same
+    /// track is loaded again, as an indexed seek or a reopened output does, so whatever was
+    /// fetched for the track still applies.
+    pub play_id: u64,
+    pub shuffle: ShuffleMode,
+    pub shuffle_weighting: ShuffleWeighting,
+    /// Queue order from before shuffling, restored when shuffle is turned off.
+    pub unshuffled: Option<Vec<TrackInfo>>,
+    /// Tracks started this session, oldest first; the last entry is the current track.
+    pub history: Vec<TrackInfo>,
+    pub undo_stack: Vec<QueueSnapshot>,
+    pub redo_stack: Vec<QueueSnapshot>,
+    /// How often position events are emitted while playing.
+    pub emit_interval: Duration,
+    /// Position past which "previous" restarts the current track instead.
+    pub prev_threshold: Duration,
+    /// Treat quick double and triple presses of the media play/pause button as next and
+    /// previous, with presses counted over `gesture_window` from the first.
+    pub button_gestures: bool,
+    pub gesture_window: Duration,
+    pub button_presses: u32,
+    /// Read-ahead window in bytes for opened tracks.
+    pub read_ahead: usize,
+    pub online_lyrics: bool,
+    /// Lyrics of the current track once loaded, and the line last emitted.
+    pub lyrics: Option<Lyrics>,
+    pub lyric_line: Option<usize>,
+    /// Position at which the current track's outro is skipped, from its album's skip rule.
+    pub skip_outro_at: Option<Duration>,
+    /// When the position in the current track was last saved for audiobook progress.
+    pub listening_saved_at: Instant,
+    /// The user's crossfade, which the queued playlist may override; see `crossfade()`.
+    pub crossfade: Duration,
+    /// Preferences of the playlist the queue was loaded from. They override the user's own
+    /// settings without replacing them, until the queue is replaced.
+    pub playlist_settings: Option<PlaylistSettings>,
+    /// Audio of the track in the sink, which a crossfade mixes into the next one.
+    pub deck: Option<Slot>,
+    pub endless: bool,
+    pub announce: AnnounceMode,
+    /// Chime for the output to mix in, shared with the source of the track playing.
+    pub announcement: Pending,
+    /// Set when a track ends and the queue moves on by itself, so the next one to start
+    /// can be announced.
+    pub announce_next: bool,
+    /// Local date and hour last announced on the hour.
+    pub announced_hour: Option<(chrono::NaiveDate, u32)>,
+    /// Set while the queue is a radio station that refills itself.
+    pub radio: Option<RadioSeed>,
+    /// Slider position; the sink's gain is this mapped through `volume_curve`.
+    pub volume: f32,
+    pub volume_curve: VolumeCurve,
+    /// Change applied by `VolumeUp` and `VolumeDown`.
+    pub volume_step: f32,
+    /// Default output device the volume was last remembered for.
+    pub output_device: Option<String>,
+    /// Silences the output without touching `volume`, so unmuting restores it exactly.
+    pub muted: bool,
+    pub sleep_inhibitor: SleepInhibitor,
+    pub exclusive: ExclusiveOutput,
+    pub audio_focus: AudioFocus,
+    pub bluetooth: BluetoothVolume,
+    /// Position saved when the system went to sleep or the output device was removed,
+    /// restored once the output is reopened.
+    pub suspended_at: Option<Duration>,
+    /// How long the player may sit paused or idle before the output is closed; zero never.
+    pub idle_release: Duration,
+    /// Track and position the output was closed at for idling, resumed from once it's
+    /// reopened.
+    pub released: Option<(String, Duration)>,
+    pub output_format: OutputFormat,
+    /// Sample rate of the track loaded last.
+    pub source_rate: Option<u32>,
+    /// Track sample rate the output was last opened for.
+    pub opened_rate: Option<u32>,
+}
+
+/// Queue contents saved around a destructive edit, for undo and redo.
+pub struct QueueSnapshot {
+    queue: Vec<TrackInfo>,
+    current_index: usize,
+    unshuffled: Option<Vec<TrackInfo>>,
+}
+
+impl AudioState {
+    /// A stopped player with an empty queue. `settings` only sets what has to be known
+    /// before `ApplySettings` is handled; the rest comes from that.
+    pub fn new(
+        host: Arc<dyn PlayerHost>,
+        sender: mpsc::Sender<AudioCommand>,
+        cache: MetadataCache,
+        audio_focus: AudioFocus,
+        bluetooth: BluetoothVolume,
+        settings: &Settings,
+    ) -> AudioState {
+        AudioState {
+            queue: Vec::new(),
+            current_index: 0,
+            queue_version: 0,
+            looped: false,
+            host,
+            controls: None,
+            controls_enabled: settings.media_controls,
+            controls_retry_at: Instant::now(),
+            sender,
+            cache,
+            cast: None,
+            skip_silence: Arc::new(AtomicBool::new(false)),
+            dither: Arc::new(AtomicBool::new(false)),
+            dsp_bypass: Arc::new(AtomicBool::new(false)),
+            skipped: Arc::new(AtomicU64::new(0)),
+            play_record: None,
+            played_mark: None,
+            remaining: Arc::new(AtomicU64::new(u64::MAX)),
+            trim_silence: Arc::new(Mutex::new(None)),
+            track_loaded: false,
+            track_id: 0,
+            play_id: 0,
+            shuffle: ShuffleMode::Off,
+            shuffle_weighting: ShuffleWeighting::default(),
+            unshuffled: None,
+            history: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            emit_interval: Duration::from_millis(500),
+            prev_threshold: Duration::from_secs(5),
+            button_gestures: false,
+            gesture_window: Duration::from_millis(400),
+            button_presses: 0,
+            read_ahead: 1024 * 1024,
+            online_lyrics: false,
+            lyrics: None,
+            lyric_line: None,
+            skip_outro_at: None,
+            listening_saved_at: Instant::now(),
+            crossfade: Duration::ZERO,
+            announce: AnnounceMode::Off,
+            announcement: Arc::new(Mutex::new(None)),
+            announce_next: false,
+            announced_hour: None,
+            playlist_settings: None,
+            deck: None,
+            endless: false,
+            radio: None,
+            volume: 1.0,
+            volume_curve: VolumeCurve::Linear,
+            volume_step: 0.05,
+            output_device: None,
+            muted: false,
+            sleep_inhibitor: SleepInhibitor::default(),
+            exclusive: ExclusiveOutput::default(),
+            audio_focus,
+            bluetooth,
+            suspended_at: None,
+            idle_release: Duration::ZERO,
+            released: None,
+            output_format: settings.output_format,
+            source_rate: None,
+            opened_rate: None,
+        }
+    }
+
+    /// Sends an event to the front end, logging a failure.
+    pub fn emit<T: serde::Serialize>(&self, event: &str, payload: T) {
+        player_host::emit(&*self.host, event, payload);
+    }
+
+    /// Position within the current track, counting audio dropped by skip-silence.
+    pub fn position(&self, sink: &dyn AudioOutput) -> Duration {
+        sink.get_pos() + Duration::from_nanos(self.skipped.load(Ordering::Relaxed))
+    }
+
+    /// Adds the audio played since the last call to the current play's time. Jumps further
+    /// than the time passed allows are seeks, which aren't counted.
+    fn count_played(&mut self, sink: &dyn AudioOutput) {
+        let now = Instant::now();
+        let position = sink.get_pos();
+        let speed = self.speed().max(1.0);
+        if let (Some((at, last)), Some((_, played))) = (self.played_mark, &mut self.play_record) {
+            let allowed = (now - at).mul_f32(speed) + PLAYED_SLACK;
+            if let Some(step) = position.checked_sub(last).filter(|step| *step <= allowed) {
+                *played += step;
+            }
+        }
+        self.played_mark = Some((now, position));
+    }
+
+    /// Writes the time the current play was heard for to its history entry.
+    pub fn end_play(&mut self) {
+        self.played_mark = None;
+        let Some((history_id, played)) = self.play_record.take() else {
+            return;
+        };
+        let library = self.host.library();
+        if let Err(e) = library.set_played_time(history_id, played.as_millis() as u64) {
+            log::error!("Failed to record play time: {}", e);
+        }
+    }
+
+    /// Saves how far into the current track playback got, which the library keeps for
+    /// audiobook files.
+    fn save_listening(&mut self, position: Duration) {
+        self.listening_saved_at = Instant::now();
+        let Some(track) = self.queue.get(self.current_index) else {
+            return;
+        };
+        self.host
+            .save_listening(track.path.clone(), position.as_millis() as u64);
+    }
+
+    /// Passes a seek forward from the opening seconds of the current track on to the library,
+    /// which learns a start offset from a few of them landing in the same place. A learned
+    /// offset is offered to the user, who has to enable it before tracks start there.
+    fn note_start_seek(&self, sink: &dyn AudioOutput, target: Duration) {
+        let Some(track) = self
+            .queue
+            .get(self.current_index)
+            .filter(|_| self.track_loaded)
+        else {
+            return;
+        };
+        let from = self.position(sink);
+        if from > START_SEEK_WINDOW || target <= from || target > MAX_START_OFFSET {
+            return;
+        }
+
+        let offset_ms = target.as_millis() as u64;
+        match self
+            .host
+            .library()
+            .record_start_seek(&track.path, offset_ms)
+        {
+            Ok(Some(offset_ms)) => {
+                let learned = StartOffset {
+                    path: track.path.clone(),
+                    title: track.title.clone(),
+                    artist: track.artist.clone(),
+                    offset_ms,
+                    enabled: false,
+                };
+                self.emit("start-offset-offered", learned);
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to record start seek: {}", e),
+        }
+    }
+
+    /// Finish times of the current track and the queue at the sink's playback speed.
+    fn eta(&self, sink: &dyn AudioOutput) -> Option<CommandResponse> {
+        let track = self.queue.get(self.current_index)?;
+        let position = self.position(sink);
+        let speed = sink.speed().max(0.01);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let at = |left: Duration| (now + left.div_f32(speed)).as_millis() as u64;
+
+        Some(CommandResponse::Eta {
+            track_end: at(Duration::from_secs(track.duration).saturating_sub(position)),
+            queue_end: (!self.looped).then(|| {
+                at(remaining_duration(
+                    &self.queue,
+                    self.current_index,
+                    position,
+                ))
+            }),
+        })
+    }
+
+    fn set_volume(&mut self, sink: &dyn AudioOutput, volume: f32) {
+        self.volume = volume.clamp(0.0, MAX_VOLUME);
+        self.apply_volume(sink);
+    }
+
+    /// Sets the volume on behalf of the user and saves it.
+    fn change_volume(
+        &mut self,
+        sink: &dyn AudioOutput,
+        volume: f32,
+    ) -> (&'static str, Result<CommandResponse, AudioError>) {
+        self.set_volume(sink, volume);
+        self.bluetooth.set_volume(self.volume);
+        let volume = self.volume;
+        let device = self.output_device.clone();
+        self.save_setting(|settings| {
+            settings.volume = volume;
+            if let Some(device) = device {
+                settings.device_volumes.insert(device, volume);
+            }
+        });
+        ("volume", Ok(CommandResponse::Volume(volume)))
+    }
+
+    fn apply_volume(&mut self, sink: &dyn AudioOutput) {
+        let volume = if self.muted { 0.0 } else { self.volume };
+        // A headset with absolute volume already applies the level itself.
+        let gain = if self.bluetooth.is_active() && !self.muted {
+            1.0
+        } else {
+            self.volume_curve.gain(volume)
+        };
+        sink.set_volume(gain * self.audio_focus.volume_scale());
+        self.send_to_cast(CastCommand::Volume(volume));
+    }
+
+    /// Whether playback is on a chosen output device other than `device`, which changes to
+    /// `device` then don't affect.
+    fn playing_elsewhere(&self, device: &str) -> bool {
+        self.host
+            .settings()
+            .get()
+            .ok()
+            .and_then(|settings| settings.output_device)
+            .is_some_and(|chosen| chosen != device && output_device::find(&chosen).is_some())
+    }
+
+    /// Records a track that started playing, ignoring restarts of the same track.
+    pub fn push_history(&mut self, track: &TrackInfo) {
+        if self.history.last().map(|last| &last.path) == Some(&track.path) {
+            return;
+        }
+
+        if self.history.len() >= MAX_SESSION_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(track.clone());
+    }
+
+    /// Drops the current track from the history and returns the queue index of the last
+    /// track heard before it that is still queued.
+    fn step_back(&mut self) -> Option<usize> {
+        if self.history.len() < 2 {
+            return None;
+        }
+
+        self.history.pop();
+        while let Some(track) = self.history.pop() {
+            if let Some(index) = self.queue.iter().position(|t| t.path == track.path) {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    /// Sets up the media controls if they're on but missing, retrying a failure no more than
+    /// once every `CONTROLS_RETRY`, and catches them up with the player.
+    fn attach_controls(&mut self, sink: &dyn AudioOutput) {
+        if !self.controls_enabled
+            || self.controls.is_some()
+            || Instant::now() < self.controls_retry_at
+        {
+            return;
+        }
+
+        let player = AudioPlayer {
+            sender: self.sender.clone(),
+        };
+        match self.host.media_controls(player) {
+            Ok(controls) => self.controls = Some(controls),
+            Err(e) => {
+                log::warn!("Media controls unavailable, retrying later: {}", e);
+                self.controls_retry_at = Instant::now() + CONTROLS_RETRY;
+                return;
+            }
+        }
+
+        let track = self.queue.get(self.current_index).cloned();
+        let playback = match track.filter(|_| self.track_loaded) {
+            Some(track) => {
+                util::show_metadata(self, &track);
+                let playing = match &self.cast {
+                    Some(cast) => cast.playing,
+                    None => !sink.is_paused(),
+                };
+                if playing {
+                    MediaPlayback::Playing { progress: None }
+                } else {
+                    MediaPlayback::Paused { progress: None }
+                }
+            }
+            None => MediaPlayback::Stopped,
+        };
+        self.set_playback(playback);
+        self.sync_controls();
+    }
+
+    /// Publishes the queue and options to the media controls.
+    fn sync_controls(&mut self) {
+        let Some(controls) = &mut self.controls else {
+            return;
+        };
+        let sync = controls.sync(
+            &self.queue,
+            self.current_index,
+            self.volume,
+            self.looped,
+            self.shuffle != ShuffleMode::Off,
+        );
+        if let Err(e) = sync {
+            log::error!("{}", e);
+        }
+    }
+
+    /// Updates the OS media controls. They only mirror the player, so a failure is logged
+    /// rather than failing the command.
+    pub fn set_playback(&mut self, playback: MediaPlayback) {
+        let Some(controls) = &mut self.controls else {
+            return;
+        };
+        if let Err(e) = controls.set_playback(playback) {
+            log::error!("Failed to update media controls: {}", e);
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: MediaMetadata) {
+        let Some(controls) = &mut self.controls else {
+            return;
+        };
+        if let Err(e) = controls.set_metadata(metadata) {
+            log::error!("Failed to update media controls: {}", e);
+        }
+    }
+
+    /// Tells the front end about files that were turned away without a caller to report
+    /// them to, such as ones opened from the OS.
+    fn report_rejected(&self, rejected: Vec<RejectedFile>) {
+        if rejected.is_empty() {
+            return;
+        }
+        self.emit("queue-rejected", rejected);
+    }
+
+    /// Crossfade between tracks, the queued playlist's own if it sets one. None while the
+    /// DSP is bypassed.
+    pub fn crossfade(&self) -> Duration {
+        if self.dsp_bypass.load(Ordering::Relaxed) {
+            return Duration::ZERO;
+        }
+        self.playlist_settings
+            .as_ref()
+            .and_then(|settings| settings.crossfade_ms)
+            .map_or(self.crossfade, Duration::from_millis)
+    }
+
+    /// Playback speed of every sink opened; 1.0 unless the queued playlist asks otherwise
+    /// and the DSP isn't bypassed.
+    pub fn speed(&self) -> f32 {
+        if self.dsp_bypass.load(Ordering::Relaxed) {
+            return 1.0;
+        }
+        self.playlist_settings
+            .as_ref()
+            .and_then(|settings| settings.speed)
+            .map_or(1.0, |speed| speed.clamp(MIN_SPEED, MAX_SPEED))
+    }
+
+    /// Goes back to the user's own playback settings once the queue no longer holds the
+    /// playlist whose settings were applied.
+    fn end_playlist_settings(&mut self, sink: &dyn AudioOutput) {
+        let Some(settings) = self.playlist_settings.take() else {
+            return;
+        };
+        sink.set_speed(self.speed());
+        if settings.shuffle.is_some() {
+            self.shuffle = match self.host.settings().get() {
+                Ok(settings) => settings.shuffle,
+                Err(e) => {
+                    log::error!("{}", e);
+                    ShuffleMode::Off
+                }
+            };
+        }
+    }
+
+    /// Shuffles the queue with `mode`, or puts it back in order when `mode` is off.
+    fn shuffle_queue(&mut self, mode: ShuffleMode) {
+        if mode == ShuffleMode::Off {
+            if let Some(original) = self.unshuffled.take() {
+                self.current_index =
+                    shuffle::unshuffle(&mut self.queue, original, self.current_index);
+            }
+        } else {
+            if self.unshuffled.is_none() {
+                self.unshuffled = Some(self.queue.clone());
+            }
+            let weights = self.shuffle_weights(mode);
+            self.current_index =
+                shuffle::shuffle(&mut self.queue, self.current_index, mode, &weights);
+        }
+        self.shuffle = mode;
+    }
+
+    /// Records a changed preference in the settings file.
+    fn save_setting<F: FnOnce(&mut Settings)>(&self, change: F) {
+        if let Err(e) = self.host.settings().update(change) {
+            log::error!("{}", e);
+        }
+    }
+
+    /// Track weights for a weighted shuffle, from ratings and play history in the library.
+    /// Other modes don't use any.
+    fn shuffle_weights(&self, mode: ShuffleMode) -> HashMap<String, f64> {
+        if mode != ShuffleMode::Weighted {
+            return HashMap::new();
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        match self.host.library().play_stats() {
+            Ok(stats) => stats
+                .into_iter()
+                .map(|(path, (rating, last_played))| {
+                    let weight = self.shuffle_weighting.weight(rating, last_played, now);
+                    (path, weight)
+                })
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to load play stats for shuffle: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Chimes the current time over whatever is playing.
+    pub fn announce_time(&self) {
+        *self.announcement.lock().unwrap_or_else(|e| e.into_inner()) = Some(Chime::now());
+    }
+
+    fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            queue: self.queue.clone(),
+            current_index: self.current_index,
+            unshuffled: self.unshuffled.clone(),
+        }
+    }
+
+    /// Saves the queue before a destructive edit. A new edit invalidates anything undone.
+    fn save_undo(&mut self) {
+        if self.undo_stack.len() >= MAX_UNDO {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+    }
+
+    /// Puts back a saved queue. A track that is still playing stays current if it is in it.
+    fn restore(&mut self, snapshot: QueueSnapshot) {
+        let playing = self
+            .track_loaded
+            .then(|| self.queue.get(self.current_index))
+            .flatten()
+            .map(|track| track.path.clone());
+
+        self.queue = snapshot.queue;
+        self.unshuffled = snapshot.unshuffled;
+        self.current_index = playing
+            .and_then(|path| self.queue.iter().position(|track| track.path == path))
+            .unwrap_or(snapshot.current_index);
+    }
+
+    /// Applies `ops` to a copy of the queue, so the queue only changes if every step is valid.
+    /// Returns whether the current track was removed.
+    fn edit_queue(&mut self, ops: Vec<QueueOp>) -> Result<bool, AudioError> {
+        // Inserted files are checked like any others queued. Later steps count on every
+        // file going in, so one that can't rejects the whole batch.
+        for op in &ops {
+            if let QueueOp::Insert { paths, .. } = op {
+                let report = QueueReport::check(paths.clone());
+                if let Some(rejected) = report.rejected.into_iter().next() {
+                    return Err(AudioError::RejectedFileError(
+                        rejected.path,
+                        rejected.reason,
+                    ));
+                }
+            }
+        }
+
+        let cache = &mut self.cache;
+        let edited = apply_queue_ops(&self.queue, self.current_index, ops, |path| {
+            cache.get_track_info(path, 0)
+        })?;
+
+        if let Err(e) = self.cache.save() {
+            log::error!("{}", e);
+        }
+
+        self.save_undo();
+        self.queue = edited.queue;
+        self.current_index = edited.current_index;
+        Ok(edited.removed_current)
+    }
+
+    /// Adds more radio tracks once only a few are left after the track at `index`.
+    pub fn refill_radio(&mut self, index: usize) {
+        let Some(seed) = &self.radio else {
+            return;
+        };
+        if self.queue.len().saturating_sub(index + 1) > RADIO_LOOKAHEAD {
+            return;
+        }
+
+        let queued: Vec<String> = self.queue.iter().map(|track| track.path.clone()).collect();
+        match self
+            .host
+            .library()
+            .similar_tracks(seed, &queued, RADIO_BATCH)
+        {
+            Ok(tracks) if !tracks.is_empty() => {
+                let paths = tracks.into_iter().map(|track| track.path).collect();
+                let _ = self.sender.send(AudioCommand::Queue(paths));
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to refill radio: {}", e),
+        }
+    }
+
+    /// Crossfade for switching away from the current track now. Only a track that is still
+    /// audibly playing is faded out.
+    pub fn transition_fade(&self, sink: &dyn AudioOutput) -> Duration {
+        if sink.empty() || sink.is_paused() || !self.track_loaded {
+            return Duration::ZERO;
+        }
+        self.crossfade()
+    }
+
+    /// Queue index that plays after the current track, honouring repeat.
+    fn next_index(&self) -> Option<usize> {
+        if self.current_index + 1 < self.queue.len() {
+            Some(self.current_index + 1)
+        } else if self.looped && !self.queue.is_empty() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Mirrors a command to the active cast session; returns false when playing locally.
+    pub fn send_to_cast(&mut self, command: CastCommand) -> bool {
+        let Some(cast) = self.cast.as_mut() else {
+            return false;
+        };
+
+        match command {
+            CastCommand::Load { autoplay, .. } => cast.playing = autoplay,
+            CastCommand::Play => cast.playing = true,
+            CastCommand::Pause | CastCommand::Stop => cast.playing = false,
+            _ => {}
+        }
+
+        if cast.sender.send(command).is_err() {
+            self.cast = None;
+            return false;
+        }
+
+        true
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum AudioCommand {
     Queue(Vec<String>),
+    QueueFolder(String, bool, ImportOrder),
+    /// Files to append after ordering them by their tags or names.
+    QueueSorted(Vec<String>, ImportOrder),
+    QueueTracks(Vec<TrackInfo>),
+    Open(Vec<String>),
     Clear,
     Play(usize),
     Pause,
     Resume,
+    TogglePlayback,
+    /// The play/pause button of a headset or the OS media controls, which may be part of a
+    /// multi-press gesture.
+    MediaButton,
+    /// The gesture window opened by the first `MediaButton` has closed.
+    MediaButtonTimeout,
     Prev,
+    RestartTrack,
     Next,
+    /// Plays the track at a position, queueing it at the end if it isn't queued.
+    PlayAt(String, Duration),
+    /// Sent by the sink when the track with this id has played to its end.
+    TrackEnded(u64),
     SetPosition(u64),
+    Seek(Duration),
     SetLooped(bool),
     SetVolume(f32),
+    SetSkipSilence(bool),
+    SetTrimSilence(Option<TrimConfig>),
+    SetShuffle(ShuffleMode),
+    SetShuffleWeighting(ShuffleWeighting),
+    SetEndless(bool),
+    SetAnnounceTime(AnnounceMode),
+    SetVolumeCurve(VolumeCurve),
+    SetMuted(bool),
+    ToggleMute,
+    VolumeUp,
+    VolumeDown,
+    OutputDeviceChanged(String),
+    OutputDeviceRemoved(String),
+    /// Sent before the system sleeps; the sender is notified once playback is paused.
+    SystemSuspending(mpsc::Sender<()>),
+    SystemResumed,
+    /// Stops playback and closes the output until something is played again.
+    Stop,
+    SetOutputBackend(OutputBackend),
+    /// Plays to the named output device, or the system default with `None`.
+    SetOutputDevice(Option<String>),
+    SetOutputFormat(OutputFormat),
+    SetMediaControlsEnabled(bool),
+    /// The loaded track's sample rate differs from the one the output was opened for.
+    SourceRateChanged,
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    AudioFocusChanged(FocusChange),
+    /// Volume set on a Bluetooth headset, or `None` once it no longer controls the level.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    BluetoothVolume(Option<f32>),
+    SetVolumeStep(f32),
+    SetDspBypass(bool),
+    /// Milliseconds between position events while playing.
+    SetPositionUpdateInterval(u64),
+    StartRadio(RadioSeed),
+    StopRadio,
+    Reshuffle(usize),
+    SortQueue(SortOrder),
+    ApplySettings(Settings),
+    ApplyPlaylistSettings(PlaylistSettings),
+    /// Index-based edits carry the queue version they were made against, if any; edits made
+    /// against an older queue are rejected.
+    RemoveFromQueue(Vec<usize>, Option<u64>),
+    MoveInQueue(usize, usize, Option<u64>),
+    DedupeQueue,
+    EditQueue(Vec<QueueOp>, Option<u64>),
+    UndoQueue,
+    RedoQueue,
+    GetState(mpsc::Sender<PlayerSnapshot>),
+    GetSessionHistory(mpsc::Sender<Vec<TrackInfo>>),
+    GetPosition(mpsc::Sender<u64>),
+    GetQueueSummary(mpsc::Sender<QueueSummary>),
+    LyricsLoaded(u64, Option<Lyrics>),
+    GetLyrics(mpsc::Sender<Option<Lyrics>>),
+    SetLyricsOffset(String, i64),
+    FindInQueue(String, mpsc::Sender<Vec<usize>>),
+    PlayMatching(String),
+    StartCast(mpsc::Sender<CastCommand>),
+    StopCast,
+}
+
+impl AudioCommand {
+    /// Whether the command can start a track, which needs the output open after a stop.
+    fn starts_playback(&self) -> bool {
+        matches!(
+            self,
+            AudioCommand::Play(_)
+                | AudioCommand::PlayMatching(_)
+                | AudioCommand::Resume
+                | AudioCommand::TogglePlayback
+                | AudioCommand::Next
+                | AudioCommand::Prev
+                | AudioCommand::RestartTrack
+                | AudioCommand::PlayAt(..)
+        )
+    }
 }
 
 #[derive(serde::Serialize, Clone)]
 #[serde(tag = "type", content = "data")]
 enum CommandResponse {
     Queue(Vec<TrackInfo>),
-    Play { index: usize, track: TrackInfo },
+    Play {
+        index: usize,
+        track: TrackInfo,
+    },
     Status(String),
     Position(u64),
     Looped(bool),
     Volume(f32),
+    VolumeStep(f32),
+    DspBypass(bool),
+    PositionUpdateInterval(u64),
+    Muted(bool),
+    Casting(bool),
+    SkipSilence(bool),
+    Endless(bool),
+    AnnounceTime(AnnounceMode),
+    MediaControls {
+        enabled: bool,
+        /// False while enabled controls are waiting to be retried.
+        attached: bool,
+    },
+    Radio(Option<RadioSeed>),
+    TrimSilence(Option<TrimConfig>),
+    Shuffle {
+        mode: ShuffleMode,
+        queue: Vec<TrackInfo>,
+        index: usize,
+    },
+    ShuffleWeighting(ShuffleWeighting),
+    QueueOrder {
+        queue: Vec<TrackInfo>,
+        index: usize,
+    },
+    Settings(Settings),
+    PlaylistSettings(PlaylistSettings),
+    Lyrics(Option<Lyrics>),
+    LyricLine(Option<usize>),
+    /// When the current track and the queue will finish, in Unix milliseconds. A repeating
+    /// queue has no end.
+    Eta {
+        track_end: u64,
+        queue_end: Option<u64>,
+    },
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -83,16 +1017,261 @@ struct Callback<T> {
     success: bool,
     data: Option<T>,
     error: Option<String>,
+    /// Version of the queue as of this event, to pass back with edits.
+    queue_version: u64,
 }
 
-#[derive(serde::Serialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct TrackInfo {
     pub index: usize,
     pub title: String,
     pub artist: String,
     pub album: String,
     pub duration: u64,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    #[serde(default)]
+    pub album_artist: Option<String>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub year: Option<u32>,
+    /// Every artist and genre the tags list, with multi-value tags split up.
+    #[serde(default)]
+    pub artists: Vec<String>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    /// Part of a various-artists album, going by the compilation flag or the album artist.
+    #[serde(default)]
+    pub compilation: bool,
+    #[serde(default)]
+    pub composer: Option<String>,
+    #[serde(default)]
+    pub conductor: Option<String>,
+    /// Soloist or ensemble, for classical recordings.
+    #[serde(default)]
+    pub performer: Option<String>,
+    /// The larger work a classical track belongs to, and its movement within it.
+    #[serde(default)]
+    pub work: Option<String>,
+    #[serde(default)]
+    pub movement: Option<String>,
+    /// A local path, or a URL for the other kinds of source.
+    pub path: String,
+    #[serde(default)]
+    pub source_kind: SourceKind,
+}
+
+/// Which files `add_queue` queued, and why the rest were turned away.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct QueueReport {
+    pub accepted: Vec<String>,
+    pub rejected: Vec<RejectedFile>,
+}
+
+/// How far `add_paths` has got through checking the files it was given or found.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct QueueProgress {
+    pub path: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+impl QueueReport {
+    /// Sorts `paths` into playable files and rejected ones.
+    fn check(paths: Vec<String>) -> QueueReport {
+        QueueReport::check_with_progress(paths, |_| {})
+    }
+
+    /// Like `check`, calling `progress` after each file.
+    fn check_with_progress(
+        paths: Vec<String>,
+        mut progress: impl FnMut(QueueProgress),
+    ) -> QueueReport {
+        let mut report = QueueReport::default();
+        let total = paths.len();
+        for (i, path) in paths.into_iter().enumerate() {
+            progress(QueueProgress {
+                path: path.clone(),
+                completed: i + 1,
+                total,
+            });
+            match util::check_audio_file(&path) {
+                Ok(()) => report.accepted.push(path),
+                Err(e) => report.rejected.push(RejectedFile {
+                    path,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        report
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct RejectedFile {
     pub path: String,
+    pub reason: String,
+}
+
+/// One step of a batch queue edit. Indices refer to the queue as left by the previous step.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum QueueOp {
+    Move { from: usize, to: usize },
+    Remove { index: usize },
+    Insert { index: usize, paths: Vec<String> },
+}
+
+/// The queue after a batch edit.
+#[derive(Debug)]
+pub struct EditedQueue {
+    pub queue: Vec<TrackInfo>,
+    /// Where the current track ended up, or where it was if it was removed.
+    pub current_index: usize,
+    pub removed_current: bool,
+}
+
+/// Applies `ops` to a copy of `queue`, reading inserted tracks with `load`. Fails without a
+/// result if any step is out of bounds.
+pub fn apply_queue_ops(
+    queue: &[TrackInfo],
+    current: usize,
+    ops: Vec<QueueOp>,
+    mut load: impl FnMut(&str) -> TrackInfo,
+) -> Result<EditedQueue, AudioError> {
+    // Each entry is flagged with whether it is the current track, to follow it around.
+    let mut entries: Vec<(TrackInfo, bool)> = queue
+        .iter()
+        .enumerate()
+        .map(|(index, track)| (track.clone(), index == current))
+        .collect();
+    let mut removed_at = None;
+
+    for op in ops {
+        match op {
+            QueueOp::Move { from, to } => {
+                if from >= entries.len() || to >= entries.len() {
+                    return Err(AudioError::OutOfBoundsError);
+                }
+                let entry = entries.remove(from);
+                entries.insert(to, entry);
+            }
+            QueueOp::Remove { index } => {
+                if index >= entries.len() {
+                    return Err(AudioError::OutOfBoundsError);
+                }
+                if entries.remove(index).1 {
+                    removed_at = Some(index);
+                }
+            }
+            QueueOp::Insert { index, paths } => {
+                if index > entries.len() {
+                    return Err(AudioError::OutOfBoundsError);
+                }
+                let tracks = paths.iter().map(|path| (load(path), false));
+                entries.splice(index..index, tracks);
+            }
+        }
+    }
+
+    let position = entries.iter().position(|(_, current)| *current);
+    let mut queue: Vec<TrackInfo> = entries.into_iter().map(|(track, _)| track).collect();
+    util::reindex(&mut queue);
+    let current_index = position
+        .or(removed_at)
+        .unwrap_or(current)
+        .min(queue.len().saturating_sub(1));
+
+    Ok(EditedQueue {
+        queue,
+        current_index,
+        removed_current: position.is_none() && removed_at.is_some(),
+    })
+}
+
+/// Removes the tracks at `indices`, in any order and possibly repeated, from a copy of
+/// `queue`. Fails without a result if any index is out of bounds.
+pub fn remove_from_queue(
+    queue: &[TrackInfo],
+    current: usize,
+    indices: &[usize],
+) -> Result<EditedQueue, AudioError> {
+    if indices.iter().any(|&index| index >= queue.len()) {
+        return Err(AudioError::OutOfBoundsError);
+    }
+
+    let mut indices = indices.to_vec();
+    indices.sort_unstable();
+    indices.dedup();
+
+    let removed_before = indices.partition_point(|&index| index < current);
+    let mut queue: Vec<TrackInfo> = queue
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| indices.binary_search(index).is_err())
+        .map(|(_, track)| track.clone())
+        .collect();
+    util::reindex(&mut queue);
+
+    Ok(EditedQueue {
+        current_index: (current - removed_before).min(queue.len().saturating_sub(1)),
+        queue,
+        removed_current: indices.binary_search(&current).is_ok(),
+    })
+}
+
+/// Point-in-time view of the player, returned by state queries.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PlayerSnapshot {
+    pub queue: Vec<TrackInfo>,
+    pub current_index: usize,
+    pub position: u64,
+    pub position_ms: u64,
+    pub playing: bool,
+    pub volume: f32,
+    pub muted: bool,
+    pub looped: bool,
+    pub radio: Option<RadioSeed>,
+    pub queue_version: u64,
+}
+
+/// Totals for the queue header. Durations are in seconds.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct QueueSummary {
+    pub track_count: usize,
+    pub total_duration: u64,
+    /// What's left of the current track plus everything after it.
+    pub remaining_duration: u64,
+    /// Track counts by file extension, lowercased.
+    pub formats: BTreeMap<String, usize>,
+}
+
+impl QueueSummary {
+    fn of(queue: &[TrackInfo], current_index: usize, position: Duration) -> QueueSummary {
+        let mut formats = BTreeMap::new();
+        for track in queue {
+            *formats.entry(util::format_of(&track.path)).or_insert(0) += 1;
+        }
+
+        QueueSummary {
+            track_count: queue.len(),
+            total_duration: queue.iter().map(|track| track.duration).sum(),
+            remaining_duration: remaining_duration(queue, current_index, position).as_secs(),
+            formats,
+        }
+    }
+}
+
+/// What's left of the track at `current_index` and everything after it, at normal speed.
+fn remaining_duration(queue: &[TrackInfo], current_index: usize, position: Duration) -> Duration {
+    let total: u64 = queue
+        .get(current_index..)
+        .unwrap_or_default()
+        .iter()
+        .map(|track| track.duration)
+        .sum();
+    Duration::from_secs(total).saturating_sub(position)
 }
 
 #[derive(Clone)]
@@ -116,134 +1295,471 @@ impl AudioPlayer {
         sender: mpsc::Sender<AudioCommand>,
     ) {
         thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let sink = Sink::try_new(&stream_handle).unwrap();
-            let controls = Self::setup_media_controls(&app_handle, sender.clone()).unwrap();
-
-            let mut state = AudioState {
-                queue: Vec::new(),
-                current_index: 0,
-                duration: None,
-                looped: false,
-                handle: app_handle.clone(),
-                controls: controls,
-                sender: sender,
-            };
+            let settings = app_handle
+                .state::<SettingsStore>()
+                .get()
+                .unwrap_or_default();
+            // Commands still need answering without a sound card, so playback goes nowhere
+            // until a device shows up.
+            let (stream, mut sink) = output::open_or_default(
+                &settings.output_backend,
+                &settings.output_format,
+                None,
+                settings.output_device.as_deref(),
+            )
+            .unwrap_or_else(|e| {
+                log::error!("No audio output available, playing silently: {}", e);
+                output::open_headless()
+            });
+            let mut stream = Some(stream);
+            let audio_focus = AudioFocus::new(&app_handle, sender.clone());
+            let bluetooth = BluetoothVolume::watch(sender.clone());
+            let cache_path = app_handle
+                .path()
+                .app_cache_dir()
+                .unwrap_or_default()
+                .join("metadata_cache.json");
 
-            let mut last_emit_time = std::time::Instant::now();
-            let emit_interval = Duration::from_millis(500);
+            let mut state = AudioState::new(
+                Arc::new(app_handle.clone()),
+                sender,
+                MetadataCache::load(cache_path),
+                audio_focus,
+                bluetooth,
+                &settings,
+            );
 
+            Self::handle_audio_command(AudioCommand::ApplySettings(settings), &mut state, &sink);
+            output_device::watch(state.sender.clone());
+            power::watch(&app_handle, state.sender.clone());
+
+            // A panic while handling a command would otherwise leave every later command
+            // unanswered. The queue survives it, so playback picks up on a fresh output.
             loop {
-                if let Ok(command) = receiver.try_recv() {
-                    println!("Handling audio command...");
-                    Self::handle_audio_command(command, &mut state, &sink);
-                }
-
-                if !sink.empty() && !sink.is_paused() {
-                    Self::track_progress(
-                        &sink,
-                        &mut state,
-                        &app_handle,
-                        &mut last_emit_time,
-                        emit_interval,
-                    );
-                }
+                let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                    Self::run_audio_loop(&mut state, &mut stream, &mut sink, &receiver)
+                }));
+                let Err(payload) = run else {
+                    break;
+                };
 
-                thread::sleep(Duration::from_millis(10));
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                log::error!("The audio thread panicked, restarting it: {}", message);
+                Self::recover(&mut state, &mut stream, &mut sink);
+                thread::sleep(RESTART_DELAY);
             }
         });
     }
 
-    fn setup_media_controls(
-        app_handle: &AppHandle,
-        sender: mpsc::Sender<AudioCommand>,
-    ) -> Result<MediaControls, souvlaki::Error> {
-        #[cfg(not(target_os = "windows"))]
-        let hwnd = None;
-
-        #[cfg(target_os = "windows")]
-        let hwnd = {
-            let window = app_handle.get_webview_window("main").unwrap();
-            let hwnd = window.hwnd().unwrap();
-
-            Some(hwnd.0)
-        };
+    /// Handles commands until every sender is gone.
+    fn run_audio_loop(
+        state: &mut AudioState,
+        stream: &mut Option<Output>,
+        sink: &mut Sink,
+        receiver: &mpsc::Receiver<AudioCommand>,
+    ) {
+        let mut last_emit_time = std::time::Instant::now();
+        let mut idle_since = Instant::now();
 
-        let config = PlatformConfig {
-            dbus_name: "my_player",
-            display_name: "My Player",
-            hwnd,
-        };
+        loop {
+            state.attach_controls(sink);
 
-        let mut controls = MediaControls::new(config)?;
-        controls
-            .attach(move |event| Self::handle_media_event(event, &sender))
-            .unwrap();
-        Ok(controls)
-    }
+            let command = if !sink.empty() && !sink.is_paused() {
+                // Wake up regularly while playing to emit positions; the sink reports the
+                // end of the track itself.
+                let timeout = match &state.lyrics {
+                    Some(lyrics) if lyrics.synced => state.emit_interval.min(LYRICS_TICK),
+                    _ => state.emit_interval,
+                };
+                match receiver.recv_timeout(timeout) {
+                    Ok(command) => Some(command),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            } else if sink.empty() && state.track_loaded {
+                None
+            } else if stream.is_some() && state.cast.is_none() && !state.idle_release.is_zero() {
+                // Idle or paused with the output open: wait for a command until it's time to
+                // let the device go.
+                let timeout = state.idle_release.saturating_sub(idle_since.elapsed());
+                match receiver.recv_timeout(timeout) {
+                    Ok(command) => Some(command),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        Self::release_output(state, stream, sink);
+                        None
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                // Nothing changes while idle or paused until a command arrives.
+                match receiver.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => break,
+                }
+            };
 
-    fn handle_media_event(event: MediaControlEvent, sender: &mpsc::Sender<AudioCommand>) {
-        match event {
-            MediaControlEvent::Play => sender.send(AudioCommand::Resume).unwrap(),
-            MediaControlEvent::Pause => sender.send(AudioCommand::Pause).unwrap(),
-            MediaControlEvent::Next => sender.send(AudioCommand::Next).unwrap(),
-            MediaControlEvent::Previous => sender.send(AudioCommand::Prev).unwrap(),
-            MediaControlEvent::Stop => sender.send(AudioCommand::Pause).unwrap(),
-            _ => {}
+            match command {
+                // Replacing the sink can't happen inside the command handler, which
+                // borrows it.
+                Some(AudioCommand::SystemResumed) => {
+                    Self::reopen_output(state, stream, sink, false);
+                }
+                Some(AudioCommand::SetOutputBackend(backend)) => {
+                    state.save_setting(|settings| settings.output_backend = backend);
+                    let playing = !sink.empty() && !sink.is_paused();
+                    Self::reopen_output(state, stream, sink, playing);
+                }
+                Some(AudioCommand::SetOutputDevice(device)) => {
+                    state.save_setting(|settings| settings.output_device = device.clone());
+                    let playing = !sink.empty() && !sink.is_paused();
+                    Self::reopen_output(state, stream, sink, playing);
+                    // The volume remembered for the device applies from now on.
+                    if let Some(device) = device.or_else(output_device::default_output_name) {
+                        let command = AudioCommand::OutputDeviceChanged(device);
+                        Self::handle_audio_command(command, state, sink);
+                    }
+                }
+                Some(AudioCommand::SetOutputFormat(format)) => {
+                    state.output_format = format;
+                    state.save_setting(|settings| settings.output_format = format);
+                    let playing = !sink.empty() && !sink.is_paused();
+                    Self::reopen_output(state, stream, sink, playing);
+                }
+                Some(AudioCommand::SourceRateChanged) => {
+                    let playing = !sink.empty() && !sink.is_paused();
+                    Self::reopen_output(state, stream, sink, playing);
+                }
+                // The stream went with the device, so playback moves to the new default.
+                Some(AudioCommand::OutputDeviceRemoved(device)) => {
+                    let command = AudioCommand::OutputDeviceRemoved(device);
+                    Self::handle_audio_command(command, state, sink);
+                    if state.cast.is_none() {
+                        Self::reopen_output(state, stream, sink, false);
+                    }
+                }
+                Some(AudioCommand::Stop) => {
+                    Self::handle_audio_command(AudioCommand::Stop, state, sink);
+                    Self::close_output(state, stream, sink);
+                }
+                // Back on the local device, which is taken again if the output is open.
+                Some(AudioCommand::StopCast) => {
+                    Self::handle_audio_command(AudioCommand::StopCast, state, sink);
+                    if stream.is_some() {
+                        state.exclusive.acquire(state.opened_rate);
+                    }
+                }
+                Some(command) if stream.is_none() && command.starts_playback() => {
+                    Self::restart_output(state, stream, sink, command);
+                }
+                Some(command) => {
+                    log::trace!("Handling audio command");
+                    Self::handle_audio_command(command, state, sink);
+                }
+                None => {}
+            }
+
+            if !sink.empty() && !sink.is_paused() {
+                idle_since = Instant::now();
+                state.count_played(sink);
+                Self::start_crossfade(sink, state);
+                Self::track_progress(sink, state, &mut last_emit_time);
+            } else {
+                // Time spent paused isn't played, so counting starts over on resuming.
+                state.played_mark = None;
+                if sink.empty() && state.track_loaded {
+                    // Fallback in case the end notification was lost with a cleared sink.
+                    Self::finish_track(state);
+                }
+            }
         }
     }
 
-    fn handle_audio_command(command: AudioCommand, state: &mut AudioState, sink: &Sink) {
+    /// Gets the player usable again after the loop panicked: the track that was playing is
+    /// stopped, in case loading it is what panicked, and the output is opened afresh.
+    fn recover(state: &mut AudioState, stream: &mut Option<Output>, sink: &mut Sink) {
+        sink.stop();
+        state.track_loaded = false;
+        state.deck = None;
+        state.current_index = state.current_index.min(state.queue.len().saturating_sub(1));
+        Self::reopen_output(state, stream, sink, false);
+
+        state.set_playback(MediaPlayback::Stopped);
+        state.host.set_current_track(None);
+        state.emit("audio-restarted", ());
+    }
+
+    fn handle_audio_command(command: AudioCommand, state: &mut AudioState, sink: &dyn AudioOutput) {
         let (event_name, result): (&str, Result<CommandResponse, AudioError>) = match command {
+            AudioCommand::GetState(reply) => {
+                let _ = reply.send(PlayerSnapshot {
+                    queue: state.queue.clone(),
+                    current_index: state.current_index,
+                    position: state.position(sink).as_secs(),
+                    position_ms: state.position(sink).as_millis() as u64,
+                    playing: !sink.empty() && !sink.is_paused(),
+                    volume: state.volume,
+                    muted: state.muted,
+                    looped: state.looped,
+                    radio: state.radio.clone(),
+                    queue_version: state.queue_version,
+                });
+                return;
+            }
+            AudioCommand::GetSessionHistory(reply) => {
+                let _ = reply.send(state.history.clone());
+                return;
+            }
+            AudioCommand::MediaButton => {
+                if !state.button_gestures {
+                    let _ = state.sender.send(AudioCommand::TogglePlayback);
+                    return;
+                }
+                state.button_presses += 1;
+                if state.button_presses == 1 {
+                    let sender = state.sender.clone();
+                    let window = state.gesture_window;
+                    thread::spawn(move || {
+                        thread::sleep(window);
+                        let _ = sender.send(AudioCommand::MediaButtonTimeout);
+                    });
+                }
+                return;
+            }
+            AudioCommand::MediaButtonTimeout => {
+                // Sent back through the queue so the output reopens if the command starts
+                // playback.
+                let command = match std::mem::take(&mut state.button_presses) {
+                    0 => return,
+                    1 => AudioCommand::TogglePlayback,
+                    2 => AudioCommand::Next,
+                    _ => AudioCommand::Prev,
+                };
+                let _ = state.sender.send(command);
+                return;
+            }
+            AudioCommand::PlayAt(path, position) => {
+                match state.queue.iter().position(|track| track.path == path) {
+                    // Loaded at the position itself, so no intro skip or start offset can
+                    // land after it.
+                    Some(index) => {
+                        let track = state.queue[index].clone();
+                        match play_track(&track, sink, state, Some(position)) {
+                            Ok(_) => {
+                                state.current_index = index;
+                                ("play", Ok(CommandResponse::Play { index, track }))
+                            }
+                            Err(e) => ("play", Err(e)),
+                        }
+                    }
+                    // Queued first, then played once the queue change is out.
+                    None => {
+                        let track_info = state.cache.get_track_info(&path, state.queue.len());
+                        state.queue.push(track_info);
+                        let _ = state.sender.send(AudioCommand::PlayAt(path, position));
+                        ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+                    }
+                }
+            }
+            AudioCommand::GetPosition(reply) => {
+                let _ = reply.send(state.position(sink).as_millis() as u64);
+                return;
+            }
+            AudioCommand::GetQueueSummary(reply) => {
+                let _ = reply.send(QueueSummary::of(
+                    &state.queue,
+                    state.current_index,
+                    state.position(sink),
+                ));
+                return;
+            }
+            AudioCommand::GetLyrics(reply) => {
+                let _ = reply.send(state.lyrics.clone());
+                return;
+            }
+            AudioCommand::LyricsLoaded(play_id, lyrics) => {
+                if play_id != state.play_id {
+                    return;
+                }
+                state.lyrics = lyrics.clone();
+                state.lyric_line = None;
+                ("lyrics", Ok(CommandResponse::Lyrics(lyrics)))
+            }
+            AudioCommand::SetLyricsOffset(path, offset_ms) => {
+                let current = state.queue.get(state.current_index);
+                if current.map(|track| &track.path) != Some(&path) {
+                    return;
+                }
+                if let Some(lyrics) = state.lyrics.as_mut() {
+                    lyrics.offset_ms = offset_ms;
+                }
+                ("lyrics", Ok(CommandResponse::Lyrics(state.lyrics.clone())))
+            }
+            AudioCommand::TrackEnded(track_id) => {
+                if track_id == state.track_id && state.track_loaded {
+                    Self::finish_track(state);
+                }
+                return;
+            }
+            AudioCommand::FindInQueue(query, reply) => {
+                let matches = state
+                    .queue
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, track)| util::track_matches(track, &query))
+                    .map(|(index, _)| index)
+                    .collect();
+                let _ = reply.send(matches);
+                return;
+            }
+            AudioCommand::PlayMatching(query) => {
+                // Search from the track after the current one so repeating the query cycles
+                // through the matches.
+                let len = state.queue.len();
+                let found = (1..=len)
+                    .map(|offset| (state.current_index + offset) % len)
+                    .find(|&index| util::track_matches(&state.queue[index], &query));
+
+                match found {
+                    Some(index) => {
+                        return Self::handle_audio_command(AudioCommand::Play(index), state, sink)
+                    }
+                    None => ("play", Err(AudioError::NoMatchError)),
+                }
+            }
             AudioCommand::Queue(file_paths) => {
                 let mut i: usize = state.queue.len();
                 for path in file_paths {
-                    let track_info = get_track_info_from_path(&path, i);
+                    let track_info = state.cache.get_track_info(&path, i);
                     state.queue.push(track_info);
                     i += 1;
                 }
 
+                if let Err(e) = state.cache.save() {
+                    log::error!("{}", e);
+                }
+
+                ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+            }
+            AudioCommand::QueueFolder(path, recursive, order) => {
+                match util::collect_audio_files(Path::new(&path), recursive) {
+                    Ok(files) => {
+                        let paths = files.iter().map(|file| paths::normalized(file)).collect();
+                        return Self::handle_audio_command(
+                            AudioCommand::QueueSorted(paths, order),
+                            state,
+                            sink,
+                        );
+                    }
+                    Err(e) => ("queue", Err(AudioError::IoError(e))),
+                }
+            }
+            AudioCommand::QueueSorted(paths, order) => {
+                let mut tracks: Vec<TrackInfo> = paths
+                    .iter()
+                    .map(|path| state.cache.get_track_info(path, 0))
+                    .collect();
+                util::sort_tracks_by(&mut tracks, order);
+
+                let offset = state.queue.len();
+                for (i, mut track) in tracks.into_iter().enumerate() {
+                    track.index = offset + i;
+                    state.queue.push(track);
+                }
+
+                if let Err(e) = state.cache.save() {
+                    log::error!("{}", e);
+                }
+
+                ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+            }
+            AudioCommand::QueueTracks(tracks) => {
+                let offset = state.queue.len();
+                for (i, mut track) in tracks.into_iter().enumerate() {
+                    track.index = offset + i;
+                    state.queue.push(track);
+                }
+
+                ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+            }
+            AudioCommand::Open(paths) => {
+                let report = QueueReport::check(playlist::expand_paths(paths));
+                state.report_rejected(report.rejected);
+
+                let offset = state.queue.len();
+                // Files opened onto a playlist's queue aren't part of the playlist, so they
+                // play with the user's own settings.
+                if offset > 0 && !report.accepted.is_empty() {
+                    state.end_playlist_settings(sink);
+                }
+                for (i, path) in report.accepted.iter().enumerate() {
+                    let track_info = state.cache.get_track_info(path, offset + i);
+                    state.queue.push(track_info);
+                }
+
+                if let Err(e) = state.cache.save() {
+                    log::error!("{}", e);
+                }
+
+                if state.queue.len() > offset {
+                    let _ = state.sender.send(AudioCommand::Play(offset));
+                }
+
                 ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
             }
-            AudioCommand::Play(index) => {
-                match play_track(&state.queue[index].clone(), &sink, state) {
+            AudioCommand::Play(index) => match state.queue.get(index).cloned() {
+                Some(track) => match play_track(&track, sink, state, None) {
                     Ok(_) => {
                         state.current_index = index;
-                        (
-                            "play",
-                            Ok(CommandResponse::Play {
-                                index,
-                                track: state.queue[index].clone(),
-                            }),
-                        )
+                        ("play", Ok(CommandResponse::Play { index, track }))
                     }
                     Err(e) => ("play", Err(e)),
-                }
-            }
+                },
+                None => ("play", Err(AudioError::OutOfBoundsError)),
+            },
             AudioCommand::Prev => {
-                let track = if state.queue.is_empty() {
-                    Err(AudioError::EmptyQueueError)
+                if state.queue.is_empty() {
+                    ("play", Err(AudioError::EmptyQueueError))
                 } else {
-                    if state.current_index > 0 && sink.get_pos().as_secs() < 5 {
-                        state.current_index -= 1;
-                        Ok(state.queue[state.current_index].clone())
+                    let restart = !state.prev_threshold.is_zero()
+                        && state.position(sink) >= state.prev_threshold;
+                    // Step back through what was actually heard, which differs from the
+                    // queue order under shuffle or after jumping around.
+                    let previous = if restart {
+                        None
                     } else {
-                        Ok(state.queue[state.current_index].clone())
-                    }
-                };
+                        state
+                            .step_back()
+                            .or_else(|| state.current_index.checked_sub(1))
+                    };
 
-                match track {
-                    Ok(t) => match play_track(&t, &sink, state) {
-                        Ok(_) => (
-                            "play",
-                            Ok(CommandResponse::Play {
-                                index: state.current_index,
-                                track: t,
-                            }),
-                        ),
-                        Err(e) => ("play", Err(e)),
-                    },
-                    Err(e) => ("play", Err(e)),
+                    // The first track has nothing before it, so it restarts too.
+                    let command = match previous {
+                        Some(index) => AudioCommand::Play(index),
+                        None => AudioCommand::RestartTrack,
+                    };
+                    return Self::handle_audio_command(command, state, sink);
+                }
+            }
+            AudioCommand::RestartTrack => {
+                if state.queue.is_empty() {
+                    ("play", Err(AudioError::EmptyQueueError))
+                } else if state.track_loaded && !sink.empty() {
+                    // Seeking keeps the track's pause state and doesn't crossfade into itself.
+                    return Self::handle_audio_command(
+                        AudioCommand::Seek(Duration::ZERO),
+                        state,
+                        sink,
+                    );
+                } else {
+                    return Self::handle_audio_command(
+                        AudioCommand::Play(state.current_index),
+                        state,
+                        sink,
+                    );
                 }
             }
             AudioCommand::Next => {
@@ -256,7 +1772,7 @@ impl AudioPlayer {
                     } else {
                         if state.looped {
                             state.current_index = 0;
-                            Ok(state.queue[state.current_index].clone())
+                            Ok(state.queue[0].clone())
                         } else {
                             Err(AudioError::OutOfBoundsError)
                         }
@@ -264,7 +1780,7 @@ impl AudioPlayer {
                 };
 
                 match track {
-                    Ok(t) => match play_track(&t, &sink, state) {
+                    Ok(t) => match play_track(&t, sink, state, None) {
                         Ok(_) => (
                             "play",
                             Ok(CommandResponse::Play {
@@ -277,13 +1793,64 @@ impl AudioPlayer {
                     Err(e) => ("play", Err(e)),
                 }
             }
+            AudioCommand::SystemSuspending(done) => {
+                state.suspended_at = Some(state.position(sink));
+                sink.pause();
+                state.send_to_cast(CastCommand::Pause);
+                state.set_playback(MediaPlayback::Paused { progress: None });
+
+                let _ = done.send(());
+                ("status", Ok(CommandResponse::Status("paused".to_string())))
+            }
+            AudioCommand::Stop => {
+                state.end_play();
+                sink.stop();
+                state.track_loaded = false;
+                state.deck = None;
+                state.suspended_at = None;
+                state.released = None;
+                state.send_to_cast(CastCommand::Pause);
+                state.audio_focus.user_paused();
+
+                state.set_playback(MediaPlayback::Stopped);
+                ("status", Ok(CommandResponse::Status("stopped".to_string())))
+            }
+            // The audio thread reopens the output for these before they get here.
+            AudioCommand::SystemResumed
+            | AudioCommand::SetOutputBackend(_)
+            | AudioCommand::SetOutputDevice(_)
+            | AudioCommand::SetOutputFormat(_)
+            | AudioCommand::SourceRateChanged => ("output", Err(AudioError::OutputCommandError)),
+            AudioCommand::AudioFocusChanged(change) => {
+                let playing = !sink.empty() && !sink.is_paused();
+                match state.audio_focus.handle_change(change, playing) {
+                    // Focus lost after ducking leaves the duck behind, so the level is
+                    // put back whenever playback stops or starts again.
+                    FocusAction::Pause => {
+                        sink.pause();
+                        state.apply_volume(sink);
+                        state.set_playback(MediaPlayback::Paused { progress: None });
+                        ("status", Ok(CommandResponse::Status("paused".to_string())))
+                    }
+                    FocusAction::Resume => {
+                        state.apply_volume(sink);
+                        Self::handle_audio_command(AudioCommand::Resume, state, sink);
+                        return;
+                    }
+                    FocusAction::UpdateVolume => {
+                        state.apply_volume(sink);
+                        return;
+                    }
+                    FocusAction::None => return,
+                }
+            }
             AudioCommand::Pause => {
                 sink.pause();
+                state.save_listening(state.position(sink));
+                state.send_to_cast(CastCommand::Pause);
+                state.audio_focus.user_paused();
 
-                state
-                    .controls
-                    .set_playback(MediaPlayback::Paused { progress: None })
-                    .unwrap();
+                state.set_playback(MediaPlayback::Paused { progress: None });
                 ("status", Ok(CommandResponse::Status("paused".to_string())))
             }
             AudioCommand::Resume => {
@@ -291,158 +1858,1050 @@ impl AudioPlayer {
                     ("play", Err(AudioError::EmptyQueueError))
                 } else {
                     let playback_result = if sink.empty() {
-                        match play_track(&state.queue[0].clone(), &sink, state) {
-                            Ok(_) => Ok(CommandResponse::Play {
-                                index: 0,
-                                track: state.queue[0].clone(),
-                            }),
+                        let track = state.queue[0].clone();
+                        match play_track(&track, sink, state, None) {
+                            Ok(_) => Ok(CommandResponse::Play { index: 0, track }),
                             Err(e) => Err(e),
                         }
+                    } else if state.cast.is_none() && !state.audio_focus.request() {
+                        Err(AudioError::AudioFocusDenied)
                     } else {
-                        sink.play();
+                        if !state.send_to_cast(CastCommand::Play) {
+                            sink.play();
+                        }
 
-                        state
-                            .controls
-                            .set_playback(MediaPlayback::Playing { progress: None })
-                            .unwrap();
+                        state.set_playback(MediaPlayback::Playing { progress: None });
 
-                        Ok(CommandResponse::Play {
-                            index: state.current_index,
-                            track: state.queue[state.current_index].clone(),
-                        })
+                        match state.queue.get(state.current_index).cloned() {
+                            Some(track) => Ok(CommandResponse::Play {
+                                index: state.current_index,
+                                track,
+                            }),
+                            None => Err(AudioError::OutOfBoundsError),
+                        }
                     };
 
                     ("play", playback_result)
                 }
             }
+            AudioCommand::TogglePlayback => {
+                let paused = match &state.cast {
+                    Some(cast) => !cast.playing,
+                    None => sink.is_paused(),
+                };
+                let command = if paused || sink.empty() {
+                    AudioCommand::Resume
+                } else {
+                    AudioCommand::Pause
+                };
+
+                return Self::handle_audio_command(command, state, sink);
+            }
             AudioCommand::SetPosition(position) => {
-                match sink.try_seek(Duration::from_secs(position)) {
+                // Only seeks from the player's own controls are learned from.
+                let position = Duration::from_secs(position);
+                state.note_start_seek(sink, position);
+                return Self::handle_audio_command(AudioCommand::Seek(position), state, sink);
+            }
+            AudioCommand::Seek(position) => {
+                state.send_to_cast(CastCommand::Seek(position.as_secs()));
+
+                let track = state.queue.get(state.current_index).cloned();
+                let result = match track {
+                    Some(track)
+                        if state.track_loaded && seek::supports_indexed_seek(&track.path) =>
+                    {
+                        // Reopen at the indexed frame; the decoder would have to scan VBR files.
+                        // The old position is crossfaded out briefly to avoid a click.
+                        let paused = sink.is_paused();
+                        let ramp = crossfade::SEEK_RAMP;
+                        util::load_track(&track, sink, state, position, ramp).map(|_| {
+                            if !paused {
+                                sink.play();
+                            }
+                        })
+                    }
+                    _ => sink
+                        .try_seek(position)
+                        .map(|_| state.skipped.store(0, Ordering::Relaxed))
+                        .map_err(AudioError::SeekError),
+                };
+
+                match result {
                     Ok(_) => (
                         "position",
-                        Ok(CommandResponse::Position(sink.get_pos().as_secs())),
+                        Ok(CommandResponse::Position(state.position(sink).as_secs())),
                     ),
-                    Err(e) => ("position", Err(AudioError::SeekError(e))),
+                    Err(e) => ("position", Err(e)),
                 }
             }
             AudioCommand::SetLooped(looped) => {
                 state.looped = looped;
+                state.save_setting(|settings| settings.looped = looped);
                 ("looped", Ok(CommandResponse::Looped(state.looped)))
             }
-            AudioCommand::SetVolume(volume) => {
-                sink.set_volume(volume);
-                ("volume", Ok(CommandResponse::Volume(sink.volume())))
+            AudioCommand::SetVolume(volume) => state.change_volume(sink, volume),
+            AudioCommand::VolumeUp => state.change_volume(sink, state.volume + state.volume_step),
+            AudioCommand::VolumeDown => state.change_volume(sink, state.volume - state.volume_step),
+            AudioCommand::OutputDeviceChanged(device) => {
+                if state.playing_elsewhere(&device) {
+                    return;
+                }
+                // Switch to the volume last used on this device; a new device keeps the
+                // current volume, which becomes its remembered one.
+                let remembered = state
+                    .host
+                    .settings()
+                    .get()
+                    .ok()
+                    .and_then(|settings| settings.device_volumes.get(&device).copied());
+                state.output_device = Some(device.clone());
+                let volume = remembered.unwrap_or(state.volume);
+                let (_, result) = state.change_volume(sink, volume);
+
+                state.emit("output-device-changed", &device);
+                ("volume", result)
             }
-            AudioCommand::Clear => {
-                sink.stop();
-                state.queue.clear();
-                state.current_index = 0;
+            AudioCommand::BluetoothVolume(volume) => {
+                // Only mirrored, not saved: the headset remembers its own level.
+                if let Some(volume) = volume {
+                    state.volume = volume.clamp(0.0, MAX_VOLUME);
+                }
+                state.apply_volume(sink);
+                ("volume", Ok(CommandResponse::Volume(state.volume)))
+            }
+            AudioCommand::OutputDeviceRemoved(device) => {
+                // Pause rather than carry on through whatever speakers are left, e.g. when
+                // headphones are unplugged. A cast device or another chosen device isn't
+                // affected.
+                if sink.empty()
+                    || sink.is_paused()
+                    || state.cast.is_some()
+                    || state.playing_elsewhere(&device)
+                {
+                    return;
+                }
 
-                state.controls.set_playback(MediaPlayback::Stopped).unwrap();
+                state.suspended_at = Some(state.position(sink));
+                sink.pause();
+                state.set_playback(MediaPlayback::Paused { progress: None });
 
-                ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+                state.emit("output-device-removed", &device);
+                ("status", Ok(CommandResponse::Status("paused".to_string())))
             }
-        };
+            AudioCommand::SetVolumeStep(step) => {
+                state.volume_step = step.clamp(MIN_VOLUME_STEP, MAX_VOLUME_STEP);
+                let step = state.volume_step;
+                state.save_setting(|settings| settings.volume_step = step);
+                ("volume_step", Ok(CommandResponse::VolumeStep(step)))
+            }
+            AudioCommand::SetDspBypass(bypass) => {
+                // Every queued track shares the flag, so the switch is heard straight away.
+                state.dsp_bypass.store(bypass, Ordering::Relaxed);
+                sink.set_speed(state.speed());
+                ("dsp_bypass", Ok(CommandResponse::DspBypass(bypass)))
+            }
+            AudioCommand::SetPositionUpdateInterval(ms) => {
+                let ms = ms.clamp(MIN_EMIT_INTERVAL_MS, MAX_EMIT_INTERVAL_MS);
+                state.emit_interval = Duration::from_millis(ms);
+                state.save_setting(|settings| settings.emit_interval_ms = ms);
+                (
+                    "position_update_interval",
+                    Ok(CommandResponse::PositionUpdateInterval(ms)),
+                )
+            }
+            AudioCommand::SetMuted(muted) => {
+                state.muted = muted;
+                state.apply_volume(sink);
+                ("muted", Ok(CommandResponse::Muted(muted)))
+            }
+            AudioCommand::ToggleMute => {
+                state.muted = !state.muted;
+                state.apply_volume(sink);
+                ("muted", Ok(CommandResponse::Muted(state.muted)))
+            }
+            AudioCommand::SetVolumeCurve(curve) => {
+                state.volume_curve = curve;
+                state.set_volume(sink, state.volume);
+                state.save_setting(|settings| settings.volume_curve = curve);
+                ("volume", Ok(CommandResponse::Volume(state.volume)))
+            }
+            AudioCommand::SetSkipSilence(enabled) => {
+                state.skip_silence.store(enabled, Ordering::Relaxed);
+                state.save_setting(|settings| settings.skip_silence = enabled);
+                ("skip_silence", Ok(CommandResponse::SkipSilence(enabled)))
+            }
+            AudioCommand::SetTrimSilence(config) => {
+                *state.trim_silence.lock().unwrap_or_else(|e| e.into_inner()) = config;
+                state.save_setting(|settings| settings.trim_silence = config);
+                ("trim_silence", Ok(CommandResponse::TrimSilence(config)))
+            }
+            AudioCommand::SetShuffle(mode) => {
+                state.shuffle_queue(mode);
+                state.save_setting(|settings| settings.shuffle = mode);
 
-        let emit_result = match result {
-            Ok(data) => state.handle.emit(
-                event_name,
-                Callback {
-                    success: true,
-                    data: Some(data),
-                    error: None,
-                },
-            ),
-            Err(err) => state.handle.emit(
-                event_name,
-                Callback::<CommandResponse> {
-                    success: false,
-                    data: None,
-                    error: Some(err.to_string()),
-                },
-            ),
-        };
+                (
+                    "shuffle",
+                    Ok(CommandResponse::Shuffle {
+                        mode,
+                        queue: state.queue.clone(),
+                        index: state.current_index,
+                    }),
+                )
+            }
+            AudioCommand::SetShuffleWeighting(weighting) => {
+                // Takes effect the next time the queue is shuffled.
+                let weighting = weighting.clamped();
+                state.shuffle_weighting = weighting;
+                state.save_setting(|settings| settings.shuffle_weighting = weighting);
+                (
+                    "shuffle_weighting",
+                    Ok(CommandResponse::ShuffleWeighting(weighting)),
+                )
+            }
+            AudioCommand::SetEndless(enabled) => {
+                state.endless = enabled;
+                state.save_setting(|settings| settings.endless = enabled);
+                ("endless", Ok(CommandResponse::Endless(enabled)))
+            }
+            AudioCommand::SetAnnounceTime(mode) => {
+                state.announce = mode;
+                state.announced_hour = None;
+                state.save_setting(|settings| settings.announce_time = mode);
+                ("announce_time", Ok(CommandResponse::AnnounceTime(mode)))
+            }
+            AudioCommand::SetMediaControlsEnabled(enabled) => {
+                state.controls_enabled = enabled;
+                state.save_setting(|settings| settings.media_controls = enabled);
+                if enabled {
+                    state.controls_retry_at = Instant::now();
+                    state.attach_controls(sink);
+                } else {
+                    state.controls = None;
+                }
 
-        if let Err(e) = emit_result {
-            eprintln!("{}", AudioError::EmitError(e));
-        }
-    }
+                (
+                    "media_controls",
+                    Ok(CommandResponse::MediaControls {
+                        enabled,
+                        attached: state.controls.is_some(),
+                    }),
+                )
+            }
+            AudioCommand::StartRadio(seed) => {
+                let library = state.host.library();
+                let mut paths = match &seed {
+                    RadioSeed::Track(path) => vec![path.clone()],
+                    RadioSeed::Artist(_) => Vec::new(),
+                };
+                match library.similar_tracks(&seed, &paths, RADIO_BATCH) {
+                    // Nothing like the seed, so the queue playing now is kept.
+                    Ok(tracks) if tracks.is_empty() => {
+                        ("radio", Err(AudioError::NothingToPlayError))
+                    }
+                    Ok(tracks) => {
+                        paths.extend(tracks.into_iter().map(|track| track.path));
 
-    fn track_progress(
-        sink: &Sink,
-        state: &mut AudioState,
-        app_handle: &AppHandle,
-        last_emit_time: &mut std::time::Instant,
-        interval: Duration,
-    ) {
-        if sink.get_pos().as_secs() >= state.duration.unwrap_or(0) {
-            if state.queue.is_empty() {
-                //
-            } else {
-                if state.current_index < state.queue.len() - 1 {
-                    state.current_index += 1;
-                    let _ = state.sender.send(AudioCommand::Play(state.current_index));
-                } else {
-                    if state.looped {
+                        state.save_undo();
+                        state.end_playlist_settings(sink);
+                        state.queue.clear();
+                        state.queue_version += 1;
+                        state.unshuffled = None;
+                        state.shuffle = ShuffleMode::Off;
                         state.current_index = 0;
-                        let _ = state.sender.send(AudioCommand::Play(state.current_index));
-                    } else {
-                        let _ = state.sender.send(AudioCommand::Pause);
+                        state.radio = Some(seed.clone());
+                        let _ = state.sender.send(AudioCommand::Open(paths));
+
+                        ("radio", Ok(CommandResponse::Radio(Some(seed))))
                     }
+                    Err(e) => ("radio", Err(AudioError::LibraryError(e))),
                 }
-            };
-        }
-
-        if !sink.is_paused() && !sink.empty() && last_emit_time.elapsed() >= interval {
-            if let Err(e) = app_handle.emit(
-                "position",
-                Callback {
-                    success: true,
-                    data: Some(CommandResponse::Position(sink.get_pos().as_secs())),
-                    error: None,
-                },
-            ) {
-                eprintln!("{}", AudioError::EmitError(e));
             }
-            *last_emit_time = std::time::Instant::now();
-        }
-    }
+            AudioCommand::StopRadio => {
+                state.radio = None;
+                ("radio", Ok(CommandResponse::Radio(None)))
+            }
+            AudioCommand::Reshuffle(avoid_recent) => {
+                let mode = match state.shuffle {
+                    ShuffleMode::Off => ShuffleMode::Tracks,
+                    mode => mode,
+                };
+                if state.unshuffled.is_none() {
+                    state.unshuffled = Some(state.queue.clone());
+                }
+                let weights = state.shuffle_weights(mode);
+                state.current_index =
+                    shuffle::shuffle(&mut state.queue, state.current_index, mode, &weights);
 
-    pub fn add_queue(&self, file_paths: Vec<String>) -> Result<(), AudioError> {
-        match self.sender.send(AudioCommand::Queue(file_paths)) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(AudioError::LockError),
-        }
-    }
+                // Albums stay whole, so only a track shuffle pushes recent plays back.
+                if mode == ShuffleMode::Tracks && avoid_recent > 0 {
+                    let recent: Vec<String> = state
+                        .history
+                        .iter()
+                        .rev()
+                        .take(avoid_recent)
+                        .map(|track| track.path.clone())
+                        .collect();
+                    state.current_index =
+                        shuffle::defer_recent(&mut state.queue, state.current_index, &recent);
+                }
 
-    pub fn clear_queue(&self) -> Result<(), AudioError> {
-        match self.sender.send(AudioCommand::Clear) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(AudioError::LockError),
-        }
-    }
+                state.shuffle = mode;
+                state.save_setting(|settings| settings.shuffle = mode);
 
-    pub fn play(&self, index: usize) -> Result<(), AudioError> {
-        match self.sender.send(AudioCommand::Play(index)) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(AudioError::LockError),
-        }
-    }
+                (
+                    "shuffle",
+                    Ok(CommandResponse::Shuffle {
+                        mode,
+                        queue: state.queue.clone(),
+                        index: state.current_index,
+                    }),
+                )
+            }
+            AudioCommand::SortQueue(order) => {
+                state.save_undo();
+                let library = state.host.library();
+                match library.sort_queue(&mut state.queue, state.current_index, order) {
+                    Ok(index) => {
+                        // An explicit sort replaces any shuffled order.
+                        state.current_index = index;
+                        state.shuffle = ShuffleMode::Off;
+                        state.unshuffled = None;
 
-    pub fn pause(&self) -> Result<(), AudioError> {
-        match self.sender.send(AudioCommand::Pause) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(AudioError::LockError),
-        }
-    }
+                        (
+                            "queue_order",
+                            Ok(CommandResponse::QueueOrder {
+                                queue: state.queue.clone(),
+                                index,
+                            }),
+                        )
+                    }
+                    Err(e) => ("queue_order", Err(AudioError::LibraryError(e))),
+                }
+            }
+            AudioCommand::RemoveFromQueue(_, Some(version))
+            | AudioCommand::MoveInQueue(_, _, Some(version))
+            | AudioCommand::EditQueue(_, Some(version))
+                if version != state.queue_version =>
+            {
+                ("queue_order", Err(AudioError::StaleQueueError(version)))
+            }
+            AudioCommand::RemoveFromQueue(indices, _) => {
+                match remove_from_queue(&state.queue, state.current_index, &indices) {
+                    Ok(edited) => {
+                        state.save_undo();
 
-    pub fn resume(&self) -> Result<(), AudioError> {
-        match self.sender.send(AudioCommand::Resume) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(AudioError::LockError),
-        }
-    }
+                        state.queue = edited.queue;
+                        state.current_index = edited.current_index;
+                        if edited.removed_current {
+                            state.end_play();
+                            sink.stop();
+                            state.track_loaded = false;
+                            state.set_playback(MediaPlayback::Stopped);
+                            state.host.set_current_track(None);
+                        }
 
-    pub fn next(&self) -> Result<(), AudioError> {
+                        (
+                            "queue_order",
+                            Ok(CommandResponse::QueueOrder {
+                                queue: state.queue.clone(),
+                                index: state.current_index,
+                            }),
+                        )
+                    }
+                    Err(e) => ("queue_order", Err(e)),
+                }
+            }
+            AudioCommand::MoveInQueue(from, to, _) => {
+                if from >= state.queue.len() || to >= state.queue.len() {
+                    ("queue_order", Err(AudioError::OutOfBoundsError))
+                } else {
+                    state.save_undo();
+
+                    let current_path = state
+                        .queue
+                        .get(state.current_index)
+                        .map(|track| track.path.clone());
+                    let track = state.queue.remove(from);
+                    state.queue.insert(to, track);
+                    util::reindex(&mut state.queue);
+                    state.current_index = state
+                        .queue
+                        .iter()
+                        .position(|track| Some(&track.path) == current_path.as_ref())
+                        .unwrap_or(0);
+
+                    (
+                        "queue_order",
+                        Ok(CommandResponse::QueueOrder {
+                            queue: state.queue.clone(),
+                            index: state.current_index,
+                        }),
+                    )
+                }
+            }
+            AudioCommand::DedupeQueue => {
+                state.save_undo();
+                state.current_index = util::dedupe(&mut state.queue, state.current_index);
+
+                (
+                    "queue_order",
+                    Ok(CommandResponse::QueueOrder {
+                        queue: state.queue.clone(),
+                        index: state.current_index,
+                    }),
+                )
+            }
+            AudioCommand::EditQueue(ops, _) => match state.edit_queue(ops) {
+                Ok(removed_current) => {
+                    if removed_current {
+                        state.end_play();
+                        sink.stop();
+                        state.track_loaded = false;
+                        state.set_playback(MediaPlayback::Stopped);
+                        state.host.set_current_track(None);
+                    }
+
+                    (
+                        "queue_order",
+                        Ok(CommandResponse::QueueOrder {
+                            queue: state.queue.clone(),
+                            index: state.current_index,
+                        }),
+                    )
+                }
+                Err(e) => ("queue_order", Err(e)),
+            },
+            AudioCommand::UndoQueue => match state.undo_stack.pop() {
+                Some(snapshot) => {
+                    let current = state.snapshot();
+                    state.redo_stack.push(current);
+                    state.restore(snapshot);
+
+                    (
+                        "queue_order",
+                        Ok(CommandResponse::QueueOrder {
+                            queue: state.queue.clone(),
+                            index: state.current_index,
+                        }),
+                    )
+                }
+                None => ("queue_order", Err(AudioError::NothingToUndoError)),
+            },
+            AudioCommand::RedoQueue => match state.redo_stack.pop() {
+                Some(snapshot) => {
+                    let current = state.snapshot();
+                    state.undo_stack.push(current);
+                    state.restore(snapshot);
+
+                    (
+                        "queue_order",
+                        Ok(CommandResponse::QueueOrder {
+                            queue: state.queue.clone(),
+                            index: state.current_index,
+                        }),
+                    )
+                }
+                None => ("queue_order", Err(AudioError::NothingToRedoError)),
+            },
+            AudioCommand::ApplyPlaylistSettings(settings) => {
+                // Only for this queue, so the user's saved shuffle is left alone.
+                if let Some(mode) = settings.shuffle {
+                    state.shuffle_queue(mode);
+                }
+                state.playlist_settings = Some(settings.clone());
+                sink.set_speed(state.speed());
+
+                (
+                    "playlist_settings",
+                    Ok(CommandResponse::PlaylistSettings(settings)),
+                )
+            }
+            AudioCommand::ApplySettings(settings) => {
+                state.volume_curve = settings.volume_curve;
+                state.volume_step = settings.volume_step.clamp(MIN_VOLUME_STEP, MAX_VOLUME_STEP);
+                state.set_volume(sink, settings.volume);
+                state.looped = settings.looped;
+                state
+                    .skip_silence
+                    .store(settings.skip_silence, Ordering::Relaxed);
+                state.dither.store(settings.dither, Ordering::Relaxed);
+                // Taking the device waits for the output to be opened again; letting it go
+                // doesn't.
+                state.exclusive.enabled = settings.exclusive_output;
+                if !settings.exclusive_output {
+                    state.exclusive.release();
+                }
+                if settings.media_controls != state.controls_enabled {
+                    Self::handle_audio_command(
+                        AudioCommand::SetMediaControlsEnabled(settings.media_controls),
+                        state,
+                        sink,
+                    );
+                }
+                *state.trim_silence.lock().unwrap_or_else(|e| e.into_inner()) =
+                    settings.trim_silence;
+                state.emit_interval = Duration::from_millis(
+                    settings
+                        .emit_interval_ms
+                        .clamp(MIN_EMIT_INTERVAL_MS, MAX_EMIT_INTERVAL_MS),
+                );
+                state.prev_threshold = Duration::from_millis(settings.prev_threshold_ms);
+                state.button_gestures = settings.media_button_gestures;
+                state.gesture_window = Duration::from_millis(settings.gesture_window_ms);
+                state.idle_release = Duration::from_secs(settings.idle_release_minutes * 60);
+                state.read_ahead = settings.read_ahead_kb * 1024;
+                state.online_lyrics = settings.online_lyrics;
+                state.crossfade = Duration::from_millis(settings.crossfade_ms);
+                state.endless = settings.endless;
+                state.announce = settings.announce_time;
+                state.shuffle_weighting = settings.shuffle_weighting.clamped();
+
+                let playlist_shuffle = state
+                    .playlist_settings
+                    .as_ref()
+                    .is_some_and(|playlist| playlist.shuffle.is_some());
+                if settings.shuffle != state.shuffle && !playlist_shuffle {
+                    Self::handle_audio_command(
+                        AudioCommand::SetShuffle(settings.shuffle),
+                        state,
+                        sink,
+                    );
+                }
+
+                ("settings", Ok(CommandResponse::Settings(settings)))
+            }
+            AudioCommand::StartCast(sender) => {
+                let was_playing = !sink.empty() && !sink.is_paused();
+                sink.pause();
+
+                state.cast = Some(CastOutput {
+                    sender,
+                    playing: false,
+                });
+                state.send_to_cast(CastCommand::Volume(if state.muted {
+                    0.0
+                } else {
+                    state.volume
+                }));
+
+                if !sink.empty() {
+                    if let Some(track) = state.queue.get(state.current_index).cloned() {
+                        state.send_to_cast(CastCommand::Load {
+                            track,
+                            position: state.position(sink).as_secs(),
+                            autoplay: was_playing,
+                        });
+                    }
+                }
+
+                ("cast", Ok(CommandResponse::Casting(true)))
+            }
+            AudioCommand::StopCast => {
+                state.send_to_cast(CastCommand::Stop);
+                state.cast = None;
+
+                state.set_playback(MediaPlayback::Paused { progress: None });
+                ("cast", Ok(CommandResponse::Casting(false)))
+            }
+            AudioCommand::Clear => {
+                state.save_undo();
+                state.end_play();
+                sink.stop();
+                state.end_playlist_settings(sink);
+                state.track_loaded = false;
+                state.radio = None;
+                state.queue.clear();
+                state.unshuffled = None;
+                state.current_index = 0;
+
+                state.set_playback(MediaPlayback::Stopped);
+                state.host.set_current_track(None);
+
+                ("queue", Ok(CommandResponse::Queue(state.queue.clone())))
+            }
+        };
+
+        // Every response carrying the queue follows a change to it.
+        if let Ok(
+            CommandResponse::Queue(_)
+            | CommandResponse::QueueOrder { .. }
+            | CommandResponse::Shuffle { .. },
+        ) = &result
+        {
+            state.queue_version += 1;
+        }
+
+        let callback = match result {
+            Ok(data) => Callback {
+                success: true,
+                data: Some(data),
+                error: None,
+                queue_version: state.queue_version,
+            },
+            Err(err) => Callback {
+                success: false,
+                data: None,
+                error: Some(err.to_string()),
+                queue_version: state.queue_version,
+            },
+        };
+        state.emit(event_name, callback);
+
+        state.sync_controls();
+
+        let playing = !sink.empty() && !sink.is_paused();
+        state.sleep_inhibitor.set_active(playing);
+        // Casting plays elsewhere, so the local device is let go meanwhile.
+        if state.cast.is_some() {
+            state.exclusive.release();
+        }
+    }
+
+    /// Closes the output. The idle sink that stands in for it plays nothing, so it stays empty
+    /// until the output is opened again.
+    fn close_output(state: &mut AudioState, stream: &mut Option<Output>, sink: &mut Sink) {
+        *stream = None;
+        *sink = Sink::new_idle().0;
+        state.opened_rate = None;
+        state.exclusive.release();
+    }
+
+    /// Closes the output once the player has been paused or idle for `idle_release`, keeping
+    /// the paused track's position so resuming carries on from it.
+    fn release_output(state: &mut AudioState, stream: &mut Option<Output>, sink: &mut Sink) {
+        let track = state.queue.get(state.current_index);
+        state.released = track
+            .filter(|_| state.track_loaded)
+            .map(|track| (track.path.clone(), state.position(sink)));
+
+        sink.stop();
+        state.track_loaded = false;
+        state.deck = None;
+        Self::close_output(state, stream, sink);
+        log::info!(
+            "Closed the audio output after {:?} idle",
+            state.idle_release
+        );
+    }
+
+    /// Opens the output closed by a stop or an idle release and runs `command`, which starts
+    /// playback. Resuming picks up where an idle release left the track; otherwise playback
+    /// starts over from the current track rather than the front of the queue.
+    fn restart_output(
+        state: &mut AudioState,
+        stream: &mut Option<Output>,
+        sink: &mut Sink,
+        command: AudioCommand,
+    ) {
+        let (new_stream, new_sink) = Self::open_output(state).unwrap_or_else(|e| {
+            log::error!("No audio output available, playing silently: {}", e);
+            output::open_headless()
+        });
+        *stream = Some(new_stream);
+        *sink = new_sink;
+        state.apply_volume(sink);
+
+        let resume = matches!(command, AudioCommand::Resume | AudioCommand::TogglePlayback);
+        let current = state
+            .queue
+            .get(state.current_index)
+            .map(|track| &track.path);
+        match state.released.take() {
+            Some((path, position)) if resume && Some(&path) == current => {
+                state.track_loaded = true;
+                Self::reload_track(state, sink, position, true);
+            }
+            _ if resume => {
+                let command = AudioCommand::Play(state.current_index);
+                Self::handle_audio_command(command, state, sink);
+            }
+            _ => Self::handle_audio_command(command, state, sink),
+        }
+    }
+
+    /// Opens the output backend and format from the settings, for the current track's rate.
+    fn open_output(state: &mut AudioState) -> Result<(Output, Sink), output::OutputError> {
+        let settings = state.host.settings().get().unwrap_or_default();
+        // Taken first, so the device is already at the track's rate when the stream opens.
+        if state.cast.is_none() {
+            state.exclusive.acquire(state.source_rate);
+        }
+        let (output, sink) = output::open_or_default(
+            &settings.output_backend,
+            &state.output_format,
+            state.source_rate,
+            settings.output_device.as_deref(),
+        )?;
+        sink.set_speed(state.speed());
+        state.opened_rate = state.source_rate;
+        Ok((output, sink))
+    }
+
+    /// Opens the configured output again, e.g. after the system wakes up as the old stream
+    /// often stops working across sleep, and reloads the current track where it was. It is
+    /// left paused unless `resume` is set. A stopped player stays closed.
+    fn reopen_output(
+        state: &mut AudioState,
+        stream: &mut Option<Output>,
+        sink: &mut Sink,
+        resume: bool,
+    ) {
+        if stream.is_none() {
+            return;
+        }
+        let position = state
+            .suspended_at
+            .take()
+            .unwrap_or_else(|| state.position(sink));
+
+        let (new_stream, new_sink) = match Self::open_output(state) {
+            Ok(output) => output,
+            Err(e) => {
+                log::error!("Failed to reopen audio output: {}", e);
+                return;
+            }
+        };
+
+        sink.stop();
+        *stream = Some(new_stream);
+        *sink = new_sink;
+        sink.pause();
+        state.apply_volume(sink);
+
+        Self::reload_track(state, sink, position, resume);
+    }
+
+    /// Loads the current track again at `position`, if one was loaded, and reports the
+    /// outcome as `reopen_output` does.
+    fn reload_track(
+        state: &mut AudioState,
+        sink: &dyn AudioOutput,
+        position: Duration,
+        resume: bool,
+    ) {
+        let track = state.queue.get(state.current_index).cloned();
+        let Some(track) = track.filter(|_| state.track_loaded) else {
+            return;
+        };
+        let start = if seek::supports_indexed_seek(&track.path) {
+            position
+        } else {
+            Duration::ZERO
+        };
+        let result = util::load_track(&track, sink, state, start, Duration::ZERO).and_then(|_| {
+            if start < position {
+                sink.try_seek(position).map_err(AudioError::SeekError)?;
+            }
+            Ok(CommandResponse::Position(state.position(sink).as_secs()))
+        });
+
+        let status = if resume && result.is_ok() {
+            sink.play();
+            let response = CommandResponse::Play {
+                index: state.current_index,
+                track,
+            };
+            state.set_playback(MediaPlayback::Playing { progress: None });
+            ("play", Ok(response))
+        } else {
+            state.set_playback(MediaPlayback::Paused { progress: None });
+            ("status", Ok(CommandResponse::Status("paused".to_string())))
+        };
+
+        let events = [status, ("position", result)];
+        for (event, result) in events {
+            let callback = match result {
+                Ok(response) => Callback {
+                    success: true,
+                    data: Some(response),
+                    error: None,
+                    queue_version: state.queue_version,
+                },
+                Err(e) => Callback {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                    queue_version: state.queue_version,
+                },
+            };
+            state.emit(event, callback);
+        }
+    }
+
+    /// Moves on from a track that played to its end.
+    fn finish_track(state: &mut AudioState) {
+        state.track_loaded = false;
+        if let Some(track) = state.queue.get(state.current_index) {
+            let duration = Duration::from_secs(track.duration);
+            state.save_listening(duration);
+        }
+
+        match state.next_index() {
+            Some(index) => {
+                state.current_index = index;
+                state.announce_next = true;
+                let _ = state.sender.send(AudioCommand::Play(index));
+            }
+            None if state.endless && Self::extend_similar(state) => {
+                state.announce_next = true;
+                let index = state.current_index + 1;
+                let _ = state.sender.send(AudioCommand::Play(index));
+            }
+            None if !state.queue.is_empty() => {
+                let _ = state.sender.send(AudioCommand::Pause);
+            }
+            None => {}
+        }
+    }
+
+    /// Appends library tracks similar to the current one that aren't queued yet. The
+    /// additions arrive as a queued command, so a `Play` sent afterwards sees them.
+    fn extend_similar(state: &mut AudioState) -> bool {
+        let Some(seed) = state.queue.get(state.current_index) else {
+            return false;
+        };
+
+        let queued: Vec<String> = state.queue.iter().map(|track| track.path.clone()).collect();
+        let library = state.host.library();
+        let seed = RadioSeed::Track(seed.path.clone());
+        let paths: Vec<String> = match library.similar_tracks(&seed, &queued, ENDLESS_BATCH) {
+            Ok(tracks) => tracks.into_iter().map(|track| track.path).collect(),
+            Err(e) => {
+                log::error!("Failed to find similar tracks: {}", e);
+                return false;
+            }
+        };
+
+        !paths.is_empty() && state.sender.send(AudioCommand::Queue(paths)).is_ok()
+    }
+
+    /// Starts the next track while the current one is still ending so the two overlap.
+    /// Tracks continuing the same album are left to play back to back.
+    fn start_crossfade(sink: &dyn AudioOutput, state: &mut AudioState) {
+        let crossfade = state.crossfade();
+        if crossfade.is_zero() || !state.track_loaded {
+            return;
+        }
+
+        let (Some(current), Some(next)) = (
+            state.queue.get(state.current_index),
+            state.next_index().and_then(|index| state.queue.get(index)),
+        ) else {
+            return;
+        };
+
+        let remaining = Duration::from_nanos(state.remaining.load(Ordering::Relaxed));
+        if remaining > crossfade || crossfade::continues_album(current, next) {
+            return;
+        }
+
+        if let Some(index) = state.next_index() {
+            state.announce_next = true;
+            Self::handle_audio_command(AudioCommand::Play(index), state, sink);
+        }
+    }
+
+    fn track_progress(
+        sink: &dyn AudioOutput,
+        state: &mut AudioState,
+        last_emit_time: &mut std::time::Instant,
+    ) {
+        if !sink.is_paused() && !sink.empty() && last_emit_time.elapsed() >= state.emit_interval {
+            let position = state.position(sink);
+            if state.track_loaded && state.skip_outro_at.is_some_and(|outro| position >= outro) {
+                state.skip_outro_at = None;
+                Self::finish_track(state);
+                return;
+            }
+            if state.announce == AnnounceMode::Hourly
+                && announce::struck_hour(&mut state.announced_hour)
+            {
+                state.announce_time();
+            }
+            let track = state.queue.get(state.current_index);
+            if let (Some(track), Some(controls)) = (track, &mut state.controls) {
+                controls.set_position(position, Duration::from_secs(track.duration));
+            }
+            state.emit(
+                "position",
+                Callback {
+                    success: true,
+                    data: Some(CommandResponse::Position(state.position(sink).as_secs())),
+                    error: None,
+                    queue_version: state.queue_version,
+                },
+            );
+            if let Some(eta) = state.eta(sink) {
+                state.emit(
+                    "eta",
+                    Callback {
+                        success: true,
+                        data: Some(eta),
+                        error: None,
+                        queue_version: state.queue_version,
+                    },
+                );
+            }
+            *last_emit_time = std::time::Instant::now();
+
+            if state.listening_saved_at.elapsed() >= LISTENING_SAVE_INTERVAL {
+                state.save_listening(position);
+            }
+        }
+
+        let line = state
+            .lyrics
+            .as_ref()
+            .and_then(|lyrics| lyrics.line_at(state.position(sink)));
+        if line != state.lyric_line {
+            state.lyric_line = line;
+            state.emit(
+                "lyric-line",
+                Callback {
+                    success: true,
+                    data: Some(CommandResponse::LyricLine(line)),
+                    error: None,
+                    queue_version: state.queue_version,
+                },
+            );
+        }
+    }
+
+    /// Queues the files that can be played and reports the ones that can't.
+    pub fn add_queue(&self, file_paths: Vec<String>) -> Result<QueueReport, AudioError> {
+        let report = QueueReport::check(file_paths);
+        if report.accepted.is_empty() {
+            return Ok(report);
+        }
+        match self
+            .sender
+            .send(AudioCommand::Queue(report.accepted.clone()))
+        {
+            Ok(_) => Ok(report),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Adds a mix of files and folders, with folders searched recursively, sorting everything
+    /// added by `order`. Searching and checking take a while for large folders, so this is
+    /// meant to be called off the main thread; `progress` hears about each file checked.
+    pub fn add_paths(
+        &self,
+        paths: Vec<String>,
+        order: ImportOrder,
+        progress: impl FnMut(QueueProgress),
+    ) -> Result<QueueReport, AudioError> {
+        let mut files = Vec::new();
+        let mut unreadable = Vec::new();
+        for path in paths {
+            let local = util::path_from_uri(&path);
+            if SourceKind::of(&path) != SourceKind::File || !Path::new(&local).is_dir() {
+                files.push(path);
+                continue;
+            }
+            match util::collect_audio_files(Path::new(&local), true) {
+                Ok(found) => files.extend(found.into_iter().map(|file| paths::normalized(&file))),
+                Err(e) => unreadable.push(RejectedFile {
+                    path,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let mut report = QueueReport::check_with_progress(files, progress);
+        report.rejected.extend(unreadable);
+        if report.accepted.is_empty() {
+            return Ok(report);
+        }
+        match self
+            .sender
+            .send(AudioCommand::QueueSorted(report.accepted.clone(), order))
+        {
+            Ok(_) => Ok(report),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn add_folder(
+        &self,
+        path: String,
+        recursive: bool,
+        order: ImportOrder,
+    ) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::QueueFolder(path, recursive, order))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn add_tracks(&self, tracks: Vec<TrackInfo>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::QueueTracks(tracks)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn open(&self, paths: Vec<String>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Open(paths)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn clear_queue(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Clear) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn play(&self, index: usize) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Play(index)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn pause(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Pause) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn resume(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Resume) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn toggle_playback(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::TogglePlayback) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn play_at(&self, path: String, position: Duration) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::PlayAt(path, position)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn media_button(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::MediaButton) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn next(&self) -> Result<(), AudioError> {
         match self.sender.send(AudioCommand::Next) {
             Ok(_) => Ok(()),
             Err(_) => Err(AudioError::LockError),
@@ -456,6 +2915,13 @@ impl AudioPlayer {
         }
     }
 
+    pub fn restart_track(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::RestartTrack) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
     pub fn set_position(&self, position: u64) -> Result<(), AudioError> {
         match self.sender.send(AudioCommand::SetPosition(position)) {
             Ok(_) => Ok(()),
@@ -463,6 +2929,13 @@ impl AudioPlayer {
         }
     }
 
+    pub fn seek(&self, position: Duration) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Seek(position)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
     pub fn set_looped(&self, looped: bool) -> Result<(), AudioError> {
         match self.sender.send(AudioCommand::SetLooped(looped)) {
             Ok(_) => Ok(()),
@@ -476,4 +2949,752 @@ impl AudioPlayer {
             Err(_) => Err(AudioError::LockError),
         }
     }
+
+    pub fn volume_up(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::VolumeUp) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn volume_down(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::VolumeDown) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_output_backend(&self, backend: OutputBackend) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetOutputBackend(backend)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_output_device(&self, device: Option<String>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetOutputDevice(device)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_output_format(&self, format: OutputFormat) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetOutputFormat(format)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_media_controls_enabled(&self, enabled: bool) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::SetMediaControlsEnabled(enabled))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn stop(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Stop) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Switches the gain and dither stages out, or back in, without interrupting playback.
+    pub fn bypass_dsp(&self, bypass: bool) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetDspBypass(bypass)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_volume_step(&self, step: f32) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetVolumeStep(step)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_position_update_interval(&self, ms: u64) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::SetPositionUpdateInterval(ms))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetMuted(muted)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn toggle_mute(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::ToggleMute) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_volume_curve(&self, curve: VolumeCurve) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetVolumeCurve(curve)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_skip_silence(&self, enabled: bool) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetSkipSilence(enabled)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_trim_silence(&self, config: Option<TrimConfig>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetTrimSilence(config)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn set_shuffle(&self, mode: ShuffleMode) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetShuffle(mode)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Sets how strongly weighted shuffle favours rated and long-unplayed tracks.
+    pub fn set_shuffle_weighting(&self, weighting: ShuffleWeighting) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::SetShuffleWeighting(weighting))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Replaces the queue with tracks related to `seed` and keeps topping it up.
+    pub fn start_radio(&self, seed: RadioSeed) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::StartRadio(seed)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Leaves the radio queue as it is but stops adding to it.
+    pub fn stop_radio(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::StopRadio) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Keeps playing similar library tracks after the last queued one.
+    pub fn set_endless(&self, enabled: bool) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetEndless(enabled)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Chimes the time on the hour or between tracks while playing, or never.
+    pub fn set_announce_time(&self, mode: AnnounceMode) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SetAnnounceTime(mode)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Shuffles the queue again with the current track first, placing the last
+    /// `avoid_recent` played tracks at the end.
+    pub fn reshuffle(&self, avoid_recent: usize) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::Reshuffle(avoid_recent)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn sort_queue(&self, order: SortOrder) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::SortQueue(order)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// `version` is the queue version the indices refer to; `None` skips the check, for
+    /// callers that look indices up right before sending.
+    pub fn remove_from_queue(
+        &self,
+        indices: Vec<usize>,
+        version: Option<u64>,
+    ) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::RemoveFromQueue(indices, version))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn move_in_queue(
+        &self,
+        from: usize,
+        to: usize,
+        version: Option<u64>,
+    ) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::MoveInQueue(from, to, version))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn dedupe_queue(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::DedupeQueue) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn edit_queue(&self, ops: Vec<QueueOp>, version: Option<u64>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::EditQueue(ops, version)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn undo_queue(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::UndoQueue) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn redo_queue(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::RedoQueue) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn apply_playlist_settings(&self, settings: PlaylistSettings) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::ApplyPlaylistSettings(settings))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn apply_settings(&self, settings: Settings) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::ApplySettings(settings)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn get_state(&self) -> Result<PlayerSnapshot, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::GetState(reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Tracks played this session, oldest first.
+    pub fn get_session_history(&self) -> Result<Vec<TrackInfo>, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::GetSessionHistory(reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Position in the current track in milliseconds, between position events.
+    pub fn get_position(&self) -> Result<u64, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::GetPosition(reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn get_queue_summary(&self) -> Result<QueueSummary, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::GetQueueSummary(reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Lyrics of the current track, if any were found.
+    pub fn get_lyrics(&self) -> Result<Option<Lyrics>, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::GetLyrics(reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Applies a changed lyrics offset if `path` is the current track.
+    pub fn set_lyrics_offset(&self, path: String, offset_ms: i64) -> Result<(), AudioError> {
+        match self
+            .sender
+            .send(AudioCommand::SetLyricsOffset(path, offset_ms))
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    /// Queue indices of tracks matching `query`.
+    pub fn find_in_queue(&self, query: String) -> Result<Vec<usize>, AudioError> {
+        let (reply, response) = mpsc::channel();
+        match self.sender.send(AudioCommand::FindInQueue(query, reply)) {
+            Ok(_) => response
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| AudioError::NoResponseError),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn play_matching(&self, query: String) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::PlayMatching(query)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn start_cast(&self, sender: mpsc::Sender<CastCommand>) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::StartCast(sender)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+
+    pub fn stop_cast(&self) -> Result<(), AudioError> {
+        match self.sender.send(AudioCommand::StopCast) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AudioError::LockError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Library;
+    use crate::media_controls::MediaControlsError;
+    use rodio::source::SeekError;
+    use rodio::Source;
+
+    fn track(path: &str) -> TrackInfo {
+        TrackInfo {
+            index: 0,
+            title: path.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration: 180,
+            track_number: None,
+            disc_number: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            artists: Vec::new(),
+            genres: Vec::new(),
+            compilation: false,
+            composer: None,
+            conductor: None,
+            performer: None,
+            work: None,
+            movement: None,
+            path: path.to_string(),
+            source_kind: SourceKind::File,
+        }
+    }
+
+    fn queue(paths: &[&str]) -> Vec<TrackInfo> {
+        let mut queue: Vec<TrackInfo> = paths.iter().map(|path| track(path)).collect();
+        util::reindex(&mut queue);
+        queue
+    }
+
+    fn paths(queue: &[TrackInfo]) -> Vec<&str> {
+        queue.iter().map(|track| track.path.as_str()).collect()
+    }
+
+    fn apply(queue: &[TrackInfo], current: usize, ops: Vec<QueueOp>) -> EditedQueue {
+        apply_queue_ops(queue, current, ops, track).unwrap()
+    }
+
+    #[test]
+    fn queue_summary_counts_from_the_current_position() {
+        let summary = QueueSummary::of(
+            &queue(&["/a.mp3", "/b.FLAC", "https://host/c.mp3?token=1"]),
+            1,
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(summary.track_count, 3);
+        assert_eq!(summary.total_duration, 540);
+        assert_eq!(summary.remaining_duration, 300);
+        assert_eq!(summary.formats.get("mp3"), Some(&2));
+        assert_eq!(summary.formats.get("flac"), Some(&1));
+    }
+
+    #[test]
+    fn removal_ignores_repeated_and_unsorted_indices() {
+        let queue = queue(&["a", "b", "c", "d"]);
+
+        let edited = remove_from_queue(&queue, 1, &[0, 0]).unwrap();
+        assert_eq!(paths(&edited.queue), ["b", "c", "d"]);
+        assert_eq!(edited.current_index, 0);
+        assert!(!edited.removed_current);
+
+        let edited = remove_from_queue(&queue, 2, &[3, 0, 2, 0]).unwrap();
+        assert_eq!(paths(&edited.queue), ["b"]);
+        assert_eq!(edited.current_index, 0);
+        assert!(edited.removed_current);
+
+        assert!(remove_from_queue(&queue, 0, &[1, 4]).is_err());
+    }
+
+    #[test]
+    fn moves_follow_the_current_track() {
+        let edited = apply(
+            &queue(&["a", "b", "c", "d"]),
+            1,
+            vec![
+                QueueOp::Move { from: 1, to: 3 },
+                QueueOp::Move { from: 0, to: 1 },
+            ],
+        );
+
+        assert_eq!(paths(&edited.queue), ["c", "a", "d", "b"]);
+        assert_eq!(edited.current_index, 3);
+        assert!(!edited.removed_current);
+    }
+
+    #[test]
+    fn indices_refer_to_the_queue_after_the_previous_step() {
+        let edited = apply(
+            &queue(&["a", "b", "c"]),
+            0,
+            vec![
+                QueueOp::Insert {
+                    index: 1,
+                    paths: vec!["x".to_string(), "y".to_string()],
+                },
+                QueueOp::Remove { index: 3 },
+            ],
+        );
+
+        assert_eq!(paths(&edited.queue), ["a", "x", "y", "c"]);
+        let indices: Vec<usize> = edited.queue.iter().map(|track| track.index).collect();
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn removing_the_current_track_is_reported() {
+        let edited = apply(
+            &queue(&["a", "b", "c"]),
+            2,
+            vec![QueueOp::Remove { index: 2 }],
+        );
+
+        assert!(edited.removed_current);
+        assert_eq!(edited.current_index, 1);
+    }
+
+    #[test]
+    fn an_invalid_step_rejects_the_whole_batch() {
+        let result = apply_queue_ops(
+            &queue(&["a", "b"]),
+            0,
+            vec![
+                QueueOp::Remove { index: 0 },
+                QueueOp::Move { from: 0, to: 1 },
+            ],
+            track,
+        );
+
+        assert!(matches!(result, Err(AudioError::OutOfBoundsError)));
+    }
+
+    /// Host that keeps the events sent to the front end for the test to look at.
+    struct TestHost {
+        events: Mutex<Vec<(String, serde_json::Value)>>,
+        library: Library,
+        settings: SettingsStore,
+    }
+
+    impl PlayerHost for TestHost {
+        fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), AudioError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event.to_string(), payload));
+            Ok(())
+        }
+
+        fn library(&self) -> &Library {
+            &self.library
+        }
+
+        fn settings(&self) -> &SettingsStore {
+            &self.settings
+        }
+
+        fn set_current_track(&self, _track: Option<&TrackInfo>) {}
+
+        fn media_controls(
+            &self,
+            _player: AudioPlayer,
+        ) -> Result<MediaControls, MediaControlsError> {
+            Err(MediaControlsError::Unavailable)
+        }
+
+        fn load_lyrics(&self, _track: &TrackInfo, _online: bool) -> Option<Lyrics> {
+            None
+        }
+
+        fn save_listening(&self, path: String, position_ms: u64) {
+            self.library.record_listening(&path, position_ms).unwrap();
+        }
+    }
+
+    /// Output that plays nothing but holds on to what it's given, so a test decides when the
+    /// queued audio runs out.
+    #[derive(Default)]
+    struct SilentOutput {
+        queued: Mutex<Vec<Box<dyn Source<Item = i16> + Send>>>,
+        paused: AtomicBool,
+    }
+
+    impl SilentOutput {
+        /// Runs everything queued to its end, as playing it through would.
+        fn play_out(&self) {
+            let queued = std::mem::take(&mut *self.queued.lock().unwrap());
+            for source in queued {
+                source.for_each(drop);
+            }
+        }
+    }
+
+    impl AudioOutput for SilentOutput {
+        fn append(&self, source: Box<dyn Source<Item = i16> + Send>) {
+            self.queued.lock().unwrap().push(source);
+        }
+
+        fn play(&self) {
+            self.paused.store(false, Ordering::Relaxed);
+        }
+
+        fn pause(&self) {
+            self.paused.store(true, Ordering::Relaxed);
+        }
+
+        fn is_paused(&self) -> bool {
+            self.paused.load(Ordering::Relaxed)
+        }
+
+        fn clear(&self) {
+            self.queued.lock().unwrap().clear();
+            self.pause();
+        }
+
+        fn stop(&self) {
+            self.queued.lock().unwrap().clear();
+        }
+
+        fn empty(&self) -> bool {
+            self.queued.lock().unwrap().is_empty()
+        }
+
+        fn get_pos(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        fn try_seek(&self, _pos: Duration) -> Result<(), SeekError> {
+            Ok(())
+        }
+
+        fn speed(&self) -> f32 {
+            1.0
+        }
+
+        fn set_speed(&self, _speed: f32) {}
+
+        fn set_volume(&self, _volume: f32) {}
+    }
+
+    /// A stopped player with `count` short silent WAVs queued, and the receiving end of the
+    /// commands it sends itself.
+    fn player(
+        name: &str,
+        count: usize,
+    ) -> (
+        AudioState,
+        Arc<TestHost>,
+        SilentOutput,
+        mpsc::Receiver<AudioCommand>,
+    ) {
+        let dir = std::env::temp_dir().join(format!("music-player-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let host = Arc::new(TestHost {
+            events: Mutex::new(Vec::new()),
+            library: Library::open(&dir.join("library.db")).unwrap(),
+            settings: SettingsStore::load(dir.join("settings.json")),
+        });
+        let (sender, receiver) = mpsc::channel();
+        let mut state = AudioState::new(
+            host.clone(),
+            sender.clone(),
+            MetadataCache::load(dir.join("metadata_cache.json")),
+            AudioFocus::granted(),
+            BluetoothVolume::watch(sender),
+            &Settings::default(),
+        );
+        state.read_ahead = 0;
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let tracks = (0..count)
+            .map(|i| {
+                let path = dir.join(format!("{}.wav", i));
+                let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+                for _ in 0..800 {
+                    writer.write_sample(0i16).unwrap();
+                }
+                writer.finalize().unwrap();
+                track(&path.to_string_lossy())
+            })
+            .collect();
+
+        let output = SilentOutput::default();
+        AudioPlayer::handle_audio_command(AudioCommand::QueueTracks(tracks), &mut state, &output);
+        (state, host, output, receiver)
+    }
+
+    /// The next command the player sent itself, skipping lyrics loaded in the background.
+    fn sent(receiver: &mpsc::Receiver<AudioCommand>) -> AudioCommand {
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+                AudioCommand::LyricsLoaded(..) => continue,
+                command => return command,
+            }
+        }
+    }
+
+    /// Payloads of the events named `event`, oldest first.
+    fn events(host: &TestHost, event: &str) -> Vec<serde_json::Value> {
+        host.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    /// Queue indices of the tracks the front end was told started playing.
+    fn played(host: &TestHost) -> Vec<u64> {
+        events(host, "play")
+            .iter()
+            .map(|payload| {
+                assert_eq!(payload["success"], true);
+                assert_eq!(payload["data"]["type"], "Play");
+                payload["data"]["data"]["index"].as_u64().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finished_tracks_advance_the_queue() {
+        let (mut state, host, output, receiver) = player("advance", 3);
+
+        AudioPlayer::handle_audio_command(AudioCommand::Play(0), &mut state, &output);
+        AudioPlayer::handle_audio_command(AudioCommand::Next, &mut state, &output);
+        assert_eq!(played(&host), [0, 1]);
+        assert!(!output.empty() && !output.is_paused());
+
+        // The output reports the end of the track, and the player asks itself for the next.
+        output.play_out();
+        let ended = sent(&receiver);
+        assert!(matches!(ended, AudioCommand::TrackEnded(id) if id == state.track_id));
+        AudioPlayer::handle_audio_command(ended, &mut state, &output);
+        let next = sent(&receiver);
+        assert!(matches!(next, AudioCommand::Play(2)));
+        AudioPlayer::handle_audio_command(next, &mut state, &output);
+
+        assert_eq!(played(&host), [0, 1, 2]);
+        assert_eq!(state.current_index, 2);
+    }
+
+    #[test]
+    fn the_end_of_a_replaced_track_is_ignored() {
+        let (mut state, host, output, receiver) = player("replaced", 3);
+
+        AudioPlayer::handle_audio_command(AudioCommand::Play(0), &mut state, &output);
+        let first = state.track_id;
+        AudioPlayer::handle_audio_command(AudioCommand::Next, &mut state, &output);
+        AudioPlayer::handle_audio_command(AudioCommand::TrackEnded(first), &mut state, &output);
+
+        assert!(receiver
+            .try_iter()
+            .all(|command| matches!(command, AudioCommand::LyricsLoaded(..))));
+        assert_eq!(played(&host), [0, 1]);
+        assert_eq!(state.current_index, 1);
+    }
+
+    #[test]
+    fn removing_the_playing_track_stops_it() {
+        let (mut state, host, output, _receiver) = player("remove-playing", 3);
+
+        AudioPlayer::handle_audio_command(AudioCommand::Play(1), &mut state, &output);
+        AudioPlayer::handle_audio_command(
+            AudioCommand::RemoveFromQueue(vec![1, 1], None),
+            &mut state,
+            &output,
+        );
+
+        assert!(output.empty());
+        assert!(!state.track_loaded);
+        let removed = events(&host, "queue_order");
+        let payload = removed.last().unwrap();
+        assert_eq!(payload["success"], true);
+        assert_eq!(payload["data"]["type"], "QueueOrder");
+        assert_eq!(payload["data"]["data"]["index"], 1);
+        assert_eq!(
+            payload["data"]["data"]["queue"].as_array().unwrap().len(),
+            2
+        );
+    }
 }
@@ -0,0 +1,96 @@
+use lofty::file::{TaggedFile, TaggedFileExt};
+use lofty::tag::{Accessor, ItemKey};
+use std::path::Path;
+
+/// Folder name, matched case-insensitively, under which files are taken as audiobooks laid
+/// out as `Author/[Series/]Book/files`.
+const BOOKS_FOLDER: &str = "audiobooks";
+
+/// Genre tags that mark a file as part of an audiobook wherever it is.
+const BOOK_GENRES: &[&str] = &["audiobook", "audiobooks", "audio book", "spoken word"];
+
+/// Played this close to its end, a file counts as finished.
+pub const FINISHED_MARGIN_MS: u64 = 10_000;
+
+/// The book a file belongs to.
+#[derive(Debug, PartialEq)]
+pub struct Book {
+    pub title: String,
+    pub author: String,
+    pub series: Option<String>,
+}
+
+/// Works out whether a file is part of an audiobook, and which. Tags come first; missing
+/// ones are filled in from the folders under an `Audiobooks` folder.
+pub fn identify(path: &str, tagged_file: Option<&TaggedFile>, genres: &[String]) -> Option<Book> {
+    let folders = book_folders(Path::new(path));
+    let tagged = genres
+        .iter()
+        .any(|genre| BOOK_GENRES.contains(&genre.to_lowercase().as_str()));
+    if !tagged && folders.is_none() {
+        return None;
+    }
+    let folders = folders.unwrap_or_default();
+
+    let tag = tagged_file.and_then(|file| file.primary_tag());
+    let text = |key: ItemKey| tag.and_then(|tag| tag.get_string(&key).map(String::from));
+
+    let title = tag
+        .and_then(|tag| tag.album().map(|album| album.into_owned()))
+        .or_else(|| folders.last().cloned())?;
+    let author = text(ItemKey::AlbumArtist)
+        .or_else(|| tag.and_then(|tag| tag.artist().map(|artist| artist.into_owned())))
+        .or_else(|| (folders.len() >= 2).then(|| folders[0].clone()))
+        .unwrap_or_else(|| "Unknown Author".to_string());
+    let series =
+        text(ItemKey::ContentGroup).or_else(|| (folders.len() >= 3).then(|| folders[1].clone()));
+
+    Some(Book {
+        title,
+        author,
+        series,
+    })
+}
+
+/// Folders between the `Audiobooks` folder and the file, if it's under one.
+fn book_folders(path: &Path) -> Option<Vec<String>> {
+    let folders: Vec<String> = path
+        .parent()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let start = folders
+        .iter()
+        .rposition(|folder| folder.eq_ignore_ascii_case(BOOKS_FOLDER))?;
+    Some(folders[start + 1..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn books_are_read_from_the_folders_under_audiobooks() {
+        let book = identify("/music/Audiobooks/Author/Series/Book/01.mp3", None, &[]);
+        assert_eq!(
+            book,
+            Some(Book {
+                title: "Book".to_string(),
+                author: "Author".to_string(),
+                series: Some("Series".to_string()),
+            })
+        );
+
+        let book = identify("/music/audiobooks/Author/Book/01.mp3", None, &[]).unwrap();
+        assert_eq!(book.author, "Author");
+        assert_eq!(book.series, None);
+    }
+
+    #[test]
+    fn other_files_are_not_books() {
+        assert_eq!(identify("/music/Artist/Album/01.mp3", None, &[]), None);
+        // A genre tag marks a book, but without tags or folders it has no title.
+        let genres = ["Audiobook".to_string()];
+        assert_eq!(identify("/music/01.mp3", None, &genres), None);
+    }
+}
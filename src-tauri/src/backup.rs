@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+use thiserror::Error;
+
+use crate::library::{self, Library, LibraryError};
+use crate::settings::{SettingsError, SettingsStore};
+
+/// Bumped when the archive layout changes in a way older versions can't restore.
+const BACKUP_FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const DATABASE: &str = "library.db";
+const SETTINGS: &str = "settings.json";
+const COVERS: &str = "covers";
+
+const BLOCK: usize = 512;
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Invalid manifest: {0}")]
+    ManifestError(#[from] serde_json::Error),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+
+    #[error("Settings error: {0}")]
+    SettingsError(#[from] SettingsError),
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Backup was made by a newer version of the app ({0})")]
+    NewerVersion(String),
+}
+
+/// Describes a backup, so a restore can tell whether this version understands it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupManifest {
+    pub format: u32,
+    pub app_version: String,
+    /// `PRAGMA user_version` of the bundled database.
+    pub schema_version: usize,
+    pub created_at: u64,
+}
+
+/// Bundles the library database (tracks, statistics, playlists, lyrics and waveform caches),
+/// the settings and the extracted album covers into a single tar archive at `dest`.
+pub fn create_backup(app: &AppHandle, dest: &Path) -> Result<BackupManifest, BackupError> {
+    let library = app.state::<Library>();
+    let settings = app.state::<SettingsStore>();
+    settings.flush()?;
+    let settings_path = settings.path()?;
+    let covers = library.covers_dir()?;
+
+    // The database is snapshotted next to the archive, as SQLite needs a real file to write.
+    let snapshot = dest.with_extension("db.tmp");
+    let _ = fs::remove_file(&snapshot);
+    library.backup_to(&snapshot)?;
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT,
+        app_version: app.package_info().version.to_string(),
+        schema_version: library.schema_version()?,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+    };
+
+    let result = write_archive(dest, &manifest, &snapshot, &settings_path, &covers);
+    let _ = fs::remove_file(&snapshot);
+    result?;
+
+    Ok(manifest)
+}
+
+fn write_archive(
+    dest: &Path,
+    manifest: &BackupManifest,
+    database: &Path,
+    settings: &Path,
+    covers: &Path,
+) -> Result<(), BackupError> {
+    let mut archive = BufWriter::new(File::create(dest)?);
+
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+    write_entry(
+        &mut archive,
+        MANIFEST,
+        manifest.len() as u64,
+        &mut &manifest[..],
+    )?;
+    write_file(&mut archive, DATABASE, database)?;
+    if settings.is_file() {
+        write_file(&mut archive, SETTINGS, settings)?;
+    }
+    if let Ok(entries) = fs::read_dir(covers) {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.is_file() {
+                let name = format!("{}/{}", COVERS, entry.file_name().to_string_lossy());
+                write_file(&mut archive, &name, &path)?;
+            }
+        }
+    }
+
+    // A tar archive ends with two empty blocks.
+    archive.write_all(&[0; BLOCK * 2])?;
+    archive.flush()?;
+    Ok(())
+}
+
+/// Replaces the library, settings and covers with the contents of the backup at `src`.
+/// Backups from older versions are upgraded; those from newer versions are refused before
+/// anything is touched.
+pub fn restore_backup(app: &AppHandle, src: &Path) -> Result<BackupManifest, BackupError> {
+    let library = app.state::<Library>();
+    let settings = app.state::<SettingsStore>();
+
+    let staging = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?
+        .join("restore");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+
+    let result = extract_archive(src, &staging)
+        .and_then(|_| validate(&staging))
+        .and_then(|manifest| {
+            library.restore_from(&staging.join(DATABASE))?;
+
+            let restored_settings = staging.join(SETTINGS);
+            if restored_settings.is_file() {
+                // Pending changes would otherwise be written over the restored file.
+                settings.flush()?;
+                fs::copy(&restored_settings, settings.path()?)?;
+                settings.reload()?;
+            }
+
+            let restored_covers = staging.join(COVERS);
+            if restored_covers.is_dir() {
+                let covers = library.covers_dir()?;
+                let _ = fs::remove_dir_all(&covers);
+                fs::create_dir_all(&covers)?;
+                for entry in fs::read_dir(&restored_covers)?.filter_map(Result::ok) {
+                    fs::copy(entry.path(), covers.join(entry.file_name()))?;
+                }
+            }
+
+            Ok(manifest)
+        });
+    let _ = fs::remove_dir_all(&staging);
+
+    if let Err(e) = library::emit_playlists(app) {
+        log::error!("{}", e);
+    }
+    result
+}
+
+fn validate(staging: &Path) -> Result<BackupManifest, BackupError> {
+    let file = File::open(staging.join(MANIFEST))
+        .map_err(|_| BackupError::InvalidBackup("Missing manifest".to_string()))?;
+    let manifest: BackupManifest = serde_json::from_reader(BufReader::new(file))?;
+
+    if manifest.format > BACKUP_FORMAT || manifest.schema_version > library::SCHEMA_VERSION {
+        return Err(BackupError::NewerVersion(manifest.app_version));
+    }
+    if !staging.join(DATABASE).is_file() {
+        return Err(BackupError::InvalidBackup("Missing database".to_string()));
+    }
+
+    Ok(manifest)
+}
+
+fn write_file(archive: &mut impl Write, name: &str, path: &Path) -> Result<(), BackupError> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    write_entry(archive, name, size, &mut BufReader::new(file))
+}
+
+/// Writes a ustar header for a regular file followed by its data, padded to a whole block.
+fn write_entry(
+    archive: &mut impl Write,
+    name: &str,
+    size: u64,
+    data: &mut impl Read,
+) -> Result<(), BackupError> {
+    if name.len() > 100 {
+        return Err(BackupError::InvalidBackup(format!(
+            "File name too long: {}",
+            name
+        )));
+    }
+
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field set to spaces.
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    archive.write_all(&header)?;
+    let copied = io::copy(&mut data.by_ref().take(size), archive)?;
+    if copied != size {
+        return Err(BackupError::InvalidBackup(format!(
+            "{} changed while reading",
+            name
+        )));
+    }
+    archive.write_all(&vec![0; padding(size)])?;
+    Ok(())
+}
+
+/// Unpacks the regular files of the archive at `src` into `dest`, refusing entries that
+/// would land outside of it.
+fn extract_archive(src: &Path, dest: &Path) -> Result<(), BackupError> {
+    let mut archive = BufReader::new(File::open(src)?);
+    let invalid = |reason: &str| BackupError::InvalidBackup(reason.to_string());
+
+    loop {
+        let mut header = [0u8; BLOCK];
+        archive
+            .read_exact(&mut header)
+            .map_err(|_| invalid("Truncated archive"))?;
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+
+        let name_end = header[..100]
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(100);
+        let name = String::from_utf8_lossy(&header[..name_end]).into_owned();
+        let size = std::str::from_utf8(&header[124..136])
+            .ok()
+            .and_then(|field| u64::from_str_radix(field.trim_matches(['\0', ' ']), 8).ok())
+            .ok_or_else(|| invalid("Corrupt entry header"))?;
+
+        let relative = Path::new(&name);
+        let safe = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        let regular = matches!(header[156], b'0' | 0);
+
+        let mut data = (&mut archive).take(size);
+        if safe && regular {
+            let path = dest.join(relative);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let copied = io::copy(&mut data, &mut File::create(&path)?)?;
+            if copied != size {
+                return Err(invalid("Truncated archive"));
+            }
+        } else {
+            io::copy(&mut data, &mut io::sink())?;
+        }
+        io::copy(
+            &mut (&mut archive).take(padding(size) as u64),
+            &mut io::sink(),
+        )?;
+    }
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
@@ -0,0 +1,177 @@
+use std::sync::mpsc;
+
+use crate::audio_player::AudioCommand;
+
+/// AVRCP absolute volume, where a Bluetooth headset's own buttons set the level of the audio
+/// stream. While such a headset is playing, the volume slider mirrors it and controls it
+/// instead of applying a second, software volume on top.
+pub struct BluetoothVolume {
+    inner: platform::Transport,
+}
+
+impl BluetoothVolume {
+    /// Reports headset volume changes to the audio thread as `BluetoothVolume` commands.
+    pub fn watch(sender: mpsc::Sender<AudioCommand>) -> BluetoothVolume {
+        BluetoothVolume {
+            inner: platform::Transport::watch(sender),
+        }
+    }
+
+    /// Whether a headset with absolute volume is currently controlling the level.
+    pub fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+
+    /// Sends a volume chosen in the app to the headset.
+    pub fn set_volume(&self, volume: f32) {
+        self.inner.set_volume(volume);
+    }
+}
+
+/// BlueZ exposes absolute volume as the `Volume` property (0-127) of a `MediaTransport1`.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+    use std::thread;
+    use zbus::blocking::{Connection, MessageIterator};
+    use zbus::message::Type;
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+    use zbus::MatchRule;
+
+    use crate::audio_player::AudioCommand;
+
+    const TRANSPORT_INTERFACE: &str = "org.bluez.MediaTransport1";
+    const MAX_VOLUME: f32 = 127.0;
+
+    #[derive(Default)]
+    struct Active {
+        connection: Option<Connection>,
+        transport: Option<OwnedObjectPath>,
+    }
+
+    pub struct Transport {
+        active: Arc<Mutex<Active>>,
+    }
+
+    fn lock(active: &Mutex<Active>) -> MutexGuard<'_, Active> {
+        active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    impl Transport {
+        pub fn watch(sender: mpsc::Sender<AudioCommand>) -> Transport {
+            let active = Arc::new(Mutex::new(Active::default()));
+
+            let shared = active.clone();
+            thread::spawn(move || {
+                if let Err(e) = listen(&shared, &sender) {
+                    log::error!("Failed to watch Bluetooth volume: {}", e);
+                }
+            });
+
+            Transport { active }
+        }
+
+        pub fn is_active(&self) -> bool {
+            lock(&self.active).transport.is_some()
+        }
+
+        pub fn set_volume(&self, volume: f32) {
+            let active = lock(&self.active);
+            let (Some(connection), Some(transport)) = (&active.connection, &active.transport)
+            else {
+                return;
+            };
+
+            let level = (volume.clamp(0.0, 1.0) * MAX_VOLUME).round() as u16;
+            if let Err(e) = connection.call_method(
+                Some("org.bluez"),
+                transport.as_str(),
+                Some("org.freedesktop.DBus.Properties"),
+                "Set",
+                &(TRANSPORT_INTERFACE, "Volume", Value::U16(level)),
+            ) {
+                log::error!("Failed to set Bluetooth volume: {}", e);
+            }
+        }
+    }
+
+    fn listen(active: &Mutex<Active>, sender: &mpsc::Sender<AudioCommand>) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        lock(active).connection = Some(connection.clone());
+
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender("org.bluez")?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .add_arg(TRANSPORT_INTERFACE)?
+            .build();
+
+        for message in MessageIterator::for_match_rule(rule, &connection, None)? {
+            let message = message?;
+            let Some(path) = message
+                .header()
+                .path()
+                .map(|path| OwnedObjectPath::from(path.to_owned()))
+            else {
+                continue;
+            };
+            let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                message.body().deserialize()?;
+
+            // A transport that stops streaming hands volume control back to the app.
+            let idle = changed
+                .get("State")
+                .and_then(|state| <&str>::try_from(state).ok())
+                .is_some_and(|state| state == "idle");
+            let volume = changed
+                .get("Volume")
+                .and_then(|volume| u16::try_from(volume).ok());
+
+            let command = {
+                let mut active = lock(active);
+                if idle && active.transport.as_ref() == Some(&path) {
+                    active.transport = None;
+                    Some(AudioCommand::BluetoothVolume(None))
+                } else if let Some(volume) = volume {
+                    active.transport = Some(path);
+                    Some(AudioCommand::BluetoothVolume(Some(
+                        volume as f32 / MAX_VOLUME,
+                    )))
+                } else {
+                    None
+                }
+            };
+
+            if let Some(command) = command {
+                if sender.send(command).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Other platforms apply Bluetooth absolute volume to the system volume, not the app's.
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::sync::mpsc;
+
+    use crate::audio_player::AudioCommand;
+
+    pub struct Transport;
+
+    impl Transport {
+        pub fn watch(_sender: mpsc::Sender<AudioCommand>) -> Transport {
+            Transport
+        }
+
+        pub fn is_active(&self) -> bool {
+            false
+        }
+
+        pub fn set_volume(&self, _volume: f32) {}
+    }
+}
@@ -0,0 +1,134 @@
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::read_from_path;
+use lofty::tag::{ItemKey, Tag};
+use rodio::{Decoder, Source};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::library::Library;
+use crate::paths;
+
+/// Samples per onset-envelope step.
+const HOP_SIZE: usize = 512;
+
+/// Only this much audio is analyzed; tempo rarely changes enough to matter beyond it.
+const MAX_ANALYZED_SECONDS: u32 = 120;
+
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+
+#[derive(Serialize, Clone, Debug)]
+struct BpmProgress {
+    path: String,
+    bpm: Option<f32>,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+/// Estimates the tempo of each file in the background, stores it in the library and
+/// optionally writes it to the file's BPM tag. Emits `bpm-progress` per file.
+pub fn analyze(app: AppHandle, paths: Vec<String>, write_tag: bool) {
+    thread::spawn(move || {
+        let library = app.state::<Library>();
+        let total = paths.len();
+
+        for (i, path) in paths.into_iter().enumerate() {
+            let result = estimate_bpm(&path).and_then(|bpm| {
+                library
+                    .add_file(&path)
+                    .and_then(|_| library.set_bpm(&path, bpm))
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+
+                if write_tag {
+                    write_bpm_tag(&path, bpm)?;
+                }
+
+                Ok(bpm)
+            });
+
+            let progress = BpmProgress {
+                path,
+                bpm: result.as_ref().ok().copied(),
+                error: result.err().map(|e| e.to_string()),
+                completed: i + 1,
+                total,
+            };
+
+            if let Err(e) = app.emit("bpm-progress", progress) {
+                log::error!("{}", e);
+            }
+        }
+    });
+}
+
+/// Autocorrelates an onset-strength envelope and picks the strongest beat period.
+fn estimate_bpm(path: &str) -> io::Result<f32> {
+    let decoder = Decoder::new(BufReader::new(File::open(paths::native(path))?))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+    let limit = (sample_rate * MAX_ANALYZED_SECONDS) as usize * channels;
+
+    let samples: Vec<i16> = decoder.take(limit).collect();
+    let mono: Vec<f32> = samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().map(|&s| s as f32).sum::<f32>() / channels as f32)
+        .collect();
+
+    let energies: Vec<f32> = mono
+        .chunks(HOP_SIZE)
+        .map(|hop| (hop.iter().map(|s| s * s).sum::<f32>() / hop.len() as f32 + 1.0).ln())
+        .collect();
+    let onsets: Vec<f32> = energies
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).max(0.0))
+        .collect();
+
+    let hops_per_second = sample_rate as f32 / HOP_SIZE as f32;
+    let min_lag = (hops_per_second * 60.0 / MAX_BPM).floor().max(1.0) as usize;
+    let max_lag = (hops_per_second * 60.0 / MIN_BPM).ceil() as usize;
+    if onsets.len() <= max_lag * 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Track is too short to detect tempo",
+        ));
+    }
+
+    let best_lag = (min_lag..=max_lag)
+        .map(|lag| {
+            let correlation: f32 = onsets
+                .iter()
+                .zip(&onsets[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (onsets.len() - lag) as f32;
+            (lag, correlation)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(lag, _)| lag)
+        .unwrap_or(min_lag);
+
+    let bpm = 60.0 * hops_per_second / best_lag as f32;
+    Ok((bpm * 10.0).round() / 10.0)
+}
+
+fn write_bpm_tag(path: &str, bpm: f32) -> io::Result<()> {
+    let mut tagged_file =
+        read_from_path(paths::native(path)).map_err(|e| io::Error::other(e.to_string()))?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+
+    if let Some(tag) = tagged_file.primary_tag_mut() {
+        tag.insert_text(ItemKey::IntegerBpm, format!("{}", bpm.round() as u32));
+    }
+
+    tagged_file
+        .save_to_path(path, WriteOptions::default())
+        .map_err(|e| io::Error::other(e.to_string()))
+}
@@ -0,0 +1,224 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::util::MediaStream;
+
+/// Bytes requested from the underlying stream per read.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Payload of `buffer-underrun`, emitted when playback had to wait for the disk or network.
+#[derive(Serialize, Clone, Debug)]
+pub struct Underrun {
+    pub path: String,
+    pub waited_ms: u64,
+}
+
+struct Shared {
+    data: VecDeque<u8>,
+    /// Stream offset of the first buffered byte.
+    start: u64,
+    /// Offset the worker should jump to before reading on.
+    seek_to: Option<u64>,
+    eof: bool,
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+/// Stream wrapper that keeps up to `window` bytes ahead of the decoder, read on a background
+/// thread so a slow network share or a disk spinning up doesn't stall playback directly.
+pub struct ReadAhead {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+    position: u64,
+    length: u64,
+    /// Set after the first read, so the initial fill isn't reported as an underrun.
+    primed: bool,
+    on_underrun: Box<dyn Fn(Duration) + Send + Sync>,
+}
+
+impl ReadAhead {
+    /// Starts reading ahead. `on_underrun` is called with the time spent waiting whenever the
+    /// decoder caught up with the buffer.
+    pub fn new<F>(
+        mut inner: Box<dyn MediaStream>,
+        window: usize,
+        on_underrun: F,
+    ) -> io::Result<ReadAhead>
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        let length = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        let shared = Arc::new((
+            Mutex::new(Shared {
+                data: VecDeque::new(),
+                start: 0,
+                seek_to: None,
+                eof: false,
+                error: None,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let worker = shared.clone();
+        thread::spawn(move || fill(inner, window.max(READ_SIZE), &worker));
+
+        Ok(ReadAhead {
+            shared,
+            position: 0,
+            length,
+            primed: false,
+            on_underrun: Box::new(on_underrun),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Worker loop: tops the buffer up to `window` bytes and services seeks outside of it.
+fn fill(mut inner: Box<dyn MediaStream>, window: usize, shared: &(Mutex<Shared>, Condvar)) {
+    let (lock, condvar) = shared;
+    let mut chunk = vec![0u8; READ_SIZE];
+
+    loop {
+        let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if guard.closed {
+                return;
+            }
+            if guard.seek_to.is_some() || (!guard.eof && guard.data.len() < window) {
+                break;
+            }
+            guard = condvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+
+        if let Some(offset) = guard.seek_to.take() {
+            guard.data.clear();
+            guard.start = offset;
+            guard.eof = false;
+            guard.error = None;
+            if let Err(e) = inner.seek(SeekFrom::Start(offset)) {
+                guard.error = Some(e);
+                guard.eof = true;
+                condvar.notify_all();
+                continue;
+            }
+        }
+        let offset = guard.start + guard.data.len() as u64;
+        drop(guard);
+
+        let result = inner.read(&mut chunk);
+
+        let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        // A seek requested while reading makes this chunk useless.
+        if guard.seek_to.is_some() || guard.start + guard.data.len() as u64 != offset {
+            continue;
+        }
+        match result {
+            Ok(0) => guard.eof = true,
+            Ok(count) => guard.data.extend(&chunk[..count]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                guard.error = Some(e);
+                guard.eof = true;
+            }
+        }
+        condvar.notify_all();
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.length {
+            return Ok(0);
+        }
+
+        let (_, condvar) = &*self.shared;
+        let position = self.position;
+        let mut guard = self.lock();
+
+        let buffered = |shared: &Shared| {
+            position >= shared.start && position <= shared.start + shared.data.len() as u64
+        };
+        // Jumping outside the buffer restarts reading there; that wait is expected.
+        let seeking = !buffered(&guard);
+        if seeking {
+            guard.seek_to = Some(position);
+            condvar.notify_all();
+        }
+
+        let mut waited = None;
+        loop {
+            if guard.seek_to.is_none() && buffered(&guard) {
+                let offset = (position - guard.start) as usize;
+                if offset < guard.data.len() || guard.eof {
+                    break;
+                }
+            }
+            waited.get_or_insert_with(Instant::now);
+            guard = condvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+
+        // Bytes behind the read position are no longer needed.
+        let consumed = (position - guard.start) as usize;
+        guard.data.drain(..consumed);
+        guard.start = position;
+
+        if guard.data.is_empty() {
+            if let Some(e) = guard.error.take() {
+                return Err(e);
+            }
+        }
+
+        let (front, back) = guard.data.as_slices();
+        let available = if front.is_empty() { back } else { front };
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        guard.data.drain(..count);
+        guard.start += count as u64;
+        condvar.notify_all();
+        drop(guard);
+
+        self.position += count as u64;
+        if let (Some(started), true, false) = (waited, self.primed, seeking) {
+            (self.on_underrun)(started.elapsed());
+        }
+        self.primed = true;
+
+        Ok(count)
+    }
+}
+
+impl Seek for ReadAhead {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.length as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before start of stream",
+            ));
+        }
+
+        self.position = target as u64;
+        Ok(self.position)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.lock().closed = true;
+        self.shared.1.notify_all();
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::audio_player::{AudioError, TrackInfo};
+use crate::util::{get_track_info_from_path, path_from_uri};
+
+/// Bumped whenever the shape of a cached `TrackInfo` changes so stale files are discarded.
+const CACHE_VERSION: u32 = 7;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    modified: u64,
+    size: u64,
+    track: TrackInfo,
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    entries: &'a HashMap<String, CacheEntry>,
+}
+
+/// On-disk cache of tag data keyed by file path, invalidated by modification time and size.
+pub struct MetadataCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+    dirty: bool,
+}
+
+impl MetadataCache {
+    pub fn load(path: PathBuf) -> MetadataCache {
+        let entries = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheFile>(BufReader::new(file)).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .map(|cache| cache.entries)
+            .unwrap_or_default();
+
+        MetadataCache {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    pub fn get_track_info(&mut self, path: &str, index: usize) -> TrackInfo {
+        // `file://` URIs are queued as plain paths, which everything local expects.
+        let path = &path_from_uri(path);
+        let Some((modified, size)) = file_stamp(Path::new(path)) else {
+            return get_track_info_from_path(path, index);
+        };
+
+        if let Some(entry) = self.entries.get(path) {
+            if entry.modified == modified && entry.size == size {
+                let mut track = entry.track.clone();
+                track.index = index;
+                return track;
+            }
+        }
+
+        let track = get_track_info_from_path(path, index);
+        self.entries.insert(
+            path.to_string(),
+            CacheEntry {
+                modified,
+                size,
+                track: track.clone(),
+            },
+        );
+        self.dirty = true;
+
+        track
+    }
+
+    pub fn save(&mut self) -> Result<(), AudioError> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = File::create(&self.path)?;
+        serde_json::to_writer(
+            BufWriter::new(file),
+            &CacheFileRef {
+                version: CACHE_VERSION,
+                entries: &self.entries,
+            },
+        )?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_millis() as u64;
+
+    Some((modified, metadata.len()))
+}
@@ -0,0 +1,351 @@
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use rust_cast::channels::media::{IdleReason, Media, PlayerState, StreamType};
+use rust_cast::channels::receiver::CastDeviceApp;
+use rust_cast::CastDevice;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::audio_player::{AudioError, AudioPlayer, TrackInfo};
+use crate::media_server::{self, MediaServer, ServedFile};
+
+const SERVICE_TYPE: &str = "_googlecast._tcp.local.";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum ChromecastError {
+    #[error("Cast error: {0}")]
+    CastError(String),
+
+    #[error("Discovery failed: {0}")]
+    DiscoveryError(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Cast device not found")]
+    DeviceNotFound,
+
+    #[error("Cast session ended before it was ready")]
+    SessionClosed,
+
+    #[error("Player error: {0}")]
+    PlayerError(#[from] AudioError),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+impl From<rust_cast::errors::Error> for ChromecastError {
+    fn from(e: rust_cast::errors::Error) -> Self {
+        ChromecastError::CastError(e.to_string())
+    }
+}
+
+/// Commands the audio thread mirrors to an active cast session.
+#[derive(Debug, Clone)]
+pub enum CastCommand {
+    Load {
+        track: TrackInfo,
+        position: u64,
+        autoplay: bool,
+    },
+    Play,
+    Pause,
+    Seek(u64),
+    Volume(f32),
+    Stop,
+}
+
+/// Held in the audio state while a cast session is active; local output stays paused meanwhile.
+pub struct CastOutput {
+    pub sender: mpsc::Sender<CastCommand>,
+    pub playing: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CastDeviceInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Clone)]
+struct DeviceEntry {
+    info: CastDeviceInfo,
+    address: SocketAddr,
+}
+
+#[derive(Default)]
+pub struct Chromecast {
+    devices: Mutex<HashMap<String, DeviceEntry>>,
+}
+
+impl Chromecast {
+    pub fn discover(&self) -> Result<Vec<CastDeviceInfo>, ChromecastError> {
+        let daemon =
+            ServiceDaemon::new().map_err(|e| ChromecastError::DiscoveryError(e.to_string()))?;
+        let events = daemon
+            .browse(SERVICE_TYPE)
+            .map_err(|e| ChromecastError::DiscoveryError(e.to_string()))?;
+
+        let mut found = HashMap::new();
+        let started = Instant::now();
+
+        while let Some(remaining) = DISCOVERY_TIMEOUT.checked_sub(started.elapsed()) {
+            let Ok(event) = events.recv_timeout(remaining) else {
+                break;
+            };
+
+            if let ServiceEvent::ServiceResolved(info) = event {
+                let Some(ip) = info.get_addresses_v4().into_iter().next() else {
+                    continue;
+                };
+
+                let id = info
+                    .get_property_val_str("id")
+                    .unwrap_or(info.get_fullname())
+                    .to_string();
+                let name = info
+                    .get_property_val_str("fn")
+                    .unwrap_or("Chromecast")
+                    .to_string();
+
+                found.insert(
+                    id.clone(),
+                    DeviceEntry {
+                        info: CastDeviceInfo { id, name },
+                        address: SocketAddr::new(IpAddr::V4(*ip), info.get_port()),
+                    },
+                );
+            }
+        }
+
+        let _ = daemon.shutdown();
+
+        let infos = found.values().map(|entry| entry.info.clone()).collect();
+        *self
+            .devices
+            .lock()
+            .map_err(|_| ChromecastError::LockError)? = found;
+
+        Ok(infos)
+    }
+
+    /// Launches the default media receiver on the device and routes playback to it.
+    pub fn connect(&self, player: &AudioPlayer, device_id: &str) -> Result<(), ChromecastError> {
+        let entry = self
+            .devices
+            .lock()
+            .map_err(|_| ChromecastError::LockError)?
+            .get(device_id)
+            .cloned()
+            .ok_or(ChromecastError::DeviceNotFound)?;
+
+        let server = media_server::shared();
+        let (sender, receiver) = mpsc::channel();
+        let (ready_sender, ready) = mpsc::channel();
+        let session_player = player.clone();
+
+        thread::spawn(move || {
+            run_session(
+                entry.address,
+                server,
+                receiver,
+                session_player,
+                ready_sender,
+            );
+        });
+
+        ready.recv().map_err(|_| ChromecastError::SessionClosed)??;
+        player.start_cast(sender)?;
+
+        Ok(())
+    }
+
+    pub fn disconnect(&self, player: &AudioPlayer) -> Result<(), ChromecastError> {
+        player.stop_cast()?;
+        Ok(())
+    }
+}
+
+struct CastSession {
+    device: CastDevice<'static>,
+    address: SocketAddr,
+    transport_id: String,
+    session_id: String,
+    media_session_id: Option<i32>,
+    /// The loaded track on the media server; replaced by the next load.
+    served: Option<ServedFile>,
+}
+
+fn run_session(
+    address: SocketAddr,
+    server: Arc<MediaServer>,
+    commands: mpsc::Receiver<CastCommand>,
+    player: AudioPlayer,
+    ready: mpsc::Sender<Result<(), ChromecastError>>,
+) {
+    let mut session = match CastSession::open(address) {
+        Ok(session) => {
+            let _ = ready.send(Ok(()));
+            session
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        match commands.recv_timeout(POLL_INTERVAL) {
+            Ok(CastCommand::Stop) | Err(RecvTimeoutError::Disconnected) => {
+                session.close();
+                return;
+            }
+            Ok(command) => {
+                if let Err(e) = session.apply(command, &server) {
+                    log::error!("{}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        match session.poll() {
+            Ok(true) => {
+                let _ = player.next();
+            }
+            Ok(false) => {}
+            Err(e) => {
+                // The device went away; hand playback back to the local output.
+                log::error!("{}", e);
+                let _ = player.stop_cast();
+                return;
+            }
+        }
+    }
+}
+
+impl CastSession {
+    fn open(address: SocketAddr) -> Result<CastSession, ChromecastError> {
+        let device = CastDevice::connect_without_host_verification(
+            address.ip().to_string(),
+            address.port(),
+        )?;
+        device.connection.connect("receiver-0")?;
+        device.heartbeat.ping()?;
+
+        let app = device
+            .receiver
+            .launch_app(&CastDeviceApp::DefaultMediaReceiver)?;
+        device.connection.connect(app.transport_id.as_str())?;
+
+        Ok(CastSession {
+            device,
+            address,
+            transport_id: app.transport_id,
+            session_id: app.session_id,
+            media_session_id: None,
+            served: None,
+        })
+    }
+
+    fn apply(&mut self, command: CastCommand, server: &MediaServer) -> Result<(), ChromecastError> {
+        match command {
+            CastCommand::Load {
+                track,
+                position,
+                autoplay,
+            } => {
+                let path = Path::new(&track.path);
+                let host = media_server::local_ip_towards(self.address)?;
+                let served = server.url_for(path, host)?;
+
+                let status = self.device.media.load(
+                    self.transport_id.as_str(),
+                    self.session_id.as_str(),
+                    &Media {
+                        content_id: served.url().to_string(),
+                        content_type: media_server::mime_type(path).to_string(),
+                        stream_type: StreamType::Buffered,
+                        duration: Some(track.duration as f32),
+                        metadata: None,
+                    },
+                )?;
+                self.media_session_id = status.entries.first().map(|entry| entry.media_session_id);
+                self.served = Some(served);
+
+                if position > 0 {
+                    self.apply(CastCommand::Seek(position), server)?;
+                }
+                if !autoplay {
+                    self.apply(CastCommand::Pause, server)?;
+                }
+            }
+            CastCommand::Play => {
+                if let Some(id) = self.media_session_id {
+                    self.device.media.play(self.transport_id.as_str(), id)?;
+                }
+            }
+            CastCommand::Pause => {
+                if let Some(id) = self.media_session_id {
+                    self.device.media.pause(self.transport_id.as_str(), id)?;
+                }
+            }
+            CastCommand::Seek(position) => {
+                if let Some(id) = self.media_session_id {
+                    self.device.media.seek(
+                        self.transport_id.as_str(),
+                        id,
+                        Some(position as f32),
+                        None,
+                    )?;
+                }
+            }
+            CastCommand::Volume(volume) => {
+                self.device.receiver.set_volume(volume.min(1.0))?;
+            }
+            CastCommand::Stop => self.close(),
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the connection alive and reports whether the loaded track just finished.
+    fn poll(&mut self) -> Result<bool, ChromecastError> {
+        self.device.heartbeat.ping()?;
+
+        let Some(id) = self.media_session_id else {
+            return Ok(false);
+        };
+
+        let status = self
+            .device
+            .media
+            .get_status(self.transport_id.as_str(), Some(id))?;
+        // A status without media says nothing about the track, so only an explicit finish
+        // moves the queue on.
+        let finished = status.entries.first().is_some_and(|entry| {
+            matches!(entry.player_state, PlayerState::Idle)
+                && matches!(entry.idle_reason, Some(IdleReason::Finished))
+        });
+
+        if finished {
+            self.media_session_id = None;
+        }
+
+        Ok(finished)
+    }
+
+    fn close(&mut self) {
+        let _ = self.device.receiver.stop_app(self.session_id.as_str());
+        self.media_session_id = None;
+    }
+}
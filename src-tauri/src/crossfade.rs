@@ -0,0 +1,251 @@
+use rodio::buffer::SamplesBuffer;
+use rodio::source::SeekError;
+use rodio::Source;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::audio_player::TrackInfo;
+
+pub type BoxedSource = Box<dyn Source<Item = i16> + Send>;
+
+/// Fade applied around a seek, so jumping mid-waveform doesn't click.
+pub const SEEK_RAMP: Duration = Duration::from_millis(5);
+
+/// A track's decoded audio, shared so the next track can take over whatever is left of it.
+pub type Slot = Arc<Mutex<Option<BoxedSource>>>;
+
+/// Nanoseconds of a track left to play once its decoder has run dry, `u64::MAX` before then.
+pub type Remaining = Arc<AtomicU64>;
+
+/// Plays the track in `incoming`, mixing in the rest of the previous track while fading
+/// between the two. Formats aren't converted, so an outgoing track with a different channel
+/// count or sample rate is cut off instead.
+pub struct Crossfade {
+    incoming: Slot,
+    outgoing: Option<BoxedSource>,
+    channels: u16,
+    sample_rate: u32,
+    fade_frames: u64,
+    frame: u64,
+    channel: u16,
+}
+
+impl Crossfade {
+    pub fn new(incoming: Slot, outgoing: Option<BoxedSource>, fade: Duration) -> Crossfade {
+        let (channels, sample_rate) = lock(&incoming)
+            .as_ref()
+            .map(|source| (source.channels().max(1), source.sample_rate()))
+            .unwrap_or((2, 44100));
+
+        let outgoing = outgoing
+            .filter(|source| source.channels() == channels && source.sample_rate() == sample_rate);
+        let fade_frames = match outgoing {
+            Some(_) => fade.as_millis() as u64 * sample_rate as u64 / 1000,
+            None => 0,
+        };
+
+        Crossfade {
+            incoming,
+            outgoing,
+            channels,
+            sample_rate,
+            fade_frames,
+            frame: 0,
+            channel: 0,
+        }
+    }
+}
+
+fn lock(slot: &Slot) -> MutexGuard<'_, Option<BoxedSource>> {
+    slot.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Iterator for Crossfade {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        // The slot is emptied when the next track takes over, which ends this source.
+        let sample = lock(&self.incoming).as_mut()?.next()?;
+
+        let mut mixed = sample as f32;
+        if self.frame < self.fade_frames {
+            let progress = self.frame as f32 / self.fade_frames as f32;
+            mixed *= progress;
+
+            match self.outgoing.as_mut().and_then(|source| source.next()) {
+                Some(old) => mixed += old as f32 * (1.0 - progress),
+                None => self.outgoing = None,
+            }
+        } else {
+            self.outgoing = None;
+        }
+
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.frame += 1;
+        }
+
+        Some(mixed.clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl Source for Crossfade {
+    fn current_frame_len(&self) -> Option<usize> {
+        lock(&self.incoming)
+            .as_ref()
+            .map_or(Some(0), |source| source.current_frame_len())
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        lock(&self.incoming)
+            .as_ref()
+            .and_then(|source| source.total_duration())
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let mut incoming = lock(&self.incoming);
+        let Some(source) = incoming.as_mut() else {
+            return Ok(());
+        };
+        let ramp_frames = SEEK_RAMP.as_micros() as u64 * self.sample_rate as u64 / 1_000_000;
+
+        // The audio from before the jump fades out under the audio after it. The rest of a
+        // part-played frame is dropped so the channels stay lined up.
+        let partial = (self.channels - self.channel) % self.channels;
+        for _ in 0..partial {
+            source.next();
+        }
+        let tail: Vec<i16> = source
+            .by_ref()
+            .take(ramp_frames as usize * self.channels as usize)
+            .collect();
+        source.try_seek(pos)?;
+
+        self.outgoing = Some(Box::new(SamplesBuffer::new(
+            self.channels,
+            self.sample_rate,
+            tail,
+        )));
+        self.fade_frames = ramp_frames;
+        self.frame = 0;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+/// Decodes up to `window` ahead of what's played, so the time left is known from the samples
+/// themselves once the decoder runs dry rather than from the tagged duration. Two samples are
+/// decoded per sample played until the window is full, so a track doesn't start with a burst
+/// of decoding.
+pub struct Lookahead<S> {
+    inner: S,
+    ahead: VecDeque<i16>,
+    window: usize,
+    channels: u16,
+    sample_rate: u32,
+    ended: bool,
+    remaining: Remaining,
+}
+
+impl<S: Source<Item = i16>> Lookahead<S> {
+    pub fn new(inner: S, window: Duration, remaining: Remaining) -> Lookahead<S> {
+        let channels = inner.channels().max(1);
+        let sample_rate = inner.sample_rate();
+
+        Lookahead {
+            window: (window.as_secs_f64() * sample_rate as f64) as usize * channels as usize,
+            inner,
+            ahead: VecDeque::new(),
+            channels,
+            sample_rate,
+            ended: false,
+            remaining,
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Lookahead<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let pulls = if self.ahead.len() < self.window { 2 } else { 1 };
+        for _ in 0..pulls {
+            if self.ended {
+                break;
+            }
+            match self.inner.next() {
+                Some(sample) => self.ahead.push_back(sample),
+                None => self.ended = true,
+            }
+        }
+
+        let sample = self.ahead.pop_front();
+        if self.ended {
+            let frames = (self.ahead.len() / self.channels as usize) as u64;
+            let nanos = frames * 1_000_000_000 / self.sample_rate.max(1) as u64;
+            self.remaining.store(nanos, Ordering::Relaxed);
+        }
+        sample
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Lookahead<S> {
+    // The buffered samples shift the inner source's frames, so the format is taken to hold
+    // for the whole track, as `Crossfade` does.
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.ahead.clear();
+        self.ended = false;
+        self.remaining.store(u64::MAX, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Whether `next` directly follows `track` on the same album, where a crossfade would break
+/// a gapless transition such as a live album or a DJ mix.
+pub fn continues_album(track: &TrackInfo, next: &TrackInfo) -> bool {
+    let folder = |track: &TrackInfo| Path::new(&track.path).parent().map(Path::to_path_buf);
+    if track.album != next.album || folder(track) != folder(next) {
+        return false;
+    }
+
+    match (track.track_number, next.track_number) {
+        (Some(number), Some(next_number)) => {
+            let same_disc = track.disc_number == next.disc_number;
+            let next_disc = next.disc_number.is_some()
+                && next.disc_number == track.disc_number.map(|disc| disc + 1);
+
+            (same_disc && next_number == number + 1) || (next_disc && next_number == 1)
+        }
+        // Without track numbers, album order is the best guess.
+        _ => true,
+    }
+}
@@ -0,0 +1,118 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A single decoder read this slow has almost certainly left the output without samples.
+const STALL_THRESHOLD: Duration = Duration::from_millis(20);
+
+static BUFFER_UNDERRUNS: AtomicU64 = AtomicU64::new(0);
+static BUFFER_WAIT_MS: AtomicU64 = AtomicU64::new(0);
+static DECODE_STALLS: AtomicU64 = AtomicU64::new(0);
+static DECODE_STALL_MS: AtomicU64 = AtomicU64::new(0);
+static OUTPUT_XRUNS: AtomicU64 = AtomicU64::new(0);
+
+/// Playback hiccups counted since the app started, for debugging stutter reports.
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioDiagnostics {
+    /// Times the read-ahead buffer ran dry and playback waited for the disk or network.
+    pub buffer_underruns: u64,
+    pub buffer_wait_ms: u64,
+    /// Times decoding a sample took long enough for the output to run out.
+    pub decode_stalls: u64,
+    pub decode_stall_ms: u64,
+    /// Overruns and underruns reported by the audio server (JACK only).
+    pub output_xruns: u64,
+}
+
+/// Payload of `decode-stall`.
+#[derive(Serialize, Clone, Debug)]
+pub struct DecodeStall {
+    pub path: String,
+    pub stalled_ms: u64,
+}
+
+pub fn snapshot() -> AudioDiagnostics {
+    AudioDiagnostics {
+        buffer_underruns: BUFFER_UNDERRUNS.load(Ordering::Relaxed),
+        buffer_wait_ms: BUFFER_WAIT_MS.load(Ordering::Relaxed),
+        decode_stalls: DECODE_STALLS.load(Ordering::Relaxed),
+        decode_stall_ms: DECODE_STALL_MS.load(Ordering::Relaxed),
+        output_xruns: OUTPUT_XRUNS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn record_underrun(waited: Duration) {
+    BUFFER_UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+    BUFFER_WAIT_MS.fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn record_xrun() {
+    OUTPUT_XRUNS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Source adapter that times each read from the decoder and reports reads slower than
+/// `STALL_THRESHOLD` to `on_stall`, besides counting them.
+pub struct StallDetector<S, F> {
+    inner: S,
+    on_stall: F,
+}
+
+impl<S, F> StallDetector<S, F>
+where
+    S: Source<Item = i16>,
+    F: FnMut(Duration),
+{
+    pub fn new(inner: S, on_stall: F) -> StallDetector<S, F> {
+        StallDetector { inner, on_stall }
+    }
+}
+
+impl<S, F> Iterator for StallDetector<S, F>
+where
+    S: Source<Item = i16>,
+    F: FnMut(Duration),
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let started = Instant::now();
+        let sample = self.inner.next();
+
+        let took = started.elapsed();
+        if took >= STALL_THRESHOLD {
+            DECODE_STALLS.fetch_add(1, Ordering::Relaxed);
+            DECODE_STALL_MS.fetch_add(took.as_millis() as u64, Ordering::Relaxed);
+            (self.on_stall)(took);
+        }
+        sample
+    }
+}
+
+impl<S, F> Source for StallDetector<S, F>
+where
+    S: Source<Item = i16>,
+    F: FnMut(Duration),
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
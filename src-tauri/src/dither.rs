@@ -0,0 +1,121 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Time taken to move between the gain and unity when the bypass is switched, so the
+/// switch doesn't click.
+const RAMP: Duration = Duration::from_millis(5);
+
+/// Source adapter that applies a gain in floating point and rounds the result back to 16
+/// bits. While `dither` is set, triangular (TPDF) noise of one step is added before rounding,
+/// so quiet passages fade into a faint hiss instead of quantization distortion. While
+/// `bypass` is set, samples pass through untouched once the gain has ramped back to unity.
+///
+/// This is the only requantization the player does itself, so it's the only one dithered.
+/// Rodio's decoder already hands over 16-bit samples, truncating 24-bit sources without
+/// dither, and the output stream does its own conversion to the device's format. A track at
+/// unity gain is therefore never dithered.
+pub struct Gain<S> {
+    inner: S,
+    factor: f32,
+    dither: Arc<AtomicBool>,
+    bypass: Arc<AtomicBool>,
+    /// Gain applied to the current sample, ramping towards `factor` or unity.
+    level: f32,
+    state: u32,
+}
+
+impl<S> Gain<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(inner: S, factor: f32, dither: Arc<AtomicBool>, bypass: Arc<AtomicBool>) -> Gain<S> {
+        let level = if bypass.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            factor
+        };
+        Gain {
+            inner,
+            factor,
+            dither,
+            bypass,
+            level,
+            state: 0x9E37_79B9,
+        }
+    }
+
+    /// Uniform noise in [0, 1) from a xorshift generator, cheap enough for the audio thread.
+    fn noise(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+impl<S> Iterator for Gain<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+
+        let target = if self.bypass.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            self.factor
+        };
+        if self.level != target {
+            let samples =
+                RAMP.as_secs_f32() * self.inner.sample_rate() as f32 * self.inner.channels() as f32;
+            let step = (self.factor - 1.0).abs() / samples.max(1.0);
+            self.level = if self.level < target {
+                (self.level + step).min(target)
+            } else {
+                (self.level - step).max(target)
+            };
+        }
+
+        // Unity gain leaves the samples exact, so there is nothing to requantize.
+        if self.level == 1.0 {
+            return Some(sample);
+        }
+
+        let mut scaled = sample as f32 * self.level;
+        if self.dither.load(Ordering::Relaxed) {
+            // The difference of two uniform values has a triangular distribution.
+            scaled += self.noise() - self.noise();
+        }
+        Some(scaled.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl<S> Source for Gain<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
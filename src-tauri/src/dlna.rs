@@ -0,0 +1,435 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::audio_player::{AudioError, AudioPlayer, TrackInfo};
+use crate::media_server::{self, MediaServer, ServedFile};
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+pub(crate) const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum DlnaError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("HTTP request failed: {0}")]
+    HttpError(String),
+
+    #[error("Invalid device description: {0}")]
+    XmlError(#[from] roxmltree::Error),
+
+    #[error("Device not found")]
+    DeviceNotFound,
+
+    #[error("Nothing is playing")]
+    NothingPlaying,
+
+    #[error("Player error: {0}")]
+    PlayerError(#[from] AudioError),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+impl From<ureq::Error> for DlnaError {
+    fn from(e: ureq::Error) -> Self {
+        DlnaError::HttpError(e.to_string())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct UpnpDeviceInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// A UPnP device together with the control URL of the service it was discovered for.
+#[derive(Clone, Debug)]
+pub(crate) struct UpnpDevice {
+    pub info: UpnpDeviceInfo,
+    pub address: SocketAddr,
+    pub control_url: String,
+}
+
+struct CastSession {
+    renderer: UpnpDevice,
+    stop: Arc<AtomicBool>,
+}
+
+/// Discovers UPnP AVTransport renderers and hands the current queue over to one of them.
+#[derive(Default)]
+pub struct DlnaCaster {
+    renderers: Mutex<HashMap<String, UpnpDevice>>,
+    session: Mutex<Option<CastSession>>,
+}
+
+impl DlnaCaster {
+    pub fn list_renderers(&self) -> Result<Vec<UpnpDeviceInfo>, DlnaError> {
+        let found = discover(AV_TRANSPORT, DISCOVERY_TIMEOUT)?;
+        let infos = found.iter().map(|r| r.info.clone()).collect();
+
+        let mut renderers = self.renderers.lock().map_err(|_| DlnaError::LockError)?;
+        *renderers = found.into_iter().map(|r| (r.info.id.clone(), r)).collect();
+
+        Ok(infos)
+    }
+
+    /// Sends the current track (and the rest of the queue) to the renderer and pauses local playback.
+    pub fn cast_to(&self, player: &AudioPlayer, renderer_id: &str) -> Result<(), DlnaError> {
+        let renderer = self
+            .renderers
+            .lock()
+            .map_err(|_| DlnaError::LockError)?
+            .get(renderer_id)
+            .cloned()
+            .ok_or(DlnaError::DeviceNotFound)?;
+
+        let snapshot = player.get_state()?;
+        self.stop_cast()?;
+
+        let media_server = media_server::shared();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let track = snapshot
+            .queue
+            .get(snapshot.current_index)
+            .ok_or(DlnaError::NothingPlaying)?;
+        let served = play_on_renderer(&renderer, &media_server, track)?;
+        if snapshot.position > 0 {
+            soap_action(
+                &renderer.control_url,
+                AV_TRANSPORT,
+                "Seek",
+                &[
+                    ("InstanceID", "0"),
+                    ("Unit", "REL_TIME"),
+                    ("Target", &format_time(snapshot.position)),
+                ],
+            )?;
+        }
+        player.pause()?;
+
+        let session_renderer = renderer.clone();
+        let session_stop = stop.clone();
+        thread::spawn(move || {
+            follow_queue(
+                session_renderer,
+                media_server,
+                snapshot.queue,
+                snapshot.current_index,
+                snapshot.looped,
+                served,
+                session_stop,
+            );
+        });
+
+        *self.session.lock().map_err(|_| DlnaError::LockError)? =
+            Some(CastSession { renderer, stop });
+        Ok(())
+    }
+
+    pub fn stop_cast(&self) -> Result<(), DlnaError> {
+        let session = self
+            .session
+            .lock()
+            .map_err(|_| DlnaError::LockError)?
+            .take();
+
+        if let Some(session) = session {
+            session.stop.store(true, Ordering::SeqCst);
+            soap_action(
+                &session.renderer.control_url,
+                AV_TRANSPORT,
+                "Stop",
+                &[("InstanceID", "0")],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Polls the renderer and feeds it the next queue entry whenever a track finishes. Only the
+/// track the renderer was last given is left on the media server.
+#[allow(clippy::too_many_arguments)]
+fn follow_queue(
+    renderer: UpnpDevice,
+    media_server: Arc<MediaServer>,
+    queue: Vec<TrackInfo>,
+    mut index: usize,
+    looped: bool,
+    mut _served: ServedFile,
+    stop: Arc<AtomicBool>,
+) {
+    let mut was_playing = false;
+
+    while !stop.load(Ordering::SeqCst) {
+        thread::sleep(POLL_INTERVAL);
+
+        let transport_state = match soap_action(
+            &renderer.control_url,
+            AV_TRANSPORT,
+            "GetTransportInfo",
+            &[("InstanceID", "0")],
+        ) {
+            Ok(body) => extract_tag(&body, "CurrentTransportState").unwrap_or_default(),
+            Err(e) => {
+                log::error!("{}", e);
+                continue;
+            }
+        };
+
+        match transport_state.as_str() {
+            "PLAYING" | "TRANSITIONING" => was_playing = true,
+            "STOPPED" | "NO_MEDIA_PRESENT" if was_playing => {
+                was_playing = false;
+                index += 1;
+                if index >= queue.len() {
+                    if !looped {
+                        break;
+                    }
+                    index = 0;
+                }
+
+                match play_on_renderer(&renderer, &media_server, &queue[index]) {
+                    Ok(next) => _served = next,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn play_on_renderer(
+    renderer: &UpnpDevice,
+    server: &MediaServer,
+    track: &TrackInfo,
+) -> Result<ServedFile, DlnaError> {
+    let path = Path::new(&track.path);
+    let host = media_server::local_ip_towards(renderer.address)?;
+    let served = server.url_for(path, host)?;
+    let url = served.url();
+    let metadata = didl_metadata(track, url, media_server::mime_type(path));
+
+    soap_action(
+        &renderer.control_url,
+        AV_TRANSPORT,
+        "SetAVTransportURI",
+        &[
+            ("InstanceID", "0"),
+            ("CurrentURI", url),
+            ("CurrentURIMetaData", &metadata),
+        ],
+    )?;
+    soap_action(
+        &renderer.control_url,
+        AV_TRANSPORT,
+        "Play",
+        &[("InstanceID", "0"), ("Speed", "1")],
+    )?;
+
+    Ok(served)
+}
+
+/// Searches the LAN via SSDP for devices offering `service_type`.
+pub(crate) fn discover(
+    service_type: &str,
+    timeout: Duration,
+) -> Result<Vec<UpnpDevice>, DlnaError> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        SSDP_ADDR, service_type
+    );
+    socket.send_to(request.as_bytes(), SSDP_ADDR)?;
+
+    let mut locations = Vec::new();
+    let mut buf = [0u8; 2048];
+    let started = Instant::now();
+
+    while started.elapsed() < timeout {
+        let Ok((len, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+
+        let response = String::from_utf8_lossy(&buf[..len]);
+        let location = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        });
+
+        if let Some(location) = location {
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+    }
+
+    let devices = locations
+        .iter()
+        .filter_map(|location| match describe(location, service_type) {
+            Ok(device) => device,
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// Fetches a device description and extracts its name, UDN and the control URL of `service_type`.
+fn describe(location: &str, service_type: &str) -> Result<Option<UpnpDevice>, DlnaError> {
+    let body = ureq::get(location)
+        .timeout(Duration::from_secs(3))
+        .call()?
+        .into_string()?;
+    let document = roxmltree::Document::parse(&body)?;
+
+    let root = document.root();
+    let service = root.descendants().find(|n| {
+        n.has_tag_name("service") && text_of(*n, "serviceType").as_deref() == Some(service_type)
+    });
+
+    let (Some(service), Some(id)) = (service, text_of(root, "UDN")) else {
+        return Ok(None);
+    };
+    let Some(control_path) = text_of(service, "controlURL") else {
+        return Ok(None);
+    };
+
+    let base = text_of(root, "URLBase").unwrap_or_else(|| location.to_string());
+    let address = url_host(&base)
+        .and_then(|host| host.to_socket_addrs().ok())
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| DlnaError::HttpError(format!("Invalid location {}", location)))?;
+
+    Ok(Some(UpnpDevice {
+        info: UpnpDeviceInfo {
+            id,
+            name: text_of(root, "friendlyName").unwrap_or_else(|| "Unknown Device".to_string()),
+        },
+        address,
+        control_url: resolve_url(&base, &control_path),
+    }))
+}
+
+pub(crate) fn text_of(node: roxmltree::Node, name: &str) -> Option<String> {
+    node.descendants()
+        .find(|n| n.has_tag_name(name))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+}
+
+pub(crate) fn soap_action(
+    control_url: &str,
+    service_type: &str,
+    action: &str,
+    args: &[(&str, &str)],
+) -> Result<String, DlnaError> {
+    let arguments: String = args
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, xml_escape(value)))
+        .collect();
+
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{0} xmlns:u=\"{1}\">{2}</u:{0}></s:Body></s:Envelope>",
+        action, service_type, arguments
+    );
+
+    let response = ureq::post(control_url)
+        .timeout(Duration::from_secs(5))
+        .set("Content-Type", "text/xml; charset=\"utf-8\"")
+        .set("SOAPAction", &format!("\"{}#{}\"", service_type, action))
+        .send_string(&body)?
+        .into_string()?;
+
+    Ok(response)
+}
+
+fn didl_metadata(track: &TrackInfo, url: &str, mime: &str) -> String {
+    format!(
+        "<DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\">\
+         <item id=\"0\" parentID=\"-1\" restricted=\"1\">\
+         <dc:title>{}</dc:title><upnp:artist>{}</upnp:artist><upnp:album>{}</upnp:album>\
+         <upnp:class>object.item.audioItem.musicTrack</upnp:class>\
+         <res protocolInfo=\"http-get:*:{}:*\">{}</res></item></DIDL-Lite>",
+        xml_escape(&track.title),
+        xml_escape(&track.artist),
+        xml_escape(&track.album),
+        mime,
+        xml_escape(url)
+    )
+}
+
+pub(crate) fn extract_tag(body: &str, name: &str) -> Option<String> {
+    let document = roxmltree::Document::parse(body).ok()?;
+    document
+        .descendants()
+        .find(|n| n.has_tag_name(name))
+        .and_then(|n| n.text())
+        .map(|t| t.to_string())
+}
+
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Returns `host:port` from an `http://host:port/...` URL.
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("http://")?;
+    let host = rest.split('/').next()?;
+
+    if host.contains(':') {
+        Some(host.to_string())
+    } else {
+        Some(format!("{}:80", host))
+    }
+}
+
+pub(crate) fn resolve_url(base: &str, path: &str) -> String {
+    if path.starts_with("http://") {
+        return path.to_string();
+    }
+
+    let host = url_host(base).unwrap_or_default();
+    format!("http://{}/{}", host, path.trim_start_matches('/'))
+}
+
+fn format_time(seconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
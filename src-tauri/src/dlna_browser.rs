@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::audio_player::TrackInfo;
+use crate::dlna::{self, DlnaError, UpnpDevice, UpnpDeviceInfo, DISCOVERY_TIMEOUT};
+use crate::util::SourceKind;
+
+const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+const PAGE_SIZE: u32 = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaEntryKind {
+    Container,
+    Item,
+}
+
+/// One child of a content-directory container, as shown in the browser.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MediaEntry {
+    pub id: String,
+    pub kind: MediaEntryKind,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: u64,
+    pub track_number: Option<u32>,
+    pub url: Option<String>,
+}
+
+impl MediaEntry {
+    pub fn to_track_info(&self) -> Option<TrackInfo> {
+        let path = self.url.clone()?;
+        Some(TrackInfo {
+            index: 0,
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            duration: self.duration,
+            track_number: self.track_number,
+            disc_number: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            artists: Vec::new(),
+            genres: Vec::new(),
+            compilation: false,
+            composer: None,
+            conductor: None,
+            performer: None,
+            work: None,
+            movement: None,
+            source_kind: SourceKind::of(&path),
+            path,
+        })
+    }
+}
+
+/// Content-directory client for browsing NAS media servers on the LAN.
+#[derive(Default)]
+pub struct DlnaBrowser {
+    servers: Mutex<HashMap<String, UpnpDevice>>,
+}
+
+impl DlnaBrowser {
+    pub fn list_servers(&self) -> Result<Vec<UpnpDeviceInfo>, DlnaError> {
+        let found = dlna::discover(CONTENT_DIRECTORY, DISCOVERY_TIMEOUT)?;
+        let infos = found.iter().map(|server| server.info.clone()).collect();
+
+        let mut servers = self.servers.lock().map_err(|_| DlnaError::LockError)?;
+        *servers = found
+            .into_iter()
+            .map(|server| (server.info.id.clone(), server))
+            .collect();
+
+        Ok(infos)
+    }
+
+    /// Lists the children of `object_id` ("0" is the root container).
+    pub fn browse(&self, server_id: &str, object_id: &str) -> Result<Vec<MediaEntry>, DlnaError> {
+        let server = self
+            .servers
+            .lock()
+            .map_err(|_| DlnaError::LockError)?
+            .get(server_id)
+            .cloned()
+            .ok_or(DlnaError::DeviceNotFound)?;
+
+        let mut entries = Vec::new();
+        let mut start = 0;
+
+        loop {
+            let start_index = start.to_string();
+            let page_size = PAGE_SIZE.to_string();
+            let response = dlna::soap_action(
+                &server.control_url,
+                CONTENT_DIRECTORY,
+                "Browse",
+                &[
+                    ("ObjectID", object_id),
+                    ("BrowseFlag", "BrowseDirectChildren"),
+                    ("Filter", "*"),
+                    ("StartingIndex", &start_index),
+                    ("RequestedCount", &page_size),
+                    ("SortCriteria", ""),
+                ],
+            )?;
+
+            let didl = dlna::extract_tag(&response, "Result").unwrap_or_default();
+            let returned: u32 = dlna::extract_tag(&response, "NumberReturned")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            let total: u32 = dlna::extract_tag(&response, "TotalMatches")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+
+            entries.extend(parse_didl(&didl)?);
+            start += returned;
+
+            if returned == 0 || start >= total {
+                break;
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+fn parse_didl(didl: &str) -> Result<Vec<MediaEntry>, DlnaError> {
+    if didl.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let document = roxmltree::Document::parse(didl)?;
+    let entries = document
+        .root_element()
+        .children()
+        .filter(|node| node.is_element())
+        .filter_map(|node| {
+            let kind = match node.tag_name().name() {
+                "container" => MediaEntryKind::Container,
+                "item" => {
+                    let class = dlna::text_of(node, "class").unwrap_or_default();
+                    if !class.starts_with("object.item.audioItem") {
+                        return None;
+                    }
+                    MediaEntryKind::Item
+                }
+                _ => return None,
+            };
+
+            let res = node.children().find(|n| n.has_tag_name("res"));
+
+            Some(MediaEntry {
+                id: node.attribute("id")?.to_string(),
+                kind,
+                title: dlna::text_of(node, "title").unwrap_or_else(|| "Unknown Title".to_string()),
+                artist: dlna::text_of(node, "artist")
+                    .or_else(|| dlna::text_of(node, "creator"))
+                    .unwrap_or_else(|| "Unknown Artist".to_string()),
+                album: dlna::text_of(node, "album").unwrap_or_else(|| "Unknown Album".to_string()),
+                duration: res
+                    .and_then(|res| res.attribute("duration"))
+                    .map(parse_duration)
+                    .unwrap_or(0),
+                track_number: dlna::text_of(node, "originalTrackNumber")
+                    .and_then(|n| n.parse().ok()),
+                url: res
+                    .and_then(|res| res.text())
+                    .map(|url| url.trim().to_string()),
+            })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Parses a DIDL `H+:MM:SS[.F+]` duration into whole seconds.
+fn parse_duration(value: &str) -> u64 {
+    let whole = value.split('.').next().unwrap_or("");
+
+    whole
+        .split(':')
+        .map(|part| part.parse::<u64>().unwrap_or(0))
+        .fold(0, |total, part| total * 60 + part)
+}
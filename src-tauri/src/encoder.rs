@@ -0,0 +1,387 @@
+use mp3lame_encoder::{Bitrate, FlushNoGap, InterleavedPcm, Quality};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use std::io::{self, Write};
+use std::num::{NonZeroU32, NonZeroU8};
+use std::sync::{Arc, Mutex};
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder, VorbisEncoderBuilder};
+
+use crate::tap::AudioBlock;
+
+/// Opus always runs at 48 kHz; input is resampled to it.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+/// 20 ms per Opus packet.
+const OPUS_FRAME_SAMPLES: usize = 960;
+
+const OPUS_SERIAL: u32 = 0x4f707573;
+
+/// Incremental encoder fed with decoded blocks; encoded bytes are appended to `out`.
+pub trait BlockEncoder {
+    fn encode(&mut self, block: &AudioBlock, out: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Flushes buffered audio and closes the stream.
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// All encoders work in stereo; mono is duplicated and extra channels are dropped.
+fn to_stereo(block: &AudioBlock) -> Vec<i16> {
+    let channels = block.channels.max(1) as usize;
+
+    block
+        .samples
+        .chunks_exact(channels)
+        .flat_map(|frame| [frame[0], frame[if channels > 1 { 1 } else { 0 }]])
+        .collect()
+}
+
+fn to_float(sample: i16) -> f32 {
+    sample as f32 / i16::MAX as f32
+}
+
+fn encoder_error<E: ToString>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+pub struct Mp3Encoder {
+    bitrate: Bitrate,
+    encoder: Option<(u32, mp3lame_encoder::Encoder)>,
+}
+
+impl Mp3Encoder {
+    pub fn new(kbps: u32) -> Mp3Encoder {
+        let bitrate = match kbps {
+            0..=96 => Bitrate::Kbps96,
+            97..=128 => Bitrate::Kbps128,
+            129..=160 => Bitrate::Kbps160,
+            161..=192 => Bitrate::Kbps192,
+            193..=256 => Bitrate::Kbps256,
+            _ => Bitrate::Kbps320,
+        };
+
+        Mp3Encoder {
+            bitrate,
+            encoder: None,
+        }
+    }
+
+    fn build(&self, sample_rate: u32) -> io::Result<mp3lame_encoder::Encoder> {
+        let mut builder = mp3lame_encoder::Builder::new()
+            .ok_or_else(|| io::Error::other("Failed to create MP3 encoder"))?;
+        builder.set_num_channels(2).map_err(encoder_error)?;
+        builder
+            .set_sample_rate(sample_rate)
+            .map_err(encoder_error)?;
+        builder.set_brate(self.bitrate).map_err(encoder_error)?;
+        builder.set_quality(Quality::Good).map_err(encoder_error)?;
+
+        builder.build().map_err(encoder_error)
+    }
+}
+
+impl BlockEncoder for Mp3Encoder {
+    fn encode(&mut self, block: &AudioBlock, out: &mut Vec<u8>) -> io::Result<()> {
+        // A block at a different rate starts a fresh MP3 stream.
+        if self.encoder.as_ref().map(|(rate, _)| *rate) != Some(block.sample_rate) {
+            self.finish(out)?;
+            self.encoder = Some((block.sample_rate, self.build(block.sample_rate)?));
+        }
+
+        let Some((_, encoder)) = self.encoder.as_mut() else {
+            return Ok(());
+        };
+
+        let samples = to_stereo(block);
+        out.reserve(mp3lame_encoder::max_required_buffer_size(samples.len() / 2));
+        let written = encoder
+            .encode(InterleavedPcm(&samples), out.spare_capacity_mut())
+            .map_err(encoder_error)?;
+        // SAFETY: the encoder initialized `written` bytes of the spare capacity.
+        unsafe { out.set_len(out.len() + written) };
+
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if let Some((_, mut encoder)) = self.encoder.take() {
+            out.reserve(7200);
+            let written = encoder
+                .flush::<FlushNoGap>(out.spare_capacity_mut())
+                .map_err(encoder_error)?;
+            // SAFETY: the encoder initialized `written` bytes of the spare capacity.
+            unsafe { out.set_len(out.len() + written) };
+        }
+
+        Ok(())
+    }
+}
+
+/// `Write` sink the Ogg based encoders page into; drained after every block.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn drain_into(&self, out: &mut Vec<u8>) {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        out.append(&mut buffer);
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct OggEncoder {
+    kbps: u32,
+    encoder: Option<(u32, VorbisEncoder<SharedBuffer>)>,
+    buffer: SharedBuffer,
+}
+
+impl OggEncoder {
+    pub fn new(kbps: u32) -> OggEncoder {
+        OggEncoder {
+            kbps,
+            encoder: None,
+            buffer: SharedBuffer::default(),
+        }
+    }
+}
+
+impl BlockEncoder for OggEncoder {
+    fn encode(&mut self, block: &AudioBlock, out: &mut Vec<u8>) -> io::Result<()> {
+        // A rate change ends the current logical stream and chains a new one.
+        if self.encoder.as_ref().map(|(rate, _)| *rate) != Some(block.sample_rate) {
+            self.finish(out)?;
+
+            let sample_rate = NonZeroU32::new(block.sample_rate)
+                .ok_or_else(|| io::Error::other("Invalid sample rate"))?;
+            let average_bitrate = NonZeroU32::new(self.kbps.max(32) * 1000).unwrap();
+            let encoder = VorbisEncoderBuilder::new(
+                sample_rate,
+                NonZeroU8::new(2).unwrap(),
+                self.buffer.clone(),
+            )
+            .and_then(|mut builder| {
+                builder
+                    .bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr {
+                        average_bitrate,
+                    })
+                    .build()
+            })
+            .map_err(encoder_error)?;
+            self.encoder = Some((block.sample_rate, encoder));
+        }
+
+        if let Some((_, encoder)) = self.encoder.as_mut() {
+            let samples = to_stereo(block);
+            let planar: [Vec<f32>; 2] = [0, 1].map(|channel| {
+                samples
+                    .iter()
+                    .skip(channel)
+                    .step_by(2)
+                    .map(|&sample| to_float(sample))
+                    .collect()
+            });
+
+            encoder.encode_audio_block(&planar).map_err(encoder_error)?;
+        }
+
+        self.buffer.drain_into(out);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if let Some((_, encoder)) = self.encoder.take() {
+            encoder.finish().map_err(encoder_error)?;
+        }
+
+        self.buffer.drain_into(out);
+        Ok(())
+    }
+}
+
+/// Linear interpolation resampler for interleaved stereo.
+struct Resampler {
+    from: u32,
+    to: u32,
+    position: f64,
+    previous: Option<[f32; 2]>,
+}
+
+impl Resampler {
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut frames: Vec<[f32; 2]> = self.previous.into_iter().collect();
+        frames.extend(input.chunks_exact(2).map(|frame| [frame[0], frame[1]]));
+        if frames.is_empty() {
+            return Vec::new();
+        }
+
+        let step = self.from as f64 / self.to as f64;
+        let mut output = Vec::new();
+        let mut t = self.position;
+
+        while t + 1.0 < frames.len() as f64 {
+            let index = t as usize;
+            let fraction = (t - index as f64) as f32;
+            for channel in 0..2 {
+                let a = frames[index][channel];
+                let b = frames[index + 1][channel];
+                output.push(a + (b - a) * fraction);
+            }
+            t += step;
+        }
+
+        self.position = t - (frames.len() - 1) as f64;
+        self.previous = frames.last().copied();
+        output
+    }
+}
+
+pub struct OpusEncoder {
+    encoder: opus::Encoder,
+    writer: PacketWriter<'static, SharedBuffer>,
+    buffer: SharedBuffer,
+    resampler: Option<Resampler>,
+    pending: Vec<f32>,
+    pre_skip: u64,
+    samples_in: u64,
+    granule: u64,
+    started: bool,
+}
+
+impl OpusEncoder {
+    pub fn new(kbps: u32) -> io::Result<OpusEncoder> {
+        let mut encoder = opus::Encoder::new(
+            OPUS_SAMPLE_RATE,
+            opus::Channels::Stereo,
+            opus::Application::Audio,
+        )
+        .map_err(encoder_error)?;
+        encoder
+            .set_bitrate(opus::Bitrate::Bits(kbps.max(16) as i32 * 1000))
+            .map_err(encoder_error)?;
+        let pre_skip = encoder.get_lookahead().map_err(encoder_error)?.max(0) as u64;
+
+        let buffer = SharedBuffer::default();
+        Ok(OpusEncoder {
+            encoder,
+            writer: PacketWriter::new(buffer.clone()),
+            buffer,
+            resampler: None,
+            pending: Vec::new(),
+            pre_skip,
+            samples_in: 0,
+            granule: pre_skip,
+            started: false,
+        })
+    }
+
+    /// Writes the `OpusHead` and `OpusTags` header packets, each on its own page.
+    fn write_headers(&mut self, input_rate: u32) -> io::Result<()> {
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(2);
+        head.extend_from_slice(&(self.pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&input_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+
+        let vendor = b"music-player";
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        self.writer
+            .write_packet(head, OPUS_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+        self.writer
+            .write_packet(tags, OPUS_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+        Ok(())
+    }
+
+    fn write_frames(&mut self, final_block: bool) -> io::Result<()> {
+        let frame_len = OPUS_FRAME_SAMPLES * 2;
+        if final_block {
+            if self.pending.is_empty() {
+                self.pending.resize(frame_len, 0.0);
+            }
+            let padding = (frame_len - self.pending.len() % frame_len) % frame_len;
+            self.pending.extend(std::iter::repeat(0.0).take(padding));
+        }
+
+        let mut packet = vec![0u8; 4000];
+        let frames = self.pending.len() / frame_len;
+        for i in 0..frames {
+            let frame = &self.pending[i * frame_len..(i + 1) * frame_len];
+            let size = self
+                .encoder
+                .encode_float(frame, &mut packet)
+                .map_err(encoder_error)?;
+
+            let last = final_block && i + 1 == frames;
+            self.granule += OPUS_FRAME_SAMPLES as u64;
+            let (end_info, granule) = if last {
+                // The last page's granule trims the padding added above.
+                (
+                    PacketWriteEndInfo::EndStream,
+                    self.pre_skip + self.samples_in,
+                )
+            } else {
+                (PacketWriteEndInfo::NormalPacket, self.granule)
+            };
+
+            self.writer
+                .write_packet(packet[..size].to_vec(), OPUS_SERIAL, end_info, granule)?;
+        }
+
+        self.pending.drain(..frames * frame_len);
+        Ok(())
+    }
+}
+
+impl BlockEncoder for OpusEncoder {
+    fn encode(&mut self, block: &AudioBlock, out: &mut Vec<u8>) -> io::Result<()> {
+        if !self.started {
+            self.write_headers(block.sample_rate)?;
+            self.started = true;
+        }
+
+        let resampler = self.resampler.get_or_insert(Resampler {
+            from: block.sample_rate,
+            to: OPUS_SAMPLE_RATE,
+            position: 0.0,
+            previous: None,
+        });
+        resampler.from = block.sample_rate;
+
+        let input: Vec<f32> = to_stereo(block).into_iter().map(to_float).collect();
+        let resampled = if block.sample_rate == OPUS_SAMPLE_RATE {
+            input
+        } else {
+            resampler.process(&input)
+        };
+
+        self.samples_in += (resampled.len() / 2) as u64;
+        self.pending.extend(resampled);
+        self.write_frames(false)?;
+
+        self.buffer.drain_into(out);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if self.started {
+            self.write_frames(true)?;
+        }
+
+        self.buffer.drain_into(out);
+        Ok(())
+    }
+}
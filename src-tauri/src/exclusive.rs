@@ -0,0 +1,215 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExclusiveError {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    #[error("CoreAudio error: {0}")]
+    CoreAudioError(String),
+
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    #[error("Exclusive output isn't available on this platform")]
+    Unsupported,
+}
+
+/// Exclusive (hog mode) access to the output device for as long as the output is open, with
+/// the device switched to the track's sample rate so nothing else is mixed in or resampled.
+/// It's taken before the stream is opened, so the stream opens at that rate, and held across
+/// pauses. The device is handed back, at its previous rate, when the output is closed by a
+/// stop or an idle release.
+#[derive(Default)]
+pub struct ExclusiveOutput {
+    pub enabled: bool,
+    guard: Option<platform::Guard>,
+}
+
+impl ExclusiveOutput {
+    /// Takes the device if enabled and not already held, and switches it to `sample_rate`.
+    pub fn acquire(&mut self, sample_rate: Option<u32>) {
+        if !self.enabled {
+            self.release();
+            return;
+        }
+        if let Some(guard) = &mut self.guard {
+            guard.set_rate(sample_rate);
+            return;
+        }
+
+        match platform::Guard::acquire() {
+            Ok(mut guard) => {
+                guard.set_rate(sample_rate);
+                self.guard = Some(guard);
+            }
+            Err(e) => log::error!("Failed to take exclusive output: {}", e),
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.guard = None;
+    }
+}
+
+/// CoreAudio hog mode on the default output device.
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::c_void;
+    use std::{mem, ptr};
+
+    use super::ExclusiveError;
+
+    const SYSTEM_OBJECT: u32 = 1;
+    const SCOPE_GLOBAL: u32 = u32::from_be_bytes(*b"glob");
+    const ELEMENT_MAIN: u32 = 0;
+    const DEFAULT_OUTPUT_DEVICE: u32 = u32::from_be_bytes(*b"dOut");
+    const HOG_MODE: u32 = u32::from_be_bytes(*b"oink");
+    const NOMINAL_SAMPLE_RATE: u32 = u32::from_be_bytes(*b"nsrt");
+    const NO_HOG: i32 = -1;
+
+    #[repr(C)]
+    struct PropertyAddress {
+        selector: u32,
+        scope: u32,
+        element: u32,
+    }
+
+    #[link(name = "CoreAudio", kind = "framework")]
+    extern "C" {
+        fn AudioObjectGetPropertyData(
+            object: u32,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+            data: *mut c_void,
+        ) -> i32;
+        fn AudioObjectSetPropertyData(
+            object: u32,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: u32,
+            data: *const c_void,
+        ) -> i32;
+    }
+
+    fn get<T: Default>(object: u32, selector: u32) -> Result<T, ExclusiveError> {
+        let address = PropertyAddress {
+            selector,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let mut value = T::default();
+        let mut size = mem::size_of::<T>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                object,
+                &address,
+                0,
+                ptr::null(),
+                &mut size,
+                &mut value as *mut T as *mut c_void,
+            )
+        };
+        check(status, selector).map(|_| value)
+    }
+
+    fn set<T>(object: u32, selector: u32, value: &T) -> Result<(), ExclusiveError> {
+        let address = PropertyAddress {
+            selector,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let status = unsafe {
+            AudioObjectSetPropertyData(
+                object,
+                &address,
+                0,
+                ptr::null(),
+                mem::size_of::<T>() as u32,
+                value as *const T as *const c_void,
+            )
+        };
+        check(status, selector)
+    }
+
+    fn check(status: i32, selector: u32) -> Result<(), ExclusiveError> {
+        if status == 0 {
+            return Ok(());
+        }
+        let name = String::from_utf8_lossy(&selector.to_be_bytes()).into_owned();
+        Err(ExclusiveError::CoreAudioError(format!(
+            "'{}' returned {}",
+            name, status
+        )))
+    }
+
+    pub struct Guard {
+        device: u32,
+        original_rate: f64,
+        rate: Option<u32>,
+    }
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, ExclusiveError> {
+            let device: u32 = get(SYSTEM_OBJECT, DEFAULT_OUTPUT_DEVICE)?;
+            let original_rate: f64 = get(device, NOMINAL_SAMPLE_RATE)?;
+
+            // Setting our own process ID asks for the device; reading it back says who got it.
+            let pid = std::process::id() as i32;
+            set(device, HOG_MODE, &pid)?;
+            let owner: i32 = get(device, HOG_MODE)?;
+            if owner != pid {
+                return Err(ExclusiveError::CoreAudioError(format!(
+                    "the device is held by process {}",
+                    owner
+                )));
+            }
+
+            Ok(Guard {
+                device,
+                original_rate,
+                rate: None,
+            })
+        }
+
+        pub fn set_rate(&mut self, rate: Option<u32>) {
+            let Some(rate) = rate.filter(|&rate| Some(rate) != self.rate) else {
+                return;
+            };
+            match set(self.device, NOMINAL_SAMPLE_RATE, &(rate as f64)) {
+                Ok(()) => self.rate = Some(rate),
+                Err(e) => log::error!("Failed to set the device to {} Hz: {}", rate, e),
+            }
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            if self.rate.is_some() {
+                if let Err(e) = set(self.device, NOMINAL_SAMPLE_RATE, &self.original_rate) {
+                    log::error!("Failed to restore the device sample rate: {}", e);
+                }
+            }
+            if let Err(e) = set(self.device, HOG_MODE, &NO_HOG) {
+                log::error!("Failed to release exclusive output: {}", e);
+            }
+        }
+    }
+}
+
+/// Hog mode is CoreAudio's; other platforms share the device with the system mixer.
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use super::ExclusiveError;
+
+    pub enum Guard {}
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, ExclusiveError> {
+            Err(ExclusiveError::Unsupported)
+        }
+
+        pub fn set_rate(&mut self, _rate: Option<u32>) {
+            match *self {}
+        }
+    }
+}
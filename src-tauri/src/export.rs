@@ -0,0 +1,196 @@
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+
+use crate::encoder::{BlockEncoder, Mp3Encoder, OggEncoder, OpusEncoder};
+use crate::tap::AudioBlock;
+use crate::util;
+
+/// Frames decoded per block handed to the encoder.
+const BLOCK_FRAMES: usize = 4096;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Mp3,
+    Ogg,
+    Opus,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Mp3 => "mp3",
+            ExportFormat::Ogg => "ogg",
+            ExportFormat::Opus => "opus",
+        }
+    }
+
+    /// `quality` is the target bitrate in kbps.
+    fn encoder(&self, quality: u32) -> io::Result<Box<dyn BlockEncoder>> {
+        Ok(match self {
+            ExportFormat::Mp3 => Box::new(Mp3Encoder::new(quality)),
+            ExportFormat::Ogg => Box::new(OggEncoder::new(quality)),
+            ExportFormat::Opus => Box::new(OpusEncoder::new(quality)?),
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ExportProgress {
+    path: String,
+    output: Option<String>,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+/// Re-encodes `paths` into `dest` on a worker pool, emitting `export-progress` per finished file.
+/// Files sharing a name are numbered, e.g. `Intro (2).mp3`, rather than overwriting each other.
+pub fn export_tracks(
+    app: AppHandle,
+    paths: Vec<String>,
+    format: ExportFormat,
+    quality: u32,
+    dest: PathBuf,
+) -> io::Result<()> {
+    fs::create_dir_all(&dest)?;
+
+    let total = paths.len();
+    let jobs = Arc::new(Mutex::new(output_names(paths, format.extension())));
+    let completed = Arc::new(AtomicUsize::new(0));
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(2)
+        .min(total.max(1));
+
+    for _ in 0..workers {
+        let app = app.clone();
+        let jobs = jobs.clone();
+        let completed = completed.clone();
+        let dest = dest.clone();
+
+        thread::spawn(move || loop {
+            let Some((path, name)) = jobs.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+            else {
+                break;
+            };
+
+            let result = export_track(&path, format, quality, &dest.join(name));
+            let progress = ExportProgress {
+                path,
+                output: result
+                    .as_ref()
+                    .ok()
+                    .map(|output| output.to_string_lossy().into_owned()),
+                error: result.err().map(|e| e.to_string()),
+                completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                total,
+            };
+
+            if let Err(e) = app.emit("export-progress", progress) {
+                log::error!("{}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Pairs each path with the file name it's exported as: its own name with `extension`,
+/// numbered when an earlier path already took it. Names are compared ignoring case, for
+/// file systems that do.
+fn output_names(paths: Vec<String>, extension: &str) -> VecDeque<(String, String)> {
+    let mut taken = HashSet::new();
+    paths
+        .into_iter()
+        .map(|path| {
+            let stem = Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "track".to_string());
+
+            let mut name = format!("{}.{}", stem, extension);
+            let mut number = 2;
+            while !taken.insert(name.to_lowercase()) {
+                name = format!("{} ({}).{}", stem, number, extension);
+                number += 1;
+            }
+            (path, name)
+        })
+        .collect()
+}
+
+fn export_track(
+    path: &str,
+    format: ExportFormat,
+    quality: u32,
+    output_path: &Path,
+) -> io::Result<PathBuf> {
+    let media = util::open_media(path)?;
+    let mut decoder = Decoder::new(BufReader::new(media))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+
+    let mut encoder = format.encoder(quality)?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    let mut encoded = Vec::new();
+
+    loop {
+        let samples: Vec<i16> = decoder
+            .by_ref()
+            .take(BLOCK_FRAMES * channels as usize)
+            .collect();
+        if samples.is_empty() {
+            break;
+        }
+
+        encoder.encode(
+            &AudioBlock {
+                channels,
+                sample_rate,
+                samples,
+            },
+            &mut encoded,
+        )?;
+        writer.write_all(&encoded)?;
+        encoded.clear();
+    }
+
+    encoder.finish(&mut encoded)?;
+    writer.write_all(&encoded)?;
+    writer.flush()?;
+
+    Ok(output_path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_sharing_a_name_are_numbered() {
+        let paths = vec![
+            "/music/A/Intro.flac".to_string(),
+            "/music/B/intro.mp3".to_string(),
+            "/music/C/Intro.wav".to_string(),
+            "/music/C/Outro.wav".to_string(),
+        ];
+        let names: Vec<String> = output_names(paths, "ogg")
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(
+            names,
+            ["Intro.ogg", "intro (2).ogg", "Intro (3).ogg", "Outro.ogg"]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+/// Bytes fetched per range request.
+const CHUNK_SIZE: u64 = 512 * 1024;
+
+/// Seekable reader over an HTTP resource, fetched lazily in chunks via `Range` requests.
+pub struct HttpStream {
+    agent: ureq::Agent,
+    url: String,
+    authorization: Option<String>,
+    length: u64,
+    position: u64,
+    buffer: Vec<u8>,
+    buffer_start: u64,
+}
+
+impl HttpStream {
+    pub fn open(url: &str, authorization: Option<String>) -> io::Result<HttpStream> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout_read(Duration::from_secs(30))
+            .build();
+
+        let mut stream = HttpStream {
+            agent,
+            url: url.to_string(),
+            authorization,
+            length: 0,
+            position: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+        };
+        stream.fill(0)?;
+
+        Ok(stream)
+    }
+
+    /// Loads the chunk starting at `start`. Servers without range support return the whole body.
+    fn fill(&mut self, start: u64) -> io::Result<()> {
+        let mut request = self.agent.get(&self.url).set(
+            "Range",
+            &format!("bytes={}-{}", start, start + CHUNK_SIZE - 1),
+        );
+        if let Some(authorization) = &self.authorization {
+            request = request.set("Authorization", authorization);
+        }
+
+        let response = request
+            .call()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        let partial = response.status() == 206;
+        let total = response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.trim().parse::<u64>().ok());
+
+        let mut buffer = Vec::new();
+        response.into_reader().read_to_end(&mut buffer)?;
+
+        if partial {
+            self.buffer_start = start;
+            self.length = total.unwrap_or(start + buffer.len() as u64);
+        } else {
+            self.buffer_start = 0;
+            self.length = buffer.len() as u64;
+        }
+        self.buffer = buffer;
+
+        Ok(())
+    }
+
+    fn buffered(&self, position: u64) -> bool {
+        position >= self.buffer_start && position < self.buffer_start + self.buffer.len() as u64
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if !self.buffered(self.position) {
+            self.fill(self.position)?;
+            if !self.buffered(self.position) {
+                return Ok(0);
+            }
+        }
+
+        let offset = (self.position - self.buffer_start) as usize;
+        let available = &self.buffer[offset..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count as u64;
+
+        Ok(count)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.length as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before start of stream",
+            ));
+        }
+
+        self.position = target as u64;
+        Ok(self.position)
+    }
+}
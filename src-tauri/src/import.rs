@@ -0,0 +1,551 @@
+use roxmltree::{Document, Node};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+
+use crate::library::{self, Library, LibraryError};
+use crate::playlist;
+use crate::util;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to parse XML: {0}")]
+    XmlError(#[from] roxmltree::Error),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+
+    #[error("Unrecognized file: {0}")]
+    FormatError(String),
+}
+
+/// Players whose libraries can be imported besides iTunes.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    /// The Rhythmbox data folder holding `rhythmdb.xml` and `playlists.xml`.
+    Rhythmbox,
+    /// A Clementine or Strawberry database file.
+    Clementine,
+    /// An `mpd.conf`, used to find the music folder, playlists and the state file.
+    Mpd,
+}
+
+/// Rewrites locations recorded on another machine or drive: paths starting with `from`
+/// get that prefix replaced by `to`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PathMap {
+    pub from: String,
+    pub to: String,
+}
+
+fn map_path(map: Option<&PathMap>, path: String) -> String {
+    match map.and_then(|map| Some((path.strip_prefix(&map.from)?, &map.to))) {
+        Some((rest, to)) => format!("{}{}", to, rest),
+        None => path,
+    }
+}
+
+/// Per-track data carried over from another player.
+struct ImportedTrack {
+    path: String,
+    /// Stars from 1 to 5.
+    rating: Option<u8>,
+    plays: u32,
+}
+
+struct ImportedPlaylist {
+    name: String,
+    paths: Vec<String>,
+}
+
+#[derive(Default)]
+struct ImportData {
+    tracks: Vec<ImportedTrack>,
+    playlists: Vec<ImportedPlaylist>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ImportProgress {
+    path: String,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+/// Imports tracks, ratings, play counts and playlists from an iTunes / Apple Music
+/// `Library.xml` export. Runs in the background, emitting `import-progress` per track.
+pub fn import_itunes(
+    app: AppHandle,
+    xml: PathBuf,
+    path_map: Option<PathMap>,
+) -> Result<(), ImportError> {
+    let data = read_itunes(&xml, path_map.as_ref())?;
+    apply(app, data);
+    Ok(())
+}
+
+/// Imports ratings, play counts and playlists from another player's library. `path` is
+/// what the player stores them in, as described on [`ImportSource`].
+pub fn import_from(
+    app: AppHandle,
+    source: ImportSource,
+    path: PathBuf,
+    path_map: Option<PathMap>,
+) -> Result<(), ImportError> {
+    let path_map = path_map.as_ref();
+    let data = match source {
+        ImportSource::Rhythmbox => read_rhythmbox(&path, path_map)?,
+        ImportSource::Clementine => read_clementine(&path, path_map)?,
+        ImportSource::Mpd => read_mpd(&path, path_map)?,
+    };
+    apply(app, data);
+    Ok(())
+}
+
+/// Adds the tracks that exist on this machine, then recreates the playlists from them.
+fn apply(app: AppHandle, data: ImportData) {
+    thread::spawn(move || {
+        let library = app.state::<Library>();
+        let total = data.tracks.len();
+
+        for (i, track) in data.tracks.into_iter().enumerate() {
+            let result = if Path::new(&track.path).is_file() {
+                library
+                    .add_file(&track.path)
+                    .and_then(|_| library.set_rating(&track.path, track.rating))
+                    .and_then(|_| library.set_imported_plays(&track.path, track.plays))
+                    .map_err(|e| e.to_string())
+            } else {
+                Err("File not found".to_string())
+            };
+
+            let progress = ImportProgress {
+                path: track.path,
+                error: result.err(),
+                completed: i + 1,
+                total,
+            };
+
+            if let Err(e) = app.emit("import-progress", progress) {
+                log::error!("{}", e);
+            }
+        }
+
+        for playlist in data.playlists {
+            let paths: Vec<String> = playlist
+                .paths
+                .into_iter()
+                .filter(|path| Path::new(path).is_file())
+                .collect();
+
+            let result = library
+                .create_playlist(&playlist.name)
+                .and_then(|id| library.add_to_playlist(id, &paths));
+            if let Err(e) = result {
+                log::error!("Failed to import playlist {}: {}", playlist.name, e);
+            }
+        }
+
+        if let Err(e) = library::emit_playlists(&app) {
+            log::error!("{}", e);
+        }
+    });
+}
+
+fn read_itunes(xml: &Path, path_map: Option<&PathMap>) -> Result<ImportData, ImportError> {
+    let text = fs::read_to_string(xml)?;
+    let document = Document::parse(&text)?;
+
+    let root = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("dict"))
+        .ok_or_else(|| ImportError::FormatError("Not an iTunes library".to_string()))?;
+
+    let mut data = ImportData::default();
+    let mut locations = HashMap::new();
+
+    if let Some(tracks) = dict_value(root, "Tracks") {
+        for (id, track) in dict_entries(tracks) {
+            let Some(path) = dict_value(track, "Location")
+                .and_then(|node| node.text())
+                .and_then(path_from_file_url)
+                .map(|path| map_path(path_map, path))
+            else {
+                continue;
+            };
+
+            // Album-derived ratings only mirror the album's, so they aren't carried over.
+            let computed =
+                dict_value(track, "Rating Computed").is_some_and(|node| node.has_tag_name("true"));
+            let rating = dict_integer(track, "Rating")
+                .filter(|&rating| rating > 0 && !computed)
+                .map(|rating| (rating / 20).clamp(1, 5) as u8);
+
+            locations.insert(id.to_string(), path.clone());
+            data.tracks.push(ImportedTrack {
+                path,
+                rating,
+                plays: dict_integer(track, "Play Count").unwrap_or(0) as u32,
+            });
+        }
+    }
+
+    if let Some(playlists) = dict_value(root, "Playlists") {
+        for playlist in playlists
+            .children()
+            .filter(|node| node.has_tag_name("dict"))
+        {
+            // Built-in lists (Library, Music, Podcasts...) and folders aren't user playlists.
+            let builtin = ["Master", "Distinguished Kind", "Folder"]
+                .iter()
+                .any(|key| {
+                    dict_value(playlist, key).is_some_and(|node| !node.has_tag_name("false"))
+                });
+            let Some(name) = dict_value(playlist, "Name").and_then(|node| node.text()) else {
+                continue;
+            };
+            if builtin {
+                continue;
+            }
+
+            let paths = dict_value(playlist, "Playlist Items")
+                .map(|items| {
+                    items
+                        .children()
+                        .filter(|node| node.has_tag_name("dict"))
+                        .filter_map(|item| dict_integer(item, "Track ID"))
+                        .filter_map(|id| locations.get(&id.to_string()).cloned())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            data.playlists.push(ImportedPlaylist {
+                name: name.to_string(),
+                paths,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+fn read_rhythmbox(dir: &Path, path_map: Option<&PathMap>) -> Result<ImportData, ImportError> {
+    let location = |node: Node| {
+        node.children()
+            .find(|child| child.has_tag_name("location"))
+            .and_then(|child| child.text())
+            .and_then(path_from_file_url)
+            .map(|path| map_path(path_map, path))
+    };
+    let number = |node: Node, name: &str| -> Option<u32> {
+        node.children()
+            .find(|child| child.has_tag_name(name))?
+            .text()?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    let mut data = ImportData::default();
+
+    let text = fs::read_to_string(dir.join("rhythmdb.xml"))?;
+    let document = Document::parse(&text)?;
+    if !document.root_element().has_tag_name("rhythmdb") {
+        return Err(ImportError::FormatError(
+            "Not a Rhythmbox database".to_string(),
+        ));
+    }
+
+    for entry in document.root_element().children() {
+        if !entry.has_tag_name("entry") || entry.attribute("type") != Some("song") {
+            continue;
+        }
+        let Some(path) = location(entry) else {
+            continue;
+        };
+
+        data.tracks.push(ImportedTrack {
+            path,
+            rating: number(entry, "rating")
+                .filter(|&rating| rating > 0)
+                .map(|rating| rating.min(5) as u8),
+            plays: number(entry, "play-count").unwrap_or(0),
+        });
+    }
+
+    // Playlists are optional; a fresh profile has none.
+    let Ok(text) = fs::read_to_string(dir.join("playlists.xml")) else {
+        return Ok(data);
+    };
+    let document = Document::parse(&text)?;
+
+    for playlist in document.root_element().children() {
+        // Automatic playlists are saved searches and the play queue isn't a playlist.
+        if !playlist.has_tag_name("playlist") || playlist.attribute("type") != Some("static") {
+            continue;
+        }
+        let Some(name) = playlist.attribute("name") else {
+            continue;
+        };
+
+        data.playlists.push(ImportedPlaylist {
+            name: name.to_string(),
+            paths: playlist
+                .children()
+                .filter(|node| node.has_tag_name("location"))
+                .filter_map(|node| node.text())
+                .filter_map(path_from_file_url)
+                .map(|path| map_path(path_map, path))
+                .collect(),
+        });
+    }
+
+    Ok(data)
+}
+
+/// Reads a Clementine database, or a Strawberry one, which renamed a few columns.
+fn read_clementine(db: &Path, path_map: Option<&PathMap>) -> Result<ImportData, ImportError> {
+    let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('songs')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let (location, song_id) = if columns.iter().any(|column| column == "url") {
+        ("url", "collection_id")
+    } else if columns.iter().any(|column| column == "filename") {
+        ("filename", "library_id")
+    } else {
+        return Err(ImportError::FormatError(
+            "Not a Clementine or Strawberry database".to_string(),
+        ));
+    };
+    // Clementine stores locations as encoded URL blobs, hence the casts below.
+    let to_path = |url: String| path_from_file_url(&url).map(|path| map_path(path_map, path));
+
+    let mut data = ImportData::default();
+
+    let mut statement = conn.prepare(&format!(
+        "SELECT CAST({location} AS TEXT), rating, playcount FROM songs WHERE unavailable = 0"
+    ))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<f64>>(1)?,
+            row.get::<_, Option<i64>>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (url, rating, plays) = row?;
+        let Some(path) = to_path(url) else {
+            continue;
+        };
+
+        // Ratings are stored from 0.0 to 1.0, with -1 meaning unrated.
+        data.tracks.push(ImportedTrack {
+            path,
+            rating: rating
+                .filter(|&rating| rating > 0.0)
+                .map(|rating| (rating * 5.0).round().clamp(1.0, 5.0) as u8),
+            plays: plays.unwrap_or(0).max(0) as u32,
+        });
+    }
+
+    let mut statement = conn.prepare(&format!(
+        "SELECT playlists.ROWID, playlists.name,
+                CAST(COALESCE(songs.{location}, playlist_items.{location}) AS TEXT)
+         FROM playlist_items
+         JOIN playlists ON playlists.ROWID = playlist_items.playlist
+         LEFT JOIN songs ON songs.ROWID = playlist_items.{song_id}
+         ORDER BY playlists.ROWID, playlist_items.ROWID"
+    ))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+        ))
+    })?;
+
+    let mut current = None;
+    for row in rows {
+        let (id, name, url) = row?;
+        if current != Some(id) {
+            current = Some(id);
+            data.playlists.push(ImportedPlaylist {
+                name,
+                paths: Vec::new(),
+            });
+        }
+        if let (Some(playlist), Some(path)) = (data.playlists.last_mut(), url.and_then(to_path)) {
+            playlist.paths.push(path);
+        }
+    }
+
+    Ok(data)
+}
+
+/// MPD keeps no ratings or play counts, so this brings over the stored playlists and the
+/// queue saved in the state file.
+fn read_mpd(conf: &Path, path_map: Option<&PathMap>) -> Result<ImportData, ImportError> {
+    let settings = read_mpd_conf(conf)?;
+    let setting = |name: &str| settings.get(name).map(|value| expand_home(value));
+    let music_dir = setting("music_directory")
+        .ok_or_else(|| ImportError::FormatError("mpd.conf has no music_directory".to_string()))?;
+
+    // Entries are relative to the music folder unless they're absolute or URLs.
+    let resolve = |entry: &str| {
+        let entry = entry.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            return None;
+        }
+        let path = match entry.strip_prefix("file://") {
+            Some(path) => PathBuf::from(path),
+            None if entry.contains("://") => return None,
+            None => music_dir.join(entry),
+        };
+        Some(map_path(path_map, path.to_string_lossy().into_owned()))
+    };
+
+    let mut data = ImportData::default();
+
+    if let Some(dir) = setting("playlist_directory") {
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| playlist::is_playlist_file(path))
+            .collect();
+        files.sort();
+
+        for file in files {
+            let Some(name) = file
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            data.playlists.push(ImportedPlaylist {
+                name,
+                paths: fs::read_to_string(&file)?
+                    .lines()
+                    .filter_map(&resolve)
+                    .collect(),
+            });
+        }
+    }
+
+    if let Some(state_file) = setting("state_file") {
+        if let Ok(state) = fs::read_to_string(state_file) {
+            // The queue is stored as `index:path` lines between these markers.
+            let paths: Vec<String> = state
+                .lines()
+                .skip_while(|line| *line != "playlist_begin")
+                .skip(1)
+                .take_while(|line| *line != "playlist_end")
+                .filter_map(|line| line.split_once(':').map(|(_, path)| path))
+                .filter_map(&resolve)
+                .collect();
+
+            if !paths.is_empty() {
+                data.playlists.push(ImportedPlaylist {
+                    name: "MPD queue".to_string(),
+                    paths,
+                });
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Top-level `name "value"` settings of an `mpd.conf`, skipping blocks like `audio_output`.
+fn read_mpd_conf(conf: &Path) -> Result<HashMap<String, String>, ImportError> {
+    let mut settings = HashMap::new();
+    let mut depth = 0usize;
+
+    for line in fs::read_to_string(conf)?.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.ends_with('{') {
+            depth += 1;
+            continue;
+        }
+        if line == "}" {
+            depth = depth.saturating_sub(1);
+            continue;
+        }
+        if depth > 0 {
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(char::is_whitespace) {
+            let value = value.trim().trim_matches('"');
+            settings.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    Ok(settings)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Key/value pairs of a plist `<dict>`.
+fn dict_entries<'a, 'input>(dict: Node<'a, 'input>) -> Vec<(&'a str, Node<'a, 'input>)> {
+    let mut children = dict.children().filter(|node| node.is_element());
+    let mut entries = Vec::new();
+
+    while let (Some(key), Some(value)) = (children.next(), children.next()) {
+        if key.has_tag_name("key") {
+            entries.push((key.text().unwrap_or_default(), value));
+        }
+    }
+
+    entries
+}
+
+fn dict_value<'a, 'input>(dict: Node<'a, 'input>, key: &str) -> Option<Node<'a, 'input>> {
+    dict_entries(dict)
+        .into_iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
+
+fn dict_integer(dict: Node, key: &str) -> Option<i64> {
+    dict_value(dict, key)?.text()?.trim().parse().ok()
+}
+
+/// Converts a `file://` URL to a local path, or `None` for other schemes.
+pub fn path_from_file_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let path = util::percent_decode(rest);
+
+    // Windows drive paths are written as `/C:/Music/...`.
+    let bytes = path.as_bytes();
+    if cfg!(windows) && bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+        return Some(path[1..].replace('/', "\\"));
+    }
+
+    Some(path)
+}
@@ -0,0 +1,77 @@
+use jack::{
+    AsyncClient, AudioOut, Client, ClientOptions, Control, NotificationHandler, Port,
+    ProcessHandler, ProcessScope,
+};
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::UniformSourceIterator;
+
+use crate::diagnostics;
+use crate::output::JackConfig;
+
+/// A running JACK client playing a sink's queue through a pair of output ports.
+pub struct JackOutput {
+    _client: AsyncClient<Notifications, Process>,
+}
+
+struct Notifications;
+
+impl NotificationHandler for Notifications {
+    fn xrun(&mut self, _client: &Client) -> Control {
+        diagnostics::record_xrun();
+        Control::Continue
+    }
+}
+
+struct Process {
+    left: Port<AudioOut>,
+    right: Port<AudioOut>,
+    /// The sink's output converted to stereo at the server's sample rate.
+    source: UniformSourceIterator<SourcesQueueOutput<f32>, f32>,
+}
+
+impl ProcessHandler for Process {
+    fn process(&mut self, _client: &Client, scope: &ProcessScope) -> Control {
+        let left = self.left.as_mut_slice(scope);
+        let right = self.right.as_mut_slice(scope);
+
+        // The queue plays silence while empty, so the source never actually runs dry.
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            *left = self.source.next().unwrap_or(0.0);
+            *right = self.source.next().unwrap_or(0.0);
+        }
+        Control::Continue
+    }
+}
+
+impl JackOutput {
+    /// Connects to a running JACK server; one is never started on the player's behalf.
+    pub fn start(
+        config: &JackConfig,
+        queue: SourcesQueueOutput<f32>,
+    ) -> Result<JackOutput, jack::Error> {
+        let (client, _status) = Client::new(&config.client_name, ClientOptions::NO_START_SERVER)?;
+
+        let [left_name, right_name] = &config.ports;
+        let left = client.register_port(left_name, AudioOut::default())?;
+        let right = client.register_port(right_name, AudioOut::default())?;
+        let port_names = [left.name()?, right.name()?];
+
+        let sample_rate = client.sample_rate() as u32;
+        let process = Process {
+            left,
+            right,
+            source: UniformSourceIterator::new(queue, 2, sample_rate),
+        };
+        let active = client.activate_async(Notifications, process)?;
+
+        if let Some(destinations) = &config.connect_to {
+            for (port, destination) in port_names.iter().zip(destinations) {
+                if let Err(e) = active.as_client().connect_ports_by_name(port, destination) {
+                    log::error!("Failed to connect {} to {}: {}", port, destination, e);
+                }
+            }
+        }
+
+        Ok(JackOutput { _client: active })
+    }
+}
@@ -1,15 +1,138 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::vec;
-use tauri::{Manager, State};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State, WindowEvent};
 
+mod announce;
+mod audio_focus;
 mod audio_player;
+mod audiobook;
+mod backup;
+mod bluetooth;
+mod bpm;
+mod buffer;
+mod cache;
+mod chromecast;
+mod crossfade;
+mod diagnostics;
+mod dither;
+mod dlna;
+mod dlna_browser;
+mod encoder;
+mod exclusive;
+mod export;
+mod http_stream;
+mod import;
+#[cfg(target_os = "linux")]
+mod jack_output;
+mod library;
+mod library_export;
+mod logging;
+mod lyrics;
+mod media_controls;
+mod media_server;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod multiroom;
+#[cfg(target_os = "ios")]
+mod now_playing;
+mod null_output;
+mod output;
+mod output_device;
+mod paths;
+#[cfg(target_os = "linux")]
+mod pipewire_output;
+mod player_host;
+mod playlist;
+mod power;
+mod profiles;
+mod recorder;
+mod remote_api;
+mod replaygain;
+mod seek;
+mod settings;
+mod shortcuts;
+mod shuffle;
+mod silence;
+mod sleep_inhibitor;
+mod smb;
+mod stream_server;
+mod tap;
+#[cfg(target_os = "windows")]
+mod taskbar;
+mod tray;
 mod util;
-use audio_player::AudioPlayer;
+mod volume;
+mod waveform;
+mod webdav;
+use announce::AnnounceMode;
+use audio_player::{
+    AudioError, AudioPlayer, PlayerSnapshot, QueueOp, QueueReport, QueueSummary, TrackInfo,
+};
+use backup::BackupManifest;
+use chromecast::{CastDeviceInfo, Chromecast};
+use diagnostics::AudioDiagnostics;
+use dlna::{DlnaCaster, UpnpDeviceInfo};
+use dlna_browser::{DlnaBrowser, MediaEntry};
+use export::ExportFormat;
+use import::{ImportSource, PathMap};
+use library::{
+    AlbumEntry, ArtistEntry, BookEntry, Bookmark, Charts, ComposerEntry, GenreEntry, Library,
+    ListeningTime, PlaylistEntry, PlaylistSettings, RadioSeed, ReportPeriod, SkipRule, SortOrder,
+    StartOffset, WorkEntry,
+};
+use library_export::LibraryExportFormat;
+use logging::LogLevel;
+use lyrics::Lyrics;
+use multiroom::{MultiRoom, SyncRole};
+use output::{OutputBackend, OutputFormat};
+use profiles::Profiles;
+use recorder::Recorder;
+use remote_api::RemoteApi;
+use replaygain::GainEstimate;
+use settings::{Settings, SettingsStore};
+use shortcuts::{GlobalShortcuts, ShortcutAction};
+use shuffle::{ShuffleMode, ShuffleWeighting};
+use silence::TrimConfig;
+use smb::SmbCredentials;
+use stream_server::StreamServer;
+use util::ImportOrder;
+use volume::VolumeCurve;
+use webdav::{WebDavCredentials, WebDavEntry};
 
+/// Searches and checks the files off the main thread, as a large batch takes a while to
+/// read, emitting `queue-add-progress` per file checked.
 #[tauri::command]
-fn add_queue(state: State<AppState>, file_paths: Vec<String>) -> Result<(), String> {
+async fn add_queue(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    file_paths: Vec<String>,
+    order: Option<ImportOrder>,
+) -> Result<QueueReport, String> {
+    let player = state.audio_player.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        player.add_paths(file_paths, order.unwrap_or_default(), |progress| {
+            if let Err(e) = app.emit("queue-add-progress", progress) {
+                log::error!("{}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_folder(
+    state: State<AppState>,
+    path: String,
+    recursive: bool,
+    order: Option<ImportOrder>,
+) -> Result<(), String> {
     state
         .audio_player
-        .add_queue(file_paths)
+        .add_folder(path, recursive, order.unwrap_or_default())
         .map_err(|e| e.to_string())
 }
 
@@ -28,6 +151,11 @@ fn pause(state: State<AppState>) -> Result<(), String> {
     state.audio_player.pause().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn stop(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.stop().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn resume(state: State<AppState>) -> Result<(), String> {
     state.audio_player.resume().map_err(|e| e.to_string())
@@ -38,6 +166,14 @@ fn prev(state: State<AppState>) -> Result<(), String> {
     state.audio_player.prev().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn restart_track(state: State<AppState>) -> Result<(), String> {
+    state
+        .audio_player
+        .restart_track()
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn next(state: State<AppState>) -> Result<(), String> {
     state.audio_player.next().map_err(|e| e.to_string())
@@ -67,36 +203,1399 @@ fn set_volume(state: State<AppState>, volume: f32) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
-struct AppState {
-    audio_player: AudioPlayer,
+#[tauri::command]
+fn volume_up(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.volume_up().map_err(|e| e.to_string())
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .setup(|app| {
-            let handle = app.handle();
+#[tauri::command]
+fn volume_down(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.volume_down().map_err(|e| e.to_string())
+}
 
-            app.manage(AppState {
-                audio_player: AudioPlayer::new(handle.clone()),
-            });
+#[tauri::command]
+fn set_output_backend(state: State<AppState>, backend: OutputBackend) -> Result<(), String> {
+    state
+        .audio_player
+        .set_output_backend(backend)
+        .map_err(|e| e.to_string())
+}
 
-            Ok(())
+/// Plays to the named output device from `list_output_devices`, or the system default
+/// with none. Remembered across restarts.
+#[tauri::command]
+fn set_output_device(state: State<AppState>, device: Option<String>) -> Result<(), String> {
+    state
+        .audio_player
+        .set_output_device(device)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_output_devices() -> Vec<String> {
+    output_device::output_names()
+}
+
+#[tauri::command]
+fn set_output_format(state: State<AppState>, format: OutputFormat) -> Result<(), String> {
+    state
+        .audio_player
+        .set_output_format(format)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_media_controls_enabled(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .audio_player
+        .set_media_controls_enabled(enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_position_update_interval(state: State<AppState>, ms: u64) -> Result<(), String> {
+    state
+        .audio_player
+        .set_position_update_interval(ms)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_position(state: State<AppState>) -> Result<u64, String> {
+    state.audio_player.get_position().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_queue_summary(state: State<AppState>) -> Result<QueueSummary, String> {
+    state
+        .audio_player
+        .get_queue_summary()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_volume_step(state: State<AppState>, step: f32) -> Result<(), String> {
+    state
+        .audio_player
+        .set_volume_step(step)
+        .map_err(|e| e.to_string())
+}
+
+/// Plays the unprocessed signal while `bypass` is set, for A/B listening.
+#[tauri::command]
+fn bypass_dsp(state: State<AppState>, bypass: bool) -> Result<(), String> {
+    state
+        .audio_player
+        .bypass_dsp(bypass)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_muted(state: State<AppState>, muted: bool) -> Result<(), String> {
+    state
+        .audio_player
+        .set_muted(muted)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn toggle_mute(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.toggle_mute().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_volume_curve(state: State<AppState>, curve: VolumeCurve) -> Result<(), String> {
+    state
+        .audio_player
+        .set_volume_curve(curve)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_skip_silence(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .audio_player
+        .set_skip_silence(enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_trim_silence(state: State<AppState>, config: Option<TrimConfig>) -> Result<(), String> {
+    state
+        .audio_player
+        .set_trim_silence(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_shuffle(state: State<AppState>, mode: ShuffleMode) -> Result<(), String> {
+    state
+        .audio_player
+        .set_shuffle(mode)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_shuffle_weighting(
+    state: State<AppState>,
+    weighting: ShuffleWeighting,
+) -> Result<(), String> {
+    state
+        .audio_player
+        .set_shuffle_weighting(weighting)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_radio(state: State<AppState>, seed: RadioSeed) -> Result<(), String> {
+    state
+        .audio_player
+        .start_radio(seed)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_radio(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.stop_radio().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_endless(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .audio_player
+        .set_endless(enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_announce_time(state: State<AppState>, mode: AnnounceMode) -> Result<(), String> {
+    state
+        .audio_player
+        .set_announce_time(mode)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reshuffle(state: State<AppState>, avoid_recent: Option<usize>) -> Result<(), String> {
+    state
+        .audio_player
+        .reshuffle(avoid_recent.unwrap_or(0))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn sort_queue(state: State<AppState>, order: SortOrder) -> Result<(), String> {
+    state
+        .audio_player
+        .sort_queue(order)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_from_queue(
+    state: State<AppState>,
+    indices: Vec<usize>,
+    version: u64,
+) -> Result<(), String> {
+    state
+        .audio_player
+        .remove_from_queue(indices, Some(version))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn move_in_queue(
+    state: State<AppState>,
+    from: usize,
+    to: usize,
+    version: u64,
+) -> Result<(), String> {
+    state
+        .audio_player
+        .move_in_queue(from, to, Some(version))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn dedupe_queue(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.dedupe_queue().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn edit_queue(state: State<AppState>, ops: Vec<QueueOp>, version: u64) -> Result<(), String> {
+    state
+        .audio_player
+        .edit_queue(ops, Some(version))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn undo_queue(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.undo_queue().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn redo_queue(state: State<AppState>) -> Result<(), String> {
+    state.audio_player.redo_queue().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn find_in_queue(state: State<AppState>, query: String) -> Result<Vec<usize>, String> {
+    state
+        .audio_player
+        .find_in_queue(query)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn play_matching(state: State<AppState>, query: String) -> Result<(), String> {
+    state
+        .audio_player
+        .play_matching(query)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_settings(settings: State<SettingsStore>) -> Result<Settings, String> {
+    settings.get().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_settings(
+    state: State<AppState>,
+    store: State<SettingsStore>,
+    settings: Settings,
+) -> Result<(), String> {
+    store.set(settings.clone()).map_err(|e| e.to_string())?;
+    state
+        .audio_player
+        .apply_settings(settings)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_player_state(state: State<AppState>) -> Result<PlayerSnapshot, String> {
+    state.audio_player.get_state().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_session_history(state: State<AppState>) -> Result<Vec<TrackInfo>, String> {
+    state
+        .audio_player
+        .get_session_history()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_log_level(level: LogLevel) {
+    logging::set_level(level);
+}
+
+/// The last `limit` log lines, for attaching to bug reports.
+#[tauri::command]
+fn get_recent_logs(limit: Option<usize>) -> Vec<String> {
+    logging::recent(limit.unwrap_or(usize::MAX))
+}
+
+/// Underrun and stall counters since the app started.
+#[tauri::command]
+fn get_audio_diagnostics() -> AudioDiagnostics {
+    diagnostics::snapshot()
+}
+
+#[tauri::command]
+fn get_lyrics(state: State<AppState>) -> Result<Option<Lyrics>, String> {
+    state.audio_player.get_lyrics().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_bookmark(
+    library: State<Library>,
+    path: String,
+    position_ms: u64,
+    label: String,
+) -> Result<i64, String> {
+    library
+        .add_bookmark(&path, position_ms, &label)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_bookmarks(library: State<Library>, path: String) -> Result<Vec<Bookmark>, String> {
+    library.bookmarks(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_bookmark(library: State<Library>, id: i64) -> Result<(), String> {
+    library.delete_bookmark(id).map_err(|e| e.to_string())
+}
+
+/// Jumps to the bookmarked moment, queueing its track if needed.
+#[tauri::command]
+fn play_bookmark(state: State<AppState>, library: State<Library>, id: i64) -> Result<(), String> {
+    let bookmark = library.bookmark(id).map_err(|e| e.to_string())?;
+    state
+        .audio_player
+        .play_at(bookmark.path, Duration::from_millis(bookmark.position_ms))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_skip_rule(library: State<Library>, rule: SkipRule) -> Result<(), String> {
+    library.set_skip_rule(&rule).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_skip_rules(library: State<Library>) -> Result<Vec<SkipRule>, String> {
+    library.skip_rules().map_err(|e| e.to_string())
+}
+
+/// Tracks with a start offset learned from the user skipping their opening, enabled or
+/// still on offer.
+#[tauri::command]
+fn get_start_offsets(library: State<Library>) -> Result<Vec<StartOffset>, String> {
+    library.start_offsets().map_err(|e| e.to_string())
+}
+
+/// Accepts or declines a learned start offset.
+#[tauri::command]
+fn set_start_offset_enabled(
+    library: State<Library>,
+    path: String,
+    enabled: bool,
+) -> Result<(), String> {
+    library
+        .set_start_offset_enabled(&path, enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_start_offset(library: State<Library>, path: String) -> Result<(), String> {
+    library.clear_start_offset(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_lyrics_offset(
+    state: State<AppState>,
+    library: State<Library>,
+    path: String,
+    ms: i64,
+) -> Result<(), String> {
+    library
+        .set_lyrics_offset(&path, ms)
+        .map_err(|e| e.to_string())?;
+    state
+        .audio_player
+        .set_lyrics_offset(path, ms)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_remote_api(
+    state: State<AppState>,
+    remote_api: State<RemoteApi>,
+    port: u16,
+    token: String,
+) -> Result<(), String> {
+    remote_api
+        .start(state.audio_player.clone(), port, token)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_remote_api(remote_api: State<RemoteApi>) -> Result<(), String> {
+    remote_api.stop().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_audio_stream(stream_server: State<StreamServer>, port: u16) -> Result<(), String> {
+    stream_server.start(port).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_audio_stream(stream_server: State<StreamServer>) -> Result<(), String> {
+    stream_server.stop().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_tracks(
+    app: AppHandle,
+    paths: Vec<String>,
+    format: ExportFormat,
+    quality: u32,
+    dest: String,
+) -> Result<(), String> {
+    export::export_tracks(app, paths, format, quality, PathBuf::from(dest))
+        .map_err(|e| e.to_string())
+}
+
+/// Writing out the whole library takes a while, so it's done off the main thread.
+#[tauri::command]
+async fn export_library(
+    app: AppHandle,
+    format: LibraryExportFormat,
+    path: String,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        library_export::export_library(&app.state::<Library>(), format, Path::new(&path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Archiving the database, settings and caches takes a while, so backups are made and
+/// restored off the main thread.
+#[tauri::command]
+async fn create_backup(app: AppHandle, path: String) -> Result<BackupManifest, String> {
+    tauri::async_runtime::spawn_blocking(move || backup::create_backup(&app, Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_backup(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+) -> Result<BackupManifest, String> {
+    let manifest = tauri::async_runtime::spawn_blocking(move || {
+        backup::restore_backup(&app, Path::new(&path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+    let settings = settings.get().map_err(|e| e.to_string())?;
+    state
+        .audio_player
+        .apply_settings(settings)
+        .map_err(|e| e.to_string())?;
+    Ok(manifest)
+}
+
+#[tauri::command]
+fn scan_replay_gain(app: AppHandle, paths: Vec<String>, album: bool) {
+    replaygain::scan(app, paths, album);
+}
+
+#[tauri::command]
+async fn estimate_gain(library: State<'_, Library>, path: String) -> Result<GainEstimate, String> {
+    let estimated_db = {
+        let path = path.clone();
+        tauri::async_runtime::spawn_blocking(move || replaygain::estimate_gain(&path))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?
+    };
+    let applied_db = library
+        .get_track_gain(&path)
+        .map_err(|e| e.to_string())?
+        .unwrap_or(0.0);
+
+    Ok(GainEstimate {
+        estimated_db,
+        applied_db,
+    })
+}
+
+#[tauri::command]
+fn analyze_bpm(app: AppHandle, paths: Vec<String>, write_tag: bool) {
+    bpm::analyze(app, paths, write_tag);
+}
+
+#[tauri::command]
+fn get_tracks_by_bpm(
+    library: State<Library>,
+    min: f32,
+    max: f32,
+    sort: Option<SortOrder>,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .tracks_by_bpm(min, max, sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_track_gain(library: State<Library>, path: String, db: Option<f32>) -> Result<(), String> {
+    library.set_track_gain(&path, db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_track_gain(library: State<Library>, path: String) -> Result<Option<f32>, String> {
+    library.get_track_gain(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_waveform(app: AppHandle, path: String, buckets: usize) -> Result<Vec<f32>, String> {
+    // Uncached waveforms decode the whole file.
+    tauri::async_runtime::spawn_blocking(move || {
+        waveform::peaks(&app.state::<Library>(), &path, buckets)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_track_rating(
+    library: State<Library>,
+    path: String,
+    rating: Option<u8>,
+) -> Result<(), String> {
+    library
+        .add_file(&path)
+        .and_then(|_| library.set_rating(&path, rating))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_itunes_library(
+    app: AppHandle,
+    path: String,
+    path_map: Option<PathMap>,
+) -> Result<(), String> {
+    import::import_itunes(app, PathBuf::from(path), path_map).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_player_library(
+    app: AppHandle,
+    source: ImportSource,
+    path: String,
+    path_map: Option<PathMap>,
+) -> Result<(), String> {
+    import::import_from(app, source, PathBuf::from(path), path_map).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn scan_library(
+    app: AppHandle,
+    settings: State<SettingsStore>,
+    folder: String,
+) -> Result<(), String> {
+    settings
+        .update(|settings| {
+            if !settings.library_folders.contains(&folder) {
+                settings.library_folders.push(folder.clone());
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    library::scan(app, PathBuf::from(folder)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_profiles(profiles: State<Profiles>) -> Result<Vec<String>, String> {
+    profiles.list().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_active_profile(profiles: State<Profiles>) -> Result<String, String> {
+    profiles.active().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn switch_profile(
+    app: AppHandle,
+    state: State<AppState>,
+    profiles: State<Profiles>,
+    settings: State<SettingsStore>,
+    name: String,
+) -> Result<(), String> {
+    profiles.switch(&app, &name).map_err(|e| e.to_string())?;
+    let settings = settings.get().map_err(|e| e.to_string())?;
+    state
+        .audio_player
+        .apply_settings(settings)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_profile(profiles: State<Profiles>, name: String) -> Result<(), String> {
+    profiles.delete(&name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_artists(library: State<Library>) -> Result<Vec<ArtistEntry>, String> {
+    library.artists().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_albums(
+    library: State<Library>,
+    artist: Option<String>,
+    sort: Option<SortOrder>,
+) -> Result<Vec<AlbumEntry>, String> {
+    library
+        .albums(artist.as_deref(), sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_artist_tracks(
+    library: State<Library>,
+    artist: String,
+    sort: Option<SortOrder>,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .artist_tracks(&artist, sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_genres(library: State<Library>) -> Result<Vec<GenreEntry>, String> {
+    library.genres().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_books(library: State<Library>) -> Result<Vec<BookEntry>, String> {
+    library.books().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_book_tracks(library: State<Library>, book_id: i64) -> Result<Vec<TrackInfo>, String> {
+    library.book_tracks(book_id).map_err(|e| e.to_string())
+}
+
+/// Replaces the queue with the book and plays it from where it was left off.
+#[tauri::command]
+fn continue_book(
+    state: State<AppState>,
+    library: State<Library>,
+    book_id: i64,
+) -> Result<(), String> {
+    let (tracks, index, position_ms) = library
+        .book_resume_point(book_id)
+        .map_err(|e| e.to_string())?;
+    let Some(path) = tracks.get(index).map(|track| track.path.clone()) else {
+        return Err(AudioError::EmptyQueueError.to_string());
+    };
+
+    let player = &state.audio_player;
+    player
+        .clear_queue()
+        .and_then(|_| player.add_tracks(tracks))
+        .and_then(|_| player.play_at(path, Duration::from_millis(position_ms)))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_composers(library: State<Library>) -> Result<Vec<ComposerEntry>, String> {
+    library.composers().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_composer_works(library: State<Library>, composer: String) -> Result<Vec<WorkEntry>, String> {
+    library.composer_works(&composer).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_composer_tracks(
+    library: State<Library>,
+    composer: String,
+    work: Option<String>,
+    sort: Option<SortOrder>,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .composer_tracks(&composer, work.as_deref(), sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_album_tracks(
+    library: State<Library>,
+    album_id: i64,
+    sort: Option<SortOrder>,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .album_tracks(album_id, sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_recently_added_tracks(
+    library: State<Library>,
+    days: u32,
+    offset: u32,
+    limit: u32,
+    sort: Option<SortOrder>,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .recently_added_tracks(days, offset, limit, sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_recently_added_albums(
+    library: State<Library>,
+    days: u32,
+    offset: u32,
+    limit: u32,
+    sort: Option<SortOrder>,
+) -> Result<Vec<AlbumEntry>, String> {
+    library
+        .recently_added_albums(days, offset, limit, sort)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_recently_played(
+    library: State<Library>,
+    offset: u32,
+    limit: u32,
+) -> Result<Vec<TrackInfo>, String> {
+    library
+        .recently_played_tracks(offset, limit)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_listening_time(
+    library: State<Library>,
+    period: ReportPeriod,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<ListeningTime>, String> {
+    library
+        .listening_time(period, since.unwrap_or(0), until.unwrap_or(i64::MAX))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_charts(
+    library: State<Library>,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: u32,
+) -> Result<Charts, String> {
+    library
+        .charts(since.unwrap_or(0), until.unwrap_or(i64::MAX), limit)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_hourly_plays(
+    library: State<Library>,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<u32>, String> {
+    library
+        .hourly_plays(since.unwrap_or(0), until.unwrap_or(i64::MAX))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_playlists(library: State<Library>) -> Result<Vec<PlaylistEntry>, String> {
+    library.playlists().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_playlist_tracks(library: State<Library>, id: i64) -> Result<Vec<TrackInfo>, String> {
+    library.playlist_tracks(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn create_playlist(app: AppHandle, library: State<Library>, name: String) -> Result<i64, String> {
+    let id = library.create_playlist(&name).map_err(|e| e.to_string())?;
+    library::emit_playlists(&app).map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+#[tauri::command]
+fn rename_playlist(
+    app: AppHandle,
+    library: State<Library>,
+    id: i64,
+    name: String,
+) -> Result<(), String> {
+    library
+        .rename_playlist(id, &name)
+        .and_then(|_| library::emit_playlists(&app))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_playlist(app: AppHandle, library: State<Library>, id: i64) -> Result<(), String> {
+    library
+        .delete_playlist(id)
+        .and_then(|_| library::emit_playlists(&app))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_to_playlist(
+    app: AppHandle,
+    library: State<Library>,
+    id: i64,
+    paths: Vec<String>,
+) -> Result<(), String> {
+    library
+        .add_to_playlist(id, &paths)
+        .and_then(|_| library::emit_playlists(&app))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_from_playlist(
+    app: AppHandle,
+    library: State<Library>,
+    id: i64,
+    positions: Vec<usize>,
+) -> Result<(), String> {
+    library
+        .remove_from_playlist(id, &positions)
+        .and_then(|_| library::emit_playlists(&app))
+        .map_err(|e| e.to_string())
+}
+
+/// Replaces the queue with the playlist and starts playing it with the playlist's own
+/// playback settings.
+#[tauri::command]
+fn queue_playlist(state: State<AppState>, library: State<Library>, id: i64) -> Result<(), String> {
+    let paths = library.playlist_paths(id).map_err(|e| e.to_string())?;
+    let settings = library.playlist_settings(id).map_err(|e| e.to_string())?;
+
+    state
+        .audio_player
+        .clear_queue()
+        .and_then(|_| state.audio_player.open(paths))
+        .and_then(|_| state.audio_player.apply_playlist_settings(settings))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_playlist_settings(
+    app: AppHandle,
+    library: State<Library>,
+    id: i64,
+    settings: PlaylistSettings,
+) -> Result<(), String> {
+    library
+        .set_playlist_settings(id, &settings)
+        .and_then(|_| library::emit_playlists(&app))
+        .map_err(|e| e.to_string())
+}
+
+/// Saves the queue, in its current order, as a new playlist and returns the playlist's id.
+#[tauri::command]
+async fn save_queue_as_playlist(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    library: State<'_, Library>,
+    name: String,
+) -> Result<i64, String> {
+    // Waiting on the audio thread blocks, so it's done off the async runtime.
+    let player = state.audio_player.clone();
+    let paths: Vec<String> = tauri::async_runtime::spawn_blocking(move || player.get_state())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?
+        .queue
+        .into_iter()
+        .map(|track| track.path)
+        .collect();
+
+    let id = library.create_playlist(&name).map_err(|e| e.to_string())?;
+    if let Err(e) = library.add_to_playlist(id, &paths) {
+        // Don't leave a half-filled playlist behind.
+        if let Err(e) = library.delete_playlist(id) {
+            log::error!("{}", e);
+        }
+        return Err(e.to_string());
+    }
+
+    library::emit_playlists(&app).map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+/// Replaces the queue with `tracks` and plays them, or appends them when `append` is set.
+/// With no tracks, e.g. from an empty library, the queue is left as it is.
+fn queue_tracks(state: &AppState, tracks: Vec<TrackInfo>, append: bool) -> Result<(), String> {
+    if tracks.is_empty() {
+        return Err(AudioError::NothingToPlayError.to_string());
+    }
+    let paths: Vec<String> = tracks.into_iter().map(|track| track.path).collect();
+
+    if append {
+        state.audio_player.add_queue(paths).map(|_| ())
+    } else {
+        state
+            .audio_player
+            .clear_queue()
+            .and_then(|_| state.audio_player.open(paths))
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn play_random_album(
+    state: State<AppState>,
+    library: State<Library>,
+    append: bool,
+) -> Result<(), String> {
+    let tracks = library.random_album_tracks().map_err(|e| e.to_string())?;
+    queue_tracks(&state, tracks, append)
+}
+
+#[tauri::command]
+fn surprise_me(
+    state: State<AppState>,
+    library: State<Library>,
+    count: usize,
+    append: bool,
+) -> Result<(), String> {
+    let tracks = library.random_tracks(count).map_err(|e| e.to_string())?;
+    queue_tracks(&state, tracks, append)
+}
+
+#[tauri::command]
+fn start_recording(recorder: State<Recorder>, path: String) -> Result<(), String> {
+    recorder
+        .start(PathBuf::from(path))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn stop_recording(recorder: State<'_, Recorder>) -> Result<(), String> {
+    recorder.stop().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_sync_master(
+    state: State<AppState>,
+    multiroom: State<MultiRoom>,
+    port: u16,
+) -> Result<(), String> {
+    multiroom
+        .start_master(state.audio_player.clone(), port)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn follow_sync_master(
+    state: State<AppState>,
+    multiroom: State<MultiRoom>,
+    address: String,
+) -> Result<(), String> {
+    multiroom
+        .follow(state.audio_player.clone(), address)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_sync(multiroom: State<MultiRoom>) -> Result<(), String> {
+    multiroom.stop().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_sync_role(multiroom: State<MultiRoom>) -> Result<Option<SyncRole>, String> {
+    multiroom.role().map_err(|e| e.to_string())
+}
+
+/// Discovery and the renderer's SOAP calls block on the network, so casting commands run
+/// off the async runtime.
+#[tauri::command]
+async fn list_renderers(app: AppHandle) -> Result<Vec<UpnpDeviceInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<DlnaCaster>().list_renderers())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cast_to(app: AppHandle, renderer_id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        app.state::<DlnaCaster>()
+            .cast_to(&state.audio_player, &renderer_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn stop_cast(app: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<DlnaCaster>().stop_cast())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// SSDP discovery and the server's SOAP calls block on the network, so browsing runs off
+/// the async runtime.
+#[tauri::command]
+async fn list_media_servers(app: AppHandle) -> Result<Vec<UpnpDeviceInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<DlnaBrowser>().list_servers())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn browse_media_server(
+    app: AppHandle,
+    server_id: String,
+    object_id: String,
+) -> Result<Vec<MediaEntry>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<DlnaBrowser>().browse(&server_id, &object_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn queue_media_items(state: State<AppState>, items: Vec<MediaEntry>) -> Result<(), String> {
+    let tracks = items
+        .iter()
+        .filter_map(|item| item.to_track_info())
+        .collect();
+
+    state
+        .audio_player
+        .add_tracks(tracks)
+        .map_err(|e| e.to_string())
+}
+
+/// Discovery waits out the mDNS timeout and connecting waits for the app to launch, so
+/// both run off the async runtime.
+#[tauri::command]
+async fn list_cast_devices(app: AppHandle) -> Result<Vec<CastDeviceInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<Chromecast>().discover())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn connect_cast_device(app: AppHandle, device_id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        app.state::<Chromecast>()
+            .connect(&state.audio_player, &device_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn disconnect_cast_device(
+    state: State<AppState>,
+    chromecast: State<Chromecast>,
+) -> Result<(), String> {
+    chromecast
+        .disconnect(&state.audio_player)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_smb_credentials(host: String, credentials: SmbCredentials) -> Result<(), String> {
+    smb::save_credentials(&host, &credentials).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_smb_credentials(host: String) -> Result<(), String> {
+    smb::remove_credentials(&host).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_webdav_credentials(host: String, credentials: WebDavCredentials) -> Result<(), String> {
+    webdav::save_credentials(&host, &credentials).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_webdav_credentials(host: String) -> Result<(), String> {
+    webdav::remove_credentials(&host).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_webdav_folder(path: String) -> Result<Vec<WebDavEntry>, String> {
+    // The PROPFIND request blocks until the server answers.
+    tauri::async_runtime::spawn_blocking(move || webdav::list(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_webdav_caching(app: AppHandle, enabled: bool) -> Result<(), String> {
+    let cache_dir = app.path().app_cache_dir().map_err(|e| e.to_string())?;
+    webdav::set_cache_dir(enabled.then(|| cache_dir.join("webdav")));
+    Ok(())
+}
+
+#[tauri::command]
+fn get_global_shortcuts(
+    shortcuts: State<GlobalShortcuts>,
+) -> Result<HashMap<ShortcutAction, String>, String> {
+    shortcuts.get_bindings().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_global_shortcut(
+    app: AppHandle,
+    shortcuts: State<GlobalShortcuts>,
+    action: ShortcutAction,
+    accelerator: Option<String>,
+) -> Result<(), String> {
+    shortcuts
+        .bind(&app, action, accelerator)
+        .map_err(|e| e.to_string())
+}
+
+struct AppState {
+    audio_player: AudioPlayer,
+}
+
+/// Enqueues and plays files handed to the app by the OS (file associations, "Open with").
+fn open_paths(handle: &AppHandle, paths: Vec<String>) {
+    if paths.is_empty() {
+        return;
+    }
+
+    if let Err(e) = handle.state::<AppState>().audio_player.open(paths) {
+        log::error!("{}", e);
+    }
+}
+
+#[allow(unused_variables)]
+fn handle_run_event(handle: &AppHandle, event: RunEvent) {
+    if let RunEvent::Exit = event {
+        if let Err(e) = handle.state::<SettingsStore>().flush() {
+            log::error!("Failed to save settings: {}", e);
+        }
+        return;
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let RunEvent::Opened { urls } = event {
+        let paths = urls
+            .iter()
+            .filter_map(|url| url.to_file_path().ok())
+            .map(|path| paths::normalized(&path))
+            .collect();
+
+        open_paths(handle, paths);
+    }
+}
+
+fn file_args<I: IntoIterator<Item = String>>(args: I, cwd: &Path) -> Vec<String> {
+    args.into_iter()
+        .skip(1)
+        .map(|arg| cwd.join(arg))
+        .filter(|path| path.is_file())
+        .map(|path| paths::normalized(&path))
+        .collect()
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    logging::init();
+    let builder = tauri::Builder::default();
+
+    // Must be the first plugin registered so a second launch exits before touching the audio device.
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        open_paths(app, file_args(args, Path::new(&cwd)));
+
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+    }));
+
+    #[cfg(target_os = "android")]
+    let builder = builder.plugin(audio_focus::init());
+
+    #[cfg(target_os = "ios")]
+    let builder = builder.plugin(now_playing::init());
+
+    builder
+        .setup(|app| {
+            let handle = app.handle();
+            if let Err(e) = logging::open_log_dir(&app.path().app_log_dir()?) {
+                log::error!("Failed to open the log file: {}", e);
+            }
+
+            let profiles = Profiles::load(app.path().app_data_dir()?, app.path().app_config_dir()?);
+            let profile = profiles.active()?;
+            app.manage(Library::open(&profiles.library_path(&profile))?);
+            app.manage(SettingsStore::load(profiles.settings_path(&profile)));
+            app.manage(profiles);
+
+            let audio_player = AudioPlayer::new(handle.clone());
+            shortcuts::setup(handle, audio_player.clone())?;
+            tray::setup(handle, audio_player.clone())?;
+
+            app.manage(AppState { audio_player });
+            app.manage(RemoteApi::default());
+            app.manage(StreamServer::default());
+            app.manage(MultiRoom::default());
+            app.manage(Recorder::default());
+            app.manage(DlnaCaster::default());
+            app.manage(Chromecast::default());
+            app.manage(DlnaBrowser::default());
+
+            let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::new());
+            open_paths(handle, file_args(std::env::args(), &cwd));
+
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            // Closing the window minimizes to the tray; playback stops only via "Quit".
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                let _ = window.hide();
+                api.prevent_close();
+            }
         })
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             add_queue,
+            add_folder,
             clear_queue,
             play,
             pause,
+            stop,
             resume,
             prev,
+            restart_track,
             next,
             set_position,
             set_looped,
             set_volume,
+            set_volume_curve,
+            volume_up,
+            volume_down,
+            set_position_update_interval,
+            get_position,
+            get_queue_summary,
+            set_volume_step,
+            bypass_dsp,
+            set_output_backend,
+            set_output_device,
+            list_output_devices,
+            set_output_format,
+            set_media_controls_enabled,
+            set_muted,
+            toggle_mute,
+            set_skip_silence,
+            set_trim_silence,
+            set_shuffle,
+            set_shuffle_weighting,
+            start_radio,
+            stop_radio,
+            set_endless,
+            set_announce_time,
+            reshuffle,
+            sort_queue,
+            remove_from_queue,
+            move_in_queue,
+            dedupe_queue,
+            edit_queue,
+            undo_queue,
+            redo_queue,
+            find_in_queue,
+            play_matching,
+            get_settings,
+            update_settings,
+            get_player_state,
+            get_session_history,
+            get_audio_diagnostics,
+            set_log_level,
+            get_recent_logs,
+            get_lyrics,
+            add_bookmark,
+            list_bookmarks,
+            delete_bookmark,
+            play_bookmark,
+            set_skip_rule,
+            get_skip_rules,
+            get_start_offsets,
+            set_start_offset_enabled,
+            clear_start_offset,
+            set_lyrics_offset,
+            start_remote_api,
+            stop_remote_api,
+            start_audio_stream,
+            stop_audio_stream,
+            export_tracks,
+            export_library,
+            create_backup,
+            restore_backup,
+            scan_replay_gain,
+            estimate_gain,
+            analyze_bpm,
+            get_tracks_by_bpm,
+            set_track_gain,
+            get_track_gain,
+            get_waveform,
+            set_track_rating,
+            import_itunes_library,
+            import_player_library,
+            scan_library,
+            list_profiles,
+            get_active_profile,
+            switch_profile,
+            delete_profile,
+            get_artists,
+            get_albums,
+            get_artist_tracks,
+            get_genres,
+            get_books,
+            get_book_tracks,
+            continue_book,
+            get_composers,
+            get_composer_works,
+            get_composer_tracks,
+            get_album_tracks,
+            get_recently_added_tracks,
+            get_recently_added_albums,
+            get_recently_played,
+            get_listening_time,
+            get_charts,
+            get_hourly_plays,
+            get_playlists,
+            get_playlist_tracks,
+            create_playlist,
+            rename_playlist,
+            delete_playlist,
+            add_to_playlist,
+            remove_from_playlist,
+            queue_playlist,
+            save_queue_as_playlist,
+            set_playlist_settings,
+            play_random_album,
+            surprise_me,
+            start_recording,
+            stop_recording,
+            start_sync_master,
+            follow_sync_master,
+            stop_sync,
+            get_sync_role,
+            list_renderers,
+            cast_to,
+            stop_cast,
+            list_media_servers,
+            browse_media_server,
+            queue_media_items,
+            list_cast_devices,
+            connect_cast_device,
+            disconnect_cast_device,
+            set_smb_credentials,
+            remove_smb_credentials,
+            set_webdav_credentials,
+            remove_webdav_credentials,
+            list_webdav_folder,
+            set_webdav_caching,
+            get_global_shortcuts,
+            set_global_shortcut,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(handle_run_event);
 }
@@ -0,0 +1,2248 @@
+use lofty::file::TaggedFile;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+
+use crate::audio_player::TrackInfo;
+use crate::audiobook;
+use crate::paths;
+use crate::shuffle::ShuffleMode;
+use crate::util::{self, SourceKind};
+
+/// Schema migrations, applied in order; `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE tracks (
+        path TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        artist TEXT NOT NULL,
+        album TEXT NOT NULL,
+        duration INTEGER NOT NULL,
+        track_number INTEGER,
+        disc_number INTEGER,
+        bpm REAL
+    );
+    CREATE INDEX tracks_bpm ON tracks (bpm);",
+    "CREATE TABLE albums (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        artist TEXT NOT NULL,
+        year INTEGER,
+        cover TEXT,
+        UNIQUE (title, artist)
+    );
+    ALTER TABLE tracks ADD COLUMN album_id INTEGER REFERENCES albums (id);
+    ALTER TABLE tracks ADD COLUMN album_artist TEXT;
+    ALTER TABLE tracks ADD COLUMN genre TEXT;
+    ALTER TABLE tracks ADD COLUMN year INTEGER;
+    CREATE INDEX tracks_album ON tracks (album_id);
+    CREATE INDEX tracks_genre ON tracks (genre);",
+    "ALTER TABLE tracks ADD COLUMN added_at INTEGER;
+    UPDATE tracks SET added_at = unixepoch();
+    CREATE INDEX tracks_added_at ON tracks (added_at);
+    CREATE TABLE history (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        played_at INTEGER NOT NULL
+    );
+    CREATE INDEX history_path_played_at ON history (path, played_at);",
+    "CREATE TABLE playlists (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE playlist_tracks (
+        playlist_id INTEGER NOT NULL REFERENCES playlists (id),
+        position INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        PRIMARY KEY (playlist_id, position)
+    );",
+    "ALTER TABLE tracks ADD COLUMN gain_db REAL;",
+    "CREATE TABLE waveforms (
+        hash TEXT NOT NULL,
+        buckets INTEGER NOT NULL,
+        peaks BLOB NOT NULL,
+        PRIMARY KEY (hash, buckets)
+    );",
+    "CREATE TABLE lyrics (
+        path TEXT PRIMARY KEY,
+        text TEXT,
+        fetched_at INTEGER NOT NULL
+    );",
+    "ALTER TABLE tracks ADD COLUMN lyrics_offset_ms INTEGER;",
+    "ALTER TABLE tracks ADD COLUMN rating INTEGER;
+    ALTER TABLE tracks ADD COLUMN imported_plays INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE tracks ADD COLUMN composer TEXT;
+    ALTER TABLE tracks ADD COLUMN conductor TEXT;
+    ALTER TABLE tracks ADD COLUMN work TEXT;
+    ALTER TABLE tracks ADD COLUMN movement TEXT;
+    CREATE INDEX tracks_composer ON tracks (composer);",
+    "ALTER TABLE tracks ADD COLUMN artists TEXT;
+    ALTER TABLE tracks ADD COLUMN genres TEXT;
+    UPDATE tracks SET artists = json_array(artist),
+        genres = CASE WHEN genre IS NULL THEN NULL ELSE json_array(genre) END;",
+    "ALTER TABLE tracks ADD COLUMN compilation INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE albums ADD COLUMN compilation INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE playlists ADD COLUMN settings TEXT;",
+    "CREATE TABLE books (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        author TEXT NOT NULL,
+        series TEXT,
+        UNIQUE (title, author)
+    );
+    ALTER TABLE tracks ADD COLUMN book_id INTEGER REFERENCES books (id);
+    ALTER TABLE tracks ADD COLUMN listened_ms INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX tracks_book ON tracks (book_id);",
+    "CREATE TABLE bookmarks (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        position_ms INTEGER NOT NULL,
+        label TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX bookmarks_path ON bookmarks (path, position_ms);",
+    "CREATE TABLE skip_rules (
+        album TEXT NOT NULL,
+        artist TEXT NOT NULL,
+        intro_ms INTEGER NOT NULL,
+        outro_ms INTEGER NOT NULL,
+        PRIMARY KEY (album, artist)
+    );",
+    "CREATE TABLE start_seeks (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        offset_ms INTEGER NOT NULL
+    );
+    CREATE INDEX start_seeks_path ON start_seeks (path);
+    ALTER TABLE tracks ADD COLUMN start_offset_ms INTEGER;",
+    "ALTER TABLE tracks ADD COLUMN start_offset_enabled INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE history ADD COLUMN played_ms INTEGER;",
+    "ALTER TABLE tracks ADD COLUMN performer TEXT;",
+];
+
+/// Seeks away from a track's opening that have to agree before it gets a start offset.
+const START_SEEKS_TO_LEARN: usize = 3;
+
+/// How far apart those seeks may land and still agree.
+const START_SEEK_TOLERANCE_MS: u64 = 3000;
+
+/// Schema version of a fully migrated database.
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Largest boost or cut a track's own gain can apply, in dB.
+const MAX_TRACK_GAIN_DB: f32 = 24.0;
+
+const TRACK_COLUMNS: &str = "path, title, artist, album, duration, track_number, disc_number, \
+    album_artist, genre, year, composer, conductor, work, movement, artists, genres, \
+    compilation, performer";
+
+/// Album columns for queries joining `albums` with its `tracks`, grouped by album.
+const ALBUM_COLUMNS: &str = "albums.id, albums.title, albums.artist, albums.year, albums.cover, \
+    COUNT(tracks.path), albums.compilation";
+
+/// Image files in an album's folder that are used as its cover, in order of preference.
+const COVER_FILE_NAMES: &[&str] = &[
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Database error: {0}")]
+    SqlError(#[from] rusqlite::Error),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Mutex lock error")]
+    LockError,
+
+    #[error("Database was written by a newer version of the app (schema {0})")]
+    NewerSchema(usize),
+
+    #[error("No bookmark with id {0}")]
+    BookmarkNotFound(i64),
+
+    #[error("Backup database is damaged: {0}")]
+    DamagedBackup(String),
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Title,
+    Artist,
+    Album,
+    Year,
+    DateAdded,
+    Duration,
+    PlayCount,
+}
+
+/// What a radio queue is built around.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum RadioSeed {
+    Track(String),
+    Artist(String),
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct SortOrder {
+    pub key: SortKey,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+impl SortKey {
+    /// SQL expression for the key over a `tracks` row.
+    fn track_expression(self) -> &'static str {
+        match self {
+            SortKey::Title => "tracks.title COLLATE NOCASE",
+            SortKey::Artist => "tracks.artist COLLATE NOCASE",
+            SortKey::Album => "tracks.album COLLATE NOCASE",
+            SortKey::Year => "tracks.year",
+            SortKey::DateAdded => "tracks.added_at",
+            SortKey::Duration => "tracks.duration",
+            SortKey::PlayCount => {
+                "(tracks.imported_plays
+                  + (SELECT COUNT(*) FROM history WHERE history.path = tracks.path))"
+            }
+        }
+    }
+
+    /// SQL expression for the key over an `albums` row grouped with its `tracks`.
+    fn album_expression(self) -> &'static str {
+        match self {
+            SortKey::Title | SortKey::Album => "albums.title COLLATE NOCASE",
+            SortKey::Artist => "albums.artist COLLATE NOCASE",
+            SortKey::Year => "albums.year",
+            SortKey::DateAdded => "MAX(tracks.added_at)",
+            SortKey::Duration => "SUM(tracks.duration)",
+            SortKey::PlayCount => {
+                "((SELECT COUNT(*) FROM history JOIN tracks AS played ON played.path = history.path
+                   WHERE played.album_id = albums.id)
+                  + (SELECT COALESCE(SUM(imported.imported_plays), 0) FROM tracks AS imported
+                     WHERE imported.album_id = albums.id))"
+            }
+        }
+    }
+}
+
+impl SortDirection {
+    fn sql(self) -> &'static str {
+        match self {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        }
+    }
+}
+
+impl SortOrder {
+    /// `ORDER BY` terms for track queries; ties keep album order.
+    fn track_clause(self) -> String {
+        format!(
+            "{} {}, tracks.album COLLATE NOCASE, tracks.disc_number, tracks.track_number, tracks.path",
+            self.key.track_expression(),
+            self.direction.sql()
+        )
+    }
+
+    fn album_clause(self) -> String {
+        format!(
+            "{} {}, albums.id",
+            self.key.album_expression(),
+            self.direction.sql()
+        )
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArtistEntry {
+    pub name: String,
+    pub album_count: u32,
+    pub track_count: u32,
+}
+
+/// An album in the library. `cover` is the path of an image file, either from the album's
+/// folder or extracted from its tags.
+#[derive(Serialize, Clone, Debug)]
+pub struct AlbumEntry {
+    pub id: i64,
+    pub title: String,
+    pub artist: String,
+    pub year: Option<u32>,
+    pub cover: Option<String>,
+    pub track_count: u32,
+    pub compilation: bool,
+}
+
+/// An audiobook, with how much of it has been listened to across its files. Durations are
+/// in seconds.
+#[derive(Serialize, Clone, Debug)]
+pub struct BookEntry {
+    pub id: i64,
+    pub title: String,
+    pub author: String,
+    pub series: Option<String>,
+    pub track_count: u32,
+    pub duration: u64,
+    pub listened: u64,
+}
+
+/// A marked moment in a track.
+#[derive(Serialize, Clone, Debug)]
+pub struct Bookmark {
+    pub id: i64,
+    pub path: String,
+    pub position_ms: u64,
+    pub label: String,
+    pub created_at: i64,
+}
+
+/// Where a track could start, learned from the user skipping its opening the same way
+/// several times. It's only used once the user enables it.
+#[derive(Serialize, Clone, Debug)]
+pub struct StartOffset {
+    pub path: String,
+    pub title: String,
+    pub artist: String,
+    pub offset_ms: u64,
+    pub enabled: bool,
+}
+
+/// Audio cut from the start and end of every track of an album, or of a podcast's episodes
+/// going by their album tag. `artist` is the album artist, or the track artist without one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SkipRule {
+    pub album: String,
+    pub artist: String,
+    pub intro_ms: u64,
+    pub outro_ms: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GenreEntry {
+    pub name: String,
+    pub track_count: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ComposerEntry {
+    pub name: String,
+    pub work_count: u32,
+    pub track_count: u32,
+}
+
+/// A composer's work. Tracks without a work tag are grouped under their album instead.
+#[derive(Serialize, Clone, Debug)]
+pub struct WorkEntry {
+    pub title: String,
+    pub track_count: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PlaylistEntry {
+    pub id: i64,
+    pub name: String,
+    pub track_count: u32,
+    pub settings: PlaylistSettings,
+}
+
+/// Playback preferences applied when a playlist is queued. Unset ones leave the player as
+/// it is.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct PlaylistSettings {
+    pub shuffle: Option<ShuffleMode>,
+    pub crossfade_ms: Option<u64>,
+    pub speed: Option<f32>,
+}
+
+/// Granularity of listening-time reports.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    Day,
+    Week,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ListeningTime {
+    /// `YYYY-MM-DD` for days, `YYYY-Www` for weeks, in local time.
+    pub period: String,
+    pub plays: u32,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChartEntry {
+    pub name: String,
+    pub artist: Option<String>,
+    pub path: Option<String>,
+    pub plays: u32,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Charts {
+    pub artists: Vec<ChartEntry>,
+    pub albums: Vec<ChartEntry>,
+    pub tracks: Vec<ChartEntry>,
+}
+
+/// Everything the library stores about a track, for exports.
+#[derive(Serialize, Clone, Debug)]
+pub struct TrackRecord {
+    pub path: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    pub performer: Option<String>,
+    pub work: Option<String>,
+    pub movement: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub duration: u64,
+    pub bpm: Option<f32>,
+    pub gain_db: Option<f32>,
+    pub rating: Option<u8>,
+    /// Plays recorded here plus any imported from another player.
+    pub play_count: u32,
+    pub last_played: Option<i64>,
+    pub added_at: Option<i64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PlaylistRecord {
+    pub name: String,
+    pub paths: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ScanProgress {
+    path: String,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+/// SQLite-backed store for per-track data that outlives the queue.
+pub struct Library {
+    conn: Mutex<Connection>,
+    /// Database file; changes when another profile is opened.
+    path: Mutex<PathBuf>,
+}
+
+impl Library {
+    pub fn open(path: &Path) -> Result<Library, LibraryError> {
+        Ok(Library {
+            conn: Mutex::new(open_database(path)?),
+            path: Mutex::new(path.to_path_buf()),
+        })
+    }
+
+    /// Closes the current database and continues with the one at `path`, creating it if
+    /// needed.
+    pub fn switch_to(&self, path: &Path) -> Result<(), LibraryError> {
+        let conn = open_database(path)?;
+        *self.conn()? = conn;
+        *self.path.lock().map_err(|_| LibraryError::LockError)? = path.to_path_buf();
+        Ok(())
+    }
+
+    fn path(&self) -> Result<PathBuf, LibraryError> {
+        self.path
+            .lock()
+            .map(|path| path.clone())
+            .map_err(|_| LibraryError::LockError)
+    }
+
+    /// Folder holding album covers extracted from tags, next to the database.
+    pub fn covers_dir(&self) -> Result<PathBuf, LibraryError> {
+        Ok(self.path()?.with_file_name("covers"))
+    }
+
+    pub fn schema_version(&self) -> Result<usize, LibraryError> {
+        Ok(self
+            .conn()?
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Writes a consistent copy of the database to `dest`, which must not exist yet.
+    pub fn backup_to(&self, dest: &Path) -> Result<(), LibraryError> {
+        self.conn()?
+            .execute("VACUUM INTO ?1", params![dest.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Replaces the database with the one at `src`, upgrading it if it's from an older
+    /// version. Databases from newer versions are refused, as this one can't read them safely.
+    pub fn restore_from(&self, src: &Path) -> Result<(), LibraryError> {
+        let version: usize =
+            Connection::open(src)?.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(LibraryError::NewerSchema(version));
+        }
+
+        // The backup is copied and checked next to the database first, so a failed or
+        // interrupted copy never touches the live file.
+        let path = self.path()?;
+        let staged = path.with_extension("restore");
+        if let Err(e) = stage_restore(src, &staged) {
+            let _ = fs::remove_file(&staged);
+            return Err(e);
+        }
+
+        let mut conn = self.conn()?;
+        // Close the current database before replacing its file.
+        let original = std::mem::replace(&mut *conn, Connection::open_in_memory()?);
+        drop(original);
+
+        match fs::rename(&staged, &path)
+            .map_err(LibraryError::from)
+            .and_then(|_| {
+                let mut restored = Connection::open(&path)?;
+                migrate(&mut restored)?;
+                Ok(restored)
+            }) {
+            Ok(restored) => {
+                *conn = restored;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&staged);
+                *conn = open_database(&path)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, LibraryError> {
+        self.conn.lock().map_err(|_| LibraryError::LockError)
+    }
+
+    /// Reads the file's tags and inserts it, or refreshes its tag data while keeping
+    /// analysis results. The album entry is created on first sight of the album.
+    pub fn add_file(&self, path: &str) -> Result<(), LibraryError> {
+        let tagged_file = util::read_tagged_file(path);
+        let track = util::track_info_from_tagged_file(tagged_file.as_ref(), path, 0);
+
+        let conn = self.conn()?;
+        // Compilation tracks without an album artist would otherwise each make an album of
+        // their own, one per track artist.
+        let album_artist = match &track.album_artist {
+            Some(album_artist) => album_artist,
+            None if track.compilation => util::VARIOUS_ARTISTS,
+            None => &track.artist,
+        };
+        let album_id: i64 = conn.query_row(
+            "INSERT INTO albums (title, artist, compilation) VALUES (?1, ?2, ?3)
+             ON CONFLICT (title, artist) DO UPDATE SET
+                compilation = albums.compilation OR excluded.compilation
+             RETURNING id",
+            params![track.album, album_artist, track.compilation],
+            |row| row.get(0),
+        )?;
+
+        let book_id: Option<i64> =
+            match audiobook::identify(path, tagged_file.as_ref(), &track.genres) {
+                Some(book) => Some(conn.query_row(
+                    "INSERT INTO books (title, author, series) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (title, author) DO UPDATE SET
+                        series = COALESCE(excluded.series, books.series)
+                     RETURNING id",
+                    params![book.title, book.author, book.series],
+                    |row| row.get(0),
+                )?),
+                None => None,
+            };
+
+        conn.execute(
+            "INSERT INTO tracks (path, title, artist, album, duration, track_number, disc_number,
+                album_id, album_artist, genre, year, composer, conductor, work, movement,
+                artists, genres, compilation, book_id, performer, added_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                ?17, ?18, ?19, ?20, unixepoch())
+             ON CONFLICT (path) DO UPDATE SET
+                title = excluded.title,
+                artist = excluded.artist,
+                album = excluded.album,
+                duration = excluded.duration,
+                track_number = excluded.track_number,
+                disc_number = excluded.disc_number,
+                album_id = excluded.album_id,
+                album_artist = excluded.album_artist,
+                genre = excluded.genre,
+                year = excluded.year,
+                composer = excluded.composer,
+                conductor = excluded.conductor,
+                work = excluded.work,
+                movement = excluded.movement,
+                artists = excluded.artists,
+                genres = excluded.genres,
+                compilation = excluded.compilation,
+                book_id = excluded.book_id,
+                performer = excluded.performer",
+            params![
+                track.path,
+                track.title,
+                track.artist,
+                track.album,
+                track.duration,
+                track.track_number,
+                track.disc_number,
+                album_id,
+                track.album_artist,
+                track.genre,
+                track.year,
+                track.composer,
+                track.conductor,
+                track.work,
+                track.movement,
+                list_to_json(&track.artists),
+                list_to_json(&track.genres),
+                track.compilation,
+                book_id,
+                track.performer,
+            ],
+        )?;
+
+        conn.execute(
+            "UPDATE albums SET year = COALESCE(year, ?2) WHERE id = ?1",
+            params![album_id, track.year],
+        )?;
+
+        let cover: Option<String> = conn.query_row(
+            "SELECT cover FROM albums WHERE id = ?1",
+            params![album_id],
+            |row| row.get(0),
+        )?;
+
+        if cover.is_none() {
+            if let Some(cover) = self.find_cover(path, tagged_file.as_ref(), album_id) {
+                conn.execute(
+                    "UPDATE albums SET cover = ?2 WHERE id = ?1",
+                    params![album_id, cover],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks for a cover image next to the file, then falls back to extracting the embedded one.
+    fn find_cover(
+        &self,
+        path: &str,
+        tagged_file: Option<&TaggedFile>,
+        album_id: i64,
+    ) -> Option<String> {
+        if !util::is_network_path(path) {
+            let folder = Path::new(path).parent()?;
+            if let Some(cover) = COVER_FILE_NAMES
+                .iter()
+                .map(|name| folder.join(name))
+                .find(|cover| cover.is_file())
+            {
+                return Some(cover.to_string_lossy().into_owned());
+            }
+        }
+
+        let picture = util::cover_picture(tagged_file?)?;
+
+        let covers_dir = self.covers_dir().ok()?;
+        let cover = covers_dir.join(format!("{}.{}", album_id, util::picture_extension(picture)));
+        if let Err(e) =
+            fs::create_dir_all(&covers_dir).and_then(|_| fs::write(&cover, picture.data()))
+        {
+            log::error!("Failed to save cover art: {}", e);
+            return None;
+        }
+
+        Some(cover.to_string_lossy().into_owned())
+    }
+
+    /// Logs a play in the history and returns its id, for `set_played_time` once it ends.
+    /// Tracks played without being scanned are added with the tag data the queue already has.
+    pub fn record_play(&self, track: &TrackInfo) -> Result<i64, LibraryError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO tracks (path, title, artist, album, duration, track_number, disc_number,
+                artists, genres, added_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, unixepoch())
+             ON CONFLICT (path) DO NOTHING",
+            params![
+                track.path,
+                track.title,
+                track.artist,
+                track.album,
+                track.duration,
+                track.track_number,
+                track.disc_number,
+                list_to_json(&track.artists),
+                list_to_json(&track.genres),
+            ],
+        )?;
+        conn.execute(
+            "INSERT INTO history (path, played_at, played_ms) VALUES (?1, unixepoch(), 0)",
+            params![track.path],
+        )?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Records how much of a play from `record_play` was actually listened to.
+    pub fn set_played_time(&self, history_id: i64, played_ms: u64) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE history SET played_ms = ?2 WHERE id = ?1",
+            params![history_id, played_ms as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_bpm(&self, path: &str, bpm: f32) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE tracks SET bpm = ?2 WHERE path = ?1",
+            params![path, bpm],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_bpm(&self, path: &str) -> Result<Option<f32>, LibraryError> {
+        let bpm = self
+            .conn()?
+            .query_row(
+                "SELECT bpm FROM tracks WHERE path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(bpm.flatten())
+    }
+
+    /// Sets the volume offset applied whenever `path` plays, within `MAX_TRACK_GAIN_DB` either
+    /// way; `None` removes it, as does a value that isn't a number.
+    pub fn set_track_gain(&self, path: &str, gain_db: Option<f32>) -> Result<(), LibraryError> {
+        let gain_db = gain_db
+            .filter(|db| db.is_finite())
+            .map(|db| db.clamp(-MAX_TRACK_GAIN_DB, MAX_TRACK_GAIN_DB));
+        self.add_file(path)?;
+        self.conn()?.execute(
+            "UPDATE tracks SET gain_db = ?2 WHERE path = ?1",
+            params![path, gain_db],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_track_gain(&self, path: &str) -> Result<Option<f32>, LibraryError> {
+        let gain_db = self
+            .conn()?
+            .query_row(
+                "SELECT gain_db FROM tracks WHERE path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(gain_db.flatten())
+    }
+
+    /// Cached waveform peaks for the file content `hash` at the given width.
+    pub fn waveform(&self, hash: &str, buckets: usize) -> Result<Option<Vec<f32>>, LibraryError> {
+        let peaks: Option<Vec<u8>> = self
+            .conn()?
+            .query_row(
+                "SELECT peaks FROM waveforms WHERE hash = ?1 AND buckets = ?2",
+                params![hash, buckets as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(peaks.map(|peaks| peaks.iter().map(|&peak| peak as f32 / 255.0).collect()))
+    }
+
+    /// Stores peaks (0.0 to 1.0) quantized to a byte each.
+    pub fn store_waveform(
+        &self,
+        hash: &str,
+        buckets: usize,
+        peaks: &[f32],
+    ) -> Result<(), LibraryError> {
+        let peaks: Vec<u8> = peaks
+            .iter()
+            .map(|peak| (peak.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO waveforms (hash, buckets, peaks) VALUES (?1, ?2, ?3)",
+            params![hash, buckets as i64, peaks],
+        )?;
+        Ok(())
+    }
+
+    /// Lyrics fetched online for `path`. The outer `None` means they were never looked up; the
+    /// inner one that the lookup found nothing.
+    pub fn cached_lyrics(&self, path: &str) -> Result<Option<Option<String>>, LibraryError> {
+        let text = self
+            .conn()?
+            .query_row(
+                "SELECT text FROM lyrics WHERE path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(text)
+    }
+
+    pub fn store_lyrics(&self, path: &str, text: Option<&str>) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO lyrics (path, text, fetched_at) VALUES (?1, ?2, unixepoch())",
+            params![path, text],
+        )?;
+        Ok(())
+    }
+
+    /// Sets a rating of 1 to 5 stars; `None` clears it.
+    pub fn set_rating(&self, path: &str, rating: Option<u8>) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE tracks SET rating = ?2 WHERE path = ?1",
+            params![path, rating.map(|rating| rating.clamp(1, 5))],
+        )?;
+        Ok(())
+    }
+
+    /// Plays counted by another player before the track was imported.
+    pub fn set_imported_plays(&self, path: &str, plays: u32) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE tracks SET imported_plays = ?2 WHERE path = ?1",
+            params![path, plays],
+        )?;
+        Ok(())
+    }
+
+    /// Shifts the lyrics of `path` by `offset_ms`; positive values show lines earlier.
+    pub fn set_lyrics_offset(&self, path: &str, offset_ms: i64) -> Result<(), LibraryError> {
+        self.add_file(path)?;
+        self.conn()?.execute(
+            "UPDATE tracks SET lyrics_offset_ms = ?2 WHERE path = ?1",
+            params![path, offset_ms],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_lyrics_offset(&self, path: &str) -> Result<i64, LibraryError> {
+        let offset_ms: Option<Option<i64>> = self
+            .conn()?
+            .query_row(
+                "SELECT lyrics_offset_ms FROM tracks WHERE path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(offset_ms.flatten().unwrap_or(0))
+    }
+
+    pub fn add_bookmark(
+        &self,
+        path: &str,
+        position_ms: u64,
+        label: &str,
+    ) -> Result<i64, LibraryError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO bookmarks (path, position_ms, label, created_at)
+             VALUES (?1, ?2, ?3, unixepoch())",
+            params![path, position_ms, label],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn delete_bookmark(&self, id: i64) -> Result<(), LibraryError> {
+        self.conn()?
+            .execute("DELETE FROM bookmarks WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// The track's bookmarks in position order.
+    pub fn bookmarks(&self, path: &str) -> Result<Vec<Bookmark>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT id, path, position_ms, label, created_at FROM bookmarks
+             WHERE path = ?1
+             ORDER BY position_ms",
+        )?;
+
+        let bookmarks = statement
+            .query_map(params![path], bookmark_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(bookmarks)
+    }
+
+    pub fn bookmark(&self, id: i64) -> Result<Bookmark, LibraryError> {
+        self.conn()?
+            .query_row(
+                "SELECT id, path, position_ms, label, created_at FROM bookmarks WHERE id = ?1",
+                params![id],
+                bookmark_from_row,
+            )
+            .optional()?
+            .ok_or(LibraryError::BookmarkNotFound(id))
+    }
+
+    /// Saves the rule, or removes it when it skips nothing.
+    pub fn set_skip_rule(&self, rule: &SkipRule) -> Result<(), LibraryError> {
+        let conn = self.conn()?;
+        if rule.intro_ms == 0 && rule.outro_ms == 0 {
+            conn.execute(
+                "DELETE FROM skip_rules WHERE album = ?1 AND artist = ?2",
+                params![rule.album, rule.artist],
+            )?;
+        } else {
+            conn.execute(
+                "INSERT OR REPLACE INTO skip_rules (album, artist, intro_ms, outro_ms)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![rule.album, rule.artist, rule.intro_ms, rule.outro_ms],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn skip_rule(&self, album: &str, artist: &str) -> Result<Option<SkipRule>, LibraryError> {
+        let rule = self
+            .conn()?
+            .query_row(
+                "SELECT album, artist, intro_ms, outro_ms FROM skip_rules
+                 WHERE album = ?1 AND artist = ?2",
+                params![album, artist],
+                skip_rule_from_row,
+            )
+            .optional()?;
+        Ok(rule)
+    }
+
+    pub fn skip_rules(&self) -> Result<Vec<SkipRule>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT album, artist, intro_ms, outro_ms FROM skip_rules
+             ORDER BY artist COLLATE NOCASE, album COLLATE NOCASE",
+        )?;
+
+        let rules = statement
+            .query_map([], skip_rule_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rules)
+    }
+
+    /// Notes a seek to `offset_ms` made just after a library track started. Once the last
+    /// few such seeks agree, their median becomes the track's start offset and is returned
+    /// to be offered; it stays disabled until the user accepts it.
+    pub fn record_start_seek(
+        &self,
+        path: &str,
+        offset_ms: u64,
+    ) -> Result<Option<u64>, LibraryError> {
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "INSERT INTO start_seeks (path, offset_ms)
+             SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM tracks WHERE path = ?1)",
+            params![path, offset_ms],
+        )?;
+        // Only the latest seeks count, so older ones are dropped.
+        transaction.execute(
+            "DELETE FROM start_seeks WHERE path = ?1 AND id NOT IN
+             (SELECT id FROM start_seeks WHERE path = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![path, START_SEEKS_TO_LEARN],
+        )?;
+
+        let seeks = transaction
+            .prepare("SELECT offset_ms FROM start_seeks WHERE path = ?1")?
+            .query_map(params![path], |row| row.get(0))?
+            .collect::<Result<Vec<u64>, _>>()?;
+        let learned = match agreed_offset(seeks) {
+            Some(offset) => {
+                let changed = transaction.execute(
+                    "UPDATE tracks SET start_offset_ms = ?2, start_offset_enabled = 0
+                     WHERE path = ?1 AND start_offset_ms IS NOT ?2",
+                    params![path, offset],
+                )?;
+                (changed > 0).then_some(offset)
+            }
+            None => None,
+        };
+
+        transaction.commit()?;
+        Ok(learned)
+    }
+
+    /// The start offset of a track, if it has one the user enabled.
+    pub fn start_offset(&self, path: &str) -> Result<Option<u64>, LibraryError> {
+        let offset = self
+            .conn()?
+            .query_row(
+                "SELECT start_offset_ms FROM tracks WHERE path = ?1 AND start_offset_enabled",
+                params![path],
+                |row| row.get::<_, Option<u64>>(0),
+            )
+            .optional()?;
+        Ok(offset.flatten())
+    }
+
+    pub fn start_offsets(&self) -> Result<Vec<StartOffset>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT path, title, artist, start_offset_ms, start_offset_enabled FROM tracks
+             WHERE start_offset_ms IS NOT NULL
+             ORDER BY artist COLLATE NOCASE, title COLLATE NOCASE",
+        )?;
+
+        let offsets = statement
+            .query_map([], |row| {
+                Ok(StartOffset {
+                    path: row.get(0)?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    offset_ms: row.get(3)?,
+                    enabled: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(offsets)
+    }
+
+    /// Accepts or declines the start offset offered for a track. A declined one is kept, so
+    /// it isn't offered again unless later seeks settle somewhere else.
+    pub fn set_start_offset_enabled(&self, path: &str, enabled: bool) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE tracks SET start_offset_enabled = ?2
+             WHERE path = ?1 AND start_offset_ms IS NOT NULL",
+            params![path, enabled],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a track's start offset along with the seeks it was learned from, so it
+    /// takes as many again to come back.
+    pub fn clear_start_offset(&self, path: &str) -> Result<(), LibraryError> {
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "UPDATE tracks SET start_offset_ms = NULL, start_offset_enabled = 0 WHERE path = ?1",
+            params![path],
+        )?;
+        transaction.execute("DELETE FROM start_seeks WHERE path = ?1", params![path])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Tracks whose detected tempo lies within `min..=max`, slowest first unless `sort` is given.
+    pub fn tracks_by_bpm(
+        &self,
+        min: f32,
+        max: f32,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks WHERE bpm BETWEEN ?1 AND ?2 ORDER BY {}",
+            TRACK_COLUMNS,
+            track_order(sort, "bpm")
+        ))?;
+
+        let tracks = statement
+            .query_map(params![min, max], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Album artists other than compilations' and every artist credited on a track, with the
+    /// albums they appear on and their track counts, alphabetically.
+    pub fn artists(&self) -> Result<Vec<ArtistEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT name, COUNT(DISTINCT album_id), COUNT(DISTINCT path) FROM (
+                SELECT albums.artist AS name, tracks.album_id, tracks.path
+                FROM tracks JOIN albums ON albums.id = tracks.album_id
+                WHERE NOT albums.compilation
+                UNION ALL
+                SELECT credit.value, tracks.album_id, tracks.path
+                FROM tracks, json_each(tracks.artists) AS credit
+             )
+             GROUP BY name
+             ORDER BY name COLLATE NOCASE",
+        )?;
+
+        let artists = statement
+            .query_map([], |row| {
+                Ok(ArtistEntry {
+                    name: row.get(0)?,
+                    album_count: row.get(1)?,
+                    track_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(artists)
+    }
+
+    /// All albums, or only those by or featuring `artist`, ordered by artist then year unless
+    /// `sort` is given.
+    pub fn albums(
+        &self,
+        artist: Option<&str>,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<AlbumEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM albums JOIN tracks ON tracks.album_id = albums.id
+             WHERE ?1 IS NULL OR albums.artist = ?1 OR albums.id IN (
+                SELECT album_id FROM tracks, json_each(tracks.artists) AS credit
+                WHERE credit.value = ?1
+             )
+             GROUP BY albums.id
+             ORDER BY {}",
+            ALBUM_COLUMNS,
+            album_order(
+                sort,
+                "albums.artist COLLATE NOCASE, albums.year, albums.title COLLATE NOCASE"
+            )
+        ))?;
+
+        let albums = statement
+            .query_map(params![artist], album_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(albums)
+    }
+
+    /// Tracks credited to `artist`, including those where they're one of several, in album
+    /// order unless `sort` is given.
+    pub fn artist_tracks(
+        &self,
+        artist: &str,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks
+             WHERE artist = ?1
+                OR EXISTS (SELECT 1 FROM json_each(tracks.artists) WHERE value = ?1)
+             ORDER BY {}",
+            TRACK_COLUMNS,
+            track_order(
+                sort,
+                "album COLLATE NOCASE, disc_number, track_number, path"
+            )
+        ))?;
+
+        let tracks = statement
+            .query_map(params![artist], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Every genre tagged on a track, with track counts. A track with several genres counts
+    /// towards each.
+    pub fn genres(&self) -> Result<Vec<GenreEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT credit.value, COUNT(*) FROM tracks, json_each(tracks.genres) AS credit
+             GROUP BY credit.value
+             ORDER BY credit.value COLLATE NOCASE",
+        )?;
+
+        let genres = statement
+            .query_map([], |row| {
+                Ok(GenreEntry {
+                    name: row.get(0)?,
+                    track_count: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(genres)
+    }
+
+    /// Audiobooks by author, then series, then title.
+    pub fn books(&self) -> Result<Vec<BookEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT books.id, books.title, books.author, books.series, COUNT(tracks.path),
+                    SUM(tracks.duration), SUM(MIN(tracks.listened_ms, tracks.duration * 1000)) / 1000
+             FROM books JOIN tracks ON tracks.book_id = books.id
+             GROUP BY books.id
+             ORDER BY books.author COLLATE NOCASE, books.series COLLATE NOCASE,
+                books.title COLLATE NOCASE",
+        )?;
+
+        let books = statement
+            .query_map([], |row| {
+                Ok(BookEntry {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    author: row.get(2)?,
+                    series: row.get(3)?,
+                    track_count: row.get(4)?,
+                    duration: row.get(5)?,
+                    listened: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(books)
+    }
+
+    /// The book's files in reading order.
+    pub fn book_tracks(&self, book_id: i64) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks WHERE book_id = ?1
+             ORDER BY disc_number, track_number, path",
+            TRACK_COLUMNS
+        ))?;
+
+        let tracks = statement
+            .query_map(params![book_id], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Where to pick the book up: the first file not yet finished and the position reached
+    /// in it. A finished book starts over.
+    pub fn book_resume_point(
+        &self,
+        book_id: i64,
+    ) -> Result<(Vec<TrackInfo>, usize, u64), LibraryError> {
+        let tracks = self.book_tracks(book_id)?;
+        let conn = self.conn()?;
+        let mut statement = conn.prepare("SELECT listened_ms FROM tracks WHERE path = ?1")?;
+
+        for (index, track) in tracks.iter().enumerate() {
+            let listened: u64 = statement.query_row(params![track.path], |row| row.get(0))?;
+            if listened + audiobook::FINISHED_MARGIN_MS < track.duration * 1000 {
+                return Ok((tracks, index, listened));
+            }
+        }
+        Ok((tracks, 0, 0))
+    }
+
+    /// Remembers how far into an audiobook file playback got. Other tracks are left alone.
+    pub fn record_listening(&self, path: &str, position_ms: u64) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE tracks SET listened_ms = ?2 WHERE path = ?1 AND book_id IS NOT NULL",
+            params![path, position_ms],
+        )?;
+        Ok(())
+    }
+
+    /// Composers with their work and track counts, alphabetically.
+    pub fn composers(&self) -> Result<Vec<ComposerEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT composer, COUNT(DISTINCT COALESCE(work, album)), COUNT(*) FROM tracks
+             WHERE composer IS NOT NULL
+             GROUP BY composer
+             ORDER BY composer COLLATE NOCASE",
+        )?;
+
+        let composers = statement
+            .query_map([], |row| {
+                Ok(ComposerEntry {
+                    name: row.get(0)?,
+                    work_count: row.get(1)?,
+                    track_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(composers)
+    }
+
+    /// The composer's works, alphabetically.
+    pub fn composer_works(&self, composer: &str) -> Result<Vec<WorkEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT COALESCE(work, album) AS title, COUNT(*) FROM tracks
+             WHERE composer = ?1
+             GROUP BY title
+             ORDER BY title COLLATE NOCASE",
+        )?;
+
+        let works = statement
+            .query_map(params![composer], |row| {
+                Ok(WorkEntry {
+                    title: row.get(0)?,
+                    track_count: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(works)
+    }
+
+    /// The composer's tracks, or only those of `work`, grouped by work and then in album,
+    /// disc and track order unless `sort` is given.
+    pub fn composer_tracks(
+        &self,
+        composer: &str,
+        work: Option<&str>,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks
+             WHERE composer = ?1 AND (?2 IS NULL OR COALESCE(work, album) = ?2)
+             ORDER BY {}",
+            TRACK_COLUMNS,
+            track_order(
+                sort,
+                "COALESCE(work, album) COLLATE NOCASE, album_id, disc_number, track_number, path"
+            )
+        ))?;
+
+        let tracks = statement
+            .query_map(params![composer, work], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Tracks added within the last `days` days, newest first unless `sort` is given.
+    pub fn recently_added_tracks(
+        &self,
+        days: u32,
+        offset: u32,
+        limit: u32,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks
+             WHERE added_at >= unixepoch() - ?1 * 86400
+             ORDER BY {}
+             LIMIT ?2 OFFSET ?3",
+            TRACK_COLUMNS,
+            track_order(sort, "added_at DESC, path")
+        ))?;
+
+        let tracks = statement
+            .query_map(params![days, limit, offset], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Albums that gained tracks within the last `days` days, newest first unless `sort` is given.
+    pub fn recently_added_albums(
+        &self,
+        days: u32,
+        offset: u32,
+        limit: u32,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<AlbumEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM albums JOIN tracks ON tracks.album_id = albums.id
+             GROUP BY albums.id
+             HAVING MAX(tracks.added_at) >= unixepoch() - ?1 * 86400
+             ORDER BY {}
+             LIMIT ?2 OFFSET ?3",
+            ALBUM_COLUMNS,
+            album_order(sort, "MAX(tracks.added_at) DESC, albums.id")
+        ))?;
+
+        let albums = statement
+            .query_map(params![days, limit, offset], album_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(albums)
+    }
+
+    /// Played tracks ordered by their most recent play, each listed once.
+    pub fn recently_played_tracks(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks
+             JOIN (SELECT path, MAX(played_at) AS last_played FROM history GROUP BY path) recent
+                ON recent.path = tracks.path
+             ORDER BY recent.last_played DESC
+             LIMIT ?1 OFFSET ?2",
+            prefixed_columns("tracks", TRACK_COLUMNS)
+        ))?;
+
+        let tracks = statement
+            .query_map(params![limit, offset], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Up to `count` tracks resembling `seed`, best matches first: same artist, then same
+    /// genre, then a close tempo or year, then tracks that were played before. Ties are
+    /// broken at random so repeated calls vary, and paths in `exclude` are skipped.
+    pub fn similar_tracks(
+        &self,
+        seed: &RadioSeed,
+        exclude: &[String],
+        count: usize,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        // An artist seed uses the artist's most common genre and average year.
+        let (seed_query, value) = match seed {
+            RadioSeed::Track(path) => (
+                "SELECT artist, genre, bpm, year FROM tracks WHERE path = ?1",
+                path,
+            ),
+            RadioSeed::Artist(artist) => (
+                "SELECT ?1 AS artist,
+                    (SELECT genre FROM tracks WHERE artist = ?1 AND genre IS NOT NULL
+                     GROUP BY genre ORDER BY COUNT(*) DESC LIMIT 1) AS genre,
+                    NULL AS bpm,
+                    (SELECT CAST(AVG(year) AS INTEGER) FROM tracks WHERE artist = ?1) AS year",
+                artist,
+            ),
+        };
+
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks
+             CROSS JOIN ({}) seed
+             LEFT JOIN (SELECT DISTINCT path FROM history) played ON played.path = tracks.path
+             WHERE tracks.path != ?1
+             ORDER BY COALESCE(tracks.artist = seed.artist, 0) * 4
+                + COALESCE(tracks.genre = seed.genre, 0) * 2
+                + COALESCE(ABS(tracks.bpm - seed.bpm) <= 8, 0)
+                + COALESCE(ABS(tracks.year - seed.year) <= 5, 0)
+                + (played.path IS NOT NULL) DESC,
+                RANDOM()
+             LIMIT ?2",
+            prefixed_columns("tracks", TRACK_COLUMNS),
+            seed_query
+        ))?;
+
+        let tracks = statement
+            .query_map(
+                params![value, (count + exclude.len()) as i64],
+                track_from_row,
+            )?
+            .filter(|track| {
+                track
+                    .as_ref()
+                    .map_or(true, |track| !exclude.contains(&track.path))
+            })
+            .take(count)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Cover image of the album a scanned track belongs to.
+    pub fn track_cover(&self, path: &str) -> Result<Option<String>, LibraryError> {
+        let conn = self.conn()?;
+        let cover = conn
+            .query_row(
+                "SELECT albums.cover FROM tracks JOIN albums ON albums.id = tracks.album_id
+                 WHERE tracks.path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(cover.flatten())
+    }
+
+    /// The album's tracks in disc and track order unless `sort` is given.
+    pub fn album_tracks(
+        &self,
+        album_id: i64,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks WHERE album_id = ?1 ORDER BY {}",
+            TRACK_COLUMNS,
+            track_order(sort, "disc_number, track_number, path")
+        ))?;
+
+        let tracks = statement
+            .query_map(params![album_id], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Tracks of an album picked at random, in album order. Empty if the library has none.
+    pub fn random_album_tracks(&self) -> Result<Vec<TrackInfo>, LibraryError> {
+        let album_id: Option<i64> = self
+            .conn()?
+            .query_row(
+                "SELECT album_id FROM tracks WHERE album_id IS NOT NULL
+                 GROUP BY album_id ORDER BY RANDOM() LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match album_id {
+            Some(album_id) => self.album_tracks(album_id, None),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Up to `count` tracks picked at random from the whole library.
+    pub fn random_tracks(&self, count: usize) -> Result<Vec<TrackInfo>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM tracks ORDER BY RANDOM() LIMIT ?1",
+            TRACK_COLUMNS
+        ))?;
+
+        let tracks = statement
+            .query_map(params![count as i64], track_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(index_tracks(tracks))
+    }
+
+    /// Plays and listening time per day or week between the unix times `since` and `until`.
+    /// Plays from before the time played was recorded count their full duration.
+    pub fn listening_time(
+        &self,
+        period: ReportPeriod,
+        since: i64,
+        until: i64,
+    ) -> Result<Vec<ListeningTime>, LibraryError> {
+        let format = match period {
+            ReportPeriod::Day => "%Y-%m-%d",
+            ReportPeriod::Week => "%Y-W%W",
+        };
+
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT strftime(?3, history.played_at, 'unixepoch', 'localtime') AS period,
+                COUNT(*),
+                COALESCE(SUM(COALESCE(history.played_ms, tracks.duration * 1000)), 0) / 1000
+             FROM history LEFT JOIN tracks ON tracks.path = history.path
+             WHERE history.played_at BETWEEN ?1 AND ?2
+             GROUP BY period
+             ORDER BY period",
+        )?;
+
+        let periods = statement
+            .query_map(params![since, until, format], |row| {
+                Ok(ListeningTime {
+                    period: row.get(0)?,
+                    plays: row.get(1)?,
+                    seconds: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(periods)
+    }
+
+    /// Most played artists, albums and tracks between `since` and `until`.
+    pub fn charts(&self, since: i64, until: i64, limit: u32) -> Result<Charts, LibraryError> {
+        let conn = self.conn()?;
+        let chart = |columns: &str, group: &str| -> Result<Vec<ChartEntry>, LibraryError> {
+            let mut statement = conn.prepare(&format!(
+                "SELECT {}, COUNT(*) AS plays,
+                    COALESCE(SUM(COALESCE(history.played_ms, tracks.duration * 1000)), 0) / 1000
+                 FROM history JOIN tracks ON tracks.path = history.path
+                 LEFT JOIN albums ON albums.id = tracks.album_id
+                 WHERE history.played_at BETWEEN ?1 AND ?2
+                 GROUP BY {}
+                 ORDER BY plays DESC
+                 LIMIT ?3",
+                columns, group
+            ))?;
+
+            let entries = statement
+                .query_map(params![since, until, limit], |row| {
+                    Ok(ChartEntry {
+                        name: row.get(0)?,
+                        artist: row.get(1)?,
+                        path: row.get(2)?,
+                        plays: row.get(3)?,
+                        seconds: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        };
+
+        Ok(Charts {
+            artists: chart("tracks.artist, NULL, NULL", "tracks.artist")?,
+            albums: chart(
+                "COALESCE(albums.title, tracks.album), COALESCE(albums.artist, tracks.artist), NULL",
+                "COALESCE(albums.id, tracks.album)",
+            )?,
+            tracks: chart("tracks.title, tracks.artist, tracks.path", "tracks.path")?,
+        })
+    }
+
+    /// Plays started in each hour of the day (local time) between `since` and `until`.
+    pub fn hourly_plays(&self, since: i64, until: i64) -> Result<Vec<u32>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT CAST(strftime('%H', played_at, 'unixepoch', 'localtime') AS INTEGER) AS hour,
+                COUNT(*)
+             FROM history
+             WHERE played_at BETWEEN ?1 AND ?2
+             GROUP BY hour",
+        )?;
+
+        let mut hours = vec![0; 24];
+        let rows = statement.query_map(params![since, until], |row| {
+            Ok((row.get::<_, usize>(0)?, row.get::<_, u32>(1)?))
+        })?;
+        for row in rows {
+            let (hour, plays) = row?;
+            if let Some(count) = hours.get_mut(hour) {
+                *count = plays;
+            }
+        }
+        Ok(hours)
+    }
+
+    pub fn track_records(&self) -> Result<Vec<TrackRecord>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT tracks.path, title, artist, album, album_artist, genre, year, composer,
+                    conductor, work, movement, track_number, disc_number, duration, bpm,
+                    gain_db, rating,
+                    imported_plays + COUNT(history.id), MAX(history.played_at), added_at,
+                    performer
+             FROM tracks LEFT JOIN history ON history.path = tracks.path
+             GROUP BY tracks.path
+             ORDER BY tracks.path",
+        )?;
+
+        let tracks = statement
+            .query_map([], |row| {
+                Ok(TrackRecord {
+                    path: row.get(0)?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    album: row.get(3)?,
+                    album_artist: row.get(4)?,
+                    genre: row.get(5)?,
+                    year: row.get(6)?,
+                    composer: row.get(7)?,
+                    conductor: row.get(8)?,
+                    work: row.get(9)?,
+                    movement: row.get(10)?,
+                    track_number: row.get(11)?,
+                    disc_number: row.get(12)?,
+                    duration: row.get(13)?,
+                    bpm: row.get(14)?,
+                    gain_db: row.get(15)?,
+                    rating: row.get(16)?,
+                    play_count: row.get(17)?,
+                    last_played: row.get(18)?,
+                    added_at: row.get(19)?,
+                    performer: row.get(20)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tracks)
+    }
+
+    pub fn playlist_records(&self) -> Result<Vec<PlaylistRecord>, LibraryError> {
+        self.playlists()?
+            .into_iter()
+            .map(|playlist| {
+                Ok(PlaylistRecord {
+                    name: playlist.name,
+                    paths: self.playlist_paths(playlist.id)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn playlists(&self) -> Result<Vec<PlaylistEntry>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT playlists.id, playlists.name, COUNT(playlist_tracks.path), playlists.settings
+             FROM playlists LEFT JOIN playlist_tracks ON playlist_tracks.playlist_id = playlists.id
+             GROUP BY playlists.id
+             ORDER BY playlists.name COLLATE NOCASE",
+        )?;
+
+        let playlists = statement
+            .query_map([], |row| {
+                Ok(PlaylistEntry {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    track_count: row.get(2)?,
+                    settings: from_json(row.get(3)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(playlists)
+    }
+
+    pub fn playlist_settings(&self, id: i64) -> Result<PlaylistSettings, LibraryError> {
+        let settings: Option<Option<String>> = self
+            .conn()?
+            .query_row(
+                "SELECT settings FROM playlists WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(from_json(settings.flatten()))
+    }
+
+    pub fn set_playlist_settings(
+        &self,
+        id: i64,
+        settings: &PlaylistSettings,
+    ) -> Result<(), LibraryError> {
+        let settings = (*settings != PlaylistSettings::default())
+            .then(|| serde_json::to_string(settings).ok())
+            .flatten();
+        self.conn()?.execute(
+            "UPDATE playlists SET settings = ?2 WHERE id = ?1",
+            params![id, settings],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_playlist(&self, name: &str) -> Result<i64, LibraryError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO playlists (name, created_at) VALUES (?1, unixepoch())",
+            params![name],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn rename_playlist(&self, id: i64, name: &str) -> Result<(), LibraryError> {
+        self.conn()?.execute(
+            "UPDATE playlists SET name = ?2 WHERE id = ?1",
+            params![id, name],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_playlist(&self, id: i64) -> Result<(), LibraryError> {
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![id],
+        )?;
+        transaction.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Appends files to the playlist. Entries needn't be in the library, so streams, network
+    /// shares and loose files can be listed without being imported.
+    pub fn add_to_playlist(&self, id: i64, paths: &[String]) -> Result<(), LibraryError> {
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        for path in paths {
+            transaction.execute(
+                "INSERT INTO playlist_tracks (playlist_id, position, path)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0)
+                              FROM playlist_tracks WHERE playlist_id = ?1), ?2)",
+                params![id, path],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Removes the entries at `positions` and closes the gaps they leave.
+    pub fn remove_from_playlist(&self, id: i64, positions: &[usize]) -> Result<(), LibraryError> {
+        let paths: Vec<String> = self
+            .playlist_paths(id)?
+            .into_iter()
+            .enumerate()
+            .filter(|(position, _)| !positions.contains(position))
+            .map(|(_, path)| path)
+            .collect();
+
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![id],
+        )?;
+        for (position, path) in paths.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO playlist_tracks (playlist_id, position, path) VALUES (?1, ?2, ?3)",
+                params![id, position, path],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn playlist_paths(&self, id: i64) -> Result<Vec<String>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn
+            .prepare("SELECT path FROM playlist_tracks WHERE playlist_id = ?1 ORDER BY position")?;
+
+        let paths = statement
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(paths)
+    }
+
+    /// The playlist's entries. Ones the library doesn't hold get their details from the
+    /// file's tags if it's local, and placeholders otherwise.
+    pub fn playlist_tracks(&self, id: i64) -> Result<Vec<TrackInfo>, LibraryError> {
+        let entries = {
+            let conn = self.conn()?;
+            let mut statement = conn.prepare(&format!(
+                "SELECT playlist_tracks.path, tracks.path IS NOT NULL, {}
+                 FROM playlist_tracks LEFT JOIN tracks ON tracks.path = playlist_tracks.path
+                 WHERE playlist_tracks.playlist_id = ?1
+                 ORDER BY playlist_tracks.position",
+                prefixed_columns("tracks", TRACK_COLUMNS)
+            ))?;
+
+            let entries = statement
+                .query_map(params![id], |row| {
+                    let path: String = row.get(0)?;
+                    let known: bool = row.get(1)?;
+                    let track = if known {
+                        Some(track_from_row_at(row, 2)?)
+                    } else {
+                        None
+                    };
+                    Ok((path, track))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            entries
+        };
+
+        // Tags are read with the database unlocked.
+        let tracks = entries
+            .into_iter()
+            .map(|(path, track)| {
+                track.unwrap_or_else(|| match SourceKind::of(&path) {
+                    SourceKind::File => util::get_track_info_from_path(&path, 0),
+                    _ => util::track_info_from_tagged_file(None, &path, 0),
+                })
+            })
+            .collect();
+        Ok(index_tracks(tracks))
+    }
+
+    /// Reorders the live queue by `order`, keeping the current track current; returns its new
+    /// index. Keys the queue doesn't carry (year, date added, play count) come from the library.
+    pub fn sort_queue(
+        &self,
+        queue: &mut Vec<TrackInfo>,
+        current: usize,
+        order: SortOrder,
+    ) -> Result<usize, LibraryError> {
+        let current_path = queue.get(current).map(|track| track.path.clone());
+        let values = match order.key {
+            SortKey::Year | SortKey::DateAdded | SortKey::PlayCount => {
+                self.sort_values(order.key)?
+            }
+            _ => HashMap::new(),
+        };
+
+        queue.sort_by(|a, b| {
+            let ordering = match order.key {
+                SortKey::Title => compare_text(&a.title, &b.title),
+                SortKey::Artist => compare_text(&a.artist, &b.artist),
+                SortKey::Album => compare_text(&a.album, &b.album),
+                SortKey::Duration => a.duration.cmp(&b.duration),
+                SortKey::Year | SortKey::DateAdded | SortKey::PlayCount => {
+                    values.get(&a.path).cmp(&values.get(&b.path))
+                }
+            };
+
+            match order.direction {
+                SortDirection::Asc => ordering,
+                SortDirection::Desc => ordering.reverse(),
+            }
+        });
+
+        *queue = index_tracks(std::mem::take(queue));
+        Ok(current_path
+            .and_then(|path| queue.iter().position(|track| track.path == path))
+            .unwrap_or(0))
+    }
+
+    /// The value of `key` for every library track that has one.
+    fn sort_values(&self, key: SortKey) -> Result<HashMap<String, i64>, LibraryError> {
+        let conn = self.conn()?;
+        let expression = key.track_expression();
+        let mut statement = conn.prepare(&format!(
+            "SELECT path, {} FROM tracks WHERE {} IS NOT NULL",
+            expression, expression
+        ))?;
+
+        let values = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(values)
+    }
+
+    /// Rating and last play time of every library track, for weighted shuffle.
+    pub fn play_stats(&self) -> Result<HashMap<String, (Option<u8>, Option<i64>)>, LibraryError> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(
+            "SELECT tracks.path, rating, MAX(history.played_at)
+             FROM tracks LEFT JOIN history ON history.path = tracks.path
+             GROUP BY tracks.path",
+        )?;
+
+        let stats = statement
+            .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(stats)
+    }
+}
+
+/// Feeds listening positions to the thread saving them, started on first use.
+static LISTENING: Mutex<Option<mpsc::Sender<(String, u64)>>> = Mutex::new(None);
+
+/// Saves how far into a track playback got on a thread of the library's own, in the order
+/// given, so the audio thread never waits on the database.
+pub fn save_listening(app: &AppHandle, path: String, position_ms: u64) {
+    let mut listening = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+    let sender = listening.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel::<(String, u64)>();
+        let app = app.clone();
+        thread::spawn(move || {
+            for (path, position_ms) in receiver {
+                if let Err(e) = app.state::<Library>().record_listening(&path, position_ms) {
+                    log::error!("Failed to save listening progress: {}", e);
+                }
+            }
+        });
+        sender
+    });
+    let _ = sender.send((path, position_ms));
+}
+
+/// Sends the current playlist list as `playlists-changed` after any playlist edit.
+pub fn emit_playlists(app: &AppHandle) -> Result<(), LibraryError> {
+    let playlists = app.state::<Library>().playlists()?;
+    if let Err(e) = app.emit("playlists-changed", playlists) {
+        log::error!("{}", e);
+    }
+    Ok(())
+}
+
+/// Adds every audio file under `folder` to the library in the background, emitting
+/// `library-scan-progress` per file.
+pub fn scan(app: AppHandle, folder: PathBuf) -> Result<(), LibraryError> {
+    let files = util::collect_audio_files(&folder, true)?;
+
+    thread::spawn(move || {
+        let library = app.state::<Library>();
+        let total = files.len();
+
+        for (i, file) in files.into_iter().enumerate() {
+            let path = paths::normalized(&file);
+            let result = library.add_file(&path);
+
+            let progress = ScanProgress {
+                path,
+                error: result.err().map(|e| e.to_string()),
+                completed: i + 1,
+                total,
+            };
+
+            if let Err(e) = app.emit("library-scan-progress", progress) {
+                log::error!("{}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Copies a backup database to `staged`, checks it is intact and brings its schema up to
+/// date.
+fn stage_restore(src: &Path, staged: &Path) -> Result<(), LibraryError> {
+    fs::copy(src, staged)?;
+
+    let mut conn = Connection::open(staged)?;
+    let check: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(LibraryError::DamagedBackup(check));
+    }
+    migrate(&mut conn)?;
+    Ok(())
+}
+
+fn open_database(path: &Path) -> Result<Connection, LibraryError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut conn = Connection::open(path)?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), LibraryError> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    let transaction = conn.transaction()?;
+    for migration in MIGRATIONS.iter().skip(applied) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// Reads a row selected with `TRACK_COLUMNS`.
+fn track_from_row(row: &Row) -> rusqlite::Result<TrackInfo> {
+    track_from_row_at(row, 0)
+}
+
+/// Reads `TRACK_COLUMNS` selected after `offset` other columns.
+fn track_from_row_at(row: &Row, offset: usize) -> rusqlite::Result<TrackInfo> {
+    let path: String = row.get(offset)?;
+    Ok(TrackInfo {
+        index: 0,
+        source_kind: SourceKind::of(&path),
+        path,
+        title: row.get(offset + 1)?,
+        artist: row.get(offset + 2)?,
+        album: row.get(offset + 3)?,
+        duration: row.get(offset + 4)?,
+        track_number: row.get(offset + 5)?,
+        disc_number: row.get(offset + 6)?,
+        album_artist: row.get(offset + 7)?,
+        genre: row.get(offset + 8)?,
+        year: row.get(offset + 9)?,
+        composer: row.get(offset + 10)?,
+        conductor: row.get(offset + 11)?,
+        work: row.get(offset + 12)?,
+        movement: row.get(offset + 13)?,
+        artists: from_json(row.get(offset + 14)?),
+        genres: from_json(row.get(offset + 15)?),
+        compilation: row.get(offset + 16)?,
+        performer: row.get(offset + 17)?,
+    })
+}
+
+/// Multi-value tags are stored as JSON arrays, so queries can expand them with `json_each`.
+fn list_to_json(values: &[String]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+    serde_json::to_string(values).ok()
+}
+
+/// Reads a JSON column, taking a missing or unreadable value as the default.
+fn from_json<T: DeserializeOwned + Default>(value: Option<String>) -> T {
+    value
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default()
+}
+
+fn track_order(sort: Option<SortOrder>, default: &str) -> String {
+    sort.map(SortOrder::track_clause)
+        .unwrap_or_else(|| default.to_string())
+}
+
+fn album_order(sort: Option<SortOrder>, default: &str) -> String {
+    sort.map(SortOrder::album_clause)
+        .unwrap_or_else(|| default.to_string())
+}
+
+fn compare_text(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// The median of `seeks` if there are enough of them and they land close together.
+fn agreed_offset(mut seeks: Vec<u64>) -> Option<u64> {
+    if seeks.len() < START_SEEKS_TO_LEARN {
+        return None;
+    }
+    seeks.sort_unstable();
+    let spread = seeks[seeks.len() - 1] - seeks[0];
+    (spread <= START_SEEK_TOLERANCE_MS).then(|| seeks[seeks.len() / 2])
+}
+
+fn skip_rule_from_row(row: &Row) -> rusqlite::Result<SkipRule> {
+    Ok(SkipRule {
+        album: row.get(0)?,
+        artist: row.get(1)?,
+        intro_ms: row.get(2)?,
+        outro_ms: row.get(3)?,
+    })
+}
+
+fn bookmark_from_row(row: &Row) -> rusqlite::Result<Bookmark> {
+    Ok(Bookmark {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        position_ms: row.get(2)?,
+        label: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+/// Reads a row selected with `ALBUM_COLUMNS`.
+fn album_from_row(row: &Row) -> rusqlite::Result<AlbumEntry> {
+    Ok(AlbumEntry {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        artist: row.get(2)?,
+        year: row.get(3)?,
+        cover: row.get(4)?,
+        track_count: row.get(5)?,
+        compilation: row.get(6)?,
+    })
+}
+
+/// Qualifies each of `columns` with `table`, for queries where names would be ambiguous.
+fn prefixed_columns(table: &str, columns: &str) -> String {
+    columns
+        .split(", ")
+        .map(|column| format!("{}.{}", table, column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn index_tracks(mut tracks: Vec<TrackInfo>) -> Vec<TrackInfo> {
+    for (i, track) in tracks.iter_mut().enumerate() {
+        track.index = i;
+    }
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database in the temp folder, holding one track at `path`.
+    fn library_with(name: &str, path: &str) -> Library {
+        let file = std::env::temp_dir().join(format!("music-player-{}.db", name));
+        let _ = fs::remove_file(&file);
+        let library = Library::open(&file).unwrap();
+        library.add_file(path).unwrap();
+        library
+    }
+
+    #[test]
+    fn seeks_agree_when_enough_land_close_together() {
+        assert_eq!(agreed_offset(vec![30_000, 31_000]), None);
+        assert_eq!(agreed_offset(vec![31_000, 30_000, 32_500]), Some(31_000));
+        assert_eq!(agreed_offset(vec![30_000, 31_000, 34_000]), None);
+    }
+
+    #[test]
+    fn learned_offsets_are_offered_before_they_are_used() {
+        let library = library_with("start-seeks", "/music/song.mp3");
+
+        assert_eq!(
+            library
+                .record_start_seek("/music/song.mp3", 30_000)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            library
+                .record_start_seek("/music/song.mp3", 31_000)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            library
+                .record_start_seek("/music/song.mp3", 32_000)
+                .unwrap(),
+            Some(31_000)
+        );
+        // Already offered, so agreeing again offers nothing new.
+        assert_eq!(
+            library
+                .record_start_seek("/music/song.mp3", 31_000)
+                .unwrap(),
+            None
+        );
+        assert_eq!(library.start_offset("/music/song.mp3").unwrap(), None);
+
+        library
+            .set_start_offset_enabled("/music/song.mp3", true)
+            .unwrap();
+        assert_eq!(
+            library.start_offset("/music/song.mp3").unwrap(),
+            Some(31_000)
+        );
+
+        library.clear_start_offset("/music/song.mp3").unwrap();
+        assert_eq!(library.start_offset("/music/song.mp3").unwrap(), None);
+        assert!(library.start_offsets().unwrap().is_empty());
+    }
+
+    #[test]
+    fn listening_time_counts_what_was_played() {
+        let library = library_with("played-time", "/music/song.mp3");
+        let track = library.random_tracks(1).unwrap().remove(0);
+
+        let first = library.record_play(&track).unwrap();
+        library.set_played_time(first, 90_500).unwrap();
+        // A play skipped straight away counts no time at all.
+        library.record_play(&track).unwrap();
+
+        let periods = library
+            .listening_time(ReportPeriod::Day, 0, i64::MAX)
+            .unwrap();
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].plays, 2);
+        assert_eq!(periods[0].seconds, 90);
+    }
+
+    #[test]
+    fn track_gain_is_kept_within_range() {
+        let library = library_with("track-gain", "/music/song.mp3");
+
+        library
+            .set_track_gain("/music/song.mp3", Some(60.0))
+            .unwrap();
+        assert_eq!(
+            library.get_track_gain("/music/song.mp3").unwrap(),
+            Some(24.0)
+        );
+
+        library
+            .set_track_gain("/music/song.mp3", Some(f32::NAN))
+            .unwrap();
+        assert_eq!(library.get_track_gain("/music/song.mp3").unwrap(), None);
+    }
+
+    #[test]
+    fn seeks_in_tracks_outside_the_library_are_ignored() {
+        let library = library_with("start-seeks-unknown", "/music/song.mp3");
+        for _ in 0..START_SEEKS_TO_LEARN {
+            assert_eq!(
+                library
+                    .record_start_seek("/music/other.mp3", 30_000)
+                    .unwrap(),
+                None
+            );
+        }
+    }
+}
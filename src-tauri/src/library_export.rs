@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use thiserror::Error;
+
+use crate::library::{Library, LibraryError, PlaylistRecord, TrackRecord};
+
+#[derive(Error, Debug)]
+pub enum LibraryExportError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to write JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Serialize)]
+struct LibraryDump {
+    tracks: Vec<TrackRecord>,
+    playlists: Vec<PlaylistRecord>,
+}
+
+/// Writes every track with its statistics and every playlist to `path`. CSV holds one table
+/// per file, so playlists go to a `<name>-playlists.csv` next to the tracks file.
+pub fn export_library(
+    library: &Library,
+    format: LibraryExportFormat,
+    path: &Path,
+) -> Result<(), LibraryExportError> {
+    let dump = LibraryDump {
+        tracks: library.track_records()?,
+        playlists: library.playlist_records()?,
+    };
+
+    match format {
+        LibraryExportFormat::Json => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(&mut writer, &dump)?;
+            writer.flush()?;
+        }
+        LibraryExportFormat::Csv => {
+            write_tracks_csv(&dump.tracks, path)?;
+
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let playlists_path = path.with_file_name(format!("{}-playlists.csv", stem));
+            write_playlists_csv(&dump.playlists, &playlists_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_tracks_csv(tracks: &[TrackRecord], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(
+        writer,
+        "path,title,artist,album,album_artist,genre,year,composer,conductor,performer,work,movement,\
+         track_number,disc_number,duration,bpm,gain_db,rating,play_count,last_played,added_at\r\n"
+    )?;
+
+    fn optional<T: ToString>(value: Option<T>) -> String {
+        value.map(|value| value.to_string()).unwrap_or_default()
+    }
+
+    for track in tracks {
+        let fields = [
+            track.path.clone(),
+            track.title.clone(),
+            track.artist.clone(),
+            track.album.clone(),
+            optional(track.album_artist.as_ref()),
+            optional(track.genre.as_ref()),
+            optional(track.year),
+            optional(track.composer.as_ref()),
+            optional(track.conductor.as_ref()),
+            optional(track.performer.as_ref()),
+            optional(track.work.as_ref()),
+            optional(track.movement.as_ref()),
+            optional(track.track_number),
+            optional(track.disc_number),
+            track.duration.to_string(),
+            optional(track.bpm),
+            optional(track.gain_db),
+            optional(track.rating),
+            track.play_count.to_string(),
+            optional(track.last_played),
+            optional(track.added_at),
+        ];
+        write_row(&mut writer, &fields)?;
+    }
+
+    writer.flush()
+}
+
+fn write_playlists_csv(playlists: &[PlaylistRecord], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "playlist,position,path\r\n")?;
+
+    for playlist in playlists {
+        for (position, track) in playlist.paths.iter().enumerate() {
+            write_row(
+                &mut writer,
+                &[playlist.name.clone(), position.to_string(), track.clone()],
+            )?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Writes one RFC 4180 row, quoting fields that contain separators, quotes or line breaks.
+fn write_row(writer: &mut impl Write, fields: &[String]) -> io::Result<()> {
+    let row: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+
+    write!(writer, "{}\r\n", row.join(","))
+}
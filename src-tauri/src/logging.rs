@@ -0,0 +1,189 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+const LOG_FILE: &str = "music-player.log";
+
+/// The log file is rotated once it grows past this size.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Rotated files kept next to the current one, as `music-player.log.1` and so on.
+const KEPT_FILES: usize = 3;
+
+/// Lines kept in memory for `get_recent_logs`.
+const RECENT_LINES: usize = 1000;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Verbosity, from quietest to noisiest.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Output {
+    file: Option<File>,
+    dir: Option<PathBuf>,
+    size: u64,
+    recent: VecDeque<String>,
+}
+
+/// Writes log records to stderr, a rotating file in the app's log directory, and a buffer of
+/// recent lines that can be attached to bug reports.
+struct Logger {
+    output: Mutex<Output>,
+}
+
+impl Logger {
+    fn lock(&self) -> MutexGuard<'_, Output> {
+        self.output.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "{} {:<5} {}: {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if record.level() <= Level::Warn || cfg!(debug_assertions) {
+            eprintln!("{}", line);
+        }
+
+        let mut output = self.lock();
+        if output.recent.len() >= RECENT_LINES {
+            output.recent.pop_front();
+        }
+        output.recent.push_back(line.clone());
+
+        if output.size >= MAX_FILE_SIZE {
+            if let Err(e) = rotate(&mut output) {
+                output.file = None;
+                eprintln!("Failed to rotate the log file: {}", e);
+            }
+        }
+        if let Some(file) = &mut output.file {
+            if writeln!(file, "{}", line).is_ok() {
+                output.size += line.len() as u64 + 1;
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &mut self.lock().file {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Shifts `music-player.log` to `.1`, `.1` to `.2` and so on, dropping the oldest.
+fn rotate(output: &mut Output) -> io::Result<()> {
+    let Some(dir) = output.dir.clone() else {
+        return Ok(());
+    };
+    output.file = None;
+
+    let rotated = |n: usize| dir.join(format!("{}.{}", LOG_FILE, n));
+    let _ = fs::remove_file(rotated(KEPT_FILES));
+    for n in (1..KEPT_FILES).rev() {
+        let _ = fs::rename(rotated(n), rotated(n + 1));
+    }
+    fs::rename(dir.join(LOG_FILE), rotated(1))?;
+
+    output.file = Some(File::create(dir.join(LOG_FILE))?);
+    output.size = 0;
+    Ok(())
+}
+
+/// Installs the logger. Records are kept in memory until `open_log_dir` is called.
+pub fn init() {
+    let logger = LOGGER.get_or_init(|| Logger {
+        output: Mutex::new(Output::default()),
+    });
+    if log::set_logger(logger).is_ok() {
+        let level = if cfg!(debug_assertions) {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        };
+        log::set_max_level(level);
+    }
+}
+
+/// Starts appending to the log file in `dir`, which the app can only resolve once it is set up.
+pub fn open_log_dir(dir: &Path) -> io::Result<()> {
+    let Some(logger) = LOGGER.get() else {
+        return Ok(());
+    };
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(LOG_FILE);
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    let mut output = logger.lock();
+    output.size = file.metadata()?.len();
+    output.dir = Some(dir.to_path_buf());
+    output.file = Some(file);
+
+    // Whatever was logged during startup goes to the file too.
+    let Output {
+        file, recent, size, ..
+    } = &mut *output;
+    if let Some(file) = file {
+        for line in recent.iter() {
+            writeln!(file, "{}", line)?;
+            *size += line.len() as u64 + 1;
+        }
+    }
+    Ok(())
+}
+
+pub fn set_level(level: LogLevel) {
+    log::set_max_level(level.into());
+}
+
+/// The most recent lines logged, oldest first.
+pub fn recent(limit: usize) -> Vec<String> {
+    let Some(logger) = LOGGER.get() else {
+        return Vec::new();
+    };
+
+    let output = logger.lock();
+    let skip = output.recent.len().saturating_sub(limit);
+    output.recent.iter().skip(skip).cloned().collect()
+}
@@ -0,0 +1,210 @@
+use lofty::file::TaggedFileExt;
+use lofty::tag::ItemKey;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use thiserror::Error;
+
+use crate::audio_player::TrackInfo;
+use crate::library::Library;
+use crate::paths;
+use crate::util;
+
+const LRCLIB_URL: &str = "https://lrclib.net/api/get";
+
+#[derive(Error, Debug)]
+pub enum LyricsError {
+    #[error("Lyrics request failed: {0}")]
+    RequestError(String),
+}
+
+impl From<ureq::Error> for LyricsError {
+    fn from(e: ureq::Error) -> Self {
+        LyricsError::RequestError(e.to_string())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LyricLine {
+    /// Start of the line in milliseconds; zero for every line of unsynced lyrics.
+    pub time_ms: u64,
+    pub text: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Lyrics {
+    pub synced: bool,
+    /// Per-track correction set by the user; positive values show lines earlier.
+    pub offset_ms: i64,
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    /// Index of the line being sung at `position`, if the lyrics are synced and it has started.
+    pub fn line_at(&self, position: Duration) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+
+        let position_ms = (position.as_millis() as i64 + self.offset_ms).max(0) as u64;
+        self.lines
+            .partition_point(|line| line.time_ms <= position_ms)
+            .checked_sub(1)
+    }
+}
+
+/// LRCLIB's answer for a single track.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+/// Lyrics for `track`: a sidecar `.lrc` file, then embedded lyrics, then (with `online` set)
+/// LRCLIB. Online results, including misses, are cached in the library.
+pub fn load(app: &AppHandle, track: &TrackInfo, online: bool) -> Option<Lyrics> {
+    let library = app.state::<Library>();
+    let offset_ms = library.get_lyrics_offset(&track.path).unwrap_or_else(|e| {
+        log::error!("Failed to read lyrics offset: {}", e);
+        0
+    });
+    let with_offset = |text: String| Lyrics {
+        offset_ms,
+        ..parse(&text)
+    };
+
+    if let Some(text) = local_lyrics(&track.path) {
+        return Some(with_offset(text));
+    }
+
+    if !online {
+        return None;
+    }
+
+    let text = match library.cached_lyrics(&track.path) {
+        Ok(Some(cached)) => cached,
+        _ => match fetch_lrclib(track) {
+            Ok(text) => {
+                if let Err(e) = library.store_lyrics(&track.path, text.as_deref()) {
+                    log::error!("Failed to cache lyrics: {}", e);
+                }
+                text
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        },
+    };
+
+    text.map(with_offset)
+}
+
+fn local_lyrics(path: &str) -> Option<String> {
+    if !util::is_network_path(path) {
+        let sidecar = Path::new(path).with_extension("lrc");
+        if let Ok(text) = fs::read_to_string(paths::native(&sidecar.to_string_lossy())) {
+            return Some(text);
+        }
+    }
+
+    let tagged_file = util::read_tagged_file(path)?;
+    tagged_file
+        .primary_tag()
+        .and_then(|tag| tag.get_string(&ItemKey::Lyrics))
+        .map(|text| text.to_string())
+}
+
+/// Looks the track up by its tags. Synced lyrics are preferred; `None` means LRCLIB has none.
+fn fetch_lrclib(track: &TrackInfo) -> Result<Option<String>, LyricsError> {
+    let response = ureq::get(LRCLIB_URL)
+        .timeout(Duration::from_secs(10))
+        .query("track_name", &track.title)
+        .query("artist_name", &track.artist)
+        .query("album_name", &track.album)
+        .query("duration", &track.duration.to_string())
+        .call();
+
+    let found: LrclibTrack = match response {
+        Ok(response) => serde_json::from_reader(response.into_reader())
+            .map_err(|e| LyricsError::RequestError(e.to_string()))?,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(found
+        .synced_lyrics
+        .or(found.plain_lyrics)
+        .filter(|text| !text.trim().is_empty()))
+}
+
+/// Parses LRC text. Text without any `[mm:ss.xx]` timestamps is treated as plain lyrics.
+pub fn parse(text: &str) -> Lyrics {
+    let mut lines = Vec::new();
+    let mut offset_ms: i64 = 0;
+
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|tag| tag.split_once(']')) {
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(offset) = tag.strip_prefix("offset:") {
+                offset_ms = offset.trim().parse().unwrap_or(0);
+            } else {
+                break;
+            }
+            rest = after.trim_start();
+        }
+
+        for time in times {
+            lines.push((time, rest.to_string()));
+        }
+    }
+
+    if lines.is_empty() {
+        return Lyrics {
+            synced: false,
+            offset_ms: 0,
+            lines: text
+                .lines()
+                .map(|line| LyricLine {
+                    time_ms: 0,
+                    text: line.trim().to_string(),
+                })
+                .collect(),
+        };
+    }
+
+    // A positive LRC offset shows lines earlier.
+    let mut lines: Vec<LyricLine> = lines
+        .into_iter()
+        .map(|(time, text)| LyricLine {
+            time_ms: (time as i64 - offset_ms).max(0) as u64,
+            text,
+        })
+        .collect();
+    lines.sort_by_key(|line| line.time_ms);
+
+    Lyrics {
+        synced: true,
+        offset_ms: 0,
+        lines,
+    }
+}
+
+/// Parses `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` into milliseconds.
+fn parse_timestamp(tag: &str) -> Option<u64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+
+    Some(minutes * 60_000 + (seconds * 1000.0).round() as u64)
+}
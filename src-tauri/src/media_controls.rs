@@ -0,0 +1,198 @@
+use souvlaki::{MediaMetadata, MediaPlayback};
+use std::time::Duration;
+use tauri::AppHandle;
+use thiserror::Error;
+
+use crate::audio_player::{AudioPlayer, TrackInfo};
+
+#[cfg(target_os = "linux")]
+use crate::mpris::Mpris;
+#[cfg(target_os = "ios")]
+use crate::now_playing::NowPlaying;
+#[cfg(target_os = "windows")]
+use crate::taskbar::Taskbar;
+
+#[cfg(not(target_os = "linux"))]
+use souvlaki::{MediaControlEvent, MediaPosition, PlatformConfig};
+
+#[derive(Error, Debug)]
+pub enum MediaControlsError {
+    #[error("Media controls error: {0:?}")]
+    PlatformError(souvlaki::Error),
+
+    #[cfg(target_os = "windows")]
+    #[error("The main window has no native handle")]
+    NoWindow,
+
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBusError(#[from] zbus::Error),
+
+    /// The player runs without an app to show controls for, as in tests.
+    #[cfg_attr(not(test), allow(dead_code))]
+    #[error("No media controls to attach to")]
+    Unavailable,
+}
+
+impl From<souvlaki::Error> for MediaControlsError {
+    fn from(e: souvlaki::Error) -> Self {
+        MediaControlsError::PlatformError(e)
+    }
+}
+
+/// The OS media controls. Linux gets our own MPRIS server, which adds the queue as a track
+/// list; elsewhere souvlaki talks to SMTC / Now Playing. Windows also gets taskbar buttons,
+/// and iOS, which souvlaki doesn't cover, gets its own Now Playing plugin.
+pub struct MediaControls {
+    #[cfg(target_os = "linux")]
+    mpris: Mpris,
+    #[cfg(not(target_os = "linux"))]
+    controls: souvlaki::MediaControls,
+    #[cfg(target_os = "windows")]
+    taskbar: Taskbar,
+    #[cfg(target_os = "ios")]
+    now_playing: NowPlaying,
+}
+
+impl MediaControls {
+    #[cfg(target_os = "linux")]
+    pub fn new(app: &AppHandle, player: AudioPlayer) -> Result<MediaControls, MediaControlsError> {
+        Ok(MediaControls {
+            mpris: Mpris::new(app.clone(), player)?,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(app: &AppHandle, player: AudioPlayer) -> Result<MediaControls, MediaControlsError> {
+        #[cfg(not(target_os = "windows"))]
+        let hwnd = {
+            let _ = app;
+            None
+        };
+
+        #[cfg(target_os = "windows")]
+        let hwnd = {
+            use tauri::Manager;
+
+            let window = app
+                .get_webview_window("main")
+                .ok_or(MediaControlsError::NoWindow)?;
+            let hwnd = window.hwnd().map_err(|_| MediaControlsError::NoWindow)?;
+
+            Some(hwnd.0)
+        };
+
+        let config = PlatformConfig {
+            dbus_name: "my_player",
+            display_name: "My Player",
+            hwnd,
+        };
+
+        #[cfg(target_os = "windows")]
+        let taskbar = Taskbar::new(app, player.clone());
+
+        #[cfg(target_os = "ios")]
+        let now_playing = {
+            let player = player.clone();
+            NowPlaying::new(app, move |event| handle_event(event, &player))
+        };
+
+        let mut controls = souvlaki::MediaControls::new(config)?;
+        controls.attach(move |event| handle_event(event, &player))?;
+        Ok(MediaControls {
+            controls,
+            #[cfg(target_os = "windows")]
+            taskbar,
+            #[cfg(target_os = "ios")]
+            now_playing,
+        })
+    }
+
+    pub fn set_metadata(&mut self, metadata: MediaMetadata) -> Result<(), MediaControlsError> {
+        // MPRIS metadata comes from the queue; only the cover isn't part of a track.
+        #[cfg(target_os = "linux")]
+        self.mpris
+            .set_cover_url(metadata.cover_url.map(String::from))?;
+
+        #[cfg(target_os = "ios")]
+        self.now_playing.set_metadata(&metadata);
+
+        #[cfg(not(target_os = "linux"))]
+        self.controls.set_metadata(metadata)?;
+
+        Ok(())
+    }
+
+    pub fn set_playback(&mut self, playback: MediaPlayback) -> Result<(), MediaControlsError> {
+        #[cfg(target_os = "windows")]
+        self.taskbar.set_playback(&playback);
+
+        #[cfg(target_os = "ios")]
+        self.now_playing.set_playback(&playback);
+
+        #[cfg(target_os = "linux")]
+        {
+            let (status, progress) = match playback {
+                MediaPlayback::Playing { progress } => ("Playing", progress),
+                MediaPlayback::Paused { progress } => ("Paused", progress),
+                MediaPlayback::Stopped => ("Stopped", None),
+            };
+            self.mpris.set_playback_status(status)?;
+            if let Some(progress) = progress {
+                self.mpris.seeked(progress.0)?;
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.controls.set_playback(playback)?;
+
+        Ok(())
+    }
+
+    /// Shows how far into the track playback is, for platforms with a progress indicator.
+    pub fn set_position(&mut self, position: Duration, duration: Duration) {
+        #[cfg(target_os = "windows")]
+        self.taskbar.set_position(position, duration);
+
+        #[cfg(not(target_os = "windows"))]
+        let _ = (position, duration);
+    }
+
+    /// Publishes the queue and player options, for platforms that show them.
+    pub fn sync(
+        &mut self,
+        queue: &[TrackInfo],
+        current: usize,
+        volume: f32,
+        looped: bool,
+        shuffle: bool,
+    ) -> Result<(), MediaControlsError> {
+        #[cfg(target_os = "linux")]
+        self.mpris
+            .sync(queue, current, volume as f64, looped, shuffle)?;
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = (queue, current, volume, looped, shuffle);
+
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn handle_event(event: MediaControlEvent, player: &AudioPlayer) {
+    let result = match event {
+        MediaControlEvent::Play => player.resume(),
+        MediaControlEvent::Pause => player.pause(),
+        MediaControlEvent::Stop => player.stop(),
+        MediaControlEvent::Toggle => player.media_button(),
+        MediaControlEvent::Next => player.next(),
+        MediaControlEvent::Previous => player.prev(),
+        MediaControlEvent::SetPosition(MediaPosition(position)) => player.seek(position),
+        MediaControlEvent::SetVolume(volume) => player.set_volume(volume as f32),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+}
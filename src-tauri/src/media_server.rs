@@ -0,0 +1,219 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use crate::paths;
+
+static SHARED: Mutex<Option<Arc<MediaServer>>> = Mutex::new(None);
+
+type ServedFiles = Arc<Mutex<HashMap<String, PathBuf>>>;
+
+/// Serves registered local files over HTTP (with range support) so network renderers can
+/// fetch them. Each file gets a random, unguessable name, and the server only listens on the
+/// interfaces renderers have been reached through.
+pub struct MediaServer {
+    files: ServedFiles,
+    /// Port listened on at each local address.
+    ports: Mutex<HashMap<IpAddr, u16>>,
+}
+
+impl MediaServer {
+    fn new() -> MediaServer {
+        MediaServer {
+            files: Arc::new(Mutex::new(HashMap::new())),
+            ports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts listening on `host` unless already doing so, and returns the port.
+    fn listen(&self, host: IpAddr) -> io::Result<u16> {
+        let mut ports = self.ports.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(port) = ports.get(&host) {
+            return Ok(*port);
+        }
+
+        let server = Server::http(SocketAddr::new(host, 0)).map_err(io::Error::other)?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| io::Error::other("Media server is not bound to an IP address"))?;
+
+        let files = self.files.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                serve_request(request, &files);
+            }
+        });
+
+        ports.insert(host, port);
+        Ok(port)
+    }
+
+    /// Registers `path` for a device reaching us via `host`. It stays fetchable until the
+    /// returned `ServedFile` is dropped.
+    pub fn url_for(&self, path: &Path, host: IpAddr) -> io::Result<ServedFile> {
+        let port = self.listen(host)?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("bin");
+        let name = format!("{:032x}.{}", rand::thread_rng().gen::<u128>(), extension);
+
+        self.files
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.clone(), path.to_path_buf());
+
+        Ok(ServedFile {
+            url: format!("http://{}/media/{}", SocketAddr::new(host, port), name),
+            name,
+            files: self.files.clone(),
+        })
+    }
+}
+
+/// A file registered with the media server. Dropping it stops the file being served, so
+/// sessions keep only the track they're playing.
+pub struct ServedFile {
+    url: String,
+    name: String,
+    files: ServedFiles,
+}
+
+impl ServedFile {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for ServedFile {
+    fn drop(&mut self) {
+        self.files
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.name);
+    }
+}
+
+/// Returns the process-wide media server.
+pub fn shared() -> Arc<MediaServer> {
+    SHARED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| Arc::new(MediaServer::new()))
+        .clone()
+}
+
+/// Returns the local address used to reach `remote`, i.e. the one a LAN device should call back on.
+pub fn local_ip_towards(remote: SocketAddr) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect(remote)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "m4a" | "aac" => "audio/mp4",
+        "aiff" => "audio/aiff",
+        _ => "application/octet-stream",
+    }
+}
+
+fn serve_request(request: Request, files: &Mutex<HashMap<String, PathBuf>>) {
+    let name = request.url().trim_start_matches("/media/").to_string();
+    let path = files
+        .lock()
+        .ok()
+        .and_then(|files| files.get(&name).cloned());
+
+    let result = match path {
+        Some(path) => serve_file(request, &path),
+        None => request.respond(Response::empty(404)),
+    };
+
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+}
+
+fn serve_file(request: Request, path: &Path) -> io::Result<()> {
+    let mut file = File::open(paths::native(&path.to_string_lossy()))?;
+    let length = file.metadata()?.len();
+
+    let range = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Range"))
+        .and_then(|header| parse_range(header.value.as_str(), length));
+
+    let mut headers = vec![
+        Header::from_bytes(&b"Content-Type"[..], mime_type(path).as_bytes()).unwrap(),
+        Header::from_bytes(&b"Accept-Ranges"[..], &b"bytes"[..]).unwrap(),
+    ];
+
+    let (status, start, end) = match range {
+        Some((start, end)) => {
+            let content_range = format!("bytes {}-{}/{}", start, end, length);
+            headers
+                .push(Header::from_bytes(&b"Content-Range"[..], content_range.as_bytes()).unwrap());
+            (206, start, end)
+        }
+        None => (200, 0, length.saturating_sub(1)),
+    };
+
+    file.seek(SeekFrom::Start(start))?;
+    let size = if length == 0 { 0 } else { end - start + 1 };
+    let body = file.take(size);
+
+    request.respond(Response::new(
+        StatusCode(status),
+        headers,
+        body,
+        Some(size as usize),
+        None,
+    ))
+}
+
+/// Parses a single `bytes=start-end` range, clamped to the file length.
+fn parse_range(value: &str, length: u64) -> Option<(u64, u64)> {
+    let spec = value.strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let last = length.checked_sub(1)?;
+
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        (length.saturating_sub(suffix), last)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            last
+        } else {
+            end.parse::<u64>().ok()?.min(last)
+        };
+        (start, end)
+    };
+
+    if start > end {
+        return None;
+    }
+
+    Some((start, end))
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tauri::AppHandle;
+use zbus::blocking::{connection, Connection};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, Value};
+use zbus::{fdo, interface};
+
+use crate::audio_player::{AudioPlayer, TrackInfo, MAX_VOLUME};
+use crate::shuffle::ShuffleMode;
+use crate::tray;
+use crate::util;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.my_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+type Metadata = HashMap<String, Value<'static>>;
+
+/// What the player last reported, read by D-Bus clients.
+struct Shared {
+    queue: Vec<TrackInfo>,
+    current: usize,
+    status: &'static str,
+    cover_url: Option<String>,
+    volume: f64,
+    looped: bool,
+    shuffle: bool,
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Track ids are queue positions, so every queue change replaces the whole track list.
+fn track_id(index: usize) -> ObjectPath<'static> {
+    ObjectPath::try_from(format!("/org/mpris/MediaPlayer2/Track/{}", index))
+        .unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(NO_TRACK))
+}
+
+fn track_index(id: &ObjectPath) -> Option<usize> {
+    id.as_str()
+        .strip_prefix("/org/mpris/MediaPlayer2/Track/")?
+        .parse()
+        .ok()
+}
+
+fn track_metadata(index: usize, track: &TrackInfo, cover_url: Option<&str>) -> Metadata {
+    let mut metadata = Metadata::new();
+    metadata.insert("mpris:trackid".into(), Value::from(track_id(index)));
+    metadata.insert(
+        "mpris:length".into(),
+        Value::from(track.duration as i64 * 1_000_000),
+    );
+    metadata.insert("xesam:title".into(), Value::from(track.title.clone()));
+    metadata.insert(
+        "xesam:artist".into(),
+        Value::from(vec![track.artist.clone()]),
+    );
+    metadata.insert("xesam:album".into(), Value::from(track.album.clone()));
+    if let Some(number) = track.track_number {
+        metadata.insert("xesam:trackNumber".into(), Value::from(number as i32));
+    }
+    if !util::is_remote_path(&track.path) {
+        metadata.insert(
+            "xesam:url".into(),
+            Value::from(format!("file://{}", track.path)),
+        );
+    }
+    if let Some(url) = cover_url {
+        metadata.insert("mpris:artUrl".into(), Value::from(url.to_string()));
+    }
+    metadata
+}
+
+struct Root {
+    app: AppHandle,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {
+        tray::show_main_window(&self.app);
+    }
+
+    fn quit(&self) {
+        self.app.exit(0);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "My Player"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["file".to_string()]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        [
+            "audio/mpeg",
+            "audio/flac",
+            "audio/ogg",
+            "audio/wav",
+            "audio/mp4",
+        ]
+        .iter()
+        .map(|mime| mime.to_string())
+        .collect()
+    }
+}
+
+struct Player {
+    player: AudioPlayer,
+    shared: Arc<Mutex<Shared>>,
+}
+
+fn to_fdo(e: impl std::fmt::Display) -> fdo::Error {
+    fdo::Error::Failed(e.to_string())
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.player.next().map_err(to_fdo)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.player.prev().map_err(to_fdo)
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.player.pause().map_err(to_fdo)
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.player.media_button().map_err(to_fdo)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.player.stop().map_err(to_fdo)
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        self.player.resume().map_err(to_fdo)
+    }
+
+    /// `offset` is relative to the current position, in microseconds.
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        let state = self.player.get_state().map_err(to_fdo)?;
+        let position = (state.position_ms as i64 * 1000 + offset).max(0);
+        self.player
+            .seek(Duration::from_micros(position as u64))
+            .map_err(to_fdo)
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        // Requests for a track that is no longer current are ignored, as the spec asks.
+        if position < 0 || track_index(&track_id) != Some(lock(&self.shared).current) {
+            return Ok(());
+        }
+        self.player
+            .seek(Duration::from_micros(position as u64))
+            .map_err(to_fdo)
+    }
+
+    fn open_uri(&self, uri: String) -> fdo::Result<()> {
+        self.player
+            .open(vec![util::path_from_uri(&uri)])
+            .map_err(to_fdo)
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        lock(&self.shared).status
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &str {
+        if lock(&self.shared).looped {
+            "Playlist"
+        } else {
+            "None"
+        }
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, status: String) -> fdo::Result<()> {
+        self.player.set_looped(status != "None").map_err(to_fdo)
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        lock(&self.shared).shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) -> fdo::Result<()> {
+        let mode = if shuffle {
+            ShuffleMode::Tracks
+        } else {
+            ShuffleMode::Off
+        };
+        self.player.set_shuffle(mode).map_err(to_fdo)
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> Metadata {
+        let shared = lock(&self.shared);
+        match shared.queue.get(shared.current) {
+            Some(track) if shared.status != "Stopped" => {
+                track_metadata(shared.current, track, shared.cover_url.as_deref())
+            }
+            _ => {
+                let mut metadata = Metadata::new();
+                metadata.insert(
+                    "mpris:trackid".into(),
+                    Value::from(ObjectPath::from_static_str_unchecked(NO_TRACK)),
+                );
+                metadata
+            }
+        }
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        lock(&self.shared).volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> fdo::Result<()> {
+        self.player
+            .set_volume(volume.clamp(0.0, MAX_VOLUME as f64) as f32)
+            .map_err(to_fdo)
+    }
+
+    /// Read from the player on request; clients extrapolate it between `Seeked` signals.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.player
+            .get_state()
+            .map(|state| state.position_ms as i64 * 1000)
+            .unwrap_or(0)
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        !lock(&self.shared).queue.is_empty()
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        !lock(&self.shared).queue.is_empty()
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        !lock(&self.shared).queue.is_empty()
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+struct TrackList {
+    player: AudioPlayer,
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackList {
+    fn get_tracks_metadata(&self, track_ids: Vec<ObjectPath<'_>>) -> Vec<Metadata> {
+        let shared = lock(&self.shared);
+        track_ids
+            .iter()
+            .filter_map(|id| {
+                let index = track_index(id)?;
+                let track = shared.queue.get(index)?;
+                let cover_url = (index == shared.current)
+                    .then_some(shared.cover_url.as_deref())
+                    .flatten();
+                Some(track_metadata(index, track, cover_url))
+            })
+            .collect()
+    }
+
+    fn add_track(
+        &self,
+        uri: String,
+        after_track: ObjectPath<'_>,
+        set_as_current: bool,
+    ) -> fdo::Result<()> {
+        // The track is appended, then moved behind `after_track`; NoTrack means the front.
+        let added = lock(&self.shared).queue.len();
+        let target = match track_index(&after_track) {
+            Some(index) => (index + 1).min(added),
+            None if after_track.as_str() == NO_TRACK => 0,
+            None => added,
+        };
+
+        let report = self
+            .player
+            .add_queue(vec![util::path_from_uri(&uri)])
+            .map_err(to_fdo)?;
+        if let Some(rejected) = report.rejected.first() {
+            return Err(to_fdo(&rejected.reason));
+        }
+        if target != added {
+            self.player
+                .move_in_queue(added, target, None)
+                .map_err(to_fdo)?;
+        }
+        if set_as_current {
+            self.player.play(target).map_err(to_fdo)?;
+        }
+        Ok(())
+    }
+
+    fn remove_track(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        match track_index(&track_id) {
+            Some(index) => self
+                .player
+                .remove_from_queue(vec![index], None)
+                .map_err(to_fdo),
+            None => Ok(()),
+        }
+    }
+
+    fn go_to(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        match track_index(&track_id) {
+            Some(index) => self.player.play(index).map_err(to_fdo),
+            None => Ok(()),
+        }
+    }
+
+    #[zbus(signal)]
+    async fn track_list_replaced(
+        emitter: &SignalEmitter<'_>,
+        tracks: Vec<ObjectPath<'_>>,
+        current_track: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property(emits_changed_signal = "invalidates"))]
+    fn tracks(&self) -> Vec<ObjectPath<'static>> {
+        (0..lock(&self.shared).queue.len()).map(track_id).collect()
+    }
+
+    #[zbus(property)]
+    fn can_edit_tracks(&self) -> bool {
+        true
+    }
+}
+
+/// MPRIS server with the `TrackList` interface, which souvlaki doesn't provide, so desktop
+/// applets and KDE Connect can show and edit the queue.
+pub struct Mpris {
+    connection: Connection,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Mpris {
+    pub fn new(app: AppHandle, player: AudioPlayer) -> zbus::Result<Mpris> {
+        let shared = Arc::new(Mutex::new(Shared {
+            queue: Vec::new(),
+            current: 0,
+            status: "Stopped",
+            cover_url: None,
+            volume: 1.0,
+            looped: false,
+            shuffle: false,
+        }));
+
+        let connection = connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, Root { app })?
+            .serve_at(
+                OBJECT_PATH,
+                Player {
+                    player: player.clone(),
+                    shared: shared.clone(),
+                },
+            )?
+            .serve_at(
+                OBJECT_PATH,
+                TrackList {
+                    player,
+                    shared: shared.clone(),
+                },
+            )?
+            .build()?;
+
+        Ok(Mpris { connection, shared })
+    }
+
+    pub fn set_cover_url(&self, cover_url: Option<String>) -> zbus::Result<()> {
+        lock(&self.shared).cover_url = cover_url;
+
+        let player = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        let iface = player.get();
+        zbus::block_on(iface.metadata_changed(player.signal_emitter()))
+    }
+
+    pub fn set_playback_status(&self, status: &'static str) -> zbus::Result<()> {
+        if std::mem::replace(&mut lock(&self.shared).status, status) == status {
+            return Ok(());
+        }
+
+        let player = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        let iface = player.get();
+        zbus::block_on(iface.playback_status_changed(player.signal_emitter()))
+    }
+
+    /// Publishes player state that changed, leaving unchanged values unsignalled.
+    pub fn sync(
+        &self,
+        queue: &[TrackInfo],
+        current: usize,
+        volume: f64,
+        looped: bool,
+        shuffle: bool,
+    ) -> zbus::Result<()> {
+        let (queue_changed, volume_changed, looped_changed, shuffle_changed) = {
+            let mut shared = lock(&self.shared);
+            let queue_changed = shared.current != current
+                || shared.queue.len() != queue.len()
+                || shared
+                    .queue
+                    .iter()
+                    .zip(queue)
+                    .any(|(a, b)| a.path != b.path);
+            if queue_changed {
+                shared.queue = queue.to_vec();
+                shared.current = current;
+            }
+
+            (
+                queue_changed,
+                std::mem::replace(&mut shared.volume, volume) != volume,
+                std::mem::replace(&mut shared.looped, looped) != looped,
+                std::mem::replace(&mut shared.shuffle, shuffle) != shuffle,
+            )
+        };
+
+        let server = self.connection.object_server();
+        let player = server.interface::<_, Player>(OBJECT_PATH)?;
+        let emitter = player.signal_emitter();
+        let iface = player.get();
+        zbus::block_on(async {
+            if volume_changed {
+                iface.volume_changed(emitter).await?;
+            }
+            if looped_changed {
+                iface.loop_status_changed(emitter).await?;
+            }
+            if shuffle_changed {
+                iface.shuffle_changed(emitter).await?;
+            }
+            if queue_changed {
+                iface.metadata_changed(emitter).await?;
+                iface.can_play_changed(emitter).await?;
+
+                let current_track = if current < queue.len() {
+                    track_id(current)
+                } else {
+                    ObjectPath::from_static_str_unchecked(NO_TRACK)
+                };
+                let tracks = (0..queue.len()).map(track_id).collect();
+                TrackList::track_list_replaced(emitter, tracks, current_track).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Tells clients the position jumped, so they stop extrapolating from the old one.
+    pub fn seeked(&self, position: Duration) -> zbus::Result<()> {
+        let player = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        zbus::block_on(Player::seeked(
+            player.signal_emitter(),
+            position.as_micros() as i64,
+        ))
+    }
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::audio_player::{AudioError, AudioPlayer, TrackInfo};
+use crate::media_server::{self, MediaServer, ServedFile};
+use crate::util;
+
+/// How often the master broadcasts its playback state.
+const STATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often followers re-measure their clock offset to the master.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Followers only seek when they drift further than this from the master.
+const DRIFT_TOLERANCE_MS: i64 = 80;
+
+/// Clock samples kept by a follower; the one with the lowest round trip wins.
+const CLOCK_SAMPLES: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum MultiRoomError {
+    #[error("A sync session is already running")]
+    AlreadyRunning,
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Player error: {0}")]
+    PlayerError(#[from] AudioError),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+/// Line-delimited JSON spoken between the master and its followers.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SyncMessage {
+    Ping {
+        client_time: u64,
+    },
+    Pong {
+        client_time: u64,
+        master_time: u64,
+    },
+    State {
+        track: Option<TrackInfo>,
+        position_ms: u64,
+        playing: bool,
+        master_time: u64,
+    },
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum SyncRole {
+    Master { port: u16 },
+    Follower { address: String },
+}
+
+struct SyncSession {
+    role: SyncRole,
+    stop: Arc<AtomicBool>,
+}
+
+/// Keeps several instances playing the same queue in time: the master streams its current
+/// track to followers via the media server and broadcasts clock-stamped positions.
+#[derive(Default)]
+pub struct MultiRoom {
+    session: Mutex<Option<SyncSession>>,
+}
+
+impl MultiRoom {
+    pub fn start_master(&self, player: AudioPlayer, port: u16) -> Result<(), MultiRoomError> {
+        let mut session = self.session.lock().map_err(|_| MultiRoomError::LockError)?;
+        if session.is_some() {
+            return Err(MultiRoomError::AlreadyRunning);
+        }
+
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let server = media_server::shared();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+
+        thread::spawn(move || {
+            while !stop_clone.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, address)) => {
+                        let player = player.clone();
+                        let server = server.clone();
+                        let stop = stop_clone.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve_follower(stream, address, player, server, stop) {
+                                log::error!("{}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => log::error!("{}", e),
+                }
+            }
+        });
+
+        *session = Some(SyncSession {
+            role: SyncRole::Master { port },
+            stop,
+        });
+        Ok(())
+    }
+
+    pub fn follow(&self, player: AudioPlayer, address: String) -> Result<(), MultiRoomError> {
+        let mut session = self.session.lock().map_err(|_| MultiRoomError::LockError)?;
+        if session.is_some() {
+            return Err(MultiRoomError::AlreadyRunning);
+        }
+
+        let stream = TcpStream::connect(address.as_str())?;
+        stream.set_read_timeout(Some(STATE_INTERVAL))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+
+        thread::spawn(move || {
+            if let Err(e) = follow_master(stream, player, stop_clone) {
+                log::error!("{}", e);
+            }
+        });
+
+        *session = Some(SyncSession {
+            role: SyncRole::Follower { address },
+            stop,
+        });
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<(), MultiRoomError> {
+        let session = self
+            .session
+            .lock()
+            .map_err(|_| MultiRoomError::LockError)?
+            .take();
+
+        if let Some(session) = session {
+            session.stop.store(true, Ordering::SeqCst);
+        }
+
+        Ok(())
+    }
+
+    pub fn role(&self) -> Result<Option<SyncRole>, MultiRoomError> {
+        let session = self.session.lock().map_err(|_| MultiRoomError::LockError)?;
+        Ok(session.as_ref().map(|session| session.role.clone()))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn send(stream: &Mutex<TcpStream>, message: &SyncMessage) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+
+    let mut stream = stream.lock().unwrap_or_else(|e| e.into_inner());
+    stream.write_all(line.as_bytes())
+}
+
+fn serve_follower(
+    stream: TcpStream,
+    address: SocketAddr,
+    player: AudioPlayer,
+    server: Arc<MediaServer>,
+    stop: Arc<AtomicBool>,
+) -> Result<(), MultiRoomError> {
+    stream.set_nonblocking(false)?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let writer = Arc::new(Mutex::new(stream));
+
+    // Answer clock pings on a separate thread so they are not delayed by state broadcasts.
+    let ping_writer = writer.clone();
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if let Ok(SyncMessage::Ping { client_time }) = serde_json::from_str(&line) {
+                let pong = SyncMessage::Pong {
+                    client_time,
+                    master_time: now_ms(),
+                };
+                if send(&ping_writer, &pong).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let host = media_server::local_ip_towards(address)?;
+    // The track being shared, as followers see it, and its media server entry.
+    let mut shared: Option<(String, TrackInfo, Option<ServedFile>)> = None;
+
+    while !stop.load(Ordering::SeqCst) {
+        let snapshot = player.get_state()?;
+        let track = snapshot.queue.get(snapshot.current_index).cloned();
+
+        // Followers fetch local files from this instance's media server.
+        let track = match track {
+            Some(track) => Some(match &shared {
+                Some((path, shared_track, _)) if *path == track.path => shared_track.clone(),
+                _ => {
+                    let mut shared_track = track.clone();
+                    let served = if util::is_network_path(&track.path) {
+                        None
+                    } else {
+                        let served = server.url_for(Path::new(&track.path), host)?;
+                        shared_track.path = served.url().to_string();
+                        Some(served)
+                    };
+                    shared = Some((track.path, shared_track.clone(), served));
+                    shared_track
+                }
+            }),
+            None => {
+                shared = None;
+                None
+            }
+        };
+
+        send(
+            &writer,
+            &SyncMessage::State {
+                track,
+                position_ms: snapshot.position_ms,
+                playing: snapshot.playing,
+                master_time: now_ms(),
+            },
+        )?;
+
+        thread::sleep(STATE_INTERVAL);
+    }
+
+    Ok(())
+}
+
+fn follow_master(
+    stream: TcpStream,
+    player: AudioPlayer,
+    stop: Arc<AtomicBool>,
+) -> Result<(), MultiRoomError> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Mutex::new(stream);
+
+    // (round trip, master clock minus local clock)
+    let mut clock_samples: Vec<(u64, i64)> = Vec::new();
+    let mut last_ping: Option<Instant> = None;
+    let mut current_url: Option<String> = None;
+    // Bytes rather than a string, so a read cut off mid-character keeps what it got.
+    let mut line = Vec::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        if last_ping.map_or(true, |at| at.elapsed() >= PING_INTERVAL) {
+            send(
+                &writer,
+                &SyncMessage::Ping {
+                    client_time: now_ms(),
+                },
+            )?;
+            last_ping = Some(Instant::now());
+        }
+
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            // Whatever arrived before the timeout stays in `line` for the next read to finish.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if line.last() != Some(&b'\n') {
+            continue;
+        }
+
+        let message = serde_json::from_slice(&line);
+        line.clear();
+        match message {
+            Ok(SyncMessage::Pong {
+                client_time,
+                master_time,
+            }) => {
+                let received = now_ms();
+                let round_trip = received.saturating_sub(client_time);
+                let offset = master_time as i64 - (client_time + round_trip / 2) as i64;
+
+                clock_samples.push((round_trip, offset));
+                if clock_samples.len() > CLOCK_SAMPLES {
+                    clock_samples.remove(0);
+                }
+            }
+            Ok(SyncMessage::State {
+                track,
+                position_ms,
+                playing,
+                master_time,
+            }) => {
+                let offset = clock_samples
+                    .iter()
+                    .min_by_key(|(round_trip, _)| *round_trip)
+                    .map_or(0, |(_, offset)| *offset);
+
+                apply_state(
+                    &player,
+                    &mut current_url,
+                    track,
+                    position_ms,
+                    playing,
+                    master_time as i64 - offset,
+                )?;
+            }
+            Ok(SyncMessage::Ping { .. }) | Err(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Brings the local player in line with the master; `sent_at` is in local clock time.
+fn apply_state(
+    player: &AudioPlayer,
+    current_url: &mut Option<String>,
+    track: Option<TrackInfo>,
+    position_ms: u64,
+    playing: bool,
+    sent_at: i64,
+) -> Result<(), MultiRoomError> {
+    let Some(track) = track else {
+        return Ok(());
+    };
+
+    if current_url.as_deref() != Some(track.path.as_str()) {
+        *current_url = Some(track.path.clone());
+        player.clear_queue()?;
+        player.add_tracks(vec![track])?;
+        player.play(0)?;
+        return Ok(());
+    }
+
+    let snapshot = player.get_state()?;
+    if snapshot.playing != playing {
+        if playing {
+            player.resume()?;
+        } else {
+            player.pause()?;
+        }
+    }
+
+    let expected = if playing {
+        position_ms as i64 + (now_ms() as i64 - sent_at).max(0)
+    } else {
+        position_ms as i64
+    };
+
+    if (snapshot.position_ms as i64 - expected).abs() > DRIFT_TOLERANCE_MS {
+        player.seek(Duration::from_millis(expected.max(0) as u64))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use souvlaki::{MediaControlEvent, MediaMetadata, MediaPlayback, MediaPosition};
+use std::time::Duration;
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::plugin::{Builder, PluginHandle, TauriPlugin};
+use tauri::{AppHandle, Manager, Wry};
+
+tauri::ios_plugin_binding!(init_plugin_now_playing);
+
+/// Handle to `NowPlayingPlugin.swift`, which sets up the audio session for background
+/// playback and owns the lock screen / CarPlay controls.
+#[derive(Clone)]
+struct NowPlayingPlugin(PluginHandle<Wry>);
+
+pub fn init() -> TauriPlugin<Wry> {
+    Builder::new("now-playing")
+        .setup(|app, api| {
+            let handle = api.register_ios_plugin(init_plugin_now_playing)?;
+            app.manage(NowPlayingPlugin(handle));
+            Ok(())
+        })
+        .build()
+}
+
+#[derive(Serialize)]
+struct ListenerArgs {
+    channel: Channel<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataArgs<'a> {
+    title: Option<&'a str>,
+    artist: Option<&'a str>,
+    album: Option<&'a str>,
+    duration: Option<f64>,
+    cover_path: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct PlaybackArgs {
+    playing: bool,
+    stopped: bool,
+    position: Option<f64>,
+}
+
+/// A command from `MPRemoteCommandCenter`.
+#[derive(Deserialize)]
+struct RemoteCommand {
+    command: String,
+    position: Option<f64>,
+}
+
+/// `MPNowPlayingInfoCenter` and `MPRemoteCommandCenter`, which souvlaki only drives on macOS.
+pub struct NowPlaying {
+    plugin: Option<NowPlayingPlugin>,
+}
+
+impl NowPlaying {
+    pub fn new(
+        app: &AppHandle,
+        on_event: impl Fn(MediaControlEvent) + Send + Sync + 'static,
+    ) -> NowPlaying {
+        let plugin = app
+            .try_state::<NowPlayingPlugin>()
+            .map(|plugin| plugin.inner().clone());
+
+        if let Some(plugin) = &plugin {
+            let channel = Channel::new(move |body| {
+                if let InvokeResponseBody::Json(json) = body {
+                    let remote: RemoteCommand = serde_json::from_str(&json)?;
+                    if let Some(event) = to_event(&remote) {
+                        on_event(event);
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = plugin
+                .0
+                .run_mobile_plugin::<()>("setListener", ListenerArgs { channel })
+            {
+                log::error!("Failed to listen for remote commands: {}", e);
+            }
+        }
+
+        NowPlaying { plugin }
+    }
+
+    pub fn set_metadata(&self, metadata: &MediaMetadata) {
+        let args = MetadataArgs {
+            title: metadata.title,
+            artist: metadata.artist,
+            album: metadata.album,
+            duration: metadata.duration.map(|duration| duration.as_secs_f64()),
+            cover_path: metadata
+                .cover_url
+                .map(|url| url.strip_prefix("file://").unwrap_or(url)),
+        };
+        self.run("setMetadata", args);
+    }
+
+    pub fn set_playback(&self, playback: &MediaPlayback) {
+        let (playing, progress) = match playback {
+            MediaPlayback::Playing { progress } => (true, *progress),
+            MediaPlayback::Paused { progress } => (false, *progress),
+            MediaPlayback::Stopped => (false, None),
+        };
+        let args = PlaybackArgs {
+            playing,
+            stopped: matches!(playback, MediaPlayback::Stopped),
+            position: progress.map(|MediaPosition(position)| position.as_secs_f64()),
+        };
+        self.run("setPlayback", args);
+    }
+
+    fn run(&self, command: &str, args: impl Serialize) {
+        if let Some(plugin) = &self.plugin {
+            if let Err(e) = plugin.0.run_mobile_plugin::<()>(command, args) {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
+
+fn to_event(remote: &RemoteCommand) -> Option<MediaControlEvent> {
+    let event = match remote.command.as_str() {
+        "play" => MediaControlEvent::Play,
+        "pause" => MediaControlEvent::Pause,
+        "toggle" => MediaControlEvent::Toggle,
+        "next" => MediaControlEvent::Next,
+        "previous" => MediaControlEvent::Previous,
+        "stop" => MediaControlEvent::Stop,
+        "seek" => MediaControlEvent::SetPosition(MediaPosition(Duration::from_secs_f64(
+            remote.position?.max(0.0),
+        ))),
+        _ => return None,
+    };
+    Some(event)
+}
@@ -0,0 +1,236 @@
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::UniformSourceIterator;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 48000;
+
+/// Audio pulled per wake-up of the playback thread.
+const PERIOD: Duration = Duration::from_millis(10);
+
+/// Paces the null output's playback thread.
+trait Clock: Send + 'static {
+    /// Blocks until the next `period` of audio is due. False once playback should stop.
+    fn wait(&mut self, period: Duration) -> bool;
+}
+
+/// Wall-clock time, so headless playback takes as long as it would on a sound card.
+#[derive(Default)]
+struct RealTime {
+    deadline: Option<Instant>,
+}
+
+impl Clock for RealTime {
+    fn wait(&mut self, period: Duration) -> bool {
+        // The first period is due straight away.
+        let Some(deadline) = self.deadline.as_mut() else {
+            self.deadline = Some(Instant::now() + period);
+            return true;
+        };
+
+        if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        *deadline += period;
+        true
+    }
+}
+
+/// Plays a sink's queue into nothing at real-time speed, so positions advance and tracks end
+/// as they would on a sound card. For machines without one, such as CI runners.
+pub struct NullOutput {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullOutput {
+    pub fn start(queue: SourcesQueueOutput<f32>) -> NullOutput {
+        NullOutput::with_clock(queue, RealTime::default())
+    }
+
+    fn with_clock(queue: SourcesQueueOutput<f32>, mut clock: impl Clock) -> NullOutput {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = thread::spawn({
+            let running = running.clone();
+            move || {
+                let mut source = UniformSourceIterator::<_, f32>::new(queue, CHANNELS, SAMPLE_RATE);
+                let samples =
+                    (SAMPLE_RATE as u128 * PERIOD.as_millis() / 1000) as usize * CHANNELS as usize;
+
+                while running.load(Ordering::Relaxed) && clock.wait(PERIOD) {
+                    // The queue plays silence while empty, so the source never actually runs dry.
+                    source.by_ref().take(samples).for_each(drop);
+                }
+            }
+        });
+
+        NullOutput {
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for NullOutput {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::{EmptyCallback, SineWave};
+    use rodio::{Sink, Source};
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    /// Lets the playback thread through one period per tick, owning up once it's played it.
+    struct ManualClock {
+        ticks: Receiver<()>,
+        played: Sender<()>,
+        ticked: bool,
+    }
+
+    impl Clock for ManualClock {
+        fn wait(&mut self, _period: Duration) -> bool {
+            if self.ticked {
+                let _ = self.played.send(());
+            }
+            self.ticked = self.ticks.recv().is_ok();
+            self.ticked
+        }
+    }
+
+    /// A sink on a null output that plays only as far as the test advances it, so nothing
+    /// depends on how the test threads get scheduled.
+    struct Manual {
+        // Dropped before the output, which would otherwise wait forever for a tick.
+        ticks: Sender<()>,
+        played: Receiver<()>,
+        _output: NullOutput,
+        sink: Sink,
+    }
+
+    impl Manual {
+        fn new() -> Manual {
+            let (ticks, clock_ticks) = mpsc::channel();
+            let (clock_played, played) = mpsc::channel();
+            let (sink, queue) = Sink::new_idle();
+            let clock = ManualClock {
+                ticks: clock_ticks,
+                played: clock_played,
+                ticked: false,
+            };
+            Manual {
+                ticks,
+                played,
+                _output: NullOutput::with_clock(queue, clock),
+                sink,
+            }
+        }
+
+        /// Plays `by` worth of audio, returning once it has been pulled from the sink.
+        fn advance(&self, by: Duration) {
+            for _ in 0..by.as_millis() / PERIOD.as_millis() {
+                self.ticks.send(()).unwrap();
+                self.played.recv().unwrap();
+            }
+        }
+    }
+
+    fn tone(millis: u64) -> impl Source<Item = f32> {
+        SineWave::new(440.0).take_duration(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn plays_as_far_as_the_clock_goes() {
+        let manual = Manual::new();
+        manual.sink.append(tone(300));
+        assert_eq!(manual.sink.get_pos(), Duration::ZERO);
+
+        manual.advance(Duration::from_millis(150));
+        let position = manual.sink.get_pos();
+        // The sink only updates its position every 5 ms of audio.
+        assert!(position >= Duration::from_millis(140), "{:?}", position);
+        assert!(position <= Duration::from_millis(150), "{:?}", position);
+        assert!(!manual.sink.empty());
+
+        manual.advance(Duration::from_millis(200));
+        assert!(manual.sink.empty());
+    }
+
+    #[test]
+    fn reports_the_end_of_a_track_when_it_is_played_out() {
+        let manual = Manual::new();
+        let (sender, ended) = mpsc::channel();
+        manual.sink.append(tone(50));
+        manual
+            .sink
+            .append(EmptyCallback::<f32>::new(Box::new(move || {
+                let _ = sender.send(());
+            })));
+
+        manual.advance(Duration::from_millis(40));
+        assert!(ended.try_recv().is_err());
+        manual.advance(Duration::from_millis(20));
+        assert!(ended.try_recv().is_ok());
+    }
+
+    #[test]
+    fn moves_on_to_the_next_track() {
+        let manual = Manual::new();
+        manual.sink.append(tone(50));
+        manual.sink.append(tone(50));
+        assert_eq!(manual.sink.len(), 2);
+
+        manual.advance(Duration::from_millis(70));
+        assert_eq!(manual.sink.len(), 1);
+    }
+
+    #[test]
+    fn pausing_stops_the_clock() {
+        let manual = Manual::new();
+        manual.sink.append(tone(1000));
+        manual.sink.pause();
+
+        // The sink picks up the pause on its next control update, 5 ms of audio in.
+        manual.advance(Duration::from_millis(20));
+        let paused_at = manual.sink.get_pos();
+        manual.advance(Duration::from_millis(100));
+        assert_eq!(manual.sink.get_pos(), paused_at);
+        assert!(paused_at <= Duration::from_millis(10), "{:?}", paused_at);
+
+        manual.sink.play();
+        manual.advance(Duration::from_millis(100));
+        assert!(manual.sink.get_pos() > paused_at + Duration::from_millis(80));
+    }
+
+    #[test]
+    fn stopping_empties_the_queue() {
+        let manual = Manual::new();
+        manual.sink.append(tone(1000));
+        manual.sink.append(tone(1000));
+        manual.advance(Duration::from_millis(20));
+
+        manual.sink.stop();
+        manual.advance(Duration::from_millis(20));
+        assert!(manual.sink.empty());
+    }
+
+    #[test]
+    fn real_time_is_paced_by_the_wall_clock() {
+        let mut clock = RealTime::default();
+        let started = Instant::now();
+        for _ in 0..4 {
+            assert!(clock.wait(PERIOD));
+        }
+        assert!(started.elapsed() >= PERIOD * 3);
+    }
+}
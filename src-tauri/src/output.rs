@@ -0,0 +1,330 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::cpal::{self, SampleFormat, SampleRate, SupportedStreamConfig};
+use rodio::source::SeekError;
+use rodio::{OutputStream, Sink, Source, StreamError};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(target_os = "linux")]
+use crate::jack_output::JackOutput;
+use crate::null_output::NullOutput;
+use crate::output_device;
+#[cfg(target_os = "linux")]
+use crate::pipewire_output::PipeWireOutput;
+
+#[derive(Error, Debug)]
+pub enum OutputError {
+    #[error("Failed to open audio output: {0}")]
+    StreamError(#[from] rodio::StreamError),
+
+    #[error("Failed to create audio sink: {0}")]
+    SinkError(#[from] rodio::PlayError),
+
+    #[cfg(target_os = "linux")]
+    #[error("JACK error: {0}")]
+    JackError(#[from] jack::Error),
+
+    #[cfg(target_os = "linux")]
+    #[error("PipeWire error: {0}")]
+    PipeWireError(String),
+
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    #[error("{0} output isn't available on this platform")]
+    Unsupported(&'static str),
+}
+
+/// Where the player's audio goes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBackend {
+    /// The system's default output device.
+    #[default]
+    Device,
+    /// A JACK client, to be routed alongside other pro-audio applications.
+    Jack(JackConfig),
+    /// A native PipeWire stream, listed as this app's music in desktop mixers and patchbays.
+    /// Its mixer volume applies on top of the player's rather than following it.
+    PipeWire,
+    /// Plays into nothing at real-time speed, for machines without a sound card.
+    Headless,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct JackConfig {
+    /// Name the client shows up under in patchbays.
+    pub client_name: String,
+    /// Left and right output port names.
+    pub ports: [String; 2],
+    /// Ports the outputs are connected to once the client starts, e.g. `system:playback_1`.
+    pub connect_to: Option<[String; 2]>,
+}
+
+impl Default for JackConfig {
+    fn default() -> Self {
+        JackConfig {
+            client_name: "My Player".to_string(),
+            ports: ["out_left".to_string(), "out_right".to_string()],
+            connect_to: Some([
+                "system:playback_1".to_string(),
+                "system:playback_2".to_string(),
+            ]),
+        }
+    }
+}
+
+/// Sample rate the output device is opened at.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputRate {
+    /// Whatever the system mixer is set to.
+    #[default]
+    Default,
+    /// The playing track's own rate; the output is reopened when it changes.
+    MatchSource,
+    Fixed(u32),
+}
+
+/// Sample format the output device is opened with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSampleFormat {
+    #[default]
+    Default,
+    I16,
+    I32,
+    F32,
+}
+
+/// Format forced on the output device, e.g. an external DAC's native rate. JACK and PipeWire
+/// run at the server's format, so only the device backend uses it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct OutputFormat {
+    pub sample_rate: OutputRate,
+    pub sample_format: OutputSampleFormat,
+}
+
+impl OutputFormat {
+    /// Rate to ask of the device while a track at `source_rate` is playing.
+    fn rate(&self, source_rate: Option<u32>) -> Option<u32> {
+        match self.sample_rate {
+            OutputRate::Default => None,
+            OutputRate::MatchSource => source_rate,
+            OutputRate::Fixed(rate) => Some(rate),
+        }
+    }
+
+    fn sample_format(&self) -> Option<SampleFormat> {
+        match self.sample_format {
+            OutputSampleFormat::Default => None,
+            OutputSampleFormat::I16 => Some(SampleFormat::I16),
+            OutputSampleFormat::I32 => Some(SampleFormat::I32),
+            OutputSampleFormat::F32 => Some(SampleFormat::F32),
+        }
+    }
+}
+
+/// Whatever has to stay alive for the sink to be heard.
+pub enum Output {
+    Device {
+        _stream: OutputStream,
+    },
+    #[cfg(target_os = "linux")]
+    Jack {
+        _client: JackOutput,
+    },
+    #[cfg(target_os = "linux")]
+    PipeWire {
+        _stream: PipeWireOutput,
+    },
+    Headless {
+        _output: NullOutput,
+    },
+}
+
+/// What the audio thread plays tracks through: the sink of an open output in the app, or a
+/// silent stand-in in tests so commands can be handled without a sound card.
+pub trait AudioOutput {
+    /// Plays `source` after whatever is already queued.
+    fn append(&self, source: Box<dyn Source<Item = i16> + Send>);
+    fn play(&self);
+    fn pause(&self);
+    fn is_paused(&self) -> bool;
+    /// Drops everything queued and pauses.
+    fn clear(&self);
+    /// Drops everything queued.
+    fn stop(&self);
+    /// Whether nothing is queued.
+    fn empty(&self) -> bool;
+    /// Position within the source playing.
+    fn get_pos(&self) -> Duration;
+    fn try_seek(&self, pos: Duration) -> Result<(), SeekError>;
+    fn speed(&self) -> f32;
+    fn set_speed(&self, speed: f32);
+    fn set_volume(&self, volume: f32);
+}
+
+impl AudioOutput for Sink {
+    fn append(&self, source: Box<dyn Source<Item = i16> + Send>) {
+        Sink::append(self, source);
+    }
+
+    fn play(&self) {
+        Sink::play(self);
+    }
+
+    fn pause(&self) {
+        Sink::pause(self);
+    }
+
+    fn is_paused(&self) -> bool {
+        Sink::is_paused(self)
+    }
+
+    fn clear(&self) {
+        Sink::clear(self);
+    }
+
+    fn stop(&self) {
+        Sink::stop(self);
+    }
+
+    fn empty(&self) -> bool {
+        Sink::empty(self)
+    }
+
+    fn get_pos(&self) -> Duration {
+        Sink::get_pos(self)
+    }
+
+    fn try_seek(&self, pos: Duration) -> Result<(), SeekError> {
+        Sink::try_seek(self, pos)
+    }
+
+    fn speed(&self) -> f32 {
+        Sink::speed(self)
+    }
+
+    fn set_speed(&self, speed: f32) {
+        Sink::set_speed(self, speed);
+    }
+
+    fn set_volume(&self, volume: f32) {
+        Sink::set_volume(self, volume);
+    }
+}
+
+/// Picks the device's config closest to `format`. A rate or sample format the device can't do
+/// falls back to its default.
+fn device_config(
+    device: &cpal::Device,
+    format: &OutputFormat,
+    source_rate: Option<u32>,
+) -> Result<SupportedStreamConfig, StreamError> {
+    let default = device
+        .default_output_config()
+        .map_err(StreamError::DefaultStreamConfigError)?;
+    let rate = format
+        .rate(source_rate)
+        .map(SampleRate)
+        .unwrap_or(default.sample_rate());
+    let sample_format = format.sample_format().unwrap_or(default.sample_format());
+    if rate == default.sample_rate() && sample_format == default.sample_format() {
+        return Ok(default);
+    }
+
+    let config = device
+        .supported_output_configs()
+        .map_err(StreamError::SupportedStreamConfigsError)?
+        .filter(|range| {
+            range.sample_format() == sample_format
+                && range.min_sample_rate() <= rate
+                && rate <= range.max_sample_rate()
+        })
+        // Keep the default channel layout where possible.
+        .max_by_key(|range| range.channels() == default.channels())
+        .map(|range| range.with_sample_rate(rate));
+
+    Ok(config.unwrap_or_else(|| {
+        log::warn!(
+            "Output device can't play {} Hz {}; using its default format",
+            rate.0,
+            sample_format
+        );
+        default
+    }))
+}
+
+/// Opens `backend` with a sink playing into it. `source_rate` is the playing track's sample
+/// rate, for outputs that match it. The device backend plays to `device` while it's
+/// connected, and to the system default otherwise.
+pub fn open(
+    backend: &OutputBackend,
+    format: &OutputFormat,
+    source_rate: Option<u32>,
+    device: Option<&str>,
+) -> Result<(Output, Sink), OutputError> {
+    match backend {
+        OutputBackend::Device => {
+            let chosen = device.and_then(output_device::find);
+            let (stream, handle) = match chosen {
+                None if *format == OutputFormat::default() => OutputStream::try_default()?,
+                chosen => {
+                    let device = match chosen {
+                        Some(device) => device,
+                        None => cpal::default_host()
+                            .default_output_device()
+                            .ok_or(StreamError::NoDevice)?,
+                    };
+                    let config = device_config(&device, format, source_rate)?;
+                    OutputStream::try_from_device_config(&device, config)?
+                }
+            };
+            let sink = Sink::try_new(&handle)?;
+            Ok((Output::Device { _stream: stream }, sink))
+        }
+        #[cfg(target_os = "linux")]
+        OutputBackend::Jack(config) => {
+            // The sink has no device of its own; the JACK client pulls its samples.
+            let (sink, queue) = Sink::new_idle();
+            let client = JackOutput::start(config, queue)?;
+            Ok((Output::Jack { _client: client }, sink))
+        }
+        #[cfg(not(target_os = "linux"))]
+        OutputBackend::Jack(_) => Err(OutputError::Unsupported("JACK")),
+        #[cfg(target_os = "linux")]
+        OutputBackend::PipeWire => {
+            let (sink, queue) = Sink::new_idle();
+            let stream = PipeWireOutput::start(queue).map_err(OutputError::PipeWireError)?;
+            Ok((Output::PipeWire { _stream: stream }, sink))
+        }
+        #[cfg(not(target_os = "linux"))]
+        OutputBackend::PipeWire => Err(OutputError::Unsupported("PipeWire")),
+        OutputBackend::Headless => Ok(open_headless()),
+    }
+}
+
+/// The headless backend, which can't fail to open.
+pub fn open_headless() -> (Output, Sink) {
+    let (sink, queue) = Sink::new_idle();
+    let output = NullOutput::start(queue);
+    (Output::Headless { _output: output }, sink)
+}
+
+/// Opens `backend`, falling back to the default device if that fails.
+pub fn open_or_default(
+    backend: &OutputBackend,
+    format: &OutputFormat,
+    source_rate: Option<u32>,
+    device: Option<&str>,
+) -> Result<(Output, Sink), OutputError> {
+    open(backend, format, source_rate, device).or_else(|e| {
+        if *backend == OutputBackend::Device {
+            return Err(e);
+        }
+        log::warn!("{}; using the default output device instead", e);
+        open(&OutputBackend::Device, format, source_rate, None)
+    })
+}
@@ -0,0 +1,75 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::cpal::Device;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::audio_player::AudioCommand;
+
+/// How often the system's default output device is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Name of the system's default output device, if there is one.
+pub fn default_output_name() -> Option<String> {
+    rodio::cpal::default_host()
+        .default_output_device()?
+        .name()
+        .ok()
+}
+
+/// Names of the connected output devices.
+pub fn output_names() -> Vec<String> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// The connected output device with this name.
+pub fn find(name: &str) -> Option<Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|other| other == name))
+}
+
+/// Whether an output device with this name is still connected.
+fn is_connected(name: &str) -> bool {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|mut devices| devices.any(|device| device.name().is_ok_and(|other| other == name)))
+        .unwrap_or(true)
+}
+
+/// Reports the default output device to the audio thread at startup and whenever it changes,
+/// e.g. when headphones are plugged in or the user picks other speakers. A previous default
+/// that disappeared entirely is reported as removed first.
+pub fn watch(sender: mpsc::Sender<AudioCommand>) {
+    thread::spawn(move || {
+        let mut current: Option<String> = None;
+        loop {
+            let name = default_output_name();
+            if name != current {
+                if let Some(previous) = current.filter(|previous| !is_connected(previous)) {
+                    if sender
+                        .send(AudioCommand::OutputDeviceRemoved(previous))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+
+                current = name.clone();
+                if let Some(name) = name {
+                    if sender
+                        .send(AudioCommand::OutputDeviceChanged(name))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
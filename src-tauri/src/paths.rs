@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+/// The path to hand to the file system for a local track. Windows gets the extended-length
+/// form, so files nested past MAX_PATH still open; macOS falls back to the decomposed
+/// spelling HFS+ keeps names in when the composed one isn't found.
+pub fn native(path: &str) -> PathBuf {
+    platform::native(path)
+}
+
+/// The spelling a local path is stored and compared in. On macOS names are composed (NFC),
+/// so a track found by the scanner matches the same track from a playlist or the dock.
+pub fn normalized(path: &Path) -> String {
+    platform::normalized(path)
+}
+
+/// Extended-length (`\\?\`) paths, which skip the MAX_PATH limit.
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::*;
+    use std::path::Component;
+
+    const MAX_PATH: usize = 260;
+    const VERBATIM: &str = r"\\?\";
+
+    pub fn native(path: &str) -> PathBuf {
+        if path.len() < MAX_PATH || path.starts_with(VERBATIM) {
+            return PathBuf::from(path);
+        }
+        let path = Path::new(path);
+        if !path.is_absolute() {
+            return path.to_path_buf();
+        }
+
+        // Verbatim paths are passed through as they are, so `.`, `..` and forward slashes
+        // have to be resolved first.
+        let mut resolved = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                component => resolved.push(component),
+            }
+        }
+
+        let resolved = resolved.to_string_lossy().into_owned();
+        match resolved.strip_prefix(r"\\") {
+            Some(share) => PathBuf::from(format!(r"{}UNC\{}", VERBATIM, share)),
+            None => PathBuf::from(format!("{}{}", VERBATIM, resolved)),
+        }
+    }
+
+    pub fn normalized(path: &Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Names are stored composed and looked up in either form.
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::*;
+    use unicode_normalization::UnicodeNormalization;
+
+    pub fn native(path: &str) -> PathBuf {
+        let composed = Path::new(path);
+        if composed.exists() {
+            return composed.to_path_buf();
+        }
+        let decomposed = PathBuf::from(path.nfd().collect::<String>());
+        if decomposed.exists() {
+            decomposed
+        } else {
+            composed.to_path_buf()
+        }
+    }
+
+    pub fn normalized(path: &Path) -> String {
+        path.to_string_lossy().nfc().collect()
+    }
+}
+
+/// Elsewhere names are bytes the file system hands back unchanged.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::*;
+
+    pub fn native(path: &str) -> PathBuf {
+        PathBuf::from(path)
+    }
+
+    pub fn normalized(path: &Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn paths_are_used_as_stored() {
+        assert_eq!(
+            native("/music/Café/01.flac"),
+            PathBuf::from("/music/Café/01.flac")
+        );
+        assert_eq!(
+            normalized(Path::new("/music/Café/01.flac")),
+            "/music/Café/01.flac"
+        );
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn long_paths_get_the_extended_length_form() {
+        assert_eq!(
+            native(r"C:\Music\01.flac"),
+            PathBuf::from(r"C:\Music\01.flac")
+        );
+
+        let folder = "a".repeat(300);
+        assert_eq!(
+            native(&format!(r"C:\Music\{}\..\{}\.\01.flac", folder, folder)),
+            PathBuf::from(format!(r"\\?\C:\Music\{}\01.flac", folder))
+        );
+        assert_eq!(
+            native(&format!(r"\\server\share\{}\01.flac", folder)),
+            PathBuf::from(format!(r"\\?\UNC\server\share\{}\01.flac", folder))
+        );
+
+        let verbatim = format!(r"\\?\C:\Music\{}\01.flac", folder);
+        assert_eq!(native(&verbatim), PathBuf::from(&verbatim));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn names_are_found_in_either_form() {
+        use unicode_normalization::UnicodeNormalization;
+
+        let dir = std::env::temp_dir().join("music-player-paths");
+        let composed = dir.join("Café.flac").to_string_lossy().into_owned();
+        let decomposed: String = composed.nfd().collect();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&decomposed, b"").unwrap();
+
+        assert!(native(&composed).exists());
+        assert_eq!(normalized(Path::new(&decomposed)), composed);
+    }
+}
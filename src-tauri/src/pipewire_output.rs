@@ -0,0 +1,151 @@
+use pipewire as pw;
+use pw::properties::properties;
+use pw::spa;
+use pw::spa::param::audio::{AudioFormat, AudioInfoRaw, MAX_CHANNELS};
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{Object, Pod, Value};
+use pw::stream::{Stream, StreamFlags};
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::UniformSourceIterator;
+use std::io::Cursor;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+const CHANNELS: u32 = 2;
+const SAMPLE_RATE: u32 = 48000;
+const SAMPLE_SIZE: usize = std::mem::size_of::<f32>();
+
+/// A native PipeWire playback stream tagged as music from this app, so desktop mixers list
+/// it by name and apply per-app volume and routing rules to it.
+///
+/// The stream's own volume isn't synced with the player's: the player's volume is applied to
+/// the samples before they get here, and the mixer's slider scales them again on top. Each
+/// starts at full and moving one leaves the other where it is.
+pub struct PipeWireOutput {
+    quit: pw::channel::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PipeWireOutput {
+    /// Starts the stream on its own thread, which runs PipeWire's main loop until dropped.
+    pub fn start(queue: SourcesQueueOutput<f32>) -> Result<PipeWireOutput, String> {
+        let (quit, quit_receiver) = pw::channel::channel();
+        let (ready, ready_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            if let Err(e) = run(queue, quit_receiver, &ready) {
+                let _ = ready.send(Err(e.to_string()));
+            }
+        });
+
+        match ready_receiver.recv() {
+            Ok(Ok(())) => Ok(PipeWireOutput {
+                quit,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("PipeWire thread exited".to_string()),
+        }
+    }
+}
+
+impl Drop for PipeWireOutput {
+    fn drop(&mut self) {
+        let _ = self.quit.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    queue: SourcesQueueOutput<f32>,
+    quit: pw::channel::Receiver<()>,
+    ready: &mpsc::Sender<Result<(), String>>,
+) -> Result<(), pw::Error> {
+    pw::init();
+    let main_loop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&main_loop)?;
+    let core = context.connect(None)?;
+
+    let _quit = quit.attach(main_loop.loop_(), {
+        let main_loop = main_loop.clone();
+        move |_| main_loop.quit()
+    });
+
+    let stream = Stream::new(
+        &core,
+        "Music",
+        properties! {
+            *pw::keys::APP_NAME => "My Player",
+            *pw::keys::APP_ID => "com.music-player.app",
+            *pw::keys::NODE_NAME => "my_player",
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Playback",
+            *pw::keys::MEDIA_ROLE => "Music",
+            *pw::keys::AUDIO_CHANNELS => "2",
+        },
+    )?;
+
+    let source = UniformSourceIterator::new(queue, CHANNELS as u16, SAMPLE_RATE);
+    let _listener = stream
+        .add_local_listener_with_user_data(source)
+        .process(|stream, source| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let data = &mut buffer.datas_mut()[0];
+            let stride = SAMPLE_SIZE * CHANNELS as usize;
+
+            let frames = match data.data() {
+                Some(bytes) => {
+                    // The queue plays silence while empty, so the source never runs dry.
+                    for sample in bytes.chunks_exact_mut(SAMPLE_SIZE) {
+                        let value = source.next().unwrap_or(0.0);
+                        sample.copy_from_slice(&value.to_le_bytes());
+                    }
+                    bytes.len() / stride
+                }
+                None => 0,
+            };
+
+            let chunk = data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = stride as i32;
+            *chunk.size_mut() = (stride * frames) as u32;
+        })
+        .register()?;
+
+    let mut info = AudioInfoRaw::new();
+    info.set_format(AudioFormat::F32LE);
+    info.set_rate(SAMPLE_RATE);
+    info.set_channels(CHANNELS);
+    let mut position = [0; MAX_CHANNELS];
+    position[0] = spa::sys::SPA_AUDIO_CHANNEL_FL;
+    position[1] = spa::sys::SPA_AUDIO_CHANNEL_FR;
+    info.set_position(position);
+
+    let format = PodSerializer::serialize(
+        Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: spa::sys::SPA_TYPE_OBJECT_Format,
+            id: spa::sys::SPA_PARAM_EnumFormat,
+            properties: info.into(),
+        }),
+    )
+    .map_err(|_| pw::Error::CreationFailed)?
+    .0
+    .into_inner();
+    let mut params = [Pod::from_bytes(&format).ok_or(pw::Error::CreationFailed)?];
+
+    stream.connect(
+        spa::utils::Direction::Output,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::RT_PROCESS,
+        &mut params,
+    )?;
+
+    let _ = ready.send(Ok(()));
+    main_loop.run();
+    Ok(())
+}
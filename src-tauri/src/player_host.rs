@@ -0,0 +1,66 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio_player::{AudioError, AudioPlayer, TrackInfo};
+use crate::library::{self, Library};
+use crate::lyrics::{self, Lyrics};
+use crate::media_controls::{MediaControls, MediaControlsError};
+use crate::settings::SettingsStore;
+use crate::tray;
+
+/// Everything the audio thread reaches outside the player for: events to the front end, the
+/// library and settings, and the tray, media controls and lyrics that follow the playing
+/// track. The app handle is the host in the app; tests stand in one that records events.
+pub trait PlayerHost: Send + Sync {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), AudioError>;
+    fn library(&self) -> &Library;
+    fn settings(&self) -> &SettingsStore;
+    /// Shows the playing track, or none, in the tray.
+    fn set_current_track(&self, track: Option<&TrackInfo>);
+    fn media_controls(&self, player: AudioPlayer) -> Result<MediaControls, MediaControlsError>;
+    /// May look the lyrics up online, so it's called off the audio thread.
+    fn load_lyrics(&self, track: &TrackInfo, online: bool) -> Option<Lyrics>;
+    /// Saves how far into an audiobook file playback got, in the background.
+    fn save_listening(&self, path: String, position_ms: u64);
+}
+
+impl PlayerHost for AppHandle {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), AudioError> {
+        Emitter::emit(self, event, payload).map_err(AudioError::EmitError)
+    }
+
+    fn library(&self) -> &Library {
+        self.state::<Library>().inner()
+    }
+
+    fn settings(&self) -> &SettingsStore {
+        self.state::<SettingsStore>().inner()
+    }
+
+    fn set_current_track(&self, track: Option<&TrackInfo>) {
+        tray::set_current_track(self, track);
+    }
+
+    fn media_controls(&self, player: AudioPlayer) -> Result<MediaControls, MediaControlsError> {
+        MediaControls::new(self, player)
+    }
+
+    fn load_lyrics(&self, track: &TrackInfo, online: bool) -> Option<Lyrics> {
+        lyrics::load(self, track, online)
+    }
+
+    fn save_listening(&self, path: String, position_ms: u64) {
+        library::save_listening(self, path, position_ms);
+    }
+}
+
+/// Sends `payload` as `event` through `host`. Events only inform the front end, so a failure
+/// is logged rather than returned.
+pub fn emit<T: Serialize>(host: &dyn PlayerHost, event: &str, payload: T) {
+    let result = serde_json::to_value(payload)
+        .map_err(AudioError::SerializeError)
+        .and_then(|payload| host.emit(event, payload));
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+}
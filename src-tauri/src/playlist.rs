@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            PLAYLIST_EXTENSIONS
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(ext))
+        })
+        .unwrap_or(false)
+}
+
+/// Reads the entries of an M3U/M3U8 playlist, resolving relative entries against its folder.
+pub fn read_m3u(path: &Path) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let entries = contents
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let entry = line.strip_prefix("file://").unwrap_or(line);
+            if entry.contains("://") {
+                return None;
+            }
+
+            Some(base.join(entry).to_string_lossy().into_owned())
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Replaces any playlist files in `paths` with the tracks they reference.
+pub fn expand_paths(paths: Vec<String>) -> Vec<String> {
+    let mut expanded = Vec::new();
+
+    for path in paths {
+        if is_playlist_file(Path::new(&path)) {
+            match read_m3u(Path::new(&path)) {
+                Ok(entries) => expanded.extend(entries),
+                Err(e) => log::warn!("Failed to read playlist {}: {}", path, e),
+            }
+        } else {
+            expanded.push(path);
+        }
+    }
+
+    expanded
+}
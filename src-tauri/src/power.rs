@@ -0,0 +1,185 @@
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::audio_player::AudioCommand;
+
+/// How long the system is held back from sleeping while the audio thread saves its position.
+const SUSPEND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Tells the audio thread when the system is about to sleep and when it has woken up, so it
+/// can pause cleanly and reopen the output stream afterwards.
+pub fn watch(app: &AppHandle, sender: mpsc::Sender<AudioCommand>) {
+    platform::watch(app, sender);
+}
+
+/// Asks the audio thread to pause and waits for it, so the position is saved before sleeping.
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+fn suspend(sender: &mpsc::Sender<AudioCommand>) {
+    let (done, receiver) = mpsc::channel();
+    if sender.send(AudioCommand::SystemSuspending(done)).is_ok() {
+        let _ = receiver.recv_timeout(SUSPEND_TIMEOUT);
+    }
+}
+
+/// logind's `PrepareForSleep` signal, with a delay inhibitor so the pause happens before the
+/// system actually goes down.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::sync::mpsc;
+    use std::thread;
+    use tauri::AppHandle;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedFd;
+
+    use crate::audio_player::AudioCommand;
+
+    pub fn watch(_app: &AppHandle, sender: mpsc::Sender<AudioCommand>) {
+        thread::spawn(move || {
+            if let Err(e) = listen(&sender) {
+                log::error!("Failed to watch for system sleep: {}", e);
+            }
+        });
+    }
+
+    fn listen(sender: &mpsc::Sender<AudioCommand>) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let proxy = Proxy::new(
+            &connection,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        let signals = proxy.receive_signal("PrepareForSleep")?;
+        let mut delay = take_delay_lock(&proxy).ok();
+
+        for message in signals {
+            let sleeping: bool = message.body().deserialize()?;
+            if sleeping {
+                super::suspend(sender);
+                drop(delay.take());
+            } else {
+                if sender.send(AudioCommand::SystemResumed).is_err() {
+                    return Ok(());
+                }
+                delay = take_delay_lock(&proxy).ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Held until the next suspend; closing it lets the system go to sleep.
+    fn take_delay_lock(proxy: &Proxy) -> zbus::Result<OwnedFd> {
+        proxy.call(
+            "Inhibit",
+            &(
+                "sleep",
+                "My Player",
+                "Saving the playback position",
+                "delay",
+            ),
+        )
+    }
+}
+
+/// `WM_POWERBROADCAST`, which only reaches windows, so the main window is subclassed.
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::cell::RefCell;
+    use std::sync::mpsc;
+    use tauri::{AppHandle, Manager};
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::UI::Shell::{DefSubclassProc, SetWindowSubclass};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WM_POWERBROADCAST,
+    };
+
+    use crate::audio_player::AudioCommand;
+
+    thread_local! {
+        static SENDER: RefCell<Option<mpsc::Sender<AudioCommand>>> = const { RefCell::new(None) };
+    }
+
+    pub fn watch(app: &AppHandle, sender: mpsc::Sender<AudioCommand>) {
+        let handle = app.clone();
+        let result = app.run_on_main_thread(move || {
+            let Some(window) = handle.get_webview_window("main") else {
+                return;
+            };
+            let Ok(hwnd) = window.hwnd() else {
+                return;
+            };
+
+            SENDER.with(|cell| *cell.borrow_mut() = Some(sender));
+            unsafe { SetWindowSubclass(HWND(hwnd.0 as isize), Some(subclass_proc), 2, 0) };
+        });
+        if let Err(e) = result {
+            log::error!("Failed to watch for system sleep: {}", e);
+        }
+    }
+
+    unsafe extern "system" fn subclass_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: usize,
+        _data: usize,
+    ) -> LRESULT {
+        if msg == WM_POWERBROADCAST {
+            SENDER.with(|sender| {
+                if let Some(sender) = sender.borrow().as_ref() {
+                    match wparam.0 as u32 {
+                        PBT_APMSUSPEND => super::suspend(sender),
+                        PBT_APMRESUMEAUTOMATIC => {
+                            let _ = sender.send(AudioCommand::SystemResumed);
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+
+        DefSubclassProc(hwnd, msg, wparam, lparam)
+    }
+}
+
+/// No notification is available without an Objective-C runtime, so a wake-up is inferred from
+/// the wall clock jumping ahead of the monotonic clock, which stops while asleep.
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime};
+    use tauri::AppHandle;
+
+    use crate::audio_player::AudioCommand;
+
+    const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn watch(_app: &AppHandle, sender: mpsc::Sender<AudioCommand>) {
+        thread::spawn(move || loop {
+            let (wall, monotonic) = (SystemTime::now(), Instant::now());
+            thread::sleep(CHECK_INTERVAL);
+
+            let slept = wall
+                .elapsed()
+                .unwrap_or_default()
+                .saturating_sub(monotonic.elapsed());
+            if slept > CHECK_INTERVAL && sender.send(AudioCommand::SystemResumed).is_err() {
+                return;
+            }
+        });
+    }
+}
+
+/// Mobile platforms suspend the app itself and restore its audio session on their own.
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::sync::mpsc;
+    use tauri::AppHandle;
+
+    use crate::audio_player::AudioCommand;
+
+    pub fn watch(_app: &AppHandle, _sender: mpsc::Sender<AudioCommand>) {}
+}
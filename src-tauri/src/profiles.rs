@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+
+use crate::library::{self, Library, LibraryError};
+use crate::settings::{SettingsError, SettingsStore};
+
+/// Profile using the files from before profiles existed, so upgrading keeps everything.
+pub const DEFAULT_PROFILE: &str = "Default";
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to save profile selection")]
+    SerializeError(#[from] serde_json::Error),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+
+    #[error("Settings error: {0}")]
+    SettingsError(#[from] SettingsError),
+
+    #[error("Invalid profile name: {0}")]
+    InvalidName(String),
+
+    #[error("Profile is in use: {0}")]
+    ProfileInUse(String),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProfileFile {
+    active: Option<String>,
+}
+
+/// Independent libraries, each with its own database, covers and settings. Profiles other
+/// than the default live in a `profiles/<name>` folder of the data and config directories.
+pub struct Profiles {
+    data_dir: PathBuf,
+    config_dir: PathBuf,
+    active: Mutex<String>,
+}
+
+impl Profiles {
+    pub fn load(data_dir: PathBuf, config_dir: PathBuf) -> Profiles {
+        let active = File::open(config_dir.join("profile.json"))
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, ProfileFile>(BufReader::new(file)).ok())
+            .and_then(|file| file.active)
+            .filter(|name| validate_name(name).is_ok())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+        Profiles {
+            data_dir,
+            config_dir,
+            active: Mutex::new(active),
+        }
+    }
+
+    pub fn active(&self) -> Result<String, ProfileError> {
+        self.active
+            .lock()
+            .map(|active| active.clone())
+            .map_err(|_| ProfileError::LockError)
+    }
+
+    pub fn library_path(&self, name: &str) -> PathBuf {
+        self.profile_dir(&self.data_dir, name).join("library.db")
+    }
+
+    pub fn settings_path(&self, name: &str) -> PathBuf {
+        self.profile_dir(&self.config_dir, name)
+            .join("settings.json")
+    }
+
+    fn profile_dir(&self, root: &Path, name: &str) -> PathBuf {
+        if name == DEFAULT_PROFILE {
+            root.to_path_buf()
+        } else {
+            root.join("profiles").join(name)
+        }
+    }
+
+    /// The default profile followed by the others in name order.
+    pub fn list(&self) -> Result<Vec<String>, ProfileError> {
+        let mut names: Vec<String> = match fs::read_dir(self.data_dir.join("profiles")) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().join("library.db").is_file())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| validate_name(name).is_ok())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        names.sort_by_key(|name| name.to_lowercase());
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        Ok(names)
+    }
+
+    /// Opens the profile `name`, creating it if it doesn't exist, and emits `profile-changed`.
+    /// Only the library and settings are swapped; the audio engine keeps running.
+    pub fn switch(&self, app: &AppHandle, name: &str) -> Result<(), ProfileError> {
+        validate_name(name)?;
+
+        let mut active = self.active.lock().map_err(|_| ProfileError::LockError)?;
+        app.state::<Library>().switch_to(&self.library_path(name))?;
+        app.state::<SettingsStore>()
+            .switch_to(self.settings_path(name))?;
+        *active = name.to_string();
+
+        fs::create_dir_all(&self.config_dir)?;
+        let file = File::create(self.config_dir.join("profile.json"))?;
+        serde_json::to_writer_pretty(
+            BufWriter::new(file),
+            &ProfileFile {
+                active: Some(name.to_string()),
+            },
+        )?;
+        drop(active);
+
+        if let Err(e) = app.emit("profile-changed", name) {
+            log::error!("{}", e);
+        }
+        if let Err(e) = library::emit_playlists(app) {
+            log::error!("{}", e);
+        }
+        Ok(())
+    }
+
+    /// Removes a profile's files. The default and active profiles can't be deleted.
+    pub fn delete(&self, name: &str) -> Result<(), ProfileError> {
+        validate_name(name)?;
+        if name == DEFAULT_PROFILE || name == self.active()? {
+            return Err(ProfileError::ProfileInUse(name.to_string()));
+        }
+
+        for dir in [
+            self.profile_dir(&self.data_dir, name),
+            self.profile_dir(&self.config_dir, name),
+        ] {
+            match fs::remove_dir_all(dir) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Names become folder names, so anything that could escape the profiles folder is refused.
+fn validate_name(name: &str) -> Result<(), ProfileError> {
+    let valid = !name.trim().is_empty()
+        && name.len() <= 64
+        && name != "."
+        && name != ".."
+        && !name.chars().any(|c| {
+            c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ProfileError::InvalidName(name.to_string()))
+    }
+}
@@ -0,0 +1,232 @@
+use flacenc::bitsink::ByteSink;
+use flacenc::component::BitRepr;
+use flacenc::error::{SourceError, Verify};
+use flacenc::source::{Fill, Source};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::tap;
+
+#[derive(Error, Debug)]
+pub enum RecorderError {
+    #[error("A recording is already in progress")]
+    AlreadyRecording,
+
+    #[error("Unsupported recording format {0}")]
+    UnsupportedFormat(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("WAV error: {0}")]
+    WavError(#[from] hound::Error),
+
+    #[error("FLAC encoding failed: {0}")]
+    FlacError(String),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RecordingFormat {
+    Wav,
+    Flac,
+}
+
+struct Recording {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<(), RecorderError>>,
+}
+
+/// Captures the player output (as delivered to the sink) into a WAV or FLAC file.
+#[derive(Default)]
+pub struct Recorder {
+    recording: Mutex<Option<Recording>>,
+}
+
+impl Recorder {
+    pub fn start(&self, path: PathBuf) -> Result<(), RecorderError> {
+        let mut recording = self
+            .recording
+            .lock()
+            .map_err(|_| RecorderError::LockError)?;
+        if recording.is_some() {
+            return Err(RecorderError::AlreadyRecording);
+        }
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let format = match extension.as_str() {
+            "wav" => RecordingFormat::Wav,
+            "flac" => RecordingFormat::Flac,
+            _ => return Err(RecorderError::UnsupportedFormat(extension)),
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let thread = thread::spawn(move || record(&path, format, &stop_clone));
+
+        *recording = Some(Recording { stop, thread });
+        Ok(())
+    }
+
+    /// Stops capturing and waits until the file has been finalized.
+    pub fn stop(&self) -> Result<(), RecorderError> {
+        let recording = self
+            .recording
+            .lock()
+            .map_err(|_| RecorderError::LockError)?
+            .take();
+
+        if let Some(recording) = recording {
+            recording.stop.store(true, Ordering::SeqCst);
+            return recording
+                .thread
+                .join()
+                .map_err(|_| RecorderError::LockError)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// FLAC recordings are captured to a WAV next to the target and encoded once stopped.
+fn record(path: &Path, format: RecordingFormat, stop: &AtomicBool) -> Result<(), RecorderError> {
+    let wav_path = match format {
+        RecordingFormat::Wav => path.to_path_buf(),
+        RecordingFormat::Flac => path.with_extension("flac.part.wav"),
+    };
+
+    let blocks = tap::subscribe();
+    let mut writer: Option<WavWriter<BufWriter<File>>> = None;
+    let mut spec: Option<WavSpec> = None;
+
+    while !stop.load(Ordering::SeqCst) {
+        let block = match blocks.recv_timeout(Duration::from_millis(200)) {
+            Ok(block) => block,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        let block_spec = WavSpec {
+            channels: block.channels,
+            sample_rate: block.sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        // A WAV file has a single format, so tracks in a different one are left out.
+        match spec {
+            None => {
+                writer = Some(WavWriter::create(&wav_path, block_spec)?);
+                spec = Some(block_spec);
+            }
+            Some(spec) if spec != block_spec => {
+                log::error!(
+                    "Skipping {} Hz / {} channel audio in recording",
+                    block.sample_rate,
+                    block.channels
+                );
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        if let Some(writer) = writer.as_mut() {
+            for &sample in &block.samples {
+                writer.write_sample(sample)?;
+            }
+        }
+    }
+
+    let Some(writer) = writer else {
+        return Ok(());
+    };
+    writer.finalize()?;
+
+    if format == RecordingFormat::Flac {
+        let result = encode_flac(&wav_path, path);
+        fs::remove_file(&wav_path)?;
+        result?;
+    }
+
+    Ok(())
+}
+
+/// Feeds a recorded WAV to the encoder a block at a time, rather than collecting every
+/// sample first.
+struct WavSource {
+    reader: WavReader<BufReader<File>>,
+    spec: WavSpec,
+    block: Vec<i32>,
+}
+
+impl Source for WavSource {
+    fn channels(&self) -> usize {
+        self.spec.channels as usize
+    }
+
+    fn bits_per_sample(&self) -> usize {
+        self.spec.bits_per_sample as usize
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.spec.sample_rate as usize
+    }
+
+    fn read_samples<F: Fill>(
+        &mut self,
+        block_size: usize,
+        dest: &mut F,
+    ) -> Result<usize, SourceError> {
+        let channels = self.spec.channels as usize;
+        self.block.clear();
+        for sample in self.reader.samples::<i16>().take(block_size * channels) {
+            self.block
+                .push(sample.map_err(SourceError::from_io_error)?.into());
+        }
+        dest.fill_interleaved(&self.block)?;
+        Ok(self.block.len() / channels)
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        Some(self.reader.duration() as usize)
+    }
+}
+
+/// Encodes a recorded WAV as FLAC. The encoded stream is built in memory, about half the
+/// size of the WAV, and written out once it's complete.
+fn encode_flac(source: &Path, target: &Path) -> Result<(), RecorderError> {
+    let reader = WavReader::open(source)?;
+    let source = WavSource {
+        spec: reader.spec(),
+        reader,
+        block: Vec::new(),
+    };
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| RecorderError::FlacError(format!("{:?}", e)))?;
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| RecorderError::FlacError(format!("{:?}", e)))?;
+
+    let mut sink = ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| RecorderError::FlacError(format!("{:?}", e)))?;
+    fs::write(target, sink.as_slice())?;
+
+    Ok(())
+}
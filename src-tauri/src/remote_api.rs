@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::audio_player::AudioPlayer;
+
+#[derive(Error, Debug)]
+pub enum RemoteApiError {
+    #[error("Remote API is already running")]
+    AlreadyRunning,
+
+    #[error("A bearer token is required")]
+    MissingToken,
+
+    #[error("Failed to start server: {0}")]
+    ServerError(String),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+#[derive(Deserialize, Default)]
+struct PlayBody {
+    index: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct QueueBody {
+    paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct VolumeBody {
+    volume: f32,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+struct RunningServer {
+    server: Arc<Server>,
+    thread: JoinHandle<()>,
+}
+
+/// Optional LAN HTTP server exposing basic playback control behind a bearer token.
+#[derive(Default)]
+pub struct RemoteApi {
+    running: Mutex<Option<RunningServer>>,
+}
+
+impl RemoteApi {
+    pub fn start(
+        &self,
+        player: AudioPlayer,
+        port: u16,
+        token: String,
+    ) -> Result<(), RemoteApiError> {
+        if token.is_empty() {
+            return Err(RemoteApiError::MissingToken);
+        }
+
+        let mut running = self.running.lock().map_err(|_| RemoteApiError::LockError)?;
+        if running.is_some() {
+            return Err(RemoteApiError::AlreadyRunning);
+        }
+
+        let server = Server::http(("0.0.0.0", port))
+            .map_err(|e| RemoteApiError::ServerError(e.to_string()))?;
+        let server = Arc::new(server);
+        let server_clone = server.clone();
+
+        let thread = thread::spawn(move || {
+            let expected = format!("Bearer {}", token);
+            for request in server_clone.incoming_requests() {
+                handle_request(request, &player, &expected);
+            }
+        });
+
+        *running = Some(RunningServer { server, thread });
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<(), RemoteApiError> {
+        let running = self
+            .running
+            .lock()
+            .map_err(|_| RemoteApiError::LockError)?
+            .take();
+
+        if let Some(running) = running {
+            running.server.unblock();
+            let _ = running.thread.join();
+        }
+
+        Ok(())
+    }
+}
+
+fn handle_request(mut request: Request, player: &AudioPlayer, expected_auth: &str) {
+    // Checked before anything else, in constant time so response timing can't reveal the
+    // token a byte at a time.
+    let authorized = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .is_some_and(|header| {
+            bool::from(
+                header
+                    .value
+                    .as_str()
+                    .as_bytes()
+                    .ct_eq(expected_auth.as_bytes()),
+            )
+        });
+
+    let response = if authorized {
+        match route(&mut request, player) {
+            Ok(body) => json_response(200, &body),
+            Err((status, error)) => json_response(status, &ErrorBody { error }),
+        }
+    } else {
+        json_response(
+            401,
+            &ErrorBody {
+                error: "Unauthorized".to_string(),
+            },
+        )
+    };
+
+    if let Err(e) = request.respond(response) {
+        log::error!("{}", e);
+    }
+}
+
+fn route(request: &mut Request, player: &AudioPlayer) -> Result<serde_json::Value, (u16, String)> {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let method = request.method().clone();
+
+    let result = match (method, path.as_str()) {
+        (Method::Get, "/state") => {
+            let snapshot = player.get_state().map_err(|e| (500, e.to_string()))?;
+            return serde_json::to_value(snapshot).map_err(|e| (500, e.to_string()));
+        }
+        (Method::Post, "/play") => {
+            let body: PlayBody = read_body(request)?.unwrap_or_default();
+            match body.index {
+                Some(index) => player.play(index),
+                None => player.resume(),
+            }
+        }
+        (Method::Post, "/pause") => player.pause(),
+        (Method::Post, "/stop") => player.stop(),
+        (Method::Post, "/next") => player.next(),
+        (Method::Post, "/prev") => player.prev(),
+        (Method::Post, "/queue") => {
+            let body: QueueBody = read_body(request)?.ok_or((400, "Missing body".to_string()))?;
+            // The report says which files were turned away, and why.
+            let report = player
+                .add_queue(body.paths)
+                .map_err(|e| (500, e.to_string()))?;
+            return serde_json::to_value(report).map_err(|e| (500, e.to_string()));
+        }
+        (Method::Post, "/volume") => {
+            let body: VolumeBody = read_body(request)?.ok_or((400, "Missing body".to_string()))?;
+            player.set_volume(body.volume)
+        }
+        _ => return Err((404, "Not found".to_string())),
+    };
+
+    result
+        .map(|_| serde_json::json!({ "ok": true }))
+        .map_err(|e| (500, e.to_string()))
+}
+
+fn read_body<T: for<'de> Deserialize<'de>>(
+    request: &mut Request,
+) -> Result<Option<T>, (u16, String)> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+
+    if body.trim().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_str(&body)
+        .map(Some)
+        .map_err(|e| (400, e.to_string()))
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<Cursor<Vec<u8>>> {
+    let data = serde_json::to_vec(body).unwrap_or_default();
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+    Response::from_data(data)
+        .with_status_code(status)
+        .with_header(content_type)
+}
@@ -0,0 +1,238 @@
+use ebur128::{EbuR128, Mode};
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::read_from_path;
+use lofty::tag::{Accessor, ItemKey, Tag};
+use rodio::{Decoder, Source};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use thiserror::Error;
+
+use crate::{paths, util};
+
+/// ReplayGain 2.0 reference loudness.
+const REFERENCE_LUFS: f64 = -18.0;
+
+/// Frames fed to the loudness meter at a time.
+const BLOCK_FRAMES: usize = 4096;
+
+/// Audio measured by `estimate_gain`, taken from the middle of the track.
+const SAMPLE_SECONDS: u64 = 20;
+
+#[derive(Error, Debug)]
+pub enum ReplayGainError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to decode audio file")]
+    DecoderError(#[from] rodio::decoder::DecoderError),
+
+    #[error("Loudness analysis failed: {0}")]
+    AnalysisError(String),
+
+    #[error("Failed to write tags: {0}")]
+    TagError(#[from] lofty::error::LoftyError),
+}
+
+impl From<ebur128::Error> for ReplayGainError {
+    fn from(e: ebur128::Error) -> Self {
+        ReplayGainError::AnalysisError(e.to_string())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ReplayGainProgress {
+    path: String,
+    track_gain: Option<f64>,
+    album_gain: Option<f64>,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+/// Loudness correction for a track, worked out before it plays.
+#[derive(Serialize, Clone, Debug)]
+pub struct GainEstimate {
+    /// ReplayGain track gain estimated from a sample of the audio.
+    pub estimated_db: f64,
+    /// Gain playback applies to the track: its stored gain, or none without one.
+    pub applied_db: f32,
+}
+
+struct TrackAnalysis {
+    meter: EbuR128,
+    peak: f64,
+}
+
+/// Scans `paths` in the background and writes ReplayGain tags, emitting `replaygain-progress`
+/// per file. With `album` set, files sharing an album tag also get album gain/peak.
+pub fn scan(app: AppHandle, paths: Vec<String>, album: bool) {
+    thread::spawn(move || {
+        let total = paths.len();
+        let mut completed = 0;
+
+        for group in group_paths(paths, album) {
+            let analyses: Vec<(String, Result<TrackAnalysis, ReplayGainError>)> = group
+                .into_iter()
+                .map(|path| {
+                    let analysis = analyze(&path);
+                    (path, analysis)
+                })
+                .collect();
+
+            let album_values = if album { album_gain(&analyses) } else { None };
+
+            for (path, analysis) in analyses {
+                completed += 1;
+
+                let result = analysis.and_then(|analysis| {
+                    let track_gain = REFERENCE_LUFS - analysis.meter.loudness_global()?;
+                    write_tags(&path, (track_gain, analysis.peak), album_values)?;
+                    Ok(track_gain)
+                });
+
+                let progress = ReplayGainProgress {
+                    path,
+                    track_gain: result.as_ref().ok().copied(),
+                    album_gain: album_values.map(|(gain, _)| gain),
+                    error: result.err().map(|e| e.to_string()),
+                    completed,
+                    total,
+                };
+
+                if let Err(e) = app.emit("replaygain-progress", progress) {
+                    log::error!("{}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Groups files by album tag in album mode; otherwise every file stands alone.
+fn group_paths(paths: Vec<String>, album: bool) -> Vec<Vec<String>> {
+    if !album {
+        return paths.into_iter().map(|path| vec![path]).collect();
+    }
+
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for path in paths {
+        let key = read_from_path(paths::native(&path))
+            .ok()
+            .and_then(|file| {
+                let tag = file.primary_tag()?;
+                Some(format!(
+                    "{}\u{0}{}",
+                    tag.artist().unwrap_or_default(),
+                    tag.album()?
+                ))
+            })
+            .unwrap_or_else(|| path.clone());
+        groups.entry(key).or_default().push(path);
+    }
+
+    groups.into_values().collect()
+}
+
+fn analyze(path: &str) -> Result<TrackAnalysis, ReplayGainError> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(paths::native(path))?))?;
+    let channels = decoder.channels();
+    let mut meter = EbuR128::new(
+        channels as u32,
+        decoder.sample_rate(),
+        Mode::I | Mode::SAMPLE_PEAK,
+    )?;
+
+    loop {
+        let samples: Vec<i16> = decoder
+            .by_ref()
+            .take(BLOCK_FRAMES * channels as usize)
+            .collect();
+        if samples.is_empty() {
+            break;
+        }
+        meter.add_frames_i16(&samples)?;
+    }
+
+    let mut peak: f64 = 0.0;
+    for channel in 0..channels as u32 {
+        peak = peak.max(meter.sample_peak(channel)?);
+    }
+
+    Ok(TrackAnalysis { meter, peak })
+}
+
+/// ReplayGain track gain measured over a short stretch from the middle of a file, quick
+/// enough to show before it plays. It can be off by a dB or so from a full scan.
+pub fn estimate_gain(path: &str) -> Result<f64, ReplayGainError> {
+    let mut decoder = Decoder::new(BufReader::new(util::open_media(path)?))?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+
+    let duration = util::read_tagged_file(path)
+        .map(|file| file.properties().duration())
+        .unwrap_or_default();
+    let start = duration.saturating_sub(Duration::from_secs(SAMPLE_SECONDS)) / 2;
+    // A decoder that can't seek is measured from the start instead.
+    if !start.is_zero() && decoder.try_seek(start).is_err() {
+        decoder = Decoder::new(BufReader::new(util::open_media(path)?))?;
+    }
+
+    let mut meter = EbuR128::new(channels as u32, sample_rate, Mode::I)?;
+    let samples: Vec<i16> = decoder
+        .take(SAMPLE_SECONDS as usize * sample_rate as usize * channels as usize)
+        .collect();
+    meter.add_frames_i16(&samples)?;
+
+    let loudness = meter.loudness_global()?;
+    if !loudness.is_finite() {
+        return Err(ReplayGainError::AnalysisError(
+            "The sampled audio is silent".to_string(),
+        ));
+    }
+    Ok(REFERENCE_LUFS - loudness)
+}
+
+fn album_gain(analyses: &[(String, Result<TrackAnalysis, ReplayGainError>)]) -> Option<(f64, f64)> {
+    let analyzed: Vec<&TrackAnalysis> = analyses
+        .iter()
+        .filter_map(|(_, analysis)| analysis.as_ref().ok())
+        .collect();
+    if analyzed.is_empty() {
+        return None;
+    }
+
+    let loudness = EbuR128::loudness_global_multiple(analyzed.iter().map(|a| &a.meter)).ok()?;
+    let peak = analyzed.iter().map(|a| a.peak).fold(0.0, f64::max);
+
+    Some((REFERENCE_LUFS - loudness, peak))
+}
+
+fn write_tags(
+    path: &str,
+    track: (f64, f64),
+    album: Option<(f64, f64)>,
+) -> Result<(), ReplayGainError> {
+    let mut tagged_file = read_from_path(paths::native(path))?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+
+    let Some(tag) = tagged_file.primary_tag_mut() else {
+        return Ok(());
+    };
+
+    tag.insert_text(ItemKey::ReplayGainTrackGain, format!("{:.2} dB", track.0));
+    tag.insert_text(ItemKey::ReplayGainTrackPeak, format!("{:.6}", track.1));
+    if let Some((gain, peak)) = album {
+        tag.insert_text(ItemKey::ReplayGainAlbumGain, format!("{:.2} dB", gain));
+        tag.insert_text(ItemKey::ReplayGainAlbumPeak, format!("{:.6}", peak));
+    }
+
+    tagged_file.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
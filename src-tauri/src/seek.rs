@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::paths;
+use crate::util;
+
+/// Frames decoded ahead of the target so the MP3 bit reservoir is filled again.
+const PRIMING_FRAMES: usize = 2;
+
+/// Seek tables kept for the most recently seeked files.
+const MAX_CACHED_INDEXES: usize = 8;
+
+static INDEXES: Mutex<Vec<(String, Arc<Mp3Index>)>> = Mutex::new(Vec::new());
+
+/// Byte offset of every MPEG audio frame in a file, so seeks can jump straight to the frame
+/// holding a timestamp instead of relying on the average bitrate or a linear scan.
+struct Mp3Index {
+    sample_rate: u32,
+    /// Encoder and decoder delay trimmed from the start of gapless playback, in samples.
+    delay: u64,
+    /// First sample and byte offset of each audio frame.
+    frames: Vec<(u64, u64)>,
+}
+
+struct FrameHeader {
+    sample_rate: u32,
+    samples: u64,
+    length: usize,
+    side_info_len: usize,
+}
+
+/// A stream positioned on a frame boundary shortly before a seek target. Dropping the first
+/// `skip` decoded frames (per channel) lands exactly on the target.
+pub struct SeekPoint {
+    pub media: OffsetReader<File>,
+    pub skip: u64,
+}
+
+/// Presents a file from `start` onwards as if it began there, so the decoder sees a fresh
+/// stream starting at a frame boundary.
+pub struct OffsetReader<R> {
+    inner: R,
+    start: u64,
+}
+
+impl<R: Seek> OffsetReader<R> {
+    pub fn new(mut inner: R, start: u64) -> io::Result<OffsetReader<R>> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(OffsetReader { inner, start })
+    }
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for OffsetReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.start + offset),
+            other => other,
+        };
+
+        let absolute = self.inner.seek(pos)?;
+        if absolute < self.start {
+            self.inner.seek(SeekFrom::Start(self.start))?;
+            return Ok(0);
+        }
+
+        Ok(absolute - self.start)
+    }
+}
+
+/// Local MP3 files get frame-indexed seeking; other formats seek accurately through the decoder.
+pub fn supports_indexed_seek(path: &str) -> bool {
+    !util::is_network_path(path)
+        && Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+}
+
+/// Opens `path` at the frame containing `position`, indexing the file on first use.
+pub fn open_at(path: &str, position: Duration) -> io::Result<SeekPoint> {
+    let index = index_for(path)?;
+    let target = (position.as_secs_f64() * index.sample_rate as f64) as u64 + index.delay;
+
+    let frame = index
+        .frames
+        .partition_point(|&(first_sample, _)| first_sample <= target)
+        .saturating_sub(1);
+    let (first_sample, offset) = index
+        .frames
+        .get(frame.saturating_sub(PRIMING_FRAMES))
+        .copied()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No MPEG frames found"))?;
+
+    Ok(SeekPoint {
+        media: OffsetReader::new(File::open(paths::native(path))?, offset)?,
+        skip: target.saturating_sub(first_sample),
+    })
+}
+
+fn index_for(path: &str) -> io::Result<Arc<Mp3Index>> {
+    let mut indexes = INDEXES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(position) = indexes.iter().position(|(indexed, _)| indexed == path) {
+        let entry = indexes.remove(position);
+        let index = entry.1.clone();
+        indexes.push(entry);
+        return Ok(index);
+    }
+
+    let index = Arc::new(build_index(path)?);
+    if indexes.len() >= MAX_CACHED_INDEXES {
+        indexes.remove(0);
+    }
+    indexes.push((path.to_string(), index.clone()));
+
+    Ok(index)
+}
+
+fn build_index(path: &str) -> io::Result<Mp3Index> {
+    let mut reader = BufReader::new(File::open(paths::native(path))?);
+    let mut offset = skip_id3v2(&mut reader)?;
+
+    let mut index = Mp3Index {
+        sample_rate: 0,
+        delay: 0,
+        frames: Vec::new(),
+    };
+    let mut next_sample = 0;
+    let mut first = true;
+    let mut word = [0u8; 4];
+
+    while reader.read_exact(&mut word).is_ok() {
+        let Some(header) = parse_header(u32::from_be_bytes(word)) else {
+            // Lost sync (junk or a trailing tag); try again one byte further on.
+            reader.seek_relative(-3)?;
+            offset += 1;
+            continue;
+        };
+
+        if first {
+            first = false;
+
+            let mut body = vec![0u8; header.length.saturating_sub(word.len())];
+            if reader.read_exact(&mut body).is_err() {
+                break;
+            }
+            // A Xing/Info frame carries no audio and is skipped by the decoder.
+            if let Some(delay) = read_info_tag(&body, &header) {
+                index.delay = delay;
+                offset += header.length as u64;
+                continue;
+            }
+            reader.seek_relative(-(body.len() as i64))?;
+        }
+
+        index.sample_rate = header.sample_rate;
+        index.frames.push((next_sample, offset));
+        next_sample += header.samples;
+
+        reader.seek_relative(header.length as i64 - word.len() as i64)?;
+        offset += header.length as u64;
+    }
+
+    Ok(index)
+}
+
+/// Skips a leading ID3v2 tag and returns the offset of the first byte after it.
+fn skip_id3v2<R: Read + Seek>(reader: &mut R) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    if reader.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return reader.seek(SeekFrom::Start(0));
+    }
+
+    // The size is stored as four 7-bit bytes and excludes the header and optional footer.
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |size, &byte| (size << 7) | (byte & 0x7f) as u64);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+
+    reader.seek(SeekFrom::Start(10 + size + footer))
+}
+
+fn parse_header(word: u32) -> Option<FrameHeader> {
+    if word >> 21 != 0x7ff {
+        return None;
+    }
+
+    // Version: 0 = MPEG 2.5, 2 = MPEG 2, 3 = MPEG 1. Layer: 1 = III, 2 = II, 3 = I.
+    let version = (word >> 19) & 0x3;
+    let layer = (word >> 17) & 0x3;
+    let bitrate_index = ((word >> 12) & 0xf) as usize;
+    let rate_index = ((word >> 10) & 0x3) as usize;
+    let padding = ((word >> 9) & 0x1) as usize;
+    let mono = (word >> 6) & 0x3 == 0x3;
+
+    // Free-format streams (bitrate index 0) can't be indexed from headers alone.
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+
+    let mpeg1 = version == 3;
+    let sample_rate = [44100, 48000, 32000][rate_index]
+        >> match version {
+            3 => 0,
+            2 => 1,
+            _ => 2,
+        };
+
+    let bitrates: [usize; 15] = match (mpeg1, layer) {
+        (true, 3) => [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        (true, 2) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        (true, _) => [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        (false, 3) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        (false, _) => [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    };
+    let bitrate = bitrates[bitrate_index] * 1000;
+    let rate = sample_rate as usize;
+
+    let (samples, length) = match layer {
+        3 => (384, (12 * bitrate / rate + padding) * 4),
+        2 => (1152, 144 * bitrate / rate + padding),
+        _ if mpeg1 => (1152, 144 * bitrate / rate + padding),
+        _ => (576, 72 * bitrate / rate + padding),
+    };
+
+    let side_info_len = match (mpeg1, mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    };
+
+    Some(FrameHeader {
+        sample_rate,
+        samples,
+        length,
+        side_info_len,
+    })
+}
+
+/// Returns the gapless delay if the frame body is a Xing/Info tag rather than audio.
+fn read_info_tag(body: &[u8], header: &FrameHeader) -> Option<u64> {
+    let tag = body.get(header.side_info_len..)?;
+    if !tag.starts_with(b"Xing") && !tag.starts_with(b"Info") {
+        return None;
+    }
+
+    let flags = u32::from_be_bytes(tag.get(4..8)?.try_into().ok()?);
+    let mut extension = 8;
+    for (flag, size) in [(0x1, 4), (0x2, 4), (0x4, 100), (0x8, 4)] {
+        if flags & flag != 0 {
+            extension += size;
+        }
+    }
+
+    // The LAME extension stores the encoder delay in the 12 bits at byte 21.
+    let lame = match tag.get(extension..extension + 24) {
+        Some(lame)
+            if lame.starts_with(b"LAME")
+                || lame.starts_with(b"Lavf")
+                || lame.starts_with(b"Lavc") =>
+        {
+            lame
+        }
+        _ => return Some(0),
+    };
+    let delay = ((lame[21] as u64) << 4) | ((lame[22] as u64) >> 4);
+
+    Some(529 + delay)
+}
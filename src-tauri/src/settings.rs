@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::announce::AnnounceMode;
+use crate::output::{OutputBackend, OutputFormat};
+use crate::shuffle::{ShuffleMode, ShuffleWeighting};
+use crate::silence::TrimConfig;
+use crate::volume::VolumeCurve;
+
+/// How long changes have to stop coming before they're written, so dragging the volume
+/// slider is one write rather than hundreds.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to serialize settings")]
+    SerializeError(#[from] serde_json::Error),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+/// Player preferences restored when the audio thread starts. Missing fields in an older
+/// settings file fall back to their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub volume: f32,
+    pub volume_curve: VolumeCurve,
+    /// Change applied by the volume up/down commands, hotkeys and tray items.
+    pub volume_step: f32,
+    /// Last volume used on each output device, by device name.
+    pub device_volumes: HashMap<String, f32>,
+    pub looped: bool,
+    pub shuffle: ShuffleMode,
+    /// How much weighted shuffle favours rated and long-unplayed tracks.
+    pub shuffle_weighting: ShuffleWeighting,
+    pub skip_silence: bool,
+    pub trim_silence: Option<TrimConfig>,
+    /// Dither audio when track gain is rounded back to 16 bits. Decoding to 16 bits and the
+    /// output's own conversion aren't dithered.
+    pub dither: bool,
+    /// How often position events are emitted while playing.
+    pub emit_interval_ms: u64,
+    /// Position past which "previous" restarts the current track. Zero always goes back.
+    pub prev_threshold_ms: u64,
+    /// Bytes read ahead of the decoder, in KiB. Zero reads local files straight from disk;
+    /// network tracks are always read ahead.
+    pub read_ahead_kb: usize,
+    /// Look up lyrics on LRCLIB when a track has none of its own.
+    pub online_lyrics: bool,
+    /// Overlap between tracks; zero switches tracks back to back.
+    pub crossfade_ms: u64,
+    /// Keep playing similar library tracks once the queue runs out.
+    pub endless: bool,
+    /// Chime the time on the hour or between tracks.
+    pub announce_time: AnnounceMode,
+    /// Folders scanned into the library, remembered per profile.
+    pub library_folders: Vec<String>,
+    pub output_backend: OutputBackend,
+    /// Output device played to by the device backend, by name. The system default is used
+    /// when unset or while the device isn't connected.
+    pub output_device: Option<String>,
+    pub output_format: OutputFormat,
+    /// Take the output device for exclusive, bit-perfect use while the output is open, through
+    /// pauses until a stop or idle release (macOS only).
+    pub exclusive_output: bool,
+    /// Show the player in the OS media controls (MPRIS, SMTC, Now Playing).
+    pub media_controls: bool,
+    /// Close the output device after this long paused or idle; 0 keeps it open.
+    pub idle_release_minutes: u64,
+    /// Double-pressing the media play/pause button skips ahead and triple-pressing goes
+    /// back, for headsets with a single button.
+    pub media_button_gestures: bool,
+    /// How long after the first press further presses count towards a gesture.
+    pub gesture_window_ms: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            volume: 1.0,
+            volume_curve: VolumeCurve::Linear,
+            volume_step: 0.05,
+            device_volumes: HashMap::new(),
+            looped: false,
+            shuffle: ShuffleMode::Off,
+            shuffle_weighting: ShuffleWeighting::default(),
+            skip_silence: false,
+            trim_silence: None,
+            dither: false,
+            emit_interval_ms: 500,
+            prev_threshold_ms: 5000,
+            read_ahead_kb: 1024,
+            online_lyrics: false,
+            crossfade_ms: 0,
+            endless: false,
+            announce_time: AnnounceMode::Off,
+            library_folders: Vec::new(),
+            output_backend: OutputBackend::Device,
+            output_device: None,
+            output_format: OutputFormat::default(),
+            exclusive_output: false,
+            media_controls: true,
+            idle_release_minutes: 0,
+            media_button_gestures: false,
+            gesture_window_ms: 400,
+        }
+    }
+}
+
+/// Settings backed by a JSON file. Changes are written in the background once they settle;
+/// `flush` writes them straight away.
+pub struct SettingsStore {
+    shared: Arc<Shared>,
+    /// Wakes the writer thread after a change.
+    changed: mpsc::Sender<()>,
+}
+
+struct Shared {
+    /// Backing file; changes when another profile is opened. Locked after `settings`.
+    path: Mutex<PathBuf>,
+    settings: Mutex<Settings>,
+    /// Set while a change hasn't been written yet.
+    dirty: AtomicBool,
+}
+
+impl SettingsStore {
+    pub fn load(path: PathBuf) -> SettingsStore {
+        let shared = Arc::new(Shared {
+            settings: Mutex::new(read_settings(&path)),
+            path: Mutex::new(path),
+            dirty: AtomicBool::new(false),
+        });
+        let (changed, receiver) = mpsc::channel();
+
+        let writer = shared.clone();
+        thread::spawn(move || write_changes(&writer, receiver));
+
+        SettingsStore { shared, changed }
+    }
+
+    /// Re-reads the settings file, e.g. after it was replaced by a restored backup. Unwritten
+    /// changes are dropped.
+    pub fn reload(&self) -> Result<(), SettingsError> {
+        let mut settings = self.shared.lock_settings()?;
+        let file = File::open(self.path()?)?;
+        *settings = serde_json::from_reader(BufReader::new(file))?;
+        self.shared.dirty.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Continues with the settings file at `path`, falling back to defaults if it doesn't
+    /// exist yet. Unwritten changes go to the old file first.
+    pub fn switch_to(&self, path: PathBuf) -> Result<(), SettingsError> {
+        let mut settings = self.shared.lock_settings()?;
+        self.shared.write(&settings)?;
+        *settings = read_settings(&path);
+        *self
+            .shared
+            .path
+            .lock()
+            .map_err(|_| SettingsError::LockError)? = path;
+        Ok(())
+    }
+
+    /// Path of the backing JSON file. Call `flush` first if it's about to be read.
+    pub fn path(&self) -> Result<PathBuf, SettingsError> {
+        self.shared
+            .path
+            .lock()
+            .map(|path| path.clone())
+            .map_err(|_| SettingsError::LockError)
+    }
+
+    pub fn get(&self) -> Result<Settings, SettingsError> {
+        self.shared.lock_settings().map(|settings| settings.clone())
+    }
+
+    pub fn set(&self, settings: Settings) -> Result<(), SettingsError> {
+        self.update(|current| *current = settings)
+    }
+
+    /// Changes some settings and schedules them to be saved.
+    pub fn update<F: FnOnce(&mut Settings)>(&self, change: F) -> Result<(), SettingsError> {
+        let mut settings = self.shared.lock_settings()?;
+        change(&mut settings);
+        self.shared.dirty.store(true, Ordering::Relaxed);
+        drop(settings);
+
+        // Without the writer thread, the change is saved straight away instead.
+        if self.changed.send(()).is_err() {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    /// Writes any changes that haven't been saved yet.
+    pub fn flush(&self) -> Result<(), SettingsError> {
+        let settings = self.shared.lock_settings()?;
+        self.shared.write(&settings)
+    }
+}
+
+impl Shared {
+    fn lock_settings(&self) -> Result<MutexGuard<'_, Settings>, SettingsError> {
+        self.settings.lock().map_err(|_| SettingsError::LockError)
+    }
+
+    /// Saves `settings` if they have unwritten changes. Called with `settings` locked, so
+    /// writes can't overtake each other.
+    fn write(&self, settings: &Settings) -> Result<(), SettingsError> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let path = self
+            .path
+            .lock()
+            .map_err(|_| SettingsError::LockError)?
+            .clone();
+        let result = write_settings(&path, settings);
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Writer thread: saves once changes have stopped coming for `SAVE_DELAY`, until the store
+/// is dropped.
+fn write_changes(shared: &Shared, changed: mpsc::Receiver<()>) {
+    while changed.recv().is_ok() {
+        while changed.recv_timeout(SAVE_DELAY).is_ok() {}
+
+        let result = shared
+            .lock_settings()
+            .and_then(|settings| shared.write(&settings));
+        if let Err(e) = result {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+}
+
+/// Writes beside the file and renames over it, so a crash mid-write can't leave it
+/// truncated.
+fn write_settings(path: &Path, settings: &Settings) -> Result<(), SettingsError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = path.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    serde_json::to_writer_pretty(&mut writer, settings)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn read_settings(path: &Path) -> Settings {
+    File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use thiserror::Error;
+
+use crate::audio_player::{AudioError, AudioPlayer};
+
+#[derive(Error, Debug)]
+pub enum ShortcutError {
+    #[error("Invalid shortcut: {0}")]
+    InvalidShortcut(String),
+
+    #[error("Failed to register shortcut")]
+    RegisterError(#[from] tauri_plugin_global_shortcut::Error),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    PlayPause,
+    Next,
+    Prev,
+    VolumeUp,
+    VolumeDown,
+}
+
+struct Binding {
+    accelerator: String,
+    shortcut: Shortcut,
+}
+
+#[derive(Default)]
+pub struct GlobalShortcuts {
+    bindings: Mutex<HashMap<ShortcutAction, Binding>>,
+}
+
+fn default_bindings() -> Vec<(ShortcutAction, &'static str)> {
+    vec![
+        (ShortcutAction::PlayPause, "ctrl+alt+p"),
+        (ShortcutAction::Next, "ctrl+alt+right"),
+        (ShortcutAction::Prev, "ctrl+alt+left"),
+        (ShortcutAction::VolumeUp, "ctrl+alt+up"),
+        (ShortcutAction::VolumeDown, "ctrl+alt+down"),
+    ]
+}
+
+/// Installs the global-shortcut plugin and registers the default hotkeys.
+pub fn setup(app: &AppHandle, player: AudioPlayer) -> tauri::Result<()> {
+    app.plugin(
+        tauri_plugin_global_shortcut::Builder::new()
+            .with_handler(move |app, shortcut, event| {
+                if event.state() != ShortcutState::Pressed {
+                    return;
+                }
+
+                let action = app.state::<GlobalShortcuts>().action_for(shortcut);
+                if let Some(action) = action {
+                    if let Err(e) = dispatch(&player, action) {
+                        log::error!("{}", e);
+                    }
+                }
+            })
+            .build(),
+    )?;
+
+    let shortcuts = GlobalShortcuts::default();
+    for (action, accelerator) in default_bindings() {
+        // Another application may already own the combination; keep the rest working.
+        if let Err(e) = shortcuts.bind(app, action, Some(accelerator.to_string())) {
+            log::error!("{}", e);
+        }
+    }
+
+    app.manage(shortcuts);
+    Ok(())
+}
+
+fn dispatch(player: &AudioPlayer, action: ShortcutAction) -> Result<(), AudioError> {
+    match action {
+        ShortcutAction::PlayPause => player.toggle_playback(),
+        ShortcutAction::Next => player.next(),
+        ShortcutAction::Prev => player.prev(),
+        ShortcutAction::VolumeUp => player.volume_up(),
+        ShortcutAction::VolumeDown => player.volume_down(),
+    }
+}
+
+impl GlobalShortcuts {
+    fn action_for(&self, shortcut: &Shortcut) -> Option<ShortcutAction> {
+        let bindings = self.bindings.lock().ok()?;
+
+        bindings
+            .iter()
+            .find(|(_, binding)| binding.shortcut == *shortcut)
+            .map(|(action, _)| *action)
+    }
+
+    pub fn get_bindings(&self) -> Result<HashMap<ShortcutAction, String>, ShortcutError> {
+        let bindings = self.bindings.lock().map_err(|_| ShortcutError::LockError)?;
+
+        Ok(bindings
+            .iter()
+            .map(|(action, binding)| (*action, binding.accelerator.clone()))
+            .collect())
+    }
+
+    /// Replaces the hotkey for `action`; `None` removes it.
+    pub fn bind(
+        &self,
+        app: &AppHandle,
+        action: ShortcutAction,
+        accelerator: Option<String>,
+    ) -> Result<(), ShortcutError> {
+        let new_binding = match accelerator {
+            Some(accelerator) => {
+                let shortcut = accelerator
+                    .parse::<Shortcut>()
+                    .map_err(|_| ShortcutError::InvalidShortcut(accelerator.clone()))?;
+
+                Some(Binding {
+                    accelerator,
+                    shortcut,
+                })
+            }
+            None => None,
+        };
+
+        let mut bindings = self.bindings.lock().map_err(|_| ShortcutError::LockError)?;
+
+        if let Some(old) = bindings.remove(&action) {
+            app.global_shortcut().unregister(old.shortcut)?;
+        }
+
+        if let Some(binding) = new_binding {
+            app.global_shortcut().register(binding.shortcut)?;
+            bindings.insert(action, binding);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::audio_player::TrackInfo;
+use crate::util;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    Tracks,
+    /// Albums play in random order, each one front to back.
+    Albums,
+    /// Tracks in random order, with higher-rated and longer-unplayed ones tending to come
+    /// up sooner.
+    Weighted,
+}
+
+/// Days without a play after which a track no longer counts as recently played.
+const RESTED_DAYS: f64 = 30.0;
+
+/// Share of its weight a track played moments ago keeps at full recency weighting.
+const MIN_RECENCY: f64 = 0.05;
+
+/// Largest exponent either weighting factor can be raised to.
+pub const MAX_WEIGHTING: f32 = 4.0;
+
+/// How strongly weighted shuffle favours ratings and time since the last play. Each factor's
+/// share of the weight is raised to these powers, so zero ignores it and higher values
+/// lean on it harder.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ShuffleWeighting {
+    pub rating: f32,
+    pub recency: f32,
+}
+
+impl Default for ShuffleWeighting {
+    fn default() -> Self {
+        ShuffleWeighting {
+            rating: 1.0,
+            recency: 1.0,
+        }
+    }
+}
+
+impl ShuffleWeighting {
+    pub fn clamped(self) -> ShuffleWeighting {
+        ShuffleWeighting {
+            rating: self.rating.clamp(0.0, MAX_WEIGHTING),
+            recency: self.recency.clamp(0.0, MAX_WEIGHTING),
+        }
+    }
+
+    /// Weight of a track with `rating` stars last played at `last_played` (Unix seconds),
+    /// relative to an unrated track that hasn't been played in a month or more.
+    pub fn weight(&self, rating: Option<u8>, last_played: Option<i64>, now: i64) -> f64 {
+        // Unrated tracks sit in the middle of the star scale.
+        let stars = rating.map_or(3.0, |rating| rating.clamp(1, 5) as f64) / 3.0;
+        let rested = last_played.map_or(1.0, |played| {
+            ((now - played) as f64 / 86_400.0 / RESTED_DAYS).clamp(0.0, 1.0)
+        });
+        let recency = MIN_RECENCY + (1.0 - MIN_RECENCY) * rested;
+
+        stars.powf(self.rating as f64) * recency.powf(self.recency as f64)
+    }
+}
+
+/// Reorders `queue` for `mode`. The track at `current` is moved to the front (with the rest
+/// of its album in album mode) so playback carries on; returns its new index. `weights` is
+/// only used in weighted mode; tracks missing from it weigh 1.
+pub fn shuffle(
+    queue: &mut Vec<TrackInfo>,
+    current: usize,
+    mode: ShuffleMode,
+    weights: &HashMap<String, f64>,
+) -> usize {
+    let mut rng = rand::thread_rng();
+    let current_path = queue.get(current).map(|track| track.path.clone());
+
+    match mode {
+        ShuffleMode::Off => return current,
+        ShuffleMode::Tracks => {
+            let playing = (current < queue.len()).then(|| queue.remove(current));
+            queue.shuffle(&mut rng);
+            if let Some(track) = playing {
+                queue.insert(0, track);
+            }
+        }
+        ShuffleMode::Albums => {
+            let mut order: Vec<String> = Vec::new();
+            let mut albums: HashMap<String, Vec<TrackInfo>> = HashMap::new();
+            for track in queue.drain(..) {
+                let key = album_key(&track);
+                if !albums.contains_key(&key) {
+                    order.push(key.clone());
+                }
+                albums.entry(key).or_default().push(track);
+            }
+
+            order.shuffle(&mut rng);
+            if let Some(position) = current_path.as_ref().and_then(|path| {
+                order
+                    .iter()
+                    .position(|key| albums[key].iter().any(|track| &track.path == path))
+            }) {
+                let key = order.remove(position);
+                order.insert(0, key);
+            }
+
+            for key in order {
+                let mut tracks = albums.remove(&key).unwrap_or_default();
+                util::sort_tracks(&mut tracks);
+                queue.extend(tracks);
+            }
+        }
+        ShuffleMode::Weighted => {
+            let playing = (current < queue.len()).then(|| queue.remove(current));
+
+            // Ordering by u^(1/w) draws each next track with probability proportional to
+            // its weight among those left.
+            let mut keyed: Vec<(f64, TrackInfo)> = queue
+                .drain(..)
+                .map(|track| {
+                    let weight = weights.get(&track.path).copied().unwrap_or(1.0);
+                    let key = rng.gen::<f64>().powf(1.0 / weight.max(f64::MIN_POSITIVE));
+                    (key, track)
+                })
+                .collect();
+            keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+            queue.extend(keyed.into_iter().map(|(_, track)| track));
+
+            if let Some(track) = playing {
+                queue.insert(0, track);
+            }
+        }
+    }
+
+    util::reindex(queue);
+    current_path
+        .and_then(|path| queue.iter().position(|track| track.path == path))
+        .unwrap_or(0)
+}
+
+/// Moves tracks whose paths are in `recent` behind the others, keeping the track at
+/// `current` in place at the front; returns its new index.
+pub fn defer_recent(queue: &mut Vec<TrackInfo>, current: usize, recent: &[String]) -> usize {
+    let current_path = queue.get(current).map(|track| track.path.clone());
+
+    let (mut deferred, kept): (Vec<TrackInfo>, Vec<TrackInfo>) =
+        std::mem::take(queue).into_iter().partition(|track| {
+            Some(&track.path) != current_path.as_ref() && recent.contains(&track.path)
+        });
+    *queue = kept;
+    queue.append(&mut deferred);
+
+    util::reindex(queue);
+    current_path
+        .and_then(|path| queue.iter().position(|track| track.path == path))
+        .unwrap_or(0)
+}
+
+/// Puts a shuffled queue back in its original order. Tracks added while shuffled keep
+/// their relative order after the original ones.
+pub fn unshuffle(queue: &mut Vec<TrackInfo>, original: Vec<TrackInfo>, current: usize) -> usize {
+    let current_path = queue.get(current).map(|track| track.path.clone());
+
+    let mut remaining = std::mem::take(queue);
+    for track in original {
+        if let Some(position) = remaining.iter().position(|t| t.path == track.path) {
+            queue.push(remaining.remove(position));
+        }
+    }
+    queue.extend(remaining);
+
+    util::reindex(queue);
+    current_path
+        .and_then(|path| queue.iter().position(|track| track.path == path))
+        .unwrap_or(0)
+}
+
+fn album_key(track: &TrackInfo) -> String {
+    let folder = Path::new(&track.path)
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    format!("{}\u{0}{}", track.album, folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::SourceKind;
+
+    fn queue(paths: &[&str]) -> Vec<TrackInfo> {
+        let mut queue: Vec<TrackInfo> = paths
+            .iter()
+            .map(|path| TrackInfo {
+                index: 0,
+                title: path.to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                duration: 180,
+                track_number: None,
+                disc_number: None,
+                album_artist: None,
+                genre: None,
+                year: None,
+                artists: Vec::new(),
+                genres: Vec::new(),
+                compilation: false,
+                composer: None,
+                conductor: None,
+                performer: None,
+                work: None,
+                movement: None,
+                path: path.to_string(),
+                source_kind: SourceKind::File,
+            })
+            .collect();
+        util::reindex(&mut queue);
+        queue
+    }
+
+    fn paths(queue: &[TrackInfo]) -> Vec<&str> {
+        queue.iter().map(|track| track.path.as_str()).collect()
+    }
+
+    #[test]
+    fn weighted_shuffle_favours_rated_and_rested_tracks() {
+        let weighting = ShuffleWeighting::default();
+        let now = 100 * 86_400;
+
+        let favourite = weighting.weight(Some(5), None, now);
+        let unrated = weighting.weight(None, Some(now - 60 * 86_400), now);
+        let just_played = weighting.weight(Some(5), Some(now), now);
+        assert!(favourite > unrated && unrated > just_played);
+        assert_eq!(unrated, 1.0);
+
+        let off = ShuffleWeighting {
+            rating: 0.0,
+            recency: 0.0,
+        };
+        assert_eq!(off.weight(Some(1), Some(now), now), 1.0);
+
+        let mut shuffled = queue(&["a", "b", "c", "d"]);
+        let weights = HashMap::from([("b".to_string(), 0.0)]);
+        let index = shuffle(&mut shuffled, 2, ShuffleMode::Weighted, &weights);
+        assert_eq!(index, 0);
+        assert_eq!(paths(&shuffled)[0], "c");
+        assert_eq!(paths(&shuffled)[3], "b");
+    }
+}
@@ -0,0 +1,313 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Frames where every channel stays below this magnitude (about -50 dBFS) count as silent.
+const SILENCE_THRESHOLD: u16 = 100;
+
+/// Silence kept before skipping starts, so gaps shrink to about a second instead of vanishing.
+const KEPT_SILENCE: Duration = Duration::from_secs(1);
+
+/// Source adapter that drops long silent stretches while `enabled` is set and `bypass` isn't.
+/// The skipped duration is added to `skipped` (in nanoseconds) so the player can keep
+/// positions accurate.
+pub struct SkipSilence<S> {
+    inner: S,
+    enabled: Arc<AtomicBool>,
+    bypass: Arc<AtomicBool>,
+    skipped: Arc<AtomicU64>,
+    frame: Vec<i16>,
+    frame_position: usize,
+    silent_frames: u64,
+}
+
+impl<S> SkipSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(
+        inner: S,
+        enabled: Arc<AtomicBool>,
+        bypass: Arc<AtomicBool>,
+        skipped: Arc<AtomicU64>,
+    ) -> SkipSilence<S> {
+        SkipSilence {
+            inner,
+            enabled,
+            bypass,
+            skipped,
+            frame: Vec::new(),
+            frame_position: 0,
+            silent_frames: 0,
+        }
+    }
+
+    /// Reads the next frame that should be played into `self.frame`.
+    fn load_frame(&mut self) -> bool {
+        loop {
+            self.frame.clear();
+            self.frame_position = 0;
+
+            for _ in 0..self.inner.channels().max(1) {
+                match self.inner.next() {
+                    Some(sample) => self.frame.push(sample),
+                    None => break,
+                }
+            }
+
+            if self.frame.is_empty() {
+                return false;
+            }
+
+            let silent = self
+                .frame
+                .iter()
+                .all(|sample| sample.unsigned_abs() < SILENCE_THRESHOLD);
+            if !silent {
+                self.silent_frames = 0;
+                return true;
+            }
+
+            self.silent_frames += 1;
+            let sample_rate = self.inner.sample_rate().max(1) as u64;
+            let kept_frames = KEPT_SILENCE.as_millis() as u64 * sample_rate / 1000;
+
+            let skipping =
+                self.enabled.load(Ordering::Relaxed) && !self.bypass.load(Ordering::Relaxed);
+            if !skipping || self.silent_frames <= kept_frames {
+                return true;
+            }
+
+            self.skipped
+                .fetch_add(1_000_000_000 / sample_rate, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S> Iterator for SkipSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.frame_position >= self.frame.len() && !self.load_frame() {
+            return None;
+        }
+
+        let sample = self.frame[self.frame_position];
+        self.frame_position += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for SkipSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.frame.clear();
+        self.frame_position = 0;
+        self.silent_frames = 0;
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Silence detection settings for trimming the start and end of tracks.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+pub struct TrimConfig {
+    pub threshold_db: f32,
+    pub min_duration_ms: u64,
+}
+
+/// Trim settings shared by the player and the playing track, so changes apply straight away.
+pub type SharedTrimConfig = Arc<Mutex<Option<TrimConfig>>>;
+
+/// Longest trailing stretch read ahead to check whether the track ends in silence.
+const MAX_TRAILING_LOOKAHEAD: Duration = Duration::from_secs(30);
+
+/// Source adapter that drops silence at the start and end of a track when it lasts at least
+/// `min_duration_ms`, unless `bypass` is set. Dropped time is added to `skipped` like
+/// `SkipSilence` does. Turned on partway through a track, only its end is trimmed.
+pub struct TrimSilence<S> {
+    inner: S,
+    config: SharedTrimConfig,
+    bypass: Arc<AtomicBool>,
+    skipped: Arc<AtomicU64>,
+    pending: std::collections::VecDeque<i16>,
+    started: bool,
+}
+
+impl<S> TrimSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(
+        inner: S,
+        config: SharedTrimConfig,
+        bypass: Arc<AtomicBool>,
+        skipped: Arc<AtomicU64>,
+    ) -> TrimSilence<S> {
+        TrimSilence {
+            inner,
+            config,
+            bypass,
+            skipped,
+            pending: std::collections::VecDeque::new(),
+            started: false,
+        }
+    }
+
+    /// Treats the stream as already past its start, for decoders opened mid-track.
+    pub fn resume(&mut self) {
+        self.started = true;
+    }
+
+    fn read_frame(&mut self) -> Option<Vec<i16>> {
+        let channels = self.inner.channels().max(1) as usize;
+        let frame: Vec<i16> = (&mut self.inner).take(channels).collect();
+        (!frame.is_empty()).then_some(frame)
+    }
+
+    fn frames_for(&self, duration: Duration) -> u64 {
+        duration.as_millis() as u64 * self.inner.sample_rate() as u64 / 1000
+    }
+
+    fn add_skipped(&self, frames: u64) {
+        let sample_rate = self.inner.sample_rate().max(1) as u64;
+        self.skipped
+            .fetch_add(frames * 1_000_000_000 / sample_rate, Ordering::Relaxed);
+    }
+
+    /// Reads the silent run starting at `first`. Short runs are queued for playback; long
+    /// leading runs and long runs that reach the end of the track are dropped.
+    fn handle_silence(&mut self, config: TrimConfig, first: Vec<i16>, leading: bool) {
+        let threshold = threshold_for(config.threshold_db);
+        let min_frames = self.frames_for(Duration::from_millis(config.min_duration_ms));
+        let max_frames = self.frames_for(MAX_TRAILING_LOOKAHEAD).max(min_frames);
+
+        let mut run: Vec<i16> = first;
+        let mut frames: u64 = 1;
+
+        loop {
+            let Some(frame) = self.read_frame() else {
+                // The track ended in silence.
+                if frames >= min_frames {
+                    self.add_skipped(frames);
+                } else {
+                    self.pending.extend(run);
+                }
+                return;
+            };
+
+            if !is_silent(&frame, threshold) {
+                if leading && frames >= min_frames {
+                    self.add_skipped(frames);
+                } else {
+                    self.pending.extend(run);
+                }
+                self.pending.extend(frame);
+                return;
+            }
+
+            frames += 1;
+            // Leading silence never needs buffering once it is known to be long enough.
+            if !(leading && frames > min_frames) {
+                run.extend(frame);
+            }
+
+            if !leading && frames >= max_frames {
+                self.pending.extend(run);
+                return;
+            }
+        }
+    }
+}
+
+fn threshold_for(threshold_db: f32) -> u16 {
+    (i16::MAX as f32 * 10f32.powf(threshold_db / 20.0)).clamp(0.0, i16::MAX as f32) as u16
+}
+
+fn is_silent(frame: &[i16], threshold: u16) -> bool {
+    frame.iter().all(|sample| sample.unsigned_abs() < threshold)
+}
+
+impl<S> Iterator for TrimSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(sample) = self.pending.pop_front() {
+            return Some(sample);
+        }
+
+        let config = *self.config.lock().unwrap_or_else(|e| e.into_inner());
+        let config = match config {
+            Some(config) if !self.bypass.load(Ordering::Relaxed) => config,
+            // Whatever plays untrimmed counts as the start, so it isn't trimmed later on.
+            _ => {
+                self.started = true;
+                return self.inner.next();
+            }
+        };
+
+        let leading = !self.started;
+        self.started = true;
+
+        let frame = self.read_frame()?;
+        if is_silent(&frame, threshold_for(config.threshold_db)) {
+            self.handle_silence(config, frame, leading);
+        } else {
+            self.pending.extend(frame);
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+impl<S> Source for TrimSilence<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.pending.clear();
+        self.started = true;
+        self.inner.try_seek(pos)
+    }
+}
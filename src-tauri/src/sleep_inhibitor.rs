@@ -0,0 +1,196 @@
+use thiserror::Error;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const APP_NAME: &str = "My Player";
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const REASON: &str = "Playing audio";
+
+#[derive(Error, Debug)]
+pub enum InhibitError {
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBusError(#[from] zbus::Error),
+
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    #[error("Platform error: {0}")]
+    PlatformError(String),
+}
+
+/// Keeps the machine from suspending while audio is playing. The inhibitor is taken when
+/// playback starts and released as soon as it pauses or stops.
+#[derive(Default)]
+pub struct SleepInhibitor {
+    guard: Option<platform::Guard>,
+    failed: bool,
+}
+
+impl SleepInhibitor {
+    pub fn set_active(&mut self, active: bool) {
+        if !active {
+            self.guard = None;
+            self.failed = false;
+            return;
+        }
+        // A failed attempt is only retried after the next pause, rather than on every command.
+        if self.guard.is_some() || self.failed {
+            return;
+        }
+
+        match platform::Guard::acquire() {
+            Ok(guard) => self.guard = Some(guard),
+            Err(e) => {
+                log::error!("Failed to inhibit sleep: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// logind releases the inhibitor when the file descriptor it hands out is closed.
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::blocking::Connection;
+    use zbus::zvariant::OwnedFd;
+
+    use super::{InhibitError, APP_NAME, REASON};
+
+    pub struct Guard {
+        _fd: OwnedFd,
+    }
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, InhibitError> {
+            let connection = Connection::system()?;
+            let reply = connection.call_method(
+                Some("org.freedesktop.login1"),
+                "/org/freedesktop/login1",
+                Some("org.freedesktop.login1.Manager"),
+                "Inhibit",
+                &("sleep:idle", APP_NAME, REASON, "block"),
+            )?;
+            Ok(Guard {
+                _fd: reply.body().deserialize()?,
+            })
+        }
+    }
+}
+
+/// The execution state belongs to the calling thread, which is the long-lived audio thread.
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+    };
+
+    use super::InhibitError;
+
+    pub struct Guard;
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, InhibitError> {
+            let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+            if previous.0 == 0 {
+                return Err(InhibitError::PlatformError(
+                    "SetThreadExecutionState failed".to_string(),
+                ));
+            }
+            Ok(Guard)
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+    }
+}
+
+/// An IOKit power assertion, the same thing `caffeinate` takes.
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CString};
+    use std::ptr;
+
+    use super::{InhibitError, APP_NAME, REASON};
+
+    type CFStringRef = *const c_void;
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const IOPM_ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(
+            allocator: *const c_void,
+            string: *const c_char,
+            encoding: u32,
+        ) -> CFStringRef;
+        fn CFRelease(object: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            assertion_id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(assertion_id: u32) -> i32;
+    }
+
+    pub struct Guard(u32);
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, InhibitError> {
+            let assertion_type = cf_string("PreventUserIdleSystemSleep");
+            let name = cf_string(&format!("{}: {}", APP_NAME, REASON));
+            let mut id = 0;
+
+            let result = unsafe {
+                let result = IOPMAssertionCreateWithName(
+                    assertion_type,
+                    IOPM_ASSERTION_LEVEL_ON,
+                    name,
+                    &mut id,
+                );
+                CFRelease(assertion_type);
+                CFRelease(name);
+                result
+            };
+
+            if result != 0 {
+                return Err(InhibitError::PlatformError(format!(
+                    "IOPMAssertionCreateWithName returned {}",
+                    result
+                )));
+            }
+            Ok(Guard(id))
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            unsafe { IOPMAssertionRelease(self.0) };
+        }
+    }
+
+    fn cf_string(value: &str) -> CFStringRef {
+        let value = CString::new(value).unwrap_or_default();
+        unsafe { CFStringCreateWithCString(ptr::null(), value.as_ptr(), CF_STRING_ENCODING_UTF8) }
+    }
+}
+
+/// Mobile platforms keep audio sessions alive themselves.
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::InhibitError;
+
+    pub struct Guard;
+
+    impl Guard {
+        pub fn acquire() -> Result<Guard, InhibitError> {
+            Ok(Guard)
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+
+use crate::util::MediaStream;
+
+const KEYRING_SERVICE: &str = "music-player-smb";
+
+#[derive(Error, Debug)]
+pub enum SmbError {
+    #[error("Credential storage error: {0}")]
+    KeyringError(#[from] keyring::Error),
+
+    #[error("Failed to serialize data")]
+    SerializeError(#[from] serde_json::Error),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SmbCredentials {
+    pub username: String,
+    pub password: String,
+    pub workgroup: Option<String>,
+}
+
+/// An `smb://host/share/path` location split into its parts.
+struct SmbLocation {
+    host: String,
+    share: String,
+    path: String,
+}
+
+pub fn is_smb_path(path: &str) -> bool {
+    path.starts_with("smb://")
+}
+
+fn parse_location(path: &str) -> io::Result<SmbLocation> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid SMB path {}", path),
+        )
+    };
+
+    let rest = path.strip_prefix("smb://").ok_or_else(invalid)?;
+    let mut parts = rest.splitn(3, '/');
+    let host = parts.next().filter(|h| !h.is_empty()).ok_or_else(invalid)?;
+    let share = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+    let path = parts.next().ok_or_else(invalid)?;
+
+    Ok(SmbLocation {
+        host: host.to_string(),
+        share: share.to_string(),
+        path: format!("/{}", path),
+    })
+}
+
+/// Stores credentials for `host` in the OS keychain.
+pub fn save_credentials(host: &str, credentials: &SmbCredentials) -> Result<(), SmbError> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, host)?;
+    entry.set_password(&serde_json::to_string(credentials)?)?;
+    Ok(())
+}
+
+pub fn remove_credentials(host: &str) -> Result<(), SmbError> {
+    match keyring::Entry::new(KEYRING_SERVICE, host)?.delete_credential() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn load_credentials(host: &str) -> Option<SmbCredentials> {
+    let password = keyring::Entry::new(KEYRING_SERVICE, host)
+        .ok()?
+        .get_password()
+        .ok()?;
+    serde_json::from_str(&password).ok()
+}
+
+/// Windows speaks SMB natively, so shares are opened as UNC paths using the session's logon.
+#[cfg(target_os = "windows")]
+pub fn open(path: &str) -> io::Result<Box<dyn MediaStream>> {
+    let location = parse_location(path)?;
+    let unc = format!(
+        "\\\\{}\\{}{}",
+        location.host,
+        location.share,
+        location.path.replace('/', "\\")
+    );
+
+    Ok(Box::new(std::fs::File::open(unc)?))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn open(path: &str) -> io::Result<Box<dyn MediaStream>> {
+    let location = parse_location(path)?;
+    let credentials = load_credentials(&location.host).unwrap_or_default();
+
+    Ok(Box::new(client::SmbStream::open(location, credentials)?))
+}
+
+#[cfg(not(target_os = "windows"))]
+mod client {
+    use pavao::{SmbClient, SmbOpenOptions, SmbOptions};
+    use std::io::{self, Read, Seek, SeekFrom};
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    use super::{SmbCredentials, SmbLocation};
+
+    /// Bytes per read request; the next chunk is always prefetched while the current one plays.
+    const CHUNK_SIZE: u64 = 1024 * 1024;
+
+    type ChunkReply = mpsc::Receiver<io::Result<Vec<u8>>>;
+
+    struct ChunkRequest {
+        start: u64,
+        reply: mpsc::Sender<io::Result<Vec<u8>>>,
+    }
+
+    /// Seekable reader over a file on an SMB share. The libsmbclient handle lives on a worker
+    /// thread, which serves chunk requests and keeps one chunk of read-ahead in flight.
+    pub struct SmbStream {
+        requests: mpsc::Sender<ChunkRequest>,
+        length: u64,
+        position: u64,
+        buffer: Vec<u8>,
+        buffer_start: u64,
+        prefetch: Option<(u64, Mutex<ChunkReply>)>,
+    }
+
+    impl SmbStream {
+        pub fn open(location: SmbLocation, credentials: SmbCredentials) -> io::Result<SmbStream> {
+            let (requests, receiver) = mpsc::channel::<ChunkRequest>();
+            let (ready_sender, ready) = mpsc::channel();
+
+            thread::spawn(move || {
+                let opened = open_file(&location, &credentials);
+                let (client, size) = match opened {
+                    Ok(opened) => opened,
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_sender.send(Ok(size));
+
+                let mut file = match client
+                    .open_with(location.path.as_str(), SmbOpenOptions::default().read(true))
+                {
+                    Ok(file) => file,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                };
+
+                for request in receiver {
+                    let result = file.seek(SeekFrom::Start(request.start)).and_then(|_| {
+                        let mut chunk = Vec::new();
+                        (&mut file).take(CHUNK_SIZE).read_to_end(&mut chunk)?;
+                        Ok(chunk)
+                    });
+                    let _ = request.reply.send(result);
+                }
+            });
+
+            let length = ready
+                .recv()
+                .map_err(|_| io::Error::other("SMB worker exited"))??;
+
+            Ok(SmbStream {
+                requests,
+                length,
+                position: 0,
+                buffer: Vec::new(),
+                buffer_start: 0,
+                prefetch: None,
+            })
+        }
+
+        fn request(&self, start: u64) -> io::Result<ChunkReply> {
+            let (reply, receiver) = mpsc::channel();
+            self.requests
+                .send(ChunkRequest { start, reply })
+                .map_err(|_| io::Error::other("SMB worker exited"))?;
+            Ok(receiver)
+        }
+
+        fn fill(&mut self, start: u64) -> io::Result<()> {
+            let chunk_start = start - start % CHUNK_SIZE;
+
+            let reply = match self.prefetch.take() {
+                Some((prefetched, reply)) if prefetched == chunk_start => reply
+                    .into_inner()
+                    .map_err(|_| io::Error::other("Mutex lock error"))?,
+                _ => self.request(chunk_start)?,
+            };
+
+            self.buffer = reply
+                .recv()
+                .map_err(|_| io::Error::other("SMB worker exited"))??;
+            self.buffer_start = chunk_start;
+
+            let next = chunk_start + CHUNK_SIZE;
+            if next < self.length {
+                self.prefetch = Some((next, Mutex::new(self.request(next)?)));
+            }
+
+            Ok(())
+        }
+
+        fn buffered(&self, position: u64) -> bool {
+            position >= self.buffer_start && position < self.buffer_start + self.buffer.len() as u64
+        }
+    }
+
+    impl Read for SmbStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.position >= self.length || buf.is_empty() {
+                return Ok(0);
+            }
+
+            if !self.buffered(self.position) {
+                self.fill(self.position)?;
+                if !self.buffered(self.position) {
+                    return Ok(0);
+                }
+            }
+
+            let offset = (self.position - self.buffer_start) as usize;
+            let available = &self.buffer[offset..];
+            let count = available.len().min(buf.len());
+            buf[..count].copy_from_slice(&available[..count]);
+            self.position += count as u64;
+
+            Ok(count)
+        }
+    }
+
+    impl Seek for SmbStream {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let target = match pos {
+                SeekFrom::Start(offset) => offset as i64,
+                SeekFrom::End(offset) => self.length as i64 + offset,
+                SeekFrom::Current(offset) => self.position as i64 + offset,
+            };
+
+            if target < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Seek before start of stream",
+                ));
+            }
+
+            self.position = target as u64;
+            Ok(self.position)
+        }
+    }
+
+    fn open_file(
+        location: &SmbLocation,
+        credentials: &SmbCredentials,
+    ) -> io::Result<(SmbClient, u64)> {
+        let mut smb_credentials = pavao::SmbCredentials::default()
+            .server(format!("smb://{}", location.host))
+            .share(format!("/{}", location.share))
+            .username(credentials.username.as_str())
+            .password(credentials.password.as_str());
+        if let Some(workgroup) = &credentials.workgroup {
+            smb_credentials = smb_credentials.workgroup(workgroup.as_str());
+        }
+
+        let client = SmbClient::new(
+            smb_credentials,
+            SmbOptions::default().one_share_per_server(true),
+        )
+        .map_err(|e| io::Error::other(e.to_string()))?;
+        let size = client
+            .stat(location.path.as_str())
+            .map_err(|e| io::Error::other(e.to_string()))?
+            .size;
+
+        Ok((client, size))
+    }
+}
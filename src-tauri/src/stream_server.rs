@@ -0,0 +1,153 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use thiserror::Error;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use crate::encoder::{BlockEncoder, Mp3Encoder, OggEncoder};
+use crate::tap::{self, AudioBlock};
+
+const STREAM_KBPS: u32 = 192;
+
+/// How often idle listener threads check whether the server was stopped.
+const STOP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum StreamServerError {
+    #[error("Audio stream is already running")]
+    AlreadyRunning,
+
+    #[error("Failed to start server: {0}")]
+    ServerError(String),
+
+    #[error("Mutex lock error")]
+    LockError,
+}
+
+struct RunningServer {
+    server: Arc<Server>,
+    thread: JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+}
+
+/// Serves whatever the player is outputting as a live MP3 (`/stream.mp3`) or Ogg Vorbis
+/// (`/stream.ogg`) stream, so other devices on the network can listen along.
+#[derive(Default)]
+pub struct StreamServer {
+    running: Mutex<Option<RunningServer>>,
+}
+
+impl StreamServer {
+    pub fn start(&self, port: u16) -> Result<(), StreamServerError> {
+        let mut running = self
+            .running
+            .lock()
+            .map_err(|_| StreamServerError::LockError)?;
+        if running.is_some() {
+            return Err(StreamServerError::AlreadyRunning);
+        }
+
+        let server = Server::http(("0.0.0.0", port))
+            .map_err(|e| StreamServerError::ServerError(e.to_string()))?;
+        let server = Arc::new(server);
+        let server_clone = server.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+
+        let thread = thread::spawn(move || {
+            for request in server_clone.incoming_requests() {
+                let stop = stop_clone.clone();
+                thread::spawn(move || handle_request(request, stop));
+            }
+        });
+
+        *running = Some(RunningServer {
+            server,
+            thread,
+            stop,
+        });
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<(), StreamServerError> {
+        let running = self
+            .running
+            .lock()
+            .map_err(|_| StreamServerError::LockError)?
+            .take();
+
+        if let Some(running) = running {
+            running.stop.store(true, Ordering::SeqCst);
+            running.server.unblock();
+            let _ = running.thread.join();
+        }
+
+        Ok(())
+    }
+}
+
+fn handle_request(request: Request, stop: Arc<AtomicBool>) {
+    let (encoder, content_type): (Box<dyn BlockEncoder>, &str) = match request.url() {
+        "/stream.mp3" => (Box::new(Mp3Encoder::new(STREAM_KBPS)), "audio/mpeg"),
+        "/stream.ogg" => (Box::new(OggEncoder::new(STREAM_KBPS)), "audio/ogg"),
+        _ => {
+            let _ = request.respond(Response::empty(404));
+            return;
+        }
+    };
+
+    let stream = EncodedStream {
+        blocks: tap::subscribe(),
+        encoder,
+        stop,
+        pending: Vec::new(),
+        offset: 0,
+    };
+
+    let headers = vec![
+        Header::from_bytes("Content-Type", content_type).unwrap(),
+        Header::from_bytes("Cache-Control", "no-cache").unwrap(),
+    ];
+
+    // No content length, so the response is sent chunked until the listener disconnects.
+    let response = Response::new(StatusCode(200), headers, stream, None, None);
+    let _ = request.respond(response);
+}
+
+/// Response body that encodes tapped output blocks as the client reads them.
+struct EncodedStream {
+    blocks: mpsc::Receiver<Arc<AudioBlock>>,
+    encoder: Box<dyn BlockEncoder>,
+    stop: Arc<AtomicBool>,
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for EncodedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset >= self.pending.len() {
+            self.pending.clear();
+            self.offset = 0;
+
+            if self.stop.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+
+            match self.blocks.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(block) => self.encoder.encode(&block, &mut self.pending)?,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let available = &self.pending[self.offset..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.offset += count;
+
+        Ok(count)
+    }
+}
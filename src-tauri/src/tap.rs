@@ -0,0 +1,149 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Frames collected before a block is handed to subscribers.
+const BLOCK_FRAMES: usize = 1024;
+
+/// Blocks a subscriber may fall behind by before new ones are dropped for it.
+const SUBSCRIBER_BACKLOG: usize = 256;
+
+static SUBSCRIBERS: Mutex<Vec<mpsc::SyncSender<Arc<AudioBlock>>>> = Mutex::new(Vec::new());
+static SUBSCRIBER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Interleaved decoded samples in the format they were played at.
+#[derive(Debug)]
+pub struct AudioBlock {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+/// Receives a copy of everything the player outputs until the receiver is dropped.
+pub fn subscribe() -> mpsc::Receiver<Arc<AudioBlock>> {
+    let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    subscribers.push(sender);
+    SUBSCRIBER_COUNT.store(subscribers.len(), Ordering::SeqCst);
+
+    receiver
+}
+
+fn publish(block: AudioBlock) {
+    let block = Arc::new(block);
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    subscribers.retain(|sender| {
+        !matches!(
+            sender.try_send(block.clone()),
+            Err(mpsc::TrySendError::Disconnected(_))
+        )
+    });
+    SUBSCRIBER_COUNT.store(subscribers.len(), Ordering::SeqCst);
+}
+
+/// Source adapter that copies samples to the tap subscribers as they are played.
+pub struct Tap<S> {
+    inner: S,
+    buffer: Vec<i16>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl<S> Tap<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(inner: S) -> Tap<S> {
+        let channels = inner.channels();
+        let sample_rate = inner.sample_rate();
+
+        Tap {
+            inner,
+            buffer: Vec::new(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        publish(AudioBlock {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            samples: std::mem::take(&mut self.buffer),
+        });
+    }
+}
+
+impl<S> Iterator for Tap<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next();
+
+        if SUBSCRIBER_COUNT.load(Ordering::Relaxed) == 0 {
+            self.buffer.clear();
+            return sample;
+        }
+
+        let Some(sample) = sample else {
+            self.flush();
+            return None;
+        };
+
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        if channels != self.channels || sample_rate != self.sample_rate {
+            self.flush();
+            self.channels = channels;
+            self.sample_rate = sample_rate;
+        }
+
+        self.buffer.push(sample);
+        if self.buffer.len() >= BLOCK_FRAMES * self.channels as usize {
+            self.flush();
+        }
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for Tap<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.buffer.clear();
+        self.inner.try_seek(pos)
+    }
+}
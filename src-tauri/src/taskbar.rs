@@ -0,0 +1,290 @@
+use souvlaki::MediaPlayback;
+use std::cell::RefCell;
+use std::time::Duration;
+use tauri::window::{ProgressBarState, ProgressBarStatus};
+use tauri::{AppHandle, Manager};
+use windows::core::Result as WinResult;
+use windows::w;
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+use windows::Win32::UI::Shell::{
+    DefSubclassProc, ITaskbarList3, SetWindowSubclass, TaskbarList, THBF_ENABLED, THBN_CLICKED,
+    THB_FLAGS, THB_ICON, THB_TOOLTIP, THUMBBUTTON,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateIcon, RegisterWindowMessageW, HICON, WM_COMMAND,
+};
+
+use crate::audio_player::AudioPlayer;
+
+const ICON_SIZE: usize = 16;
+
+const PREV_BUTTON: u32 = 0;
+const PLAY_PAUSE_BUTTON: u32 = 1;
+const NEXT_BUTTON: u32 = 2;
+
+thread_local! {
+    // COM objects and window procedures belong to the UI thread, so the toolbar lives there.
+    static THUMBBAR: RefCell<Option<Thumbbar>> = const { RefCell::new(None) };
+}
+
+/// The Windows taskbar button: play/pause, next and previous buttons in the thumbnail
+/// toolbar, and the track position as its progress indicator.
+pub struct Taskbar {
+    app: AppHandle,
+    playing: Option<bool>,
+    progress: u64,
+}
+
+impl Taskbar {
+    pub fn new(app: &AppHandle, player: AudioPlayer) -> Taskbar {
+        let handle = app.clone();
+        let result = app.run_on_main_thread(move || {
+            if let Err(e) = Thumbbar::install(&handle, player) {
+                log::error!("Failed to add taskbar buttons: {}", e);
+            }
+        });
+        if let Err(e) = result {
+            log::error!("{}", e);
+        }
+
+        Taskbar {
+            app: app.clone(),
+            playing: None,
+            progress: 0,
+        }
+    }
+
+    pub fn set_playback(&mut self, playback: &MediaPlayback) {
+        let playing = match playback {
+            MediaPlayback::Playing { .. } => Some(true),
+            MediaPlayback::Paused { .. } => Some(false),
+            MediaPlayback::Stopped => None,
+        };
+        if playing == self.playing {
+            return;
+        }
+        self.playing = playing;
+        if playing.is_none() {
+            self.progress = 0;
+        }
+
+        let result = self.app.run_on_main_thread(move || {
+            THUMBBAR.with(|thumbbar| {
+                if let Some(thumbbar) = thumbbar.borrow_mut().as_mut() {
+                    thumbbar.set_playing(playing == Some(true));
+                }
+            })
+        });
+        if let Err(e) = result {
+            log::error!("{}", e);
+        }
+        self.update_progress();
+    }
+
+    pub fn set_position(&mut self, position: Duration, duration: Duration) {
+        let progress = match duration.as_millis() {
+            0 => 0,
+            total => (position.as_millis() * 100 / total).min(100) as u64,
+        };
+        if progress != self.progress {
+            self.progress = progress;
+            self.update_progress();
+        }
+    }
+
+    fn update_progress(&self) {
+        let Some(window) = self.app.get_webview_window("main") else {
+            return;
+        };
+
+        let status = match self.playing {
+            Some(true) => ProgressBarStatus::Normal,
+            Some(false) => ProgressBarStatus::Paused,
+            None => ProgressBarStatus::None,
+        };
+        if let Err(e) = window.set_progress_bar(ProgressBarState {
+            status: Some(status),
+            progress: Some(self.progress),
+        }) {
+            log::error!("{}", e);
+        }
+    }
+}
+
+struct Thumbbar {
+    list: ITaskbarList3,
+    hwnd: HWND,
+    player: AudioPlayer,
+    /// Sent when Explorer (re)creates the taskbar button, which drops its toolbar.
+    button_created: u32,
+    playing: bool,
+    play_icon: HICON,
+    pause_icon: HICON,
+    prev_icon: HICON,
+    next_icon: HICON,
+}
+
+impl Thumbbar {
+    fn install(app: &AppHandle, player: AudioPlayer) -> WinResult<()> {
+        let Some(window) = app.get_webview_window("main") else {
+            return Ok(());
+        };
+        let hwnd = match window.hwnd() {
+            Ok(hwnd) => HWND(hwnd.0 as isize),
+            Err(_) => return Ok(()),
+        };
+
+        unsafe {
+            let list: ITaskbarList3 = CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER)?;
+            list.HrInit()?;
+
+            let thumbbar = Thumbbar {
+                list,
+                hwnd,
+                player,
+                button_created: RegisterWindowMessageW(w!("TaskbarButtonCreated")),
+                playing: false,
+                play_icon: create_icon(play_glyph)?,
+                pause_icon: create_icon(pause_glyph)?,
+                prev_icon: create_icon(|x, y| next_glyph(ICON_SIZE as f32 - x, y))?,
+                next_icon: create_icon(next_glyph)?,
+            };
+            // The button usually exists already; if not, the buttons are added once it does.
+            let _ = thumbbar.add_buttons();
+            THUMBBAR.with(|cell| *cell.borrow_mut() = Some(thumbbar));
+
+            SetWindowSubclass(hwnd, Some(subclass_proc), 1, 0);
+        }
+        Ok(())
+    }
+
+    fn buttons(&self) -> [THUMBBUTTON; 3] {
+        let (play_pause_icon, play_pause_tip) = if self.playing {
+            (self.pause_icon, "Pause")
+        } else {
+            (self.play_icon, "Play")
+        };
+
+        [
+            button(PREV_BUTTON, self.prev_icon, "Previous"),
+            button(PLAY_PAUSE_BUTTON, play_pause_icon, play_pause_tip),
+            button(NEXT_BUTTON, self.next_icon, "Next"),
+        ]
+    }
+
+    fn add_buttons(&self) -> WinResult<()> {
+        unsafe { self.list.ThumbBarAddButtons(self.hwnd, &self.buttons()) }
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        if let Err(e) = unsafe { self.list.ThumbBarUpdateButtons(self.hwnd, &self.buttons()) } {
+            log::error!("{}", e);
+        }
+    }
+
+    fn clicked(&self, id: u32) {
+        let result = match id {
+            PREV_BUTTON => self.player.prev(),
+            PLAY_PAUSE_BUTTON => self.player.toggle_playback(),
+            NEXT_BUTTON => self.player.next(),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            log::error!("{}", e);
+        }
+    }
+}
+
+unsafe extern "system" fn subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _id: usize,
+    _data: usize,
+) -> LRESULT {
+    let handled = THUMBBAR.with(|thumbbar| {
+        let Ok(thumbbar) = thumbbar.try_borrow() else {
+            return false;
+        };
+        let Some(thumbbar) = thumbbar.as_ref() else {
+            return false;
+        };
+
+        if msg == WM_COMMAND && (wparam.0 >> 16) as u32 & 0xffff == THBN_CLICKED {
+            thumbbar.clicked(wparam.0 as u32 & 0xffff);
+            true
+        } else {
+            if msg == thumbbar.button_created {
+                if let Err(e) = thumbbar.add_buttons() {
+                    log::error!("Failed to add taskbar buttons: {}", e);
+                }
+            }
+            false
+        }
+    });
+
+    if handled {
+        LRESULT(0)
+    } else {
+        DefSubclassProc(hwnd, msg, wparam, lparam)
+    }
+}
+
+fn button(id: u32, icon: HICON, tip: &str) -> THUMBBUTTON {
+    let mut sz_tip = [0u16; 260];
+    for (slot, unit) in sz_tip.iter_mut().zip(tip.encode_utf16()) {
+        *slot = unit;
+    }
+
+    THUMBBUTTON {
+        dwMask: THB_ICON | THB_TOOLTIP | THB_FLAGS,
+        iId: id,
+        iBitmap: 0,
+        hIcon: icon,
+        szTip: sz_tip,
+        dwFlags: THBF_ENABLED,
+    }
+}
+
+/// Draws a white glyph on a transparent background. `shape` is given pixel centres.
+fn create_icon(shape: impl Fn(f32, f32) -> bool) -> WinResult<HICON> {
+    let mut color = Vec::with_capacity(ICON_SIZE * ICON_SIZE * 4);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let filled = shape(x as f32 + 0.5, y as f32 + 0.5);
+            color.extend_from_slice(&[if filled { 255 } else { 0 }; 4]);
+        }
+    }
+    // With 32-bit colour the alpha channel decides visibility, so the mask is left clear.
+    let mask = [0u8; ICON_SIZE * ICON_SIZE / 8];
+
+    unsafe {
+        CreateIcon(
+            HINSTANCE::default(),
+            ICON_SIZE as i32,
+            ICON_SIZE as i32,
+            1,
+            32,
+            mask.as_ptr(),
+            color.as_ptr(),
+        )
+    }
+}
+
+fn play_glyph(x: f32, y: f32) -> bool {
+    (4.0..=13.0).contains(&x) && (y - 8.0).abs() <= (13.0 - x) * 0.55
+}
+
+fn pause_glyph(x: f32, y: f32) -> bool {
+    (3.0..=13.0).contains(&y) && ((4.0..=7.0).contains(&x) || (9.0..=12.0).contains(&x))
+}
+
+fn next_glyph(x: f32, y: f32) -> bool {
+    let arrow = (3.0..=10.0).contains(&x) && (y - 8.0).abs() <= (10.0 - x) * 0.7;
+    let bar = (11.0..=13.0).contains(&x) && (3.0..=13.0).contains(&y);
+    arrow || bar
+}
@@ -0,0 +1,107 @@
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager};
+
+use crate::audio_player::{AudioError, AudioPlayer, TrackInfo};
+
+const TRAY_ID: &str = "main";
+const DEFAULT_TOOLTIP: &str = "Music Player";
+
+/// Creates the tray icon with its playback menu. Left-clicking the icon restores the window.
+pub fn setup(app: &AppHandle, player: AudioPlayer) -> tauri::Result<()> {
+    let play_pause = MenuItem::with_id(app, "play_pause", "Play/Pause", true, None::<&str>)?;
+    let next = MenuItem::with_id(app, "next", "Next", true, None::<&str>)?;
+    let prev = MenuItem::with_id(app, "prev", "Previous", true, None::<&str>)?;
+    let volume_up = MenuItem::with_id(app, "volume_up", "Volume Up", true, None::<&str>)?;
+    let volume_down = MenuItem::with_id(app, "volume_down", "Volume Down", true, None::<&str>)?;
+    let mute = MenuItem::with_id(app, "mute", "Mute/Unmute", true, None::<&str>)?;
+    let show = MenuItem::with_id(app, "show", "Show Window", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let volume_separator = PredefinedMenuItem::separator(app)?;
+
+    let menu = Menu::with_items(
+        app,
+        &[
+            &play_pause,
+            &next,
+            &prev,
+            &volume_separator,
+            &volume_up,
+            &volume_down,
+            &mute,
+            &separator,
+            &show,
+            &quit,
+        ],
+    )?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(DEFAULT_TOOLTIP)
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(move |app, event| {
+            let result: Result<(), AudioError> = match event.id.as_ref() {
+                "play_pause" => player.toggle_playback(),
+                "next" => player.next(),
+                "prev" => player.prev(),
+                "volume_up" => player.volume_up(),
+                "volume_down" => player.volume_down(),
+                "mute" => player.toggle_mute(),
+                "show" => {
+                    show_main_window(app);
+                    Ok(())
+                }
+                "quit" => {
+                    app.exit(0);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+
+            if let Err(e) = result {
+                log::error!("{}", e);
+            }
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+
+    builder.build(app)?;
+    Ok(())
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Shows the current track in the tray tooltip, or the app name when nothing is playing.
+pub fn set_current_track(app: &AppHandle, track: Option<&TrackInfo>) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+
+    let tooltip = match track {
+        Some(track) => format!("{} - {}", track.artist, track.title),
+        None => DEFAULT_TOOLTIP.to_string(),
+    };
+
+    if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+        log::error!("{}", e);
+    }
+}
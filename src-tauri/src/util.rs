@@ -1,17 +1,71 @@
-use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::file::{AudioFile, TaggedFile, TaggedFileExt};
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::probe::Probe;
 use lofty::read_from_path;
-use lofty::tag::Accessor;
-use rodio::{Decoder, Sink};
+use lofty::tag::{Accessor, ItemKey};
+use rodio::source::EmptyCallback;
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
 use souvlaki::{MediaMetadata, MediaPlayback};
-use std::fs::File;
-use std::io::BufReader;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
+use crate::announce::{AnnounceMode, Announcer};
 use crate::audio_player;
-use audio_player::{AudioError, AudioState, TrackInfo};
+use crate::buffer::{ReadAhead, Underrun};
+use crate::chromecast::CastCommand;
+use crate::crossfade::{Crossfade, Lookahead, Slot};
+use crate::diagnostics::{self, DecodeStall, StallDetector};
+use crate::dither::Gain;
+use crate::http_stream::HttpStream;
+use crate::output::{AudioOutput, OutputRate};
+use crate::paths;
+use crate::player_host;
+use crate::seek;
+use crate::silence::{SkipSilence, TrimSilence};
+use crate::smb;
+use crate::tap::Tap;
+use crate::webdav;
+use audio_player::{AudioCommand, AudioError, AudioState, TrackInfo};
+
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "flac", "wav", "ogg", "m4a", "aac", "aiff"];
+
+/// Read-ahead window for network tracks when the setting is lower, since their reads make
+/// requests the output can't wait on.
+const NETWORK_READ_AHEAD: usize = 256 * 1024;
+
+/// Reads tags from a local file, or through `open_media` for network locations.
+pub fn read_tagged_file(path: &str) -> Option<TaggedFile> {
+    if !is_network_path(path) {
+        return read_from_path(paths::native(path)).ok();
+    }
+
+    let media = open_media(path).ok()?;
+    Probe::new(BufReader::new(media))
+        .guess_file_type()
+        .ok()?
+        .read()
+        .ok()
+}
 
 pub fn get_track_info_from_path(path: &str, index: usize) -> TrackInfo {
-    if let Ok(tagged_file) = read_from_path(path) {
+    track_info_from_tagged_file(read_tagged_file(path).as_ref(), path, index)
+}
+
+/// Builds a `TrackInfo` from already-read tags, so callers needing other tags parse the file once.
+pub fn track_info_from_tagged_file(
+    tagged_file: Option<&TaggedFile>,
+    path: &str,
+    index: usize,
+) -> TrackInfo {
+    if let Some(tagged_file) = tagged_file {
         let tag = tagged_file.primary_tag();
         let title = tag
             .and_then(|t| t.title().map(|s| s.into_owned()))
@@ -23,6 +77,28 @@ pub fn get_track_info_from_path(path: &str, index: usize) -> TrackInfo {
             .and_then(|t| t.artist().map(|s| s.into_owned()))
             .unwrap_or_else(|| "Unknown Title".to_string());
 
+        let track_number = tag.and_then(|t| t.track());
+        let disc_number = tag.and_then(|t| t.disk());
+        let album_artist = tag.and_then(|t| t.get_string(&ItemKey::AlbumArtist).map(String::from));
+        let genre = tag.and_then(|t| t.genre().map(|s| s.into_owned()));
+        let year = tag.and_then(|t| t.year());
+        let artists = tag
+            .map(|t| split_values(t.get_strings(&ItemKey::TrackArtist)))
+            .unwrap_or_default();
+        let genres = tag
+            .map(|t| split_values(t.get_strings(&ItemKey::Genre)))
+            .unwrap_or_default();
+        let compilation = tag
+            .and_then(|t| t.get_string(&ItemKey::FlagCompilation))
+            .is_some_and(|flag| flag == "1" || flag.eq_ignore_ascii_case("true"))
+            || album_artist.as_deref().is_some_and(is_various_artists);
+        let text = |key: ItemKey| tag.and_then(|t| t.get_string(&key).map(String::from));
+        let composer = text(ItemKey::Composer);
+        let conductor = text(ItemKey::Conductor);
+        let performer = text(ItemKey::Performer);
+        let work = text(ItemKey::Work);
+        let movement = text(ItemKey::Movement);
+
         // let cover = tag
         //     .and_then(|t| t.get_picture_type(PictureType::CoverFront))
         //     .unwrap();
@@ -35,7 +111,21 @@ pub fn get_track_info_from_path(path: &str, index: usize) -> TrackInfo {
             album: album,
             artist: artist,
             duration: duration,
+            track_number: track_number,
+            disc_number: disc_number,
+            album_artist,
+            genre,
+            year,
+            artists,
+            genres,
+            compilation,
+            composer,
+            conductor,
+            performer,
+            work,
+            movement,
             path: path.to_string(),
+            source_kind: SourceKind::of(path),
         }
     } else {
         TrackInfo {
@@ -44,41 +134,824 @@ pub fn get_track_info_from_path(path: &str, index: usize) -> TrackInfo {
             album: "Unknown Album".to_string(),
             artist: "Unknown Artist".to_string(),
             duration: 0,
+            track_number: None,
+            disc_number: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            artists: Vec::new(),
+            genres: Vec::new(),
+            compilation: false,
+            composer: None,
+            conductor: None,
+            performer: None,
+            work: None,
+            movement: None,
             path: path.to_string(),
+            source_kind: SourceKind::of(path),
         }
     }
 }
 
-pub fn play_track(
+/// Album artist for compilations that don't name one.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// Whether an album artist is one of the usual stand-ins for a compilation.
+pub fn is_various_artists(album_artist: &str) -> bool {
+    ["various artists", "various", "va", "v.a."]
+        .contains(&album_artist.trim().to_lowercase().as_str())
+}
+
+/// Separators tag editors use to list several values in one field.
+const VALUE_SEPARATORS: &[&str] = &["\0", ";", " / "];
+
+/// Splits multi-value tags, whether stored as repeated items or as one separated string,
+/// into distinct values in tag order.
+pub fn split_values<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut split: Vec<String> = Vec::new();
+    for value in values {
+        let mut parts = vec![value];
+        for separator in VALUE_SEPARATORS {
+            parts = parts
+                .into_iter()
+                .flat_map(|part| part.split(separator))
+                .collect();
+        }
+        for part in parts.into_iter().map(str::trim) {
+            if !part.is_empty() && !split.iter().any(|existing| existing == part) {
+                split.push(part.to_string());
+            }
+        }
+    }
+    split
+}
+
+/// The front cover embedded in the file's tags, or failing that its first picture.
+pub fn cover_picture(tagged_file: &TaggedFile) -> Option<&Picture> {
+    let tag = tagged_file.primary_tag()?;
+    tag.pictures()
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or_else(|| tag.pictures().first())
+}
+
+pub fn picture_extension(picture: &Picture) -> &'static str {
+    match picture.mime_type() {
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Gif) => "gif",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Tiff) => "tiff",
+        _ => "jpg",
+    }
+}
+
+/// `file://` URL for a local path, as the OS media controls expect for cover art. SMTC strips
+/// the scheme and opens the rest as a Windows path, so it is left as is there.
+pub fn file_url(path: &Path) -> String {
+    format!("file://{}", path.to_string_lossy())
+}
+
+/// Cover art for the OS media controls: the library's album cover when the track has been
+/// scanned, otherwise the embedded picture written to a temp file. Network tracks outside the
+/// library get none, as reading their tags would mean another download.
+fn cover_url(state: &AudioState, track_info: &TrackInfo) -> Option<String> {
+    match state.host.library().track_cover(&track_info.path) {
+        Ok(Some(cover)) if Path::new(&cover).is_file() => return Some(file_url(Path::new(&cover))),
+        Ok(_) => {}
+        Err(e) => log::error!("Failed to look up cover art: {}", e),
+    }
+
+    if is_network_path(&track_info.path) {
+        return None;
+    }
+    let tagged_file = read_from_path(paths::native(&track_info.path)).ok()?;
+    let picture = cover_picture(&tagged_file)?;
+
+    // Only the playing track's cover is kept; the name changes so controls don't show a
+    // cached image for the previous track.
+    let dir = std::env::temp_dir().join("music-player-cover");
+    let _ = fs::remove_dir_all(&dir);
+    let cover = dir.join(format!("{}.{}", state.track_id, picture_extension(picture)));
+    if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(&cover, picture.data())) {
+        log::error!("Failed to save cover art: {}", e);
+        return None;
+    }
+
+    Some(file_url(&cover))
+}
+
+/// Shows the track's tags and cover in the OS media controls.
+pub fn show_metadata(state: &mut AudioState, track_info: &TrackInfo) {
+    let cover_url = cover_url(state, track_info);
+    state.set_metadata(MediaMetadata {
+        title: Some(track_info.title.as_str()),
+        artist: Some(track_info.artist.as_str()),
+        album: Some(track_info.album.as_str()),
+        cover_url: cover_url.as_deref(),
+        duration: Some(Duration::from_secs(track_info.duration)),
+    });
+}
+
+pub fn is_supported_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(ext))
+        })
+        .unwrap_or(false)
+}
+
+/// The file extension of a path or URL, lowercased, for grouping tracks by format.
+pub fn format_of(path: &str) -> String {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Checks that a local file exists and looks like audio, so it is turned away when queued
+/// instead of failing once it comes up. Only the header is read, which keeps checking a
+/// large batch cheap. Network files are left to fail when played.
+pub fn check_audio_file(path: &str) -> Result<(), AudioError> {
+    match SourceKind::of(path) {
+        SourceKind::File => {}
+        SourceKind::Unsupported => return Err(AudioError::UnsupportedFileError),
+        _ => return Ok(()),
+    }
+
+    let path = &path_from_uri(path);
+    let file = File::open(paths::native(path))?;
+    if !is_supported_audio_file(Path::new(path)) {
+        return Err(AudioError::UnsupportedFileError);
+    }
+    Probe::new(BufReader::new(file))
+        .guess_file_type()?
+        .file_type()
+        .ok_or(AudioError::UnsupportedFileError)?;
+    Ok(())
+}
+
+pub fn collect_audio_files(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(paths::native(&dir.to_string_lossy()))? {
+        // One unreadable entry or subfolder shouldn't lose the rest of the folder.
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Skipping an entry of {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        // Built from `dir` rather than taken from the entry, so files keep the spelling
+        // they're stored in instead of the native form they were read through.
+        let path = dir.join(entry.file_name());
+
+        if entry.path().is_dir() {
+            if recursive {
+                match collect_audio_files(&path, recursive) {
+                    Ok(found) => files.extend(found),
+                    Err(e) => log::warn!("Skipping {}: {}", path.display(), e),
+                }
+            }
+        } else if is_supported_audio_file(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// How files added together are ordered in the queue.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOrder {
+    /// Folder by folder, then by disc and track number, falling back to the file name.
+    #[default]
+    Tags,
+    /// Folder by folder, then by file name, ignoring tags.
+    FileName,
+    /// The order the files were given in.
+    AsGiven,
+}
+
+/// Orders tracks folder by folder, then by disc and track number, falling back to the file name.
+pub fn sort_tracks(tracks: &mut [TrackInfo]) {
+    sort_tracks_by(tracks, ImportOrder::Tags);
+}
+
+pub fn sort_tracks_by(tracks: &mut [TrackInfo], order: ImportOrder) {
+    let use_tags = match order {
+        ImportOrder::Tags => true,
+        ImportOrder::FileName => false,
+        ImportOrder::AsGiven => return,
+    };
+
+    tracks.sort_by(|a, b| {
+        let (path_a, path_b) = (Path::new(&a.path), Path::new(&b.path));
+        let tags = || {
+            if use_tags {
+                (a.disc_number, a.track_number).cmp(&(b.disc_number, b.track_number))
+            } else {
+                Ordering::Equal
+            }
+        };
+
+        natural_cmp(&sort_key(path_a.parent()), &sort_key(path_b.parent()))
+            .then_with(tags)
+            .then_with(|| natural_cmp(&sort_key(path_a.file_name()), &sort_key(path_b.file_name())))
+    });
+}
+
+/// A path component as text to sort by; a missing one sorts first.
+fn sort_key<P: AsRef<std::ffi::OsStr>>(part: Option<P>) -> String {
+    part.map(|part| part.as_ref().to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compares names the way people count, so "track2" comes before "track10". Letters are
+/// compared ignoring case, which only breaks ties.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut left, mut right) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        let (x, y) = match (left.peek(), right.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&x), Some(&y)) => (x, y),
+        };
+
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(digit);
+                }
+                digits
+            };
+            let (x, y) = (take_number(&mut left), take_number(&mut right));
+            let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            left.next();
+            right.next();
+            x.to_lowercase().cmp(y.to_lowercase())
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Case-insensitive search over title, artist and album; every word of `query` must match.
+pub fn track_matches(track: &TrackInfo, query: &str) -> bool {
+    let haystack = format!("{} {} {}", track.title, track.artist, track.album).to_lowercase();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| haystack.contains(word))
+}
+
+/// Drops repeats of a track, matched by file or by title, artist and duration, keeping the
+/// first occurrence's position. Returns the new index of `current`, which always survives
+/// so the playing track isn't interrupted. Untitled tracks only match by file.
+pub fn dedupe(queue: &mut Vec<TrackInfo>, current: usize) -> usize {
+    let mut by_path: HashMap<String, usize> = HashMap::new();
+    let mut by_song: HashMap<(String, String, u64), usize> = HashMap::new();
+    let mut kept: Vec<TrackInfo> = Vec::with_capacity(queue.len());
+    let mut current_index = 0;
+
+    for (index, track) in std::mem::take(queue).into_iter().enumerate() {
+        let song = (!track.title.is_empty()).then(|| {
+            (
+                track.title.to_lowercase(),
+                track.artist.to_lowercase(),
+                track.duration,
+            )
+        });
+        let duplicate = by_path
+            .get(&track.path)
+            .or_else(|| song.as_ref().and_then(|song| by_song.get(song)))
+            .copied();
+
+        let position = match duplicate {
+            Some(position) if index == current => {
+                kept[position] = track;
+                position
+            }
+            Some(_) => continue,
+            None => {
+                kept.push(track);
+                kept.len() - 1
+            }
+        };
+        by_path.insert(kept[position].path.clone(), position);
+        if let Some(song) = song {
+            by_song.entry(song).or_insert(position);
+        }
+        if index == current {
+            current_index = position;
+        }
+    }
+
+    reindex(&mut kept);
+    *queue = kept;
+    current_index
+}
+
+/// Renumbers `TrackInfo::index` after the tracks were reordered.
+pub fn reindex(tracks: &mut [TrackInfo]) {
+    for (i, track) in tracks.iter_mut().enumerate() {
+        track.index = i;
+    }
+}
+
+/// Any byte source the decoder can play from: local files or remote streams.
+pub trait MediaStream: Read + Seek + Send + Sync {}
+
+impl<T: Read + Seek + Send + Sync> MediaStream for T {}
+
+/// Where a queue entry's audio comes from, so local and network tracks can share a queue.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    #[default]
+    File,
+    Http,
+    Smb,
+    WebDav,
+    /// A URL with a scheme no source handles.
+    Unsupported,
+}
+
+impl SourceKind {
+    pub fn of(path: &str) -> SourceKind {
+        if is_remote_path(path) {
+            SourceKind::Http
+        } else if smb::is_smb_path(path) {
+            SourceKind::Smb
+        } else if webdav::is_webdav_path(path) {
+            SourceKind::WebDav
+        } else if path.contains("://") && !path.starts_with("file://") {
+            SourceKind::Unsupported
+        } else {
+            SourceKind::File
+        }
+    }
+}
+
+/// Local path for a `file://` URI; anything else is returned as is.
+pub fn path_from_uri(uri: &str) -> String {
+    match uri.strip_prefix("file://") {
+        Some(path) => percent_decode(path),
+        None => uri.to_string(),
+    }
+}
+
+pub fn is_remote_path(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Decodes `%XX` escapes in a URL component.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub fn is_network_path(path: &str) -> bool {
+    is_remote_path(path) || smb::is_smb_path(path) || webdav::is_webdav_path(path)
+}
+
+pub fn open_media(path: &str) -> io::Result<Box<dyn MediaStream>> {
+    match SourceKind::of(path) {
+        SourceKind::File => Ok(Box::new(File::open(paths::native(&path_from_uri(path)))?)),
+        SourceKind::Http => Ok(Box::new(HttpStream::open(path, None)?)),
+        SourceKind::Smb => smb::open(path),
+        SourceKind::WebDav => webdav::open(path),
+        SourceKind::Unsupported => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("No source can open {}", path),
+        )),
+    }
+}
+
+/// Replaces the sink's contents with the track decoded from `start`, leaving the sink paused.
+/// Indexed files are opened at the frame holding `start`; others are seeked by the decoder,
+/// and play from the beginning if it can't. A nonzero `fade` crossfades from the track that
+/// was playing.
+pub fn load_track(
     track_info: &TrackInfo,
-    sink: &Sink,
+    sink: &dyn AudioOutput,
     state: &mut AudioState,
+    start: Duration,
+    fade: Duration,
 ) -> Result<(), AudioError> {
+    let outgoing = match state.deck.take() {
+        Some(slot) if !fade.is_zero() => slot.lock().unwrap_or_else(|e| e.into_inner()).take(),
+        _ => None,
+    };
     sink.clear();
 
-    let file = File::open(&track_info.path)?;
-    let source = Decoder::new(BufReader::new(file))?;
+    let indexed = !start.is_zero() && seek::supports_indexed_seek(&track_info.path);
+    let (media, skip): (Box<dyn MediaStream>, u64) = if indexed {
+        let point = seek::open_at(&track_info.path, start)?;
+        (Box::new(point.media), point.skip)
+    } else {
+        (open_media(&track_info.path)?, 0)
+    };
+
+    let read_ahead = if is_network_path(&track_info.path) {
+        state.read_ahead.max(NETWORK_READ_AHEAD)
+    } else {
+        state.read_ahead
+    };
+    let media: Box<dyn MediaStream> = if read_ahead > 0 {
+        let (host, path) = (state.host.clone(), track_info.path.clone());
+        Box::new(ReadAhead::new(media, read_ahead, move |waited| {
+            diagnostics::record_underrun(waited);
+            let underrun = Underrun {
+                path: path.clone(),
+                waited_ms: waited.as_millis() as u64,
+            };
+            player_host::emit(&*host, "buffer-underrun", underrun);
+        })?)
+    } else {
+        media
+    };
+
+    let mut source = Decoder::new(BufReader::new(media))?;
+    let channels = source.channels() as u64;
 
-    sink.append(source);
-    sink.play();
+    // The audio thread reopens the output at this rate once the track is in the sink.
+    state.source_rate = Some(source.sample_rate());
+    if state.output_format.sample_rate == OutputRate::MatchSource
+        && state.opened_rate != state.source_rate
+        && state.cast.is_none()
+    {
+        let _ = state.sender.send(AudioCommand::SourceRateChanged);
+    }
+    source
+        .by_ref()
+        .take((skip * channels) as usize)
+        .for_each(drop);
 
-    state.duration = Some(track_info.duration);
+    let mut start = start;
+    if !start.is_zero() && !indexed {
+        if let Err(e) = source.try_seek(start) {
+            log::error!("Failed to start {} mid-track: {}", track_info.path, e);
+            start = Duration::ZERO;
+        }
+    }
 
-    state
-        .controls
-        .set_metadata(MediaMetadata {
-            title: Some(track_info.title.as_str()),
-            artist: Some(track_info.artist.as_str()),
-            album: Some(track_info.album.as_str()),
-            duration: Some(Duration::from_secs(track_info.duration)),
-            ..Default::default()
-        })
-        .unwrap();
+    // Each track counts its own skipped audio, so an outgoing track can't shift the new position.
+    // The sink counts from zero, so the start offset is reported like skipped audio.
+    state.skipped = Arc::new(AtomicU64::new(start.as_nanos() as u64));
+
+    let (host, path) = (state.host.clone(), track_info.path.clone());
+    let source = StallDetector::new(source, move |stalled| {
+        let stall = DecodeStall {
+            path: path.clone(),
+            stalled_ms: stalled.as_millis() as u64,
+        };
+        player_host::emit(&*host, "decode-stall", stall);
+    });
+    // Decoded a crossfade ahead, so the next track starts as much before the real end.
+    state.remaining = Arc::new(AtomicU64::new(u64::MAX));
+    let source = Lookahead::new(source, state.crossfade(), state.remaining.clone());
+
+    let mut source = TrimSilence::new(
+        source,
+        state.trim_silence.clone(),
+        state.dsp_bypass.clone(),
+        state.skipped.clone(),
+    );
+    if !start.is_zero() {
+        source.resume();
+    }
+    let source = SkipSilence::new(
+        source,
+        state.skip_silence.clone(),
+        state.dsp_bypass.clone(),
+        state.skipped.clone(),
+    );
+
+    let gain_db = state
+        .host
+        .library()
+        .get_track_gain(&track_info.path)
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read track gain: {}", e);
+            None
+        });
+    let factor = 10f32.powf(gain_db.unwrap_or(0.0) / 20.0);
+    let source = Gain::new(
+        source,
+        factor,
+        state.dither.clone(),
+        state.dsp_bypass.clone(),
+    );
+
+    let slot: Slot = Arc::new(Mutex::new(Some(Box::new(source))));
+    state.deck = Some(slot.clone());
+    // Chimes are mixed in after the tap so they're only heard locally.
+    sink.append(Box::new(Announcer::new(
+        Tap::new(Crossfade::new(slot, outgoing, fade)),
+        state.announcement.clone(),
+    )));
+
+    // Advance when the decoder actually runs dry rather than trusting the tagged duration.
+    state.track_id += 1;
+    let (track_id, sender) = (state.track_id, state.sender.clone());
+    sink.append(Box::new(EmptyCallback::<i16>::new(Box::new(move || {
+        let _ = sender.send(AudioCommand::TrackEnded(track_id));
+    }))));
+    state.track_loaded = true;
+
+    Ok(())
+}
+
+/// Plays a track from `start`, or without one from past its intro or at its enabled start
+/// offset.
+pub fn play_track(
+    track_info: &TrackInfo,
+    sink: &dyn AudioOutput,
+    state: &mut AudioState,
+    start: Option<Duration>,
+) -> Result<(), AudioError> {
+    if state.cast.is_none() && !state.audio_focus.request() {
+        return Err(AudioError::AudioFocusDenied);
+    }
+
+    let fade = state.transition_fade(sink);
+    let intro = apply_skip_rule(state, track_info);
+    let start = start.unwrap_or_else(|| {
+        if intro.is_zero() {
+            start_offset(state, track_info)
+        } else {
+            intro
+        }
+    });
+    load_track(track_info, sink, state, start, fade)?;
+
+    // While casting the local sink only tracks the queue; the cast device does the playing.
+    let cast_load = CastCommand::Load {
+        track: track_info.clone(),
+        position: start.as_secs(),
+        autoplay: true,
+    };
+    if !state.send_to_cast(cast_load) {
+        sink.play();
+    }
+
+    state.push_history(track_info);
+    if std::mem::take(&mut state.announce_next) && state.announce == AnnounceMode::BetweenTracks {
+        state.announce_time();
+    }
+
+    show_metadata(state, track_info);
+    state.set_playback(MediaPlayback::Playing { progress: None });
+
+    state.host.set_current_track(Some(track_info));
 
-    state
-        .controls
-        .set_playback(MediaPlayback::Playing { progress: None })
-        .unwrap();
+    state.end_play();
+    match state.host.library().record_play(track_info) {
+        Ok(history_id) => state.play_record = Some((history_id, Duration::ZERO)),
+        Err(e) => log::error!("Failed to record play: {}", e),
+    }
+
+    state.refill_radio(track_info.index);
+
+    // Lyrics may come from the network, so they are loaded off the audio thread.
+    state.lyrics = None;
+    state.lyric_line = None;
+    state.play_id += 1;
+    let (host, sender, play_id) = (state.host.clone(), state.sender.clone(), state.play_id);
+    let (track, online) = (track_info.clone(), state.online_lyrics);
+    thread::spawn(move || {
+        let lyrics = host.load_lyrics(&track, online);
+        let _ = sender.send(AudioCommand::LyricsLoaded(play_id, lyrics));
+    });
 
     Ok(())
 }
+
+/// Notes where the outro of a track from an album with a skip rule starts, so playback moves
+/// on from there, and returns where to start it to skip the intro.
+fn apply_skip_rule(state: &mut AudioState, track_info: &TrackInfo) -> Duration {
+    state.skip_outro_at = None;
+    let artist = track_info
+        .album_artist
+        .as_deref()
+        .unwrap_or(&track_info.artist);
+    let rule = match state.host.library().skip_rule(&track_info.album, artist) {
+        Ok(Some(rule)) => rule,
+        Ok(None) => return Duration::ZERO,
+        Err(e) => {
+            log::error!("Failed to look up skip rule: {}", e);
+            return Duration::ZERO;
+        }
+    };
+
+    let (intro, outro) = (
+        Duration::from_millis(rule.intro_ms),
+        Duration::from_millis(rule.outro_ms),
+    );
+    let duration = Duration::from_secs(track_info.duration);
+    // Nothing would be left to play of a track shorter than the cuts.
+    if intro + outro >= duration {
+        return Duration::ZERO;
+    }
+    if !outro.is_zero() {
+        state.skip_outro_at = Some(duration - outro);
+    }
+    intro
+}
+
+/// Where to start a track that has a start offset learned from the user skipping its
+/// opening, once they've enabled it.
+fn start_offset(state: &AudioState, track_info: &TrackInfo) -> Duration {
+    match state.host.library().start_offset(&track_info.path) {
+        Ok(Some(offset_ms)) if offset_ms < track_info.duration * 1000 => {
+            Duration::from_millis(offset_ms)
+        }
+        Ok(_) => Duration::ZERO,
+        Err(e) => {
+            log::error!("Failed to look up start offset: {}", e);
+            Duration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str, title: &str, duration: u64) -> TrackInfo {
+        TrackInfo {
+            index: 0,
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration,
+            track_number: None,
+            disc_number: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            artists: Vec::new(),
+            genres: Vec::new(),
+            compilation: false,
+            composer: None,
+            conductor: None,
+            performer: None,
+            work: None,
+            movement: None,
+            path: path.to_string(),
+            source_kind: SourceKind::of(path),
+        }
+    }
+
+    fn paths(queue: &[TrackInfo]) -> Vec<&str> {
+        queue.iter().map(|track| track.path.as_str()).collect()
+    }
+
+    #[test]
+    fn dedupe_keeps_the_first_copy_of_a_file_or_song() {
+        let mut queue = vec![
+            track("/a.mp3", "A", 100),
+            track("/b.mp3", "B", 200),
+            track("/a.mp3", "A", 100),
+            track("/copy/b.flac", "b", 200),
+            track("/c.mp3", "C", 300),
+        ];
+
+        let current = dedupe(&mut queue, 4);
+
+        assert_eq!(paths(&queue), ["/a.mp3", "/b.mp3", "/c.mp3"]);
+        assert_eq!(current, 2);
+        assert_eq!(queue[2].index, 2);
+    }
+
+    #[test]
+    fn dedupe_keeps_the_playing_copy() {
+        let mut queue = vec![
+            track("/a.mp3", "A", 100),
+            track("/b.mp3", "B", 200),
+            track("/live/a.flac", "A", 100),
+        ];
+
+        let current = dedupe(&mut queue, 2);
+
+        assert_eq!(paths(&queue), ["/live/a.flac", "/b.mp3"]);
+        assert_eq!(current, 0);
+    }
+
+    #[test]
+    fn dedupe_only_matches_untitled_tracks_by_file() {
+        let mut queue = vec![track("/a.mp3", "", 100), track("/b.mp3", "", 100)];
+
+        dedupe(&mut queue, 0);
+
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn numbers_in_names_sort_by_value() {
+        let mut names = vec!["Track10.mp3", "track2.mp3", "Track1.mp3", "track02b.mp3"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["Track1.mp3", "track2.mp3", "track02b.mp3", "Track10.mp3"]
+        );
+    }
+
+    #[test]
+    fn tags_order_before_file_names() {
+        let mut tracks = vec![
+            track("/album/10 b.mp3", "B", 0),
+            track("/album/9 a.mp3", "A", 0),
+            track("/album/1 c.mp3", "C", 0),
+        ];
+        tracks[0].track_number = Some(1);
+        tracks[1].track_number = Some(2);
+        tracks[2].track_number = Some(3);
+
+        sort_tracks_by(&mut tracks, ImportOrder::Tags);
+        assert_eq!(
+            paths(&tracks),
+            ["/album/10 b.mp3", "/album/9 a.mp3", "/album/1 c.mp3"]
+        );
+
+        sort_tracks_by(&mut tracks, ImportOrder::FileName);
+        assert_eq!(
+            paths(&tracks),
+            ["/album/1 c.mp3", "/album/9 a.mp3", "/album/10 b.mp3"]
+        );
+    }
+
+    #[test]
+    fn source_kind_follows_the_scheme() {
+        assert_eq!(SourceKind::of("/music/a.mp3"), SourceKind::File);
+        assert_eq!(SourceKind::of("file:///music/a.mp3"), SourceKind::File);
+        assert_eq!(
+            SourceKind::of("https://example.com/a.mp3"),
+            SourceKind::Http
+        );
+        assert_eq!(SourceKind::of("smb://nas/music/a.mp3"), SourceKind::Smb);
+        assert_eq!(SourceKind::of("webdavs://nas/a.mp3"), SourceKind::WebDav);
+        assert_eq!(
+            SourceKind::of("subsonic://server/1"),
+            SourceKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn multi_value_tags_are_split_and_deduplicated() {
+        assert_eq!(
+            split_values(["Artist A; Artist B", "Artist C", "Artist A"].into_iter()),
+            ["Artist A", "Artist B", "Artist C"]
+        );
+        assert_eq!(
+            split_values(["Rock\0Pop / Jazz"].into_iter()),
+            ["Rock", "Pop", "Jazz"]
+        );
+        assert_eq!(split_values(["AC/DC"].into_iter()), ["AC/DC"]);
+        assert!(split_values([" ; "].into_iter()).is_empty());
+    }
+
+    #[test]
+    fn file_uris_become_paths() {
+        assert_eq!(
+            path_from_uri("file:///music/My%20Song.mp3"),
+            "/music/My Song.mp3"
+        );
+        assert_eq!(path_from_uri("/music/a.mp3"), "/music/a.mp3");
+    }
+}
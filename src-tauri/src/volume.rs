@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Attenuation at the bottom of the slider for the logarithmic curve.
+const DB_RANGE: f32 = 50.0;
+
+/// Slider travel below which the logarithmic curve fades linearly to silence.
+const FADE_OUT: f32 = 0.1;
+
+/// How the volume slider position maps to output gain.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeCurve {
+    /// Gain equals the slider position.
+    #[default]
+    Linear,
+    /// Equal slider steps change loudness by equal amounts (in dB) up to 100%.
+    Logarithmic,
+}
+
+impl VolumeCurve {
+    /// Sink gain for a slider position from 0.0 to `MAX_VOLUME`. Both curves meet at 100%,
+    /// and the boost above it stays linear.
+    pub fn gain(self, volume: f32) -> f32 {
+        match self {
+            VolumeCurve::Linear => volume,
+            VolumeCurve::Logarithmic if volume >= 1.0 => volume,
+            VolumeCurve::Logarithmic if volume <= 0.0 => 0.0,
+            VolumeCurve::Logarithmic => {
+                let gain = 10f32.powf((volume - 1.0) * DB_RANGE / 20.0);
+                // -50 dB is still audible, so the last stretch ramps down to silence.
+                gain * (volume / FADE_OUT).min(1.0)
+            }
+        }
+    }
+}
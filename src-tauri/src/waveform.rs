@@ -0,0 +1,100 @@
+use rodio::{Decoder, Source};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use thiserror::Error;
+
+use crate::library::{Library, LibraryError};
+use crate::util;
+
+/// Bytes hashed from each end of a file; enough to tell files apart without reading all of it.
+const HASHED_BYTES: u64 = 256 * 1024;
+
+/// Most buckets a waveform is split into; wider requests get this many.
+const MAX_BUCKETS: usize = 4096;
+
+/// Frames folded into each intermediate peak before resampling to the requested width.
+const PEAK_FRAMES: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum WaveformError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to decode audio file")]
+    DecoderError(#[from] rodio::decoder::DecoderError),
+
+    #[error("Library error: {0}")]
+    LibraryError(#[from] LibraryError),
+}
+
+/// Peak amplitude (0.0 to 1.0) of `buckets` equal slices of the track, read from the library
+/// when this file content was seen before and computed and stored otherwise. `buckets` is
+/// kept between 1 and `MAX_BUCKETS`.
+pub fn peaks(library: &Library, path: &str, buckets: usize) -> Result<Vec<f32>, WaveformError> {
+    let buckets = buckets.clamp(1, MAX_BUCKETS);
+    let hash = file_hash(path)?;
+    if let Some(peaks) = library.waveform(&hash, buckets)? {
+        return Ok(peaks);
+    }
+
+    let peaks = compute_peaks(path, buckets)?;
+    library.store_waveform(&hash, buckets, &peaks)?;
+    Ok(peaks)
+}
+
+/// FNV-1a over the length and both ends of the file, so renamed or moved copies share a
+/// cache entry and retagged files (which shift the audio data) don't.
+fn file_hash(path: &str) -> io::Result<String> {
+    let mut media = util::open_media(path)?;
+    let length = media.seek(SeekFrom::End(0))?;
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(&length.to_le_bytes());
+
+    let mut buffer = Vec::new();
+    for start in [0, length.saturating_sub(HASHED_BYTES)] {
+        media.seek(SeekFrom::Start(start))?;
+        buffer.clear();
+        media.by_ref().take(HASHED_BYTES).read_to_end(&mut buffer)?;
+        feed(&buffer);
+    }
+
+    Ok(format!("{:016x}", hash))
+}
+
+fn compute_peaks(path: &str, buckets: usize) -> Result<Vec<f32>, WaveformError> {
+    let decoder = Decoder::new(BufReader::new(util::open_media(path)?))?;
+    let channels = decoder.channels().max(1) as usize;
+
+    let mut fine = Vec::new();
+    let mut peak = 0u16;
+    let mut samples = 0;
+    for sample in decoder {
+        peak = peak.max(sample.unsigned_abs());
+        samples += 1;
+        if samples == PEAK_FRAMES * channels {
+            fine.push(peak);
+            peak = 0;
+            samples = 0;
+        }
+    }
+    if samples > 0 {
+        fine.push(peak);
+    }
+
+    let buckets = buckets.max(1);
+    Ok((0..buckets)
+        .map(|bucket| {
+            let start = bucket * fine.len() / buckets;
+            let end = ((bucket + 1) * fine.len() / buckets).max(start + 1);
+            let peak = fine.get(start..end.min(fine.len())).unwrap_or_default();
+            peak.iter().copied().max().unwrap_or(0) as f32 / i16::MAX as f32
+        })
+        .map(|peak| peak.min(1.0))
+        .collect())
+}
@@ -0,0 +1,256 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::http_stream::HttpStream;
+use crate::util::{self, MediaStream};
+
+const KEYRING_SERVICE: &str = "music-player-webdav";
+
+const PROPFIND_BODY: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+     <d:propfind xmlns:d=\"DAV:\"><d:prop>\
+     <d:resourcetype/><d:getcontentlength/>\
+     </d:prop></d:propfind>";
+
+/// Where fetched files are kept; `None` while local caching is turned off.
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static DOWNLOADING: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+#[derive(Error, Debug)]
+pub enum WebDavError {
+    #[error("Credential storage error: {0}")]
+    KeyringError(#[from] keyring::Error),
+
+    #[error("Failed to serialize data")]
+    SerializeError(#[from] serde_json::Error),
+
+    #[error("HTTP request failed: {0}")]
+    HttpError(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Invalid listing: {0}")]
+    XmlError(#[from] roxmltree::Error),
+
+    #[error("Invalid WebDAV location {0}")]
+    InvalidLocation(String),
+}
+
+impl From<ureq::Error> for WebDavError {
+    fn from(e: ureq::Error) -> Self {
+        WebDavError::HttpError(e.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebDavCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WebDavEntry {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+}
+
+/// `webdav://` and `webdavs://` locations map onto plain HTTP and HTTPS respectively.
+pub fn is_webdav_path(path: &str) -> bool {
+    path.starts_with("webdav://") || path.starts_with("webdavs://")
+}
+
+fn http_url(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix("webdavs://") {
+        Some(format!("https://{}", rest))
+    } else {
+        path.strip_prefix("webdav://")
+            .map(|rest| format!("http://{}", rest))
+    }
+}
+
+fn host_of(path: &str) -> Option<&str> {
+    let rest = path.split_once("://")?.1;
+    rest.split('/').next().filter(|host| !host.is_empty())
+}
+
+/// Stores credentials for `host` in the OS keychain.
+pub fn save_credentials(host: &str, credentials: &WebDavCredentials) -> Result<(), WebDavError> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, host)?;
+    entry.set_password(&serde_json::to_string(credentials)?)?;
+    Ok(())
+}
+
+pub fn remove_credentials(host: &str) -> Result<(), WebDavError> {
+    match keyring::Entry::new(KEYRING_SERVICE, host)?.delete_credential() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Builds a basic `Authorization` header from the credentials stored for the path's host.
+fn authorization(path: &str) -> Option<String> {
+    let password = keyring::Entry::new(KEYRING_SERVICE, host_of(path)?)
+        .ok()?
+        .get_password()
+        .ok()?;
+    let credentials: WebDavCredentials = serde_json::from_str(&password).ok()?;
+
+    let token = STANDARD.encode(format!("{}:{}", credentials.username, credentials.password));
+    Some(format!("Basic {}", token))
+}
+
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CACHE_DIR.lock().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+/// Lists the folders and playable files directly inside a `webdav(s)://` folder.
+pub fn list(path: &str) -> Result<Vec<WebDavEntry>, WebDavError> {
+    let invalid = || WebDavError::InvalidLocation(path.to_string());
+    let url = http_url(path).ok_or_else(invalid)?;
+    let scheme = path.split_once("://").ok_or_else(invalid)?.0;
+    let host = host_of(path).ok_or_else(invalid)?;
+
+    let mut request = ureq::request("PROPFIND", &url)
+        .timeout(Duration::from_secs(10))
+        .set("Depth", "1")
+        .set("Content-Type", "application/xml; charset=\"utf-8\"");
+    if let Some(authorization) = authorization(path) {
+        request = request.set("Authorization", &authorization);
+    }
+
+    let body = request.send_string(PROPFIND_BODY)?.into_string()?;
+    let document = roxmltree::Document::parse(&body)?;
+
+    let requested = url
+        .splitn(4, '/')
+        .nth(3)
+        .map(|p| format!("/{}", p))
+        .unwrap_or_default();
+
+    let mut entries: Vec<WebDavEntry> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("response"))
+        .filter_map(|node| {
+            let href = node
+                .descendants()
+                .find(|n| n.has_tag_name("href"))
+                .and_then(|n| n.text())?
+                .trim();
+            // Some servers answer with absolute URLs, others with paths.
+            let href_path = match href.split_once("://") {
+                Some((_, rest)) => format!("/{}", rest.split_once('/').map_or("", |(_, p)| p)),
+                None => href.to_string(),
+            };
+            if href_path.trim_end_matches('/') == requested.trim_end_matches('/') {
+                return None;
+            }
+
+            let is_dir = node.descendants().any(|n| n.has_tag_name("collection"));
+            let name = util::percent_decode(href_path.trim_end_matches('/').rsplit('/').next()?);
+            if !is_dir && !util::is_supported_audio_file(Path::new(&name)) {
+                return None;
+            }
+
+            Some(WebDavEntry {
+                name,
+                path: format!("{}://{}{}", scheme, host, href_path),
+                is_dir,
+                size: node
+                    .descendants()
+                    .find(|n| n.has_tag_name("getcontentlength"))
+                    .and_then(|n| n.text())
+                    .and_then(|size| size.trim().parse().ok()),
+            })
+        })
+        .collect();
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// Opens a remote file, reading it from the local cache when a copy is already there.
+pub fn open(path: &str) -> io::Result<Box<dyn MediaStream>> {
+    let url = http_url(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid WebDAV path {}", path),
+        )
+    })?;
+
+    let cache_dir = CACHE_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(cache_dir) = cache_dir {
+        let cached = cache_dir.join(cache_name(&url));
+        if cached.is_file() {
+            return Ok(Box::new(File::open(cached)?));
+        }
+
+        start_download(url.clone(), authorization(path), cached);
+    }
+
+    Ok(Box::new(HttpStream::open(&url, authorization(path))?))
+}
+
+fn cache_name(url: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+
+    match Path::new(url).extension().and_then(|ext| ext.to_str()) {
+        Some(extension) => format!("{:016x}.{}", hasher.finish(), extension),
+        None => format!("{:016x}", hasher.finish()),
+    }
+}
+
+/// Copies the file into the cache in the background; playback keeps streaming meanwhile.
+fn start_download(url: String, authorization: Option<String>, target: PathBuf) {
+    {
+        let mut downloading = DOWNLOADING.lock().unwrap_or_else(|e| e.into_inner());
+        if !downloading
+            .get_or_insert_with(HashSet::new)
+            .insert(target.clone())
+        {
+            return;
+        }
+    }
+
+    thread::spawn(move || {
+        if let Err(e) = download(&url, authorization, &target) {
+            log::error!("{}", e);
+        }
+
+        let mut downloading = DOWNLOADING.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(downloading) = downloading.as_mut() {
+            downloading.remove(&target);
+        }
+    });
+}
+
+fn download(url: &str, authorization: Option<String>, target: &Path) -> Result<(), WebDavError> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut request = ureq::get(url);
+    if let Some(authorization) = &authorization {
+        request = request.set("Authorization", authorization);
+    }
+
+    let partial = target.with_extension("part");
+    let mut file = File::create(&partial)?;
+    io::copy(&mut request.call()?.into_reader(), &mut file)?;
+    fs::rename(&partial, target)?;
+
+    Ok(())
+}
@@ -11,12 +11,26 @@ type TrackInfo = {
   album: string;
   artist: string;
   duration: number;
+  track_number: number | null;
+  disc_number: number | null;
+  album_artist: string | null;
+  genre: string | null;
+  year: number | null;
+  artists: string[];
+  genres: string[];
+  compilation: boolean;
+  composer: string | null;
+  conductor: string | null;
+  performer: string | null;
+  work: string | null;
+  movement: string | null;
 };
 
 type EventPayload<T> = {
   success: boolean;
   data: { type: string; data: T };
   error: string | null;
+  queue_version: number;
 };
 
 const elements = {